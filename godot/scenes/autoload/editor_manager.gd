@@ -1,6 +1,11 @@
 class_name EditorManagerClass
 extends Node
 
+# コンベア系のブロックID（建物定義の behaviour が Conveyor のもの、初回参照時に取得）
+var _conveyor_ids: Array[int] = []
+# behaviour 名から建物種別の番号へ（初回参照時に取得）
+var _building_types: Dictionary = {}
+
 # 建物選択状態
 var selected_building_id: int = -1
 var selected_building_rotation: int = 0
@@ -86,12 +91,34 @@ func rotate_selected_building() -> void:
 		selected_building_rotation = (selected_building_rotation + 1) % 4
 		print("Building ", selected_building_id, " rotated to: ", selected_building_rotation)
 
+# コンベア系のブロックか
+func is_conveyor(building_id: int) -> bool:
+	return building_id in get_conveyor_ids()
+
+func get_conveyor_ids() -> Array[int]:
+	if _conveyor_ids.is_empty():
+		var definitions = rust("get_building_definitions")
+		if definitions != null:
+			for definition in definitions:
+				if definition.get("behaviour", "") == "Conveyor":
+					_conveyor_ids.append(int(definition["id"]))
+	return _conveyor_ids
+
+# behaviour 名（"Honeypot" など）の建物種別の番号。パケットの報告の building_type と比べる
+func get_building_type(behaviour: String) -> int:
+	if _building_types.is_empty():
+		var definitions = rust("get_building_definitions")
+		if definitions != null:
+			for definition in definitions:
+				_building_types[definition.get("behaviour", "")] = int(definition.get("building_type", -1))
+	return _building_types.get(behaviour, -1)
+
 # 回転に応じた atlas coords を計算
 # コンベアはalternativeを使うのでVector2i(0,0)を返す
 # その他のbuildingはatlas coordsのx座標を変更
 func get_atlas_coords_for_rotation(building_id: int, rotation: int, building_size: Vector2i) -> Vector2i:
 	# コンベアは常に(0,0) - alternativeで回転管理
-	if building_id == 2:
+	if is_conveyor(building_id):
 		return Vector2i(0, 0)
 	
 	# その他のbuildingはatlas coordsで回転管理
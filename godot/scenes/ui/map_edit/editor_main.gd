@@ -53,6 +53,7 @@ func _ready() -> void:
 	
 	map_controller.building_placed.connect(_on_building_placed)
 	map_controller.building_removed.connect(_on_building_removed)
+	map_controller.building_updated.connect(_on_building_updated)
 
 	# マップの読み込みと初期化
 	_initialize_map()
@@ -125,7 +126,7 @@ func _update_mouse_pos_preview() -> void:
 	var building_size = get_building_size(source_id)
 	
 	# コンベアの場合はalternativeを使用
-	if source_id == 2:
+	if EditorManager.is_conveyor(source_id):
 		buildplan_mouse_pos_preview_layer.set_cell(tile_coords, source_id, Vector2i(0, 0), rotation)
 	else:
 		# その他のbuildingはatlas coordsを使用
@@ -176,6 +177,9 @@ func _on_building_placed(info: Dictionary):
 func _on_building_removed(id: int, pos: Vector2i):
 	building_layer.erase_cell(pos)
 
+func _on_building_updated(info: Dictionary):
+	building_layer.set_cell(info["pos"], info["type"], Vector2i(0, 0), info["rotation"])
+
 var _map_metadata
 
 # マップの初期化処理
@@ -361,7 +365,7 @@ func _handle_left_click_press(tile_coords: Vector2i) -> void:
 		var building_size = get_building_size(source_id)
 		
 		# コンベアはalternativeで、その他はatlas coordsで回転管理
-		if source_id == 2:
+		if EditorManager.is_conveyor(source_id):
 			buildplan_dragging_preview_layer.set_cell(tile_coords, source_id, Vector2i(0, 0), rotation)
 		else:
 			var atlas_coords = EditorManager.get_atlas_coords_for_rotation(source_id, rotation, building_size)
@@ -646,7 +650,7 @@ func _handle_mouse_drag(tile_coords: Vector2i) -> void:
 		var atlas_coords = Vector2i(0, 0)
 		
 		# コンベアはライン方向に自動回転、alternativeを使用
-		if source_id == 2:
+		if EditorManager.is_conveyor(source_id):
 			rotation = line_direction
 			
 			if tile_set:
@@ -706,7 +710,7 @@ func _commit_building_plan():
 						continue
 					var neighbor = occupied_cell + Vector2i(dx, dy)
 					var neighbor_id = building_layer.get_cell_source_id(neighbor)
-					if neighbor_id == 2 and not affected_conveyor_cells.has(neighbor):
+					if EditorManager.is_conveyor(neighbor_id) and not affected_conveyor_cells.has(neighbor):
 						affected_conveyor_cells.append(neighbor)
 			
 			# Building Layerから削除
@@ -732,7 +736,7 @@ func _commit_building_plan():
 		# コンベアの場合はalternative_tileをそのまま使用
 		# その他の場合はatlas_coordsから回転を計算
 		var rotation_state = alternative_tile
-		if source_id != 2:  # コンベア以外の場合
+		if not EditorManager.is_conveyor(source_id):  # コンベア以外の場合
 			var building_size = get_building_size(source_id)
 			rotation_state = atlas_coords.x / building_size.x  # x座標のオフセットから回転を計算
 		
@@ -740,7 +744,7 @@ func _commit_building_plan():
 		map_controller.place_building(cell, source_id, rotation_state)
 		
 		# コンベアの場合は記録
-		if source_id == 2:
+		if EditorManager.is_conveyor(source_id):
 			affected_conveyor_cells.append(cell)
 		
 		# 新規building周辺のコンベアも更新対象に追加（マルチブロック対応）
@@ -753,7 +757,7 @@ func _commit_building_plan():
 						continue
 					var neighbor = occupied_cell + Vector2i(dx, dy)
 					var neighbor_id = building_layer.get_cell_source_id(neighbor)
-					if neighbor_id == 2 and not affected_conveyor_cells.has(neighbor):
+					if EditorManager.is_conveyor(neighbor_id) and not affected_conveyor_cells.has(neighbor):
 						affected_conveyor_cells.append(neighbor)
 		
 		print("Building placed at: ", cell, " type: ", source_id, " atlas: ", atlas_coords, " rotation: ", rotation_state)
@@ -906,13 +910,13 @@ func _update_conveyor_connections(changed_cells: Array[Vector2i]) -> void:
 					continue
 				var neighbor = cell + Vector2i(dx, dy)
 				var neighbor_id = building_layer.get_cell_source_id(neighbor)
-				if neighbor_id == 2 and not cells_to_update.has(neighbor):
+				if EditorManager.is_conveyor(neighbor_id) and not cells_to_update.has(neighbor):
 					cells_to_update.append(neighbor)
 	
 	# 各コンベアの接続状況を更新
 	for cell in cells_to_update:
 		var source_id = building_layer.get_cell_source_id(cell)
-		if source_id != 2:  # コンベアでない場合はスキップ
+		if not EditorManager.is_conveyor(source_id):  # コンベアでない場合はスキップ
 			continue
 		
 		var rotation = building_layer.get_cell_alternative_tile(cell)
@@ -922,7 +926,7 @@ func _update_conveyor_connections(changed_cells: Array[Vector2i]) -> void:
 		var new_alt = result[1]
 		
 		# Atlas座標とalternative_tileを更新
-		building_layer.set_cell(cell, 2, new_atlas, new_alt)
+		building_layer.set_cell(cell, source_id, new_atlas, new_alt)
 		print("Updated conveyor at ", cell, " atlas: ", new_atlas, " alt: ", new_alt)
 
 func _check_conveyor_connections(pos: Vector2i, rotation: int) -> Dictionary:
@@ -953,7 +957,7 @@ func _check_conveyor_connections(pos: Vector2i, rotation: int) -> Dictionary:
 		
 		var is_connected = false
 		
-		if neighbor_id == 2:  # コンベアの場合
+		if EditorManager.is_conveyor(neighbor_id):  # コンベアの場合
 			var neighbor_alt = building_layer.get_cell_alternative_tile(neighbor_pos)
 			var neighbor_base_rotation = neighbor_alt % 4
 			var neighbor_output_dir = _get_output_direction(neighbor_base_rotation)
@@ -5,6 +5,7 @@ const BUILDING_ITEM_SCENE = preload("res://scenes/ui/map_edit/hud/result_window/
 const DATACENTER_TYPE = 1
 const RECYCLE_BIN_TYPE = 9
 
+@onready var honeypot_type: int = EditorManager.get_building_type("Honeypot")
 @onready var datacenter_list: VBoxContainer = $HBoxContainer/DatacenterColumn/DatacenterScrollContainer/DatacenterList
 @onready var recyclebin_list: VBoxContainer = $HBoxContainer/RecycleBinColumn/RecycleBinScrollContainer/RecycleBinList
 @onready var datacenter_label: Label = $HBoxContainer/DatacenterColumn/DatacenterLabel
@@ -38,7 +39,8 @@ func setup_buildings(buildings_data: Dictionary, result_window) -> void:
 		if b_type == DATACENTER_TYPE:
 			datacenter_list.add_child(item)
 			datacenter_count += 1
-		elif b_type == RECYCLE_BIN_TYPE:
+		elif b_type == RECYCLE_BIN_TYPE or b_type == honeypot_type:
+			# ハニーポットも破棄側の列に並べる
 			recyclebin_list.add_child(item)
 			recyclebin_count += 1
 	
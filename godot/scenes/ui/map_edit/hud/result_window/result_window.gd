@@ -3,12 +3,15 @@ extends Control
 const PACKET_LIST_SCENE = preload("res://scenes/ui/map_edit/hud/packet_list/packet_list_main.tscn")
 const DATACENTER_TYPE = 1
 const RECYCLE_BIN_TYPE = 9
+# 攻撃元サマリーに並べる送信元の数
+const ATTACKER_SUMMARY_LIMIT = 5
 
 @onready var score_label: Label = $AnimationPlayer/BottomRightRect2/ScoreLabel
 @onready var retry_button: Button = $AnimationPlayer/BottomRightRect2/RetryButton
 @onready var stage_button: Button = $AnimationPlayer/BottomRightRect2/StageButton
 @onready var buildings_container = $AnimationPlayer/CenterContainer/BuildingItemsContainer
 @onready var hud_layer = $HUD
+@onready var honeypot_type: int = EditorManager.get_building_type("Honeypot")
 
 var _packet_list_instance = null
 
@@ -25,6 +28,8 @@ func _ready():
 	
 	# Building一覧を読み込み
 	_load_buildings()
+	# ハニーポットが集めた攻撃元のサマリー
+	_show_attacker_summary()
 
 func _load_buildings():
 	if not EditorManager.map_controller:
@@ -43,7 +48,7 @@ func _load_buildings():
 		var b_id = packet_info.get("building_id", -1)
 		var b_type = packet_info.get("building_type", -1)
 		
-		if b_id == -1 or (b_type != DATACENTER_TYPE and b_type != RECYCLE_BIN_TYPE):
+		if b_id == -1 or not b_type in [DATACENTER_TYPE, RECYCLE_BIN_TYPE, honeypot_type]:
 			continue
 		
 		if not buildings_data.has(b_id):
@@ -68,6 +73,44 @@ func _load_buildings():
 	if buildings_container:
 		buildings_container.setup_buildings(buildings_data, self)
 
+# ハニーポットが観測した攻撃元を、攻撃パケットの多い順に表示する
+func _show_attacker_summary():
+	if not EditorManager.map_controller:
+		return
+	var profiles = EditorManager.map_controller.get_attacker_profiles()
+	if profiles == null or profiles.is_empty():
+		return
+	
+	var lines: Array[String] = ["Attackers (%d)" % profiles.size()]
+	for profile in profiles.slice(0, ATTACKER_SUMMARY_LIMIT):
+		var ports = profile.get("dest_ports", {}).keys()
+		ports.sort()
+		var line = "%s  攻撃 %d件  ポート %s" % [
+			profile.get("source_ip", ""),
+			profile.get("attack_packets", 0),
+			", ".join(PackedStringArray(ports.map(func(port): return str(port)))),
+		]
+		# 最も多かったペイロードの特徴
+		var signatures: Dictionary = profile.get("signatures", {})
+		var top_signature = ""
+		for signature in signatures:
+			if top_signature == "" or signatures[signature] > signatures[top_signature]:
+				top_signature = signature
+		if top_signature != "":
+			line += "  「%s」" % top_signature
+		lines.append(line)
+	if profiles.size() > ATTACKER_SUMMARY_LIMIT:
+		lines.append("ほか %d 件" % (profiles.size() - ATTACKER_SUMMARY_LIMIT))
+	
+	var label = Label.new()
+	label.text = "\n".join(PackedStringArray(lines))
+	var panel = PanelContainer.new()
+	panel.add_child(label)
+	add_child(panel)
+	# 左下に寄せ、中身に合わせて上へ伸ばす
+	panel.set_anchors_and_offsets_preset(Control.PRESET_BOTTOM_LEFT, Control.PRESET_MODE_MINSIZE, 16)
+	panel.grow_vertical = Control.GROW_DIRECTION_BEGIN
+
 # 特定のBuildingのパケットを表示
 func show_packets_for_building(building_id: int, packets: Array):
 	# 既存のパケット一覧があれば閉じる
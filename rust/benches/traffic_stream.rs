@@ -0,0 +1,121 @@
+//! Peak heap usage of streaming a capture versus materializing it.
+//!
+//! Run with `cargo bench --bench traffic_stream`. Each row writes a synthetic capture with the
+//! given number of frames to a temporary file, then reports the peak number of heap bytes
+//! allocated while (a) draining it through `PcapTrafficSource` and (b) collecting every packet
+//! into a `Vec`, which is what loading it as `Traffic` amounts to. The streaming column should
+//! stay flat as the capture grows.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use gdr_mws::core::packet::{Packet, Protocol};
+use gdr_mws::packet::pcap_writer::build_frame;
+use gdr_mws::packet::traffic_source::{DEFAULT_LOOKAHEAD, PcapTrafficSource, TrafficSource};
+use pcap_file::pcap::{PcapPacket, PcapWriter};
+
+struct CountingAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(now, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+const PAYLOAD: &[u8] =
+    b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nUser-Agent: bench\r\n\r\n";
+
+fn write_capture(path: &std::path::Path, frames: usize) {
+    let file = BufWriter::new(File::create(path).expect("create capture"));
+    let mut writer = PcapWriter::new(file).expect("pcap header");
+    for i in 0..frames {
+        let packet = Packet::new(
+            format!("10.{}.{}.{}", (i >> 16) & 0xff, (i >> 8) & 0xff, i & 0xff),
+            "192.168.0.10".to_string(),
+            1024 + (i % 50_000) as u16,
+            80,
+            Protocol::Tcp,
+            1500,
+            PAYLOAD.to_vec(),
+        );
+        let frame = build_frame(&packet).expect("frame");
+        let timestamp = Duration::from_micros(i as u64 * 100);
+        writer
+            .write_packet(&PcapPacket::new(timestamp, 1500, &frame))
+            .expect("write frame");
+    }
+}
+
+fn open_source(path: &std::path::Path) -> PcapTrafficSource<BufReader<File>> {
+    PcapTrafficSource::new(BufReader::new(File::open(path).unwrap()), DEFAULT_LOOKAHEAD).unwrap()
+}
+
+/// Run `f` and return its result together with the peak heap growth it caused.
+fn measure<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let result = f();
+    (result, PEAK.load(Ordering::Relaxed) - baseline)
+}
+
+fn main() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    println!(
+        "{:>10} {:>12} {:>16} {:>16} {:>10}",
+        "frames", "file (KiB)", "stream (KiB)", "collect (KiB)", "stream ms"
+    );
+
+    for frames in [10_000, 100_000, 400_000] {
+        let path = dir.path().join(format!("capture_{}.pcap", frames));
+        write_capture(&path, frames);
+        let file_size = std::fs::metadata(&path).unwrap().len() / 1024;
+
+        let started = Instant::now();
+        let (streamed, stream_peak) = measure(|| {
+            let mut source = open_source(&path);
+            let mut count = 0usize;
+            while let Some(packet) = source.pop_due(i64::MAX) {
+                std::hint::black_box(&packet);
+                count += 1;
+            }
+            count
+        });
+        let elapsed = started.elapsed().as_millis();
+
+        let (collected, collect_peak) = measure(|| {
+            let mut source = open_source(&path);
+            std::iter::from_fn(|| source.pop_due(i64::MAX)).collect::<Vec<_>>()
+        });
+
+        assert_eq!(streamed, frames);
+        assert_eq!(collected.len(), frames);
+        drop(collected);
+
+        println!(
+            "{:>10} {:>12} {:>16} {:>16} {:>10}",
+            frames,
+            file_size,
+            stream_peak / 1024,
+            collect_peak / 1024,
+            elapsed
+        );
+    }
+}
@@ -0,0 +1,65 @@
+//! Headless simulation runner.
+//!
+//! ```text
+//! headless replay <replay.json> [--root <project dir>] [--pcap <out.pcap> [--scope <scope>]]
+//! ```
+//!
+//! Plays a replay file back without Godot and prints the outcome as JSON. `res://` paths in
+//! the replay and its stage are resolved against `--root` (default: the current directory).
+//! `--pcap` also writes the packets held by the sinks at the end to a pcap file; `--scope`
+//! picks the sinks (`datacenter`, `recyclebin`, `honeypot` or `all`, the default).
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use gdr_mws::logic::replay::{self, Replay};
+use gdr_mws::logic::stage;
+use gdr_mws::map_controller::PcapExportScope;
+
+const USAGE: &str = "usage: headless replay <replay.json> [--root <project dir>] \
+                     [--pcap <out.pcap> [--scope datacenter|recyclebin|honeypot|all]]";
+
+fn main() -> ExitCode {
+    match run(std::env::args().skip(1).collect()) {
+        Ok(output) => {
+            println!("{}", output);
+            ExitCode::SUCCESS
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: Vec<String>) -> Result<String, String> {
+    let mut args = args.into_iter();
+    if args.next().as_deref() != Some("replay") {
+        return Err(USAGE.to_string());
+    }
+    let file = args.next().map(PathBuf::from).ok_or(USAGE)?;
+    let mut root = PathBuf::from(".");
+    let mut pcap = None;
+    let mut scope = PcapExportScope::AllSinks;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--root" => root = args.next().map(PathBuf::from).ok_or(USAGE)?,
+            "--pcap" => pcap = Some(args.next().map(PathBuf::from).ok_or(USAGE)?),
+            "--scope" => {
+                scope = args
+                    .next()
+                    .as_deref()
+                    .and_then(PcapExportScope::from_name)
+                    .ok_or(USAGE)?
+            }
+            _ => return Err(USAGE.to_string()),
+        }
+    }
+
+    let replay = Replay::load(&file)?;
+    let mut world = stage::load_stage(&replay.stage, &root)?;
+    replay::play(&mut world, &replay.edits, replay.end_tick);
+    if let Some(path) = pcap {
+        world.export_pcap(scope, &path)?;
+    }
+    serde_json::to_string_pretty(&replay::outcome(&world)).map_err(|err| err.to_string())
+}
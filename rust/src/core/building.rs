@@ -1,8 +1,13 @@
+use crate::core::building_config::BuildingConfig;
+use crate::core::building_layout::{BuildingLayout, OutputRole, PortKind};
+use crate::core::building_registry::{block_id, registry};
 use crate::core::dto::Vec2i;
 use crate::core::packet::Packet;
+use crate::logic::blocklist::Blocklists;
+use serde::{Deserialize, Serialize};
 use std::any::Any;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum BuildingType {
     Internet,
     Datacenter,
@@ -14,6 +19,42 @@ pub enum BuildingType {
     ContentFilter,
     Junction,
     RecycleBin,
+    // Later types go last so the `as i32` values Godot sees keep their meaning.
+    TunnelEntrance,
+    TunnelExit,
+    Splitter,
+    Merger,
+    Tap,
+    Analyzer,
+    RateLimiter,
+    StatefulFirewall,
+    ScanDetector,
+    Honeypot,
+}
+
+impl BuildingType {
+    pub const ALL: [BuildingType; 20] = [
+        BuildingType::Internet,
+        BuildingType::Datacenter,
+        BuildingType::Conveyor,
+        BuildingType::IpFilter,
+        BuildingType::PortFilter,
+        BuildingType::LengthFilter,
+        BuildingType::ProtocolFilter,
+        BuildingType::ContentFilter,
+        BuildingType::Junction,
+        BuildingType::RecycleBin,
+        BuildingType::TunnelEntrance,
+        BuildingType::TunnelExit,
+        BuildingType::Splitter,
+        BuildingType::Merger,
+        BuildingType::Tap,
+        BuildingType::Analyzer,
+        BuildingType::RateLimiter,
+        BuildingType::StatefulFirewall,
+        BuildingType::ScanDetector,
+        BuildingType::Honeypot,
+    ];
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -23,16 +64,115 @@ pub enum BuildingAction {
     SubScore(u32),
 }
 
+use crate::core::buildings::analyzer::AnalyzerStats;
+use crate::core::buildings::conveyor::EntrySide;
+use crate::core::buildings::datacenter::DatacenterState;
+use crate::core::buildings::filters::rate_limiter::RateLimiterState;
+use crate::core::buildings::filters::scan_detector::ScanDetectorState;
+use crate::core::buildings::filters::stateful_firewall::StatefulFirewallState;
 use crate::core::dto::BuildingId;
 
+/// Simulation state of a building (buffered packets with their progress, traffic cursor),
+/// as captured by world snapshots. Layout and filter rules are not part of it.
+#[derive(Debug, Clone)]
+pub enum BuildingState {
+    /// Packets held by the building, in buffer order.
+    Packets(Vec<Packet>),
+    /// A conveyor's packets, front first, and the sides they entered from.
+    Conveyor(Vec<(Packet, EntrySide)>),
+    /// A junction's packet and the tile it came from.
+    Junction(Option<(Packet, Vec2i)>),
+    /// A tunnel exit's packets underground, front first, and the entrances they came from.
+    Tunnel(Vec<(Packet, Vec2i)>),
+    /// A splitter's packet and the weighted round-robin credit of its outputs.
+    Splitter {
+        packet: Option<Packet>,
+        credit: [i64; 3],
+    },
+    /// The packet waiting at each merger input and the input it serves next.
+    Merger {
+        slots: Vec<Option<Packet>>,
+        cursor: usize,
+    },
+    /// What an analyzer has counted so far.
+    Analyzer(AnalyzerStats),
+    /// A rate limiter's packet and the passes still inside its window.
+    RateLimiter(RateLimiterState),
+    /// A stateful firewall's packet and its session table.
+    StatefulFirewall(StatefulFirewallState),
+    /// A scan detector's packet, the recent destinations of each source and flagged sources.
+    ScanDetector(ScanDetectorState),
+    /// A datacenter's processed packets, its queue and its processing budget.
+    Datacenter(DatacenterState),
+    /// A sink in a world snapshot. Sinks only ever append packets, so the number received is
+    /// enough to roll them back; `rest` is the remaining state, without packets.
+    Sink {
+        received: usize,
+        rest: Box<BuildingState>,
+    },
+    /// Packets waiting in an `Internet` plus its position in the traffic.
+    Internet {
+        packets: Vec<Packet>,
+        emitted: u64,
+        time: f32,
+    },
+}
+
+impl BuildingState {
+    /// State of a building that holds nothing.
+    pub fn empty() -> Self {
+        BuildingState::Packets(Vec::new())
+    }
+}
+
 pub trait Building {
     fn id(&self) -> BuildingId;
     fn position(&self) -> Vec2i;
     fn rotation(&self) -> i32;
+    /// Turn the building in place; ports follow the new rotation.
+    fn set_rotation(&mut self, rot: i32);
     fn building_type(&self) -> BuildingType;
-    fn get_size(&self) -> Vec2i;
-    fn get_output_poses(&self) -> Vec<Vec2i>;
-    fn get_input_poses(&self) -> Vec<Vec2i>;
+    /// Block id of the building's definition; only behaviours shared by several blocks
+    /// need to override it.
+    fn def_id(&self) -> i32 {
+        block_id(self.building_type())
+    }
+    fn layout(&self) -> &'static BuildingLayout {
+        &registry()
+            .by_id(self.def_id())
+            .expect("buildings are created from registry definitions")
+            .layout
+    }
+    fn get_size(&self) -> Vec2i {
+        self.layout().size(self.rotation())
+    }
+    fn get_output_poses(&self) -> Vec<Vec2i> {
+        self.layout()
+            .port_poses(PortKind::Output, self.position(), self.rotation())
+    }
+    fn get_input_poses(&self) -> Vec<Vec2i> {
+        self.layout()
+            .port_poses(PortKind::Input, self.position(), self.rotation())
+    }
+    /// Role of the output port on `out_pos`.
+    fn output_role(&self, out_pos: Vec2i) -> OutputRole {
+        self.layout()
+            .output_role(self.position(), self.rotation(), out_pos)
+    }
+    /// Whether `packet` matches the rule, for filters; `None` for other buildings.
+    fn filter_packet(&self, _packet: &Packet, _blocklists: &Blocklists) -> Option<bool> {
+        None
+    }
+    /// Current rule, for buildings that take one and have it set.
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        None
+    }
+    /// Replace the rule. Returns `false` when the building takes no rule of that kind.
+    fn set_filter_config(&mut self, _config: BuildingConfig) -> bool {
+        false
+    }
+    /// Remove the rule; a filter without one passes nothing.
+    fn clear_filter_config(&mut self) {}
     fn update(&mut self, delta: f32);
     fn can_offload(&self) -> bool;
     fn can_accept(&self, packet: &Packet, source_pos: Vec2i) -> bool;
@@ -41,6 +181,14 @@ pub trait Building {
     fn get_progress(&self) -> f32;
     fn get_packets(&self) -> Vec<Packet>;
     fn get_packet_progresses(&self) -> Vec<f32>;
+    fn save_state(&self) -> BuildingState;
+    /// State kept by world snapshots; sinks return a cheap `BuildingState::Sink`.
+    fn snapshot_state(&self) -> BuildingState {
+        self.save_state()
+    }
+    /// Restore a state captured by `save_state` or `snapshot_state`. A state of another kind
+    /// empties the building.
+    fn restore_state(&mut self, state: BuildingState);
     fn as_any(&self) -> &dyn Any;
     fn as_any_mut(&mut self) -> &mut dyn Any;
 }
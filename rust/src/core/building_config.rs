@@ -0,0 +1,103 @@
+//! The rule a building takes: what a filter matches, how a splitter or merger routes, or
+//! what a datacenter hosts. Layout edits and replays carry rules in this form.
+use serde::{Deserialize, Serialize};
+
+use crate::core::building::BuildingType;
+use crate::core::buildings::datacenter::{DatacenterConfig, HostedService};
+use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+use crate::core::buildings::filters::ip_filter::{IpBlocklistConfig, IpFilterConfig};
+use crate::core::buildings::filters::length_filter::LengthFilterConfig;
+use crate::core::buildings::filters::port_filter::PortFilterConfig;
+use crate::core::buildings::filters::protocol_filter::ProtocolFilterConfig;
+use crate::core::buildings::filters::rate_limiter::RateLimiterConfig;
+use crate::core::buildings::filters::scan_detector::{BlocklistFeed, ScanDetectorConfig};
+use crate::core::buildings::filters::stateful_firewall::{FirewallService, StatefulFirewallConfig};
+use crate::core::buildings::merger::MergerConfig;
+use crate::core::buildings::splitter::SplitterConfig;
+
+/// Rule of any filter building, the routing rule of a splitter or merger, or the services
+/// and capacity of a datacenter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BuildingConfig {
+    Ip(IpFilterConfig),
+    /// An IP filter matching the addresses on a world blocklist.
+    IpBlocklist(IpBlocklistConfig),
+    Port(PortFilterConfig),
+    Length(LengthFilterConfig),
+    Protocol(ProtocolFilterConfig),
+    Content(ContentFilterConfig),
+    RateLimit(RateLimiterConfig),
+    Firewall(StatefulFirewallConfig),
+    ScanDetector(ScanDetectorConfig),
+    /// A scan detector that also adds the sources it flags to a world blocklist.
+    ScanDetectorFeed {
+        detector: ScanDetectorConfig,
+        feed: BlocklistFeed,
+    },
+    Splitter(SplitterConfig),
+    Merger(MergerConfig),
+    Datacenter(DatacenterConfig),
+}
+
+impl BuildingConfig {
+    pub fn building_type(&self) -> BuildingType {
+        match self {
+            BuildingConfig::Ip(_) | BuildingConfig::IpBlocklist(_) => BuildingType::IpFilter,
+            BuildingConfig::Port(_) => BuildingType::PortFilter,
+            BuildingConfig::Length(_) => BuildingType::LengthFilter,
+            BuildingConfig::Protocol(_) => BuildingType::ProtocolFilter,
+            BuildingConfig::Content(_) => BuildingType::ContentFilter,
+            BuildingConfig::RateLimit(_) => BuildingType::RateLimiter,
+            BuildingConfig::Firewall(_) => BuildingType::StatefulFirewall,
+            BuildingConfig::ScanDetector(_) | BuildingConfig::ScanDetectorFeed { .. } => {
+                BuildingType::ScanDetector
+            }
+            BuildingConfig::Splitter(_) => BuildingType::Splitter,
+            BuildingConfig::Merger(_) => BuildingType::Merger,
+            BuildingConfig::Datacenter(_) => BuildingType::Datacenter,
+        }
+    }
+
+    /// This rule carried over to a building of `building_type` replacing its own, where it
+    /// has a meaning there: the open services of a stateful firewall and of a datacenter
+    /// translate into each other. Other rules only fit their own type.
+    pub fn migrate_to(&self, building_type: BuildingType) -> Option<BuildingConfig> {
+        if self.building_type() == building_type {
+            return Some(self.clone());
+        }
+        match (self, building_type) {
+            (BuildingConfig::Firewall(firewall), BuildingType::Datacenter) => {
+                Some(BuildingConfig::Datacenter(DatacenterConfig {
+                    services: firewall
+                        .services
+                        .iter()
+                        .map(|service| HostedService {
+                            protocol: service.protocol.clone(),
+                            port: service.port,
+                            ip: None,
+                        })
+                        .collect(),
+                    capacity_pps: 0,
+                    queue_size: 0,
+                }))
+            }
+            (BuildingConfig::Datacenter(datacenter), BuildingType::StatefulFirewall) => {
+                let mut services: Vec<FirewallService> = Vec::new();
+                for service in &datacenter.services {
+                    let service = FirewallService {
+                        protocol: service.protocol.clone(),
+                        port: service.port,
+                    };
+                    if !services.contains(&service) {
+                        services.push(service);
+                    }
+                }
+                Some(BuildingConfig::Firewall(StatefulFirewallConfig::new(
+                    services,
+                )))
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,181 @@
+//! Size and ports of each building type, described at rotation `0` and turned clockwise by
+//! the building's rotation.
+//!
+//! Layouts come from the building registry (see [`building_registry`](super::building_registry)).
+//!
+//! A port sits on the tile just outside one side of the footprint. `offset` counts tiles
+//! along that side in clockwise order (left to right on the north side, top to bottom on the
+//! east side, and so on), so turning a building only moves its ports to the next side.
+use serde::{Deserialize, Serialize};
+
+use crate::core::building::BuildingType;
+use crate::core::building_registry::registry;
+use crate::core::dto::Vec2i;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputRole {
+    #[default]
+    Default,
+    FilterMatch,
+    FilterMismatch,
+    /// Where a `Tap` sends copies; never used for the original packet.
+    Mirror,
+}
+
+/// Side of the footprint, in clockwise order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Side {
+    const CLOCKWISE: [Side; 4] = [Side::North, Side::East, Side::South, Side::West];
+
+    /// The side this one ends up on after `rotation` clockwise quarter turns.
+    pub fn rotated(self, rotation: i32) -> Side {
+        let index = Self::CLOCKWISE
+            .iter()
+            .position(|side| *side == self)
+            .unwrap() as i32;
+        Self::CLOCKWISE[(index + rotation).rem_euclid(4) as usize]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PortKind {
+    Input,
+    Output,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PortDef {
+    pub name: String,
+    pub kind: PortKind,
+    pub side: Side,
+    #[serde(default)]
+    pub offset: i32,
+    /// How routing treats the output; ignored for inputs.
+    #[serde(default)]
+    pub role: OutputRole,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildingLayout {
+    /// Footprint at rotation `0`.
+    pub size: Vec2i,
+    pub ports: Vec<PortDef>,
+}
+
+impl BuildingLayout {
+    /// Footprint after `rotation`; odd rotations swap width and height.
+    pub fn size(&self, rotation: i32) -> Vec2i {
+        if rotation.rem_euclid(2) == 0 {
+            self.size
+        } else {
+            Vec2i {
+                x: self.size.y,
+                y: self.size.x,
+            }
+        }
+    }
+
+    /// Tile of `port` for a building at `pos` (its top-left tile) turned by `rotation`.
+    pub fn port_pos(&self, port: &PortDef, pos: Vec2i, rotation: i32) -> Vec2i {
+        let size = self.size(rotation);
+        let offset = port.offset;
+        let (dx, dy) = match port.side.rotated(rotation) {
+            Side::North => (offset, -1),
+            Side::East => (size.x, offset),
+            Side::South => (size.x - 1 - offset, size.y),
+            Side::West => (-1, size.y - 1 - offset),
+        };
+        Vec2i {
+            x: pos.x + dx,
+            y: pos.y + dy,
+        }
+    }
+
+    /// Tiles of every port of `kind`, in definition order.
+    pub fn port_poses(&self, kind: PortKind, pos: Vec2i, rotation: i32) -> Vec<Vec2i> {
+        self.ports
+            .iter()
+            .filter(|port| port.kind == kind)
+            .map(|port| self.port_pos(port, pos, rotation))
+            .collect()
+    }
+
+    /// Role of the output port on `out_pos`, `Default` when there is none.
+    pub fn output_role(&self, pos: Vec2i, rotation: i32, out_pos: Vec2i) -> OutputRole {
+        self.ports
+            .iter()
+            .filter(|port| port.kind == PortKind::Output)
+            .find(|port| self.port_pos(port, pos, rotation) == out_pos)
+            .map_or(OutputRole::Default, |port| port.role)
+    }
+
+    pub fn port(&self, name: &str) -> Option<&PortDef> {
+        self.ports.iter().find(|port| port.name == name)
+    }
+}
+
+/// Layout of `building_type`, as defined in the building registry.
+pub fn layout_of(building_type: BuildingType) -> &'static BuildingLayout {
+    &registry().by_type(building_type).layout
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ports_follow_rotation() {
+        let layout = layout_of(BuildingType::IpFilter);
+        let pos = Vec2i { x: 5, y: 5 };
+        let reject = layout.port("reject").unwrap();
+        let expected = [(5, 4), (6, 5), (5, 6), (4, 5)];
+        for (rotation, (x, y)) in expected.into_iter().enumerate() {
+            assert_eq!(
+                layout.port_pos(reject, pos, rotation as i32),
+                Vec2i { x, y }
+            );
+        }
+        assert_eq!(
+            layout.output_role(pos, 1, Vec2i { x: 5, y: 6 }),
+            OutputRole::FilterMatch
+        );
+    }
+
+    #[test]
+    fn rotation_swaps_size_and_keeps_offsets_clockwise() {
+        let layout = BuildingLayout {
+            size: Vec2i { x: 3, y: 1 },
+            ports: vec![PortDef {
+                name: "out".to_string(),
+                kind: PortKind::Output,
+                side: Side::North,
+                offset: 2,
+                role: OutputRole::Default,
+            }],
+        };
+        let origin = Vec2i { x: 0, y: 0 };
+        assert_eq!(layout.size(1), Vec2i { x: 1, y: 3 });
+        assert_eq!(
+            layout.port_poses(PortKind::Output, origin, 0),
+            vec![Vec2i { x: 2, y: -1 }]
+        );
+        assert_eq!(
+            layout.port_poses(PortKind::Output, origin, 1),
+            vec![Vec2i { x: 1, y: 2 }]
+        );
+        assert_eq!(
+            layout.port_poses(PortKind::Output, origin, 2),
+            vec![Vec2i { x: 0, y: 1 }]
+        );
+    }
+}
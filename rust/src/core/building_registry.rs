@@ -0,0 +1,259 @@
+//! Building definitions: block id, palette data, footprint and ports of every building.
+//!
+//! The definitions live in `godot/assets/buildings.json`, which GDScript can read as well.
+//! The game loads the file at startup with [`load_registry`]; a copy built into the library
+//! is used when it cannot be read, and by the headless runner and tests, which have no Godot
+//! project. `behaviour` names the `BuildingType` that simulates the block; several blocks
+//! may share one, e.g. the conveyor tiers differ only in speed and capacity.
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::building::BuildingType;
+use crate::core::building_layout::{BuildingLayout, PortDef, Side};
+
+pub const REGISTRY_VERSION: u32 = 1;
+
+const BUILTIN_REGISTRY: &str = include_str!("../../../godot/assets/buildings.json");
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BuildingDef {
+    /// Block id used by stage maps and `MapController` (`blockId`).
+    pub id: i32,
+    pub key: String,
+    pub name: String,
+    /// Palette tab the block is listed under.
+    #[serde(default)]
+    pub category: String,
+    pub behaviour: BuildingType,
+    #[serde(default)]
+    pub cost: u32,
+    /// Tiles per second a packet travels, for buildings that move packets.
+    #[serde(default)]
+    pub speed: Option<f32>,
+    /// Packets the building holds at once; `None` means unbounded.
+    #[serde(default)]
+    pub capacity: Option<u32>,
+    /// Farthest tile a tunnel entrance reaches to find its exit.
+    #[serde(default)]
+    pub range: Option<u32>,
+    #[serde(default)]
+    pub texture: Option<String>,
+    /// Whether the player can pick the block from the palette.
+    #[serde(default = "default_palette")]
+    pub palette: bool,
+    #[serde(flatten)]
+    pub layout: BuildingLayout,
+}
+
+fn default_palette() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RegistryFile {
+    version: u32,
+    buildings: Vec<BuildingDef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildingRegistry {
+    defs: Vec<BuildingDef>,
+    /// Index into `defs` by block id.
+    by_id: HashMap<i32, usize>,
+}
+
+impl BuildingRegistry {
+    /// Parse and validate a registry file. Every `BuildingType` must be the behaviour of at
+    /// least one definition.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let file: RegistryFile =
+            serde_json::from_str(text).map_err(|err| format!("建物定義が不正です: {}", err))?;
+        if file.version != REGISTRY_VERSION {
+            return Err(format!("未対応の建物定義バージョン: {}", file.version));
+        }
+
+        let mut by_id = HashMap::new();
+        let mut keys = HashSet::new();
+        for (index, def) in file.buildings.iter().enumerate() {
+            if by_id.insert(def.id, index).is_some() {
+                return Err(format!("建物IDが重複しています: {}", def.id));
+            }
+            if !keys.insert(def.key.as_str()) {
+                return Err(format!("建物キーが重複しています: '{}'", def.key));
+            }
+            validate_layout(def)?;
+        }
+        for building_type in BuildingType::ALL {
+            if !file
+                .buildings
+                .iter()
+                .any(|def| def.behaviour == building_type)
+            {
+                return Err(format!("{:?} の定義がありません", building_type));
+            }
+        }
+
+        Ok(Self {
+            defs: file.buildings,
+            by_id,
+        })
+    }
+
+    /// Definitions in file order.
+    pub fn defs(&self) -> &[BuildingDef] {
+        &self.defs
+    }
+
+    pub fn by_id(&self, id: i32) -> Option<&BuildingDef> {
+        self.by_id.get(&id).map(|&index| &self.defs[index])
+    }
+
+    pub fn by_key(&self, key: &str) -> Option<&BuildingDef> {
+        self.defs.iter().find(|def| def.key == key)
+    }
+
+    /// The first definition with `building_type` as its behaviour, which is the block
+    /// placed when only the behaviour is known.
+    pub fn by_type(&self, building_type: BuildingType) -> &BuildingDef {
+        self.defs
+            .iter()
+            .find(|def| def.behaviour == building_type)
+            .expect("validated registries define every building type")
+    }
+}
+
+fn validate_layout(def: &BuildingDef) -> Result<(), String> {
+    let size = def.layout.size;
+    if size.x < 1 || size.y < 1 {
+        return Err(format!("'{}' のサイズが不正です", def.key));
+    }
+    let mut names = HashSet::new();
+    for port in &def.layout.ports {
+        if !names.insert(port.name.as_str()) {
+            return Err(format!(
+                "'{}' のポート名が重複しています: '{}'",
+                def.key, port.name
+            ));
+        }
+        if !(0..side_length(port, size.x, size.y)).contains(&port.offset) {
+            return Err(format!(
+                "'{}' のポート '{}' の offset が範囲外です",
+                def.key, port.name
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn side_length(port: &PortDef, width: i32, height: i32) -> i32 {
+    match port.side {
+        Side::North | Side::South => width,
+        Side::East | Side::West => height,
+    }
+}
+
+static REGISTRY: OnceLock<BuildingRegistry> = OnceLock::new();
+
+/// The loaded registry, or the one built into the library when none was loaded.
+pub fn registry() -> &'static BuildingRegistry {
+    REGISTRY.get_or_init(builtin_registry)
+}
+
+/// Use the definitions in `text` from now on. Fails, leaving the registry as it is or on the
+/// built-in copy, when they are invalid or the registry is already in use.
+pub fn load_registry(text: &str) -> Result<(), String> {
+    let loaded = match BuildingRegistry::from_json(text) {
+        Ok(loaded) => loaded,
+        Err(err) => {
+            registry();
+            return Err(err);
+        }
+    };
+    REGISTRY
+        .set(loaded)
+        .map_err(|_| "建物定義は既に使用されています".to_string())
+}
+
+fn builtin_registry() -> BuildingRegistry {
+    BuildingRegistry::from_json(BUILTIN_REGISTRY).expect("buildings.json is valid")
+}
+
+/// `BuildingType` simulating block `id`.
+pub fn building_type_from_id(id: i32) -> Option<BuildingType> {
+    registry().by_id(id).map(|def| def.behaviour)
+}
+
+/// Block id of the first definition of `building_type`.
+pub fn block_id(building_type: BuildingType) -> i32 {
+    registry().by_type(building_type).id
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builtin_registry_keeps_block_ids() {
+        let expected = [
+            (0, BuildingType::Internet),
+            (1, BuildingType::Datacenter),
+            (2, BuildingType::Conveyor),
+            (5, BuildingType::TunnelEntrance),
+            (6, BuildingType::TunnelExit),
+            (8, BuildingType::Splitter),
+            (9, BuildingType::Merger),
+            (10, BuildingType::IpFilter),
+            (11, BuildingType::PortFilter),
+            (12, BuildingType::LengthFilter),
+            (13, BuildingType::ProtocolFilter),
+            (14, BuildingType::ContentFilter),
+            (15, BuildingType::Junction),
+            (16, BuildingType::RecycleBin),
+            (17, BuildingType::Tap),
+            (18, BuildingType::Analyzer),
+            (19, BuildingType::RateLimiter),
+            (20, BuildingType::StatefulFirewall),
+            (21, BuildingType::ScanDetector),
+            (22, BuildingType::Honeypot),
+        ];
+        for (id, building_type) in expected {
+            assert_eq!(building_type_from_id(id), Some(building_type));
+            assert_eq!(block_id(building_type), id);
+        }
+        assert_eq!(building_type_from_id(7), None, "7 is the wall tile");
+    }
+
+    #[test]
+    fn conveyor_tiers_share_the_conveyor_behaviour() {
+        for (id, key) in [(3, "fast_conveyor"), (4, "express_conveyor")] {
+            let def = registry().by_id(id).unwrap();
+            assert_eq!(def.key, key);
+            assert_eq!(def.behaviour, BuildingType::Conveyor);
+            assert_eq!(registry().by_key(key), Some(def));
+        }
+        assert_eq!(block_id(BuildingType::Conveyor), 2);
+    }
+
+    #[test]
+    fn invalid_definitions_fall_back_to_the_builtin_copy() {
+        assert!(load_registry("{").is_err());
+        assert_eq!(registry().defs(), builtin_registry().defs());
+        assert!(load_registry(BUILTIN_REGISTRY).is_err(), "already in use");
+    }
+
+    #[test]
+    fn rejects_duplicate_ids_and_missing_types() {
+        let mut file: serde_json::Value = serde_json::from_str(BUILTIN_REGISTRY).unwrap();
+        let buildings = file["buildings"].as_array_mut().unwrap();
+        buildings[1]["id"] = buildings[0]["id"].clone();
+        let err = BuildingRegistry::from_json(&file.to_string()).unwrap_err();
+        assert!(err.contains("重複"), "{}", err);
+
+        let mut file: serde_json::Value = serde_json::from_str(BUILTIN_REGISTRY).unwrap();
+        file["buildings"].as_array_mut().unwrap().remove(0);
+        let err = BuildingRegistry::from_json(&file.to_string()).unwrap_err();
+        assert!(err.contains("Internet"), "{}", err);
+    }
+}
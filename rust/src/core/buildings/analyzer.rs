@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::dto::{BuildingId, DropReason, Vec2i};
+use crate::core::packet::{Packet, PacketLabel, Protocol};
+
+/// What an `Analyzer` has seen so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AnalyzerStats {
+    pub packets: u64,
+    pub bytes: u64,
+    pub tcp: u64,
+    pub udp: u64,
+    pub other_protocols: u64,
+    pub correct: u64,
+    pub incorrect: u64,
+    pub unknown: u64,
+    /// Packets per destination port.
+    pub dest_ports: BTreeMap<u16, u64>,
+    /// Packets per source IP.
+    pub source_ips: BTreeMap<String, u64>,
+}
+
+impl AnalyzerStats {
+    fn record(&mut self, packet: &Packet) {
+        self.packets += 1;
+        self.bytes += packet.length as u64;
+        match packet.protocol {
+            Protocol::Tcp => self.tcp += 1,
+            Protocol::Udp => self.udp += 1,
+            Protocol::Unknown => self.other_protocols += 1,
+        }
+        match packet.label {
+            PacketLabel::Correct => self.correct += 1,
+            PacketLabel::Incorrect => self.incorrect += 1,
+            PacketLabel::Unknown => self.unknown += 1,
+        }
+        *self.dest_ports.entry(packet.dest_port).or_insert(0) += 1;
+        *self.source_ips.entry(packet.source_ip.clone()).or_insert(0) += 1;
+    }
+}
+
+/// Sink for the copies made by taps. It keeps statistics instead of the packets, and only
+/// counts copies: original traffic has to reach a datacenter or recycle bin, so originals
+/// routed here are taken off the belt and lost as `DropReason::Misrouted`.
+pub struct Analyzer {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    stats: AnalyzerStats,
+    /// Originals lost since the last `take_rejected`.
+    rejected: Vec<(Packet, DropReason)>,
+}
+
+impl Analyzer {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            stats: AnalyzerStats::default(),
+            rejected: Vec::new(),
+        }
+    }
+
+    pub fn stats(&self) -> &AnalyzerStats {
+        &self.stats
+    }
+
+    /// Original packets lost since the last call, with the reason.
+    pub fn take_rejected(&mut self) -> Vec<(Packet, DropReason)> {
+        std::mem::take(&mut self.rejected)
+    }
+}
+
+impl Building for Analyzer {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::Analyzer
+    }
+    fn update(&mut self, _delta: f32) {}
+    fn can_offload(&self) -> bool {
+        false
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        true
+    }
+    fn offload(&mut self) -> Packet {
+        panic!("Cannot offload from analyzer")
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        if packet.is_mirror_copy() {
+            self.stats.record(&packet);
+        } else {
+            self.rejected.push((packet, DropReason::Misrouted));
+        }
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        Vec::new()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        vec![]
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Analyzer(self.stats.clone())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.stats = match state {
+            BuildingState::Analyzer(stats) => stats,
+            _ => AnalyzerStats::default(),
+        };
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(protocol: Protocol, dest_port: u16) -> Packet {
+        Packet::new(
+            "10.0.0.1".to_string(),
+            "10.0.0.2".to_string(),
+            1000,
+            dest_port,
+            protocol,
+            100,
+            vec![],
+        )
+    }
+
+    #[test]
+    fn analyzer_counts_copies_only() {
+        let mut analyzer = Analyzer::new(1, Vec2i { x: 0, y: 0 }, 0);
+        let source = Vec2i { x: -1, y: 0 };
+        let original = packet(Protocol::Tcp, 80);
+        assert!(analyzer.can_accept(&original, source));
+        analyzer.accept(original.clone(), source);
+
+        for copy in [
+            original.mirror_copy(7),
+            packet(Protocol::Udp, 53).mirror_copy(7),
+            packet(Protocol::Tcp, 80).mirror_copy(7),
+        ] {
+            assert!(analyzer.can_accept(&copy, source));
+            analyzer.accept(copy, source);
+        }
+
+        let stats = analyzer.stats();
+        assert_eq!((stats.packets, stats.bytes), (3, 300));
+        assert_eq!((stats.tcp, stats.udp), (2, 1));
+        assert_eq!(stats.dest_ports.get(&80), Some(&2));
+        assert_eq!(stats.source_ips.get("10.0.0.1"), Some(&3));
+        assert!(analyzer.get_packets().is_empty());
+        let rejected = analyzer.take_rejected();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].1, DropReason::Misrouted);
+        assert!(analyzer.take_rejected().is_empty());
+    }
+}
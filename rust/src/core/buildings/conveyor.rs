@@ -1,8 +1,13 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_registry::{BuildingDef, registry};
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::core::packet::Packet;
+use std::collections::VecDeque;
 
-const CONVEYOR_SPEED: f32 = 1.0; // 1タイル/秒
+/// 建物定義に速度がない場合の速度 (1タイル/秒)
+const DEFAULT_CONVEYOR_SPEED: f32 = 1.0;
+/// 建物定義に容量がない場合のスロット数
+const DEFAULT_CONVEYOR_SLOTS: u32 = 1;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum EntrySide {
@@ -11,23 +16,50 @@ pub enum EntrySide {
     Right,
 }
 
+/// Carries packets one tile towards its front. Up to `slots` packets ride at once, kept at
+/// least `1 / slots` of a tile apart; the one in front leaves first.
 pub struct Conveyor {
     id: BuildingId,
     pos: Vec2i,
     rot: i32,
-    buffer: Option<(Packet, EntrySide)>,
+    /// Block id of the tier.
+    def_id: i32,
+    /// タイル/秒
+    speed: f32,
+    slots: usize,
+    /// Front (most advanced) packet first.
+    buffer: VecDeque<(Packet, EntrySide)>,
 }
 
 impl Conveyor {
     pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self::from_def(id, pos, rot, registry().by_type(BuildingType::Conveyor))
+    }
+
+    /// A conveyor of the tier `def`, with the speed and slot count it declares.
+    pub fn from_def(id: BuildingId, pos: Vec2i, rot: i32, def: &BuildingDef) -> Self {
+        debug_assert_eq!(def.behaviour, BuildingType::Conveyor);
         Self {
             id,
             pos,
             rot,
-            buffer: None,
+            def_id: def.id,
+            speed: def.speed.unwrap_or(DEFAULT_CONVEYOR_SPEED),
+            slots: def.capacity.unwrap_or(DEFAULT_CONVEYOR_SLOTS).max(1) as usize,
+            buffer: VecDeque::new(),
         }
     }
 
+    /// Number of packets that can ride at once.
+    pub fn capacity(&self) -> usize {
+        self.slots
+    }
+
+    /// Smallest progress gap between two packets on the belt.
+    fn spacing(&self) -> f32 {
+        1.0 / self.slots as f32
+    }
+
     pub fn get_front_pos(&self) -> Vec2i {
         let (dx, dy) = match self.rot.rem_euclid(4) {
             0 => (1, 0),  // East
@@ -81,10 +113,22 @@ impl Conveyor {
         }
     }
 
-    pub fn buffer_state(&self) -> Option<(Packet, EntrySide)> {
-        self.buffer
-            .as_ref()
-            .map(|(packet, side)| (packet.clone(), *side))
+    /// Packets on the belt with the side they entered from, front packet first.
+    pub fn slots(&self) -> impl Iterator<Item = &(Packet, EntrySide)> {
+        self.buffer.iter()
+    }
+
+    fn entry_side(&self, source_pos: Vec2i) -> EntrySide {
+        self.entry_side_from_delta(source_pos.x - self.pos.x, source_pos.y - self.pos.y)
+            .unwrap_or(EntrySide::Back)
+    }
+}
+
+/// Where a packet entering from `entry_side` starts on the belt.
+fn entry_progress(entry_side: EntrySide) -> f32 {
+    match entry_side {
+        EntrySide::Back => 0.0,
+        EntrySide::Left | EntrySide::Right => 0.5,
     }
 }
 
@@ -98,81 +142,57 @@ impl Building for Conveyor {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::Conveyor
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 1, y: 1 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        vec![self.get_front_pos()]
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        let front = self.get_front_pos();
-
-        let candidates = [
-            Vec2i {
-                x: self.pos.x - 1,
-                y: self.pos.y,
-            },
-            Vec2i {
-                x: self.pos.x + 1,
-                y: self.pos.y,
-            },
-            Vec2i {
-                x: self.pos.x,
-                y: self.pos.y - 1,
-            },
-            Vec2i {
-                x: self.pos.x,
-                y: self.pos.y + 1,
-            },
-        ];
-
-        candidates.into_iter().filter(|pos| *pos != front).collect()
+    fn def_id(&self) -> i32 {
+        self.def_id
     }
 
     fn update(&mut self, delta: f32) {
-        if let Some((packet, _)) = &mut self.buffer {
-            packet.progress += CONVEYOR_SPEED * delta;
-            if packet.progress > 1.0 {
-                packet.progress = 1.0;
-            }
+        let spacing = self.spacing();
+        // Packets queue up behind the one ahead instead of overlapping it.
+        let mut limit = 1.0;
+        for (packet, _) in self.buffer.iter_mut() {
+            packet.progress = (packet.progress + self.speed * delta).min(limit);
+            limit = packet.progress - spacing;
         }
     }
 
     fn can_offload(&self) -> bool {
-        matches!(&self.buffer, Some((p, _)) if p.progress >= 1.0)
+        matches!(self.buffer.front(), Some((p, _)) if p.progress >= 1.0)
     }
 
     fn can_accept(&self, _packet: &Packet, source_pos: Vec2i) -> bool {
-        if self.buffer.is_some() {
+        if self.buffer.len() >= self.slots || source_pos == self.get_front_pos() {
             return false;
         }
-        source_pos != self.get_front_pos()
+        // The new packet joins at the back of the queue, one spacing behind the last one.
+        let start = entry_progress(self.entry_side(source_pos));
+        self.buffer
+            .back()
+            .is_none_or(|(last, _)| last.progress - start >= self.spacing() - f32::EPSILON)
     }
 
     fn offload(&mut self) -> Packet {
-        self.buffer.take().expect("Offload called without packet").0
+        self.buffer
+            .pop_front()
+            .expect("Offload called without packet")
+            .0
     }
 
     fn accept(&mut self, mut packet: Packet, source_pos: Vec2i) -> BuildingAction {
-        let source_dx = source_pos.x - self.pos.x;
-        let source_dy = source_pos.y - self.pos.y;
-
-        let entry_side = self
-            .entry_side_from_delta(source_dx, source_dy)
-            .unwrap_or(EntrySide::Back);
-
-        let is_lateral = matches!(entry_side, EntrySide::Left | EntrySide::Right);
-
-        packet.progress = if is_lateral { 0.5 } else { 0.0 };
-        self.buffer = Some((packet, entry_side));
+        let entry_side = self.entry_side(source_pos);
+        packet.progress = entry_progress(entry_side);
+        self.buffer.push_back((packet, entry_side));
         BuildingAction::None
     }
 
     fn get_progress(&self) -> f32 {
-        self.buffer.as_ref().map_or(0.0, |(p, _)| p.progress)
+        self.buffer.front().map_or(0.0, |(p, _)| p.progress)
     }
 
     fn get_packets(&self) -> Vec<Packet> {
@@ -183,6 +203,17 @@ impl Building for Conveyor {
         self.buffer.iter().map(|(p, _)| p.progress).collect()
     }
 
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Conveyor(self.buffer.iter().cloned().collect())
+    }
+
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Conveyor(buffer) => buffer.into(),
+            _ => VecDeque::new(),
+        };
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -233,7 +264,64 @@ mod tests {
 
         let offloaded_packet = conveyor.offload();
         assert_eq!(offloaded_packet.source_ip, "192.168.1.1");
-        assert!(conveyor.buffer.is_none());
+        assert!(conveyor.buffer.is_empty());
         assert!(conveyor.get_packets().is_empty());
     }
+
+    #[test]
+    fn conveyor_queues_packets_with_spacing() {
+        // The basic conveyor holds a single packet; a faster tier has room for more.
+        let def = registry().by_key("fast_conveyor").unwrap();
+        let mut conveyor = Conveyor::from_def(1, Vec2i { x: 0, y: 0 }, 0, def);
+        let back = Vec2i { x: -1, y: 0 };
+        let packet = create_test_packet();
+        let spacing = conveyor.spacing();
+        assert!(conveyor.slots > 1);
+
+        conveyor.accept(packet.clone(), back);
+        assert!(!conveyor.can_accept(&packet, back));
+        conveyor.update(spacing / conveyor.speed);
+        assert!(conveyor.can_accept(&packet, back));
+        conveyor.accept(packet.clone(), back);
+
+        // The front packet waits at the end; the next one stops a spacing behind it.
+        conveyor.update(10.0);
+        assert!(conveyor.can_offload());
+        let progresses = conveyor.get_packet_progresses();
+        assert_eq!(progresses[0], 1.0);
+        assert!((progresses[0] - progresses[1] - spacing).abs() < 1e-5);
+
+        conveyor.offload();
+        assert_eq!(conveyor.slots().count(), 1);
+        assert!(!conveyor.can_offload());
+    }
+
+    #[test]
+    fn conveyor_refuses_packets_when_slots_are_full() {
+        let mut conveyor = Conveyor::new(1, Vec2i { x: 0, y: 0 }, 0);
+        let back = Vec2i { x: -1, y: 0 };
+        let packet = create_test_packet();
+        for _ in 0..conveyor.slots {
+            assert!(conveyor.can_accept(&packet, back));
+            conveyor.accept(packet.clone(), back);
+            conveyor.update(10.0);
+        }
+        assert!(!conveyor.can_accept(&packet, back));
+        assert!(!conveyor.can_accept(&packet, conveyor.get_front_pos()));
+    }
+
+    #[test]
+    fn faster_tiers_move_packets_further() {
+        let back = Vec2i { x: -1, y: 0 };
+        let mut progresses = Vec::new();
+        for key in ["conveyor", "fast_conveyor", "express_conveyor"] {
+            let def = registry().by_key(key).unwrap();
+            let mut conveyor = Conveyor::from_def(1, Vec2i { x: 0, y: 0 }, 0, def);
+            assert_eq!(conveyor.def_id(), def.id);
+            conveyor.accept(create_test_packet(), back);
+            conveyor.update(0.2);
+            progresses.push(conveyor.get_progress());
+        }
+        assert!(progresses.windows(2).all(|pair| pair[0] < pair[1]));
+    }
 }
@@ -1,16 +1,66 @@
+use std::collections::VecDeque;
+
 use godot::obj::Gd;
+use serde::{Deserialize, Serialize};
 
-use crate::core::building::{Building, BuildingAction, BuildingType};
-use crate::core::dto::{BuildingId, Vec2i};
-use crate::core::packet::{Packet, PacketLabel};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::dto::{BuildingId, DropReason, Vec2i};
+use crate::core::packet::{Packet, PacketLabel, Protocol};
 use crate::packet::Traffic;
 
+/// A service a datacenter hosts. `ip` limits it to one destination address; without it the
+/// port is open on every address the datacenter receives.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HostedService {
+    pub protocol: Protocol,
+    pub port: u16,
+    #[serde(default)]
+    pub ip: Option<String>,
+}
+
+impl HostedService {
+    fn serves(&self, packet: &Packet) -> bool {
+        self.protocol == packet.protocol
+            && self.port == packet.dest_port
+            && self.ip.as_ref().is_none_or(|ip| *ip == packet.dest_ip)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DatacenterConfig {
+    /// Hosted services; packets to anything else are refused. Empty means everything is open.
+    pub services: Vec<HostedService>,
+    /// Packets processed per second of simulated time; `0` processes them on arrival.
+    pub capacity_pps: u32,
+    /// Packets that can wait for processing; arrivals beyond it are lost to overload.
+    pub queue_size: u32,
+}
+
+/// Simulation state of a `Datacenter` with a capacity, kept by world snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct DatacenterState {
+    pub packets: Vec<Packet>,
+    pub queue: Vec<Packet>,
+    pub budget: f64,
+}
+
+/// Sink for legitimate traffic. Without a rule it takes every packet on arrival. With one,
+/// packets to services it does not host are refused, and the rest wait in a queue that is
+/// worked through at `capacity_pps`; arrivals to a full queue are lost. Refused and lost
+/// packets are handed to the world through `take_rejected` and count as drops.
 pub struct Datacenter {
     id: BuildingId,
     pos: Vec2i,
     rot: i32,
     packets: Vec<Packet>,
+    queue: VecDeque<Packet>,
+    /// Packets that may still be processed; refilled at `capacity_pps`, up to one second's
+    /// worth.
+    budget: f64,
+    rejected: Vec<(Packet, DropReason)>,
     traffic: Option<Gd<Traffic>>,
+    pub config: Option<DatacenterConfig>,
 }
 
 impl Datacenter {
@@ -20,13 +70,66 @@ impl Datacenter {
             pos,
             rot,
             packets: Vec::new(),
+            queue: VecDeque::new(),
+            budget: 0.0,
+            rejected: Vec::new(),
             traffic: None,
+            config: None,
+        }
+    }
+
+    pub fn new_with_config(id: BuildingId, pos: Vec2i, rot: i32, config: DatacenterConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(id, pos, rot)
         }
     }
 
     pub fn set_traffic(&mut self, traffic: Gd<Traffic>) {
         self.traffic = Some(traffic);
     }
+
+    /// Packets still waiting in the queue are processed on arrival from now on when the new
+    /// rule has no capacity. With a smaller queue, the latest arrivals that no longer fit are
+    /// lost to overload.
+    pub fn set_config(&mut self, config: DatacenterConfig) {
+        if config.capacity_pps > 0 {
+            while self.queue.len() > config.queue_size as usize {
+                let packet = self
+                    .queue
+                    .pop_back()
+                    .expect("queue is longer than its size");
+                self.rejected.push((packet, DropReason::Overloaded));
+            }
+        }
+        self.config = Some(config);
+        self.budget = 0.0;
+    }
+
+    /// Number of packets waiting to be processed.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Packets waiting to be processed, oldest first.
+    pub fn queued_packets(&self) -> impl Iterator<Item = &Packet> {
+        self.queue.iter()
+    }
+
+    /// Packets refused or lost since the last call, with the reason.
+    pub fn take_rejected(&mut self) -> Vec<(Packet, DropReason)> {
+        std::mem::take(&mut self.rejected)
+    }
+
+    fn serve(&mut self, packet: Packet) -> BuildingAction {
+        let outcome = match packet.label {
+            PacketLabel::Correct => BuildingAction::AddScore(10),
+            PacketLabel::Incorrect => BuildingAction::SubScore(10),
+            PacketLabel::Unknown => BuildingAction::None,
+        };
+        self.packets.push(packet);
+        outcome
+    }
 }
 
 impl Building for Datacenter {
@@ -39,47 +142,48 @@ impl Building for Datacenter {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::Datacenter
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 2, y: 2 }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::Datacenter)
     }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        vec![]
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::Datacenter(config) = config else {
+            return false;
+        };
+        self.set_config(config);
+        true
     }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        let size = self.get_size();
-        let mut poses = Vec::new();
-
-        for x_offset in 0..size.x {
-            poses.push(Vec2i {
-                x: self.pos.x + x_offset,
-                y: self.pos.y - 1,
-            });
-            poses.push(Vec2i {
-                x: self.pos.x + x_offset,
-                y: self.pos.y + size.y,
-            });
-        }
-
-        for y_offset in 0..size.y {
-            poses.push(Vec2i {
-                x: self.pos.x - 1,
-                y: self.pos.y + y_offset,
-            });
-            poses.push(Vec2i {
-                x: self.pos.x + size.x,
-                y: self.pos.y + y_offset,
-            });
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+    }
+    fn update(&mut self, delta: f32) {
+        let capacity = match &self.config {
+            Some(config) if config.capacity_pps > 0 => config.capacity_pps as f64,
+            _ => {
+                while let Some(packet) = self.queue.pop_front() {
+                    self.serve(packet);
+                }
+                return;
+            }
+        };
+        self.budget = (self.budget + capacity * delta as f64).min(capacity);
+        while self.budget >= 1.0 {
+            let Some(packet) = self.queue.pop_front() else {
+                break;
+            };
+            self.serve(packet);
+            self.budget -= 1.0;
         }
-
-        poses
     }
-    fn update(&mut self, _delta: f32) {}
     fn can_offload(&self) -> bool {
         false
     }
+    /// A datacenter never holds traffic back; packets it cannot take are lost instead.
     fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
         true
     }
@@ -87,23 +191,70 @@ impl Building for Datacenter {
         panic!("Cannot offload from datacenter")
     }
     fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
-        let outcome = match packet.label {
-            PacketLabel::Correct => BuildingAction::AddScore(10),
-            PacketLabel::Incorrect => BuildingAction::SubScore(10),
-            PacketLabel::Unknown => BuildingAction::None,
+        let Some(config) = &self.config else {
+            return self.serve(packet);
         };
-        self.packets.push(packet);
-        outcome
+        if !config.services.is_empty() && !config.services.iter().any(|s| s.serves(&packet)) {
+            self.rejected.push((packet, DropReason::ClosedService));
+            return BuildingAction::None;
+        }
+        if config.capacity_pps == 0 {
+            return self.serve(packet);
+        }
+        if self.queue.len() >= config.queue_size as usize {
+            self.rejected.push((packet, DropReason::Overloaded));
+            return BuildingAction::None;
+        }
+        self.queue.push_back(packet);
+        BuildingAction::None
     }
     fn get_progress(&self) -> f32 {
         0.0
     }
+    /// Processed packets; the queue is not included.
     fn get_packets(&self) -> Vec<Packet> {
         self.packets.clone()
     }
     fn get_packet_progresses(&self) -> Vec<f32> {
         vec![]
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Datacenter(DatacenterState {
+            packets: self.packets.clone(),
+            queue: self.queue.iter().cloned().collect(),
+            budget: self.budget,
+        })
+    }
+    fn snapshot_state(&self) -> BuildingState {
+        BuildingState::Sink {
+            received: self.packets.len(),
+            rest: Box::new(BuildingState::Datacenter(DatacenterState {
+                packets: Vec::new(),
+                queue: self.queue.iter().cloned().collect(),
+                budget: self.budget,
+            })),
+        }
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.queue.clear();
+        self.budget = 0.0;
+        self.rejected.clear();
+        self.packets = match state {
+            BuildingState::Packets(packets) => packets,
+            BuildingState::Datacenter(state) => {
+                self.queue = state.queue.into();
+                self.budget = state.budget;
+                state.packets
+            }
+            BuildingState::Sink { received, rest } => {
+                let mut packets = std::mem::take(&mut self.packets);
+                packets.truncate(received);
+                self.restore_state(*rest);
+                packets
+            }
+            _ => Vec::new(),
+        };
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -111,3 +262,88 @@ impl Building for Datacenter {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packet(dest_port: u16, label: PacketLabel) -> Packet {
+        let mut packet = Packet::new(
+            "198.51.100.7".to_string(),
+            "10.0.0.2".to_string(),
+            40000,
+            dest_port,
+            Protocol::Tcp,
+            64,
+            vec![],
+        );
+        packet.label = label;
+        packet
+    }
+
+    #[test]
+    fn refuses_closed_services_and_loses_packets_to_overload() {
+        let config = DatacenterConfig {
+            services: vec![HostedService {
+                protocol: Protocol::Tcp,
+                port: 443,
+                ip: None,
+            }],
+            capacity_pps: 10,
+            queue_size: 2,
+        };
+        let mut datacenter = Datacenter::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config);
+        let from = Vec2i { x: 0, y: 1 };
+
+        datacenter.accept(packet(22, PacketLabel::Incorrect), from);
+        for _ in 0..3 {
+            datacenter.accept(packet(443, PacketLabel::Correct), from);
+        }
+        let reasons: Vec<DropReason> = datacenter
+            .take_rejected()
+            .into_iter()
+            .map(|(_, reason)| reason)
+            .collect();
+        assert_eq!(reasons, [DropReason::ClosedService, DropReason::Overloaded]);
+        assert_eq!(datacenter.queued(), 2);
+
+        // 10 packets per second: one every 0.1 s.
+        datacenter.update(0.1);
+        assert_eq!(
+            (datacenter.get_packets().len(), datacenter.queued()),
+            (1, 1)
+        );
+
+        let mut restored = Datacenter::new(1, Vec2i { x: 0, y: 0 }, 0);
+        restored.restore_state(datacenter.save_state());
+        assert_eq!(restored.queued(), 1);
+    }
+    #[test]
+    fn shrinking_the_queue_loses_the_latest_arrivals() {
+        let config = DatacenterConfig {
+            services: vec![],
+            capacity_pps: 1,
+            queue_size: 3,
+        };
+        let mut datacenter =
+            Datacenter::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config.clone());
+        for port in [80, 81, 82] {
+            datacenter.accept(packet(port, PacketLabel::Correct), Vec2i { x: 0, y: 1 });
+        }
+
+        datacenter.set_config(DatacenterConfig {
+            queue_size: 1,
+            ..config
+        });
+        let lost: Vec<(u16, DropReason)> = datacenter
+            .take_rejected()
+            .into_iter()
+            .map(|(packet, reason)| (packet.dest_port, reason))
+            .collect();
+        assert_eq!(
+            lost,
+            [(82, DropReason::Overloaded), (81, DropReason::Overloaded)]
+        );
+        assert_eq!(datacenter.queued(), 1);
+    }
+}
@@ -1,13 +1,46 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::core::packet::Packet;
+use crate::logic::blocklist::Blocklists;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::str;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Which bytes a content filter matches against.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentMatchScope {
+    /// The payload of the individual segment.
+    #[default]
+    Segment,
+    /// The reassembled TCP stream of the segment's flow, falling back to the payload when the
+    /// traffic was loaded without reassembly.
+    Stream,
+}
+
+impl ContentMatchScope {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "segment" => Some(Self::Segment),
+            "stream" => Some(Self::Stream),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Segment => "segment",
+            Self::Stream => "stream",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ContentFilterConfig {
     pub pattern: String,
+    #[serde(default)]
+    pub scope: ContentMatchScope,
 }
 
 pub struct ContentFilter {
@@ -54,9 +87,13 @@ impl ContentFilter {
     }
 
     pub fn filter(&self, packet: &Packet) -> bool {
-        if let (Some(_config), Some(regex)) = (&self.config, &self.compiled_regex) {
+        if let (Some(config), Some(regex)) = (&self.config, &self.compiled_regex) {
+            let content = match (config.scope, packet.stream.as_deref()) {
+                (ContentMatchScope::Stream, Some(stream)) => stream,
+                _ => packet.payload.as_slice(),
+            };
             // Try to convert payload to string for regex matching
-            if let Ok(payload_str) = str::from_utf8(&packet.payload) {
+            if let Ok(payload_str) = str::from_utf8(content) {
                 regex.is_match(payload_str)
             } else {
                 false
@@ -77,43 +114,27 @@ impl Building for ContentFilter {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::ContentFilter
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 1, y: 1 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        let rotation = self.rot.rem_euclid(4);
-        let offsets = match rotation {
-            0 => [(0, -1), (1, 0), (-1, 0)],
-            1 => [(1, 0), (0, 1), (0, -1)],
-            2 => [(0, 1), (-1, 0), (1, 0)],
-            3 => [(-1, 0), (0, -1), (0, 1)],
-            _ => [(0, -1), (1, 0), (-1, 0)],
-        };
-
-        offsets
-            .into_iter()
-            .map(|(dx, dy)| Vec2i {
-                x: self.pos.x + dx,
-                y: self.pos.y + dy,
-            })
-            .collect()
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        let (dx, dy) = match self.rot.rem_euclid(4) {
-            0 => (0, 1),
-            1 => (-1, 0),
-            2 => (0, -1),
-            3 => (1, 0),
-            _ => (0, 1),
+    fn filter_packet(&self, packet: &Packet, _blocklists: &Blocklists) -> Option<bool> {
+        Some(self.filter(packet))
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::Content)
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::Content(config) = config else {
+            return false;
         };
-
-        vec![Vec2i {
-            x: self.pos.x + dx,
-            y: self.pos.y + dy,
-        }]
+        self.set_config(config);
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
     }
     fn update(&mut self, _delta: f32) {}
     fn can_offload(&self) -> bool {
@@ -138,6 +159,15 @@ impl Building for ContentFilter {
     fn get_packet_progresses(&self) -> Vec<f32> {
         self.buffer.iter().map(|_| 0.0).collect()
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.buffer.iter().cloned().collect())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Packets(packets) => packets.into_iter().next(),
+            _ => None,
+        };
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
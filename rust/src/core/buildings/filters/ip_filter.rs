@@ -1,26 +1,37 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::core::packet::Packet;
+use crate::logic::blocklist::Blocklists;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IpFilterDirection {
     Source,
     Destination,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IpFilterConfig {
     pub target_ip: String,
     pub direction: IpFilterDirection,
 }
 
+/// Rule of an IP filter that matches the addresses on a world blocklist instead of one IP.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IpBlocklistConfig {
+    pub list: String,
+    pub direction: IpFilterDirection,
+}
+
 pub struct IpFilter {
     id: BuildingId,
     pos: Vec2i,
     rot: i32,
     buffer: Option<Packet>,
     pub config: Option<IpFilterConfig>,
+    /// Set instead of `config` when the filter matches a blocklist.
+    pub blocklist: Option<IpBlocklistConfig>,
 }
 
 impl IpFilter {
@@ -31,6 +42,7 @@ impl IpFilter {
             rot,
             buffer: None,
             config: None,
+            blocklist: None,
         }
     }
 
@@ -41,16 +53,24 @@ impl IpFilter {
             rot,
             buffer: None,
             config: Some(config),
+            blocklist: None,
         }
     }
 
     pub fn filter(&self, packet: &Packet) -> bool {
-        if let Some(config) = &self.config {
-            let target_ip = match config.direction {
-                IpFilterDirection::Source => &packet.source_ip,
-                IpFilterDirection::Destination => &packet.dest_ip,
-            };
-            target_ip == &config.target_ip
+        self.filter_with(packet, &Blocklists::default())
+    }
+
+    /// Like `filter`, looking up the blocklist the rule refers to in `blocklists`.
+    pub fn filter_with(&self, packet: &Packet, blocklists: &Blocklists) -> bool {
+        let address = |direction: &IpFilterDirection| match direction {
+            IpFilterDirection::Source => &packet.source_ip,
+            IpFilterDirection::Destination => &packet.dest_ip,
+        };
+        if let Some(rule) = &self.blocklist {
+            blocklists.contains(&rule.list, address(&rule.direction))
+        } else if let Some(config) = &self.config {
+            address(&config.direction) == &config.target_ip
         } else {
             false // フィルター設定がない場合は何も通さない
         }
@@ -58,6 +78,12 @@ impl IpFilter {
 
     pub fn set_config(&mut self, config: IpFilterConfig) {
         self.config = Some(config);
+        self.blocklist = None;
+    }
+
+    pub fn set_blocklist(&mut self, rule: IpBlocklistConfig) {
+        self.blocklist = Some(rule);
+        self.config = None;
     }
 }
 
@@ -71,43 +97,32 @@ impl Building for IpFilter {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::IpFilter
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 1, y: 1 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        let rotation = self.rot.rem_euclid(4);
-        let offsets = match rotation {
-            0 => [(0, -1), (1, 0), (-1, 0)],
-            1 => [(1, 0), (0, 1), (0, -1)],
-            2 => [(0, 1), (-1, 0), (1, 0)],
-            3 => [(-1, 0), (0, -1), (0, 1)],
-            _ => [(0, -1), (1, 0), (-1, 0)],
-        };
-
-        offsets
-            .into_iter()
-            .map(|(dx, dy)| Vec2i {
-                x: self.pos.x + dx,
-                y: self.pos.y + dy,
-            })
-            .collect()
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        let (dx, dy) = match self.rot.rem_euclid(4) {
-            0 => (0, 1),
-            1 => (-1, 0),
-            2 => (0, -1),
-            3 => (1, 0),
-            _ => (0, 1),
-        };
-
-        vec![Vec2i {
-            x: self.pos.x + dx,
-            y: self.pos.y + dy,
-        }]
+    fn filter_packet(&self, packet: &Packet, blocklists: &Blocklists) -> Option<bool> {
+        Some(self.filter_with(packet, blocklists))
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        match &self.blocklist {
+            Some(rule) => Some(BuildingConfig::IpBlocklist(rule.clone())),
+            None => self.config.clone().map(BuildingConfig::Ip),
+        }
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        match config {
+            BuildingConfig::Ip(config) => self.set_config(config),
+            BuildingConfig::IpBlocklist(rule) => self.set_blocklist(rule),
+            _ => return false,
+        }
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+        self.blocklist = None;
     }
     fn update(&mut self, _delta: f32) {}
     fn can_offload(&self) -> bool {
@@ -132,6 +147,15 @@ impl Building for IpFilter {
     fn get_packet_progresses(&self) -> Vec<f32> {
         self.buffer.iter().map(|_| 0.0).collect()
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.buffer.iter().cloned().collect())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Packets(packets) => packets.into_iter().next(),
+            _ => None,
+        };
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -1,16 +1,18 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::core::packet::Packet;
+use crate::logic::blocklist::Blocklists;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LengthFilterDirection {
     Exact,
     LessThan,
     GreaterThan,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LengthFilterConfig {
     pub threshold: u32,
     pub direction: LengthFilterDirection,
@@ -77,43 +79,27 @@ impl Building for LengthFilter {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::LengthFilter
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 1, y: 1 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        let rotation = self.rot.rem_euclid(4);
-        let offsets = match rotation {
-            0 => [(0, -1), (1, 0), (-1, 0)],
-            1 => [(1, 0), (0, 1), (0, -1)],
-            2 => [(0, 1), (-1, 0), (1, 0)],
-            3 => [(-1, 0), (0, -1), (0, 1)],
-            _ => [(0, -1), (1, 0), (-1, 0)],
-        };
-
-        offsets
-            .into_iter()
-            .map(|(dx, dy)| Vec2i {
-                x: self.pos.x + dx,
-                y: self.pos.y + dy,
-            })
-            .collect()
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        let (dx, dy) = match self.rot.rem_euclid(4) {
-            0 => (0, 1),
-            1 => (-1, 0),
-            2 => (0, -1),
-            3 => (1, 0),
-            _ => (0, 1),
+    fn filter_packet(&self, packet: &Packet, _blocklists: &Blocklists) -> Option<bool> {
+        Some(self.filter(packet))
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::Length)
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::Length(config) = config else {
+            return false;
         };
-
-        vec![Vec2i {
-            x: self.pos.x + dx,
-            y: self.pos.y + dy,
-        }]
+        self.set_config(config);
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
     }
     fn update(&mut self, _delta: f32) {}
     fn can_offload(&self) -> bool {
@@ -138,6 +124,15 @@ impl Building for LengthFilter {
     fn get_packet_progresses(&self) -> Vec<f32> {
         self.buffer.iter().map(|_| 0.0).collect()
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.buffer.iter().cloned().collect())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Packets(packets) => packets.into_iter().next(),
+            _ => None,
+        };
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
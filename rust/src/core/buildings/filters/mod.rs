@@ -5,6 +5,9 @@ pub mod ip_filter;
 pub mod length_filter;
 pub mod port_filter;
 pub mod protocol_filter;
+pub mod rate_limiter;
+pub mod scan_detector;
+pub mod stateful_firewall;
 
 pub trait Filter {
     fn filter(&self, packet: &Packet) -> bool;
@@ -0,0 +1,322 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::Packet;
+use crate::logic::blocklist::Blocklists;
+use serde::{Deserialize, Serialize};
+
+/// What the rate limiter counts packets by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    #[default]
+    SourceIp,
+    DestinationPort,
+    /// Source and destination address and port plus protocol.
+    Flow,
+}
+
+impl RateLimitKey {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "source_ip" => Some(Self::SourceIp),
+            "destination_port" => Some(Self::DestinationPort),
+            "flow" => Some(Self::Flow),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SourceIp => "source_ip",
+            Self::DestinationPort => "destination_port",
+            Self::Flow => "flow",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RateLimiterConfig {
+    /// Packets per key allowed through within one window.
+    pub limit: u32,
+    /// Length of the sliding window, in milliseconds of simulated time.
+    pub window_ms: u32,
+    #[serde(default)]
+    pub key: RateLimitKey,
+}
+
+impl RateLimiterConfig {
+    fn key_of(&self, packet: &Packet) -> String {
+        match self.key {
+            RateLimitKey::SourceIp => packet.source_ip.clone(),
+            RateLimitKey::DestinationPort => packet.dest_port.to_string(),
+            RateLimitKey::Flow => format!(
+                "{}:{}>{}:{}/{:?}",
+                packet.source_ip,
+                packet.source_port,
+                packet.dest_ip,
+                packet.dest_port,
+                packet.protocol
+            ),
+        }
+    }
+
+    fn window_secs(&self) -> f64 {
+        self.window_ms as f64 / 1000.0
+    }
+}
+
+/// Simulation state of a `RateLimiter`, kept by world snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimiterState {
+    pub packet: Option<Packet>,
+    /// Simulated seconds since the limiter was placed.
+    pub clock: f64,
+    /// Times packets of each key went through, oldest first.
+    pub passed: Vec<(String, Vec<f64>)>,
+}
+
+/// Sends packets to the match output while their key stays within `limit` packets per
+/// window (a sliding window over simulated time), and the rest to the mismatch output.
+/// Only packets that went through count towards the limit.
+pub struct RateLimiter {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    buffer: Option<Packet>,
+    clock: f64,
+    passed: HashMap<String, VecDeque<f64>>,
+    pub config: Option<RateLimiterConfig>,
+}
+
+impl RateLimiter {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+            clock: 0.0,
+            passed: HashMap::new(),
+            config: None,
+        }
+    }
+
+    pub fn new_with_config(
+        id: BuildingId,
+        pos: Vec2i,
+        rot: i32,
+        config: RateLimiterConfig,
+    ) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(id, pos, rot)
+        }
+    }
+
+    /// Whether `packet` would stay within the limit if it left now.
+    pub fn filter(&self, packet: &Packet) -> bool {
+        let Some(config) = &self.config else {
+            return false; // No config means no packets pass through
+        };
+        let since = self.clock - config.window_secs();
+        let recent = self
+            .passed
+            .get(&config.key_of(packet))
+            .map_or(0, |times| times.iter().filter(|&&t| t > since).count());
+        recent < config.limit as usize
+    }
+
+    /// Changing the rule starts counting afresh.
+    pub fn set_config(&mut self, config: RateLimiterConfig) {
+        self.config = Some(config);
+        self.passed.clear();
+    }
+
+    /// Forget passes that have left the window, and keys with none left.
+    fn expire(&mut self) {
+        let Some(config) = &self.config else {
+            self.passed.clear();
+            return;
+        };
+        let since = self.clock - config.window_secs();
+        self.passed.retain(|_, times| {
+            while times.front().is_some_and(|&t| t <= since) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+    }
+}
+
+impl Building for RateLimiter {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::RateLimiter
+    }
+    fn filter_packet(&self, packet: &Packet, _blocklists: &Blocklists) -> Option<bool> {
+        Some(self.filter(packet))
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::RateLimit)
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::RateLimit(config) = config else {
+            return false;
+        };
+        self.set_config(config);
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+    }
+    fn update(&mut self, delta: f32) {
+        self.clock += delta as f64;
+        self.expire();
+    }
+    fn can_offload(&self) -> bool {
+        self.buffer.is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.buffer.is_none()
+    }
+    /// The packet leaves by the verdict of `filter` in the same tick, so a pass is counted
+    /// here.
+    fn offload(&mut self) -> Packet {
+        let packet = self.buffer.take().expect("Offload called without packet");
+        if self.filter(&packet)
+            && let Some(config) = &self.config
+        {
+            self.passed
+                .entry(config.key_of(&packet))
+                .or_default()
+                .push_back(self.clock);
+        }
+        packet
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        self.buffer = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.buffer.iter().cloned().collect()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.buffer.iter().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::RateLimiter(RateLimiterState {
+            packet: self.buffer.clone(),
+            clock: self.clock,
+            passed: self
+                .passed
+                .iter()
+                .map(|(key, times)| (key.clone(), times.iter().copied().collect()))
+                .collect(),
+        })
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = None;
+        self.clock = 0.0;
+        self.passed.clear();
+        match state {
+            BuildingState::RateLimiter(state) => {
+                self.buffer = state.packet;
+                self.clock = state.clock;
+                self.passed = state
+                    .passed
+                    .into_iter()
+                    .map(|(key, times)| (key, times.into()))
+                    .collect();
+            }
+            BuildingState::Packets(packets) => self.buffer = packets.into_iter().next(),
+            _ => {}
+        }
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::packet::Protocol;
+
+    fn packet(source_ip: &str) -> Packet {
+        Packet::new(
+            source_ip.to_string(),
+            "10.0.0.2".to_string(),
+            1000,
+            22,
+            Protocol::Tcp,
+            64,
+            vec![],
+        )
+    }
+
+    /// Feed one packet and let it leave, returning whether it went to the match output.
+    fn pass(limiter: &mut RateLimiter, source_ip: &str) -> bool {
+        limiter.accept(packet(source_ip), Vec2i { x: 0, y: 1 });
+        let within = limiter.filter(&limiter.get_packets()[0]);
+        limiter.offload();
+        within
+    }
+
+    #[test]
+    fn limits_each_source_within_the_window() {
+        let config = RateLimiterConfig {
+            limit: 2,
+            window_ms: 1000,
+            key: RateLimitKey::SourceIp,
+        };
+        let mut limiter = RateLimiter::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config);
+
+        assert!(pass(&mut limiter, "10.0.0.1"));
+        assert!(pass(&mut limiter, "10.0.0.1"));
+        assert!(!pass(&mut limiter, "10.0.0.1"));
+        // Another host has its own allowance.
+        assert!(pass(&mut limiter, "10.0.0.9"));
+
+        limiter.update(0.6);
+        assert!(!pass(&mut limiter, "10.0.0.1"));
+        // Once the first passes leave the window, the host gets through again.
+        limiter.update(0.5);
+        assert!(pass(&mut limiter, "10.0.0.1"));
+        assert_eq!(limiter.passed.len(), 1);
+    }
+
+    #[test]
+    fn state_survives_snapshot_restore() {
+        let config = RateLimiterConfig {
+            limit: 1,
+            window_ms: 1000,
+            key: RateLimitKey::Flow,
+        };
+        let mut limiter = RateLimiter::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config.clone());
+        assert!(pass(&mut limiter, "10.0.0.1"));
+
+        let mut restored = RateLimiter::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config);
+        restored.restore_state(limiter.save_state());
+        assert!(!pass(&mut restored, "10.0.0.1"));
+    }
+}
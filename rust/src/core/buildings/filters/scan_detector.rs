@@ -0,0 +1,452 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::Packet;
+use crate::logic::blocklist::Blocklists;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScanDetectorConfig {
+    /// Length of the observation window, in milliseconds of simulated time.
+    pub window_ms: u32,
+    /// A source is flagged once it reaches more distinct destination ports than this within
+    /// the window. `0` disables the port check.
+    pub port_threshold: u32,
+    /// Same for distinct destination hosts. `0` disables the host check.
+    #[serde(default)]
+    pub host_threshold: u32,
+    /// How long a flagged source stays diverted; `0` keeps it flagged for good.
+    #[serde(default)]
+    pub grace_ms: u32,
+}
+
+/// World blocklist a scan detector adds the sources it flags to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlocklistFeed {
+    pub list: String,
+    /// How long the entries last; `0` keeps them for good.
+    #[serde(default)]
+    pub ttl_ms: u32,
+}
+
+impl ScanDetectorConfig {
+    fn window_secs(&self) -> f64 {
+        self.window_ms as f64 / 1000.0
+    }
+
+    fn grace_secs(&self) -> Option<f64> {
+        (self.grace_ms > 0).then(|| self.grace_ms as f64 / 1000.0)
+    }
+
+    fn exceeded(threshold: u32, distinct: usize) -> bool {
+        threshold > 0 && distinct > threshold as usize
+    }
+}
+
+/// A destination a source sent to, and when.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    pub time: f64,
+    pub source_ip: String,
+    pub dest_ip: String,
+    pub dest_port: u16,
+}
+
+/// Simulation state of a `ScanDetector`, kept by world snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct ScanDetectorState {
+    pub packet: Option<Packet>,
+    pub clock: f64,
+    /// Probes still in the window, oldest first.
+    pub probes: Vec<Probe>,
+    pub flagged: Vec<(String, f64)>,
+}
+
+/// Distinct destinations a source reached within the window, with when each was last seen.
+#[derive(Debug, Default)]
+struct Reach {
+    ports: HashMap<u16, f64>,
+    hosts: HashMap<String, f64>,
+}
+
+impl Reach {
+    fn record(&mut self, probe: &Probe) {
+        self.ports.insert(probe.dest_port, probe.time);
+        self.hosts.insert(probe.dest_ip.clone(), probe.time);
+    }
+
+    /// Forget the destinations `probe` was the last visit of.
+    fn forget(&mut self, probe: &Probe) {
+        if self.ports.get(&probe.dest_port) == Some(&probe.time) {
+            self.ports.remove(&probe.dest_port);
+        }
+        if self.hosts.get(&probe.dest_ip) == Some(&probe.time) {
+            self.hosts.remove(&probe.dest_ip);
+        }
+    }
+}
+
+/// IDS-style detector: counts the distinct destination ports and hosts each source reaches
+/// within a window. A source that crosses a threshold is flagged, and its packets, starting
+/// with the one that crossed it, go to the mismatch output until the grace period ends.
+/// Other packets go to the match output.
+pub struct ScanDetector {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    buffer: Option<Packet>,
+    clock: f64,
+    /// Packets that went through, oldest first. Expiring them keeps `reach` to the window.
+    probes: VecDeque<Probe>,
+    reach: HashMap<String, Reach>,
+    /// Flagged sources and when they were flagged.
+    flagged: HashMap<String, f64>,
+    /// Sources flagged since `take_reported` was last called.
+    reported: Vec<String>,
+    pub config: Option<ScanDetectorConfig>,
+    pub feed: Option<BlocklistFeed>,
+}
+
+impl ScanDetector {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+            clock: 0.0,
+            probes: VecDeque::new(),
+            reach: HashMap::new(),
+            flagged: HashMap::new(),
+            reported: Vec::new(),
+            config: None,
+            feed: None,
+        }
+    }
+
+    pub fn new_with_config(
+        id: BuildingId,
+        pos: Vec2i,
+        rot: i32,
+        config: ScanDetectorConfig,
+    ) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(id, pos, rot)
+        }
+    }
+
+    /// Whether `packet` goes through, i.e. its source is not (and would not become) flagged.
+    pub fn filter(&self, packet: &Packet) -> bool {
+        self.config.is_some() && !self.is_flagged(&packet.source_ip) && !self.crosses(packet)
+    }
+
+    /// Changing the rule starts observing afresh and forgets flagged sources. The detector
+    /// stops feeding its blocklist, if any.
+    pub fn set_config(&mut self, config: ScanDetectorConfig) {
+        self.config = Some(config);
+        self.feed = None;
+        self.probes.clear();
+        self.reach.clear();
+        self.flagged.clear();
+    }
+
+    pub fn is_flagged(&self, source_ip: &str) -> bool {
+        self.flagged.contains_key(source_ip)
+    }
+
+    /// Sources flagged since the last call, for the world to add to the blocklist.
+    pub fn take_reported(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.reported)
+    }
+
+    /// Flagged sources with the seconds since they were flagged, earliest first.
+    pub fn flagged_sources(&self) -> Vec<(&str, f64)> {
+        let mut sources: Vec<(&str, f64)> = self
+            .flagged
+            .iter()
+            .map(|(source, at)| (source.as_str(), self.clock - at))
+            .collect();
+        sources.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        sources
+    }
+
+    /// Seconds until a source flagged `age` seconds ago is let through again; `None` when it
+    /// stays flagged.
+    pub fn releases_in(&self, age: f64) -> Option<f64> {
+        let grace = self.config.as_ref()?.grace_secs()?;
+        Some((grace - age).max(0.0))
+    }
+
+    /// Whether sending `packet` would take its source over a threshold.
+    fn crosses(&self, packet: &Packet) -> bool {
+        let Some(config) = &self.config else {
+            return false;
+        };
+        let (ports, hosts) = match self.reach.get(&packet.source_ip) {
+            Some(reach) => (
+                reach.ports.len() + usize::from(!reach.ports.contains_key(&packet.dest_port)),
+                reach.hosts.len() + usize::from(!reach.hosts.contains_key(&packet.dest_ip)),
+            ),
+            None => (1, 1),
+        };
+        ScanDetectorConfig::exceeded(config.port_threshold, ports)
+            || ScanDetectorConfig::exceeded(config.host_threshold, hosts)
+    }
+
+    /// Forget probes that left the window, and release sources whose grace period ended.
+    fn expire(&mut self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        let since = self.clock - config.window_secs();
+        while self.probes.front().is_some_and(|probe| probe.time <= since) {
+            let probe = self.probes.pop_front().unwrap();
+            if let Some(reach) = self.reach.get_mut(&probe.source_ip) {
+                reach.forget(&probe);
+                if reach.ports.is_empty() {
+                    self.reach.remove(&probe.source_ip);
+                }
+            }
+        }
+        if let Some(grace) = config.grace_secs() {
+            let clock = self.clock;
+            self.flagged.retain(|_, at| clock - *at < grace);
+        }
+    }
+}
+
+impl Building for ScanDetector {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::ScanDetector
+    }
+    fn filter_packet(&self, packet: &Packet, _blocklists: &Blocklists) -> Option<bool> {
+        Some(self.filter(packet))
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        let detector = self.config.clone()?;
+        Some(match &self.feed {
+            Some(feed) => BuildingConfig::ScanDetectorFeed {
+                detector,
+                feed: feed.clone(),
+            },
+            None => BuildingConfig::ScanDetector(detector),
+        })
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        match config {
+            BuildingConfig::ScanDetector(config) => self.set_config(config),
+            BuildingConfig::ScanDetectorFeed { detector, feed } => {
+                self.set_config(detector);
+                self.feed = Some(feed);
+            }
+            _ => return false,
+        }
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+        self.feed = None;
+    }
+    fn update(&mut self, delta: f32) {
+        self.clock += delta as f64;
+        self.expire();
+    }
+    fn can_offload(&self) -> bool {
+        self.buffer.is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.buffer.is_none()
+    }
+    /// The packet leaves by the verdict of `filter` in the same tick, so it is recorded
+    /// here.
+    fn offload(&mut self) -> Packet {
+        let packet = self.buffer.take().expect("Offload called without packet");
+        if self.config.is_none() || self.is_flagged(&packet.source_ip) {
+            return packet;
+        }
+        if self.crosses(&packet) {
+            // Flagging is rare, so the source's probes are dropped from the queue right away.
+            self.probes
+                .retain(|probe| probe.source_ip != packet.source_ip);
+            self.reach.remove(&packet.source_ip);
+            self.flagged.insert(packet.source_ip.clone(), self.clock);
+            self.reported.push(packet.source_ip.clone());
+        } else {
+            let probe = Probe {
+                time: self.clock,
+                source_ip: packet.source_ip.clone(),
+                dest_ip: packet.dest_ip.clone(),
+                dest_port: packet.dest_port,
+            };
+            self.reach
+                .entry(packet.source_ip.clone())
+                .or_default()
+                .record(&probe);
+            self.probes.push_back(probe);
+        }
+        packet
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        self.buffer = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.buffer.iter().cloned().collect()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.buffer.iter().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::ScanDetector(ScanDetectorState {
+            packet: self.buffer.clone(),
+            clock: self.clock,
+            probes: self.probes.iter().cloned().collect(),
+            flagged: self
+                .flagged
+                .iter()
+                .map(|(source, at)| (source.clone(), *at))
+                .collect(),
+        })
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = None;
+        self.clock = 0.0;
+        self.probes.clear();
+        self.reach.clear();
+        self.flagged.clear();
+        match state {
+            BuildingState::ScanDetector(state) => {
+                self.buffer = state.packet;
+                self.clock = state.clock;
+                for probe in &state.probes {
+                    self.reach
+                        .entry(probe.source_ip.clone())
+                        .or_default()
+                        .record(probe);
+                }
+                self.probes = state.probes.into();
+                self.flagged = state.flagged.into_iter().collect();
+            }
+            BuildingState::Packets(packets) => self.buffer = packets.into_iter().next(),
+            _ => {}
+        }
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::packet::Protocol;
+
+    fn packet(source_ip: &str, dest_ip: &str, dest_port: u16) -> Packet {
+        Packet::new(
+            source_ip.to_string(),
+            dest_ip.to_string(),
+            40000,
+            dest_port,
+            Protocol::Tcp,
+            64,
+            vec![],
+        )
+    }
+
+    /// Feed one packet and let it leave, returning whether it went to the match output.
+    fn pass(detector: &mut ScanDetector, packet: Packet) -> bool {
+        detector.accept(packet, Vec2i { x: 0, y: 1 });
+        let clean = detector.filter(&detector.get_packets()[0]);
+        detector.offload();
+        clean
+    }
+
+    fn detector(grace_ms: u32) -> ScanDetector {
+        let config = ScanDetectorConfig {
+            window_ms: 1000,
+            port_threshold: 3,
+            host_threshold: 2,
+            grace_ms,
+        };
+        ScanDetector::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config)
+    }
+
+    #[test]
+    fn flags_a_port_scan_and_diverts_the_source() {
+        let mut detector = detector(0);
+        let scanner = "10.0.0.66";
+        for port in [21, 22, 23] {
+            assert!(pass(&mut detector, packet(scanner, "10.0.0.2", port)));
+        }
+        // The fourth distinct port crosses the threshold.
+        assert!(!pass(&mut detector, packet(scanner, "10.0.0.2", 25)));
+        assert!(detector.is_flagged(scanner));
+        assert_eq!(detector.take_reported(), [scanner]);
+        // Even traffic to a port it used before is now diverted, but other sources are not.
+        assert!(!pass(&mut detector, packet(scanner, "10.0.0.2", 22)));
+        assert!(pass(&mut detector, packet("10.0.0.1", "10.0.0.2", 80)));
+
+        // Repeating one port does not count as a scan.
+        for _ in 0..10 {
+            assert!(pass(&mut detector, packet("10.0.0.1", "10.0.0.2", 80)));
+        }
+        detector.update(5.0);
+        assert!(detector.is_flagged(scanner));
+    }
+
+    #[test]
+    fn ports_leave_the_window_and_survive_a_restore() {
+        let mut detector = detector(0);
+        let source = "10.0.0.5";
+        assert!(pass(&mut detector, packet(source, "10.0.0.2", 21)));
+        detector.update(0.6);
+        for port in [22, 23] {
+            assert!(pass(&mut detector, packet(source, "10.0.0.2", port)));
+        }
+        // Port 21 has left the window, so a fourth port is only the third distinct one.
+        detector.update(0.6);
+        assert!(pass(&mut detector, packet(source, "10.0.0.2", 24)));
+
+        let mut restored = ScanDetector::new(1, Vec2i { x: 0, y: 0 }, 0);
+        restored.config = detector.config.clone();
+        restored.restore_state(detector.save_state());
+        assert!(!pass(&mut restored, packet(source, "10.0.0.2", 25)));
+    }
+
+    #[test]
+    fn host_sweeps_are_flagged_and_the_grace_period_ends() {
+        let mut detector = detector(2000);
+        let sweeper = "10.0.0.77";
+        assert!(pass(&mut detector, packet(sweeper, "10.0.0.2", 445)));
+        assert!(pass(&mut detector, packet(sweeper, "10.0.0.3", 445)));
+        assert!(!pass(&mut detector, packet(sweeper, "10.0.0.4", 445)));
+
+        detector.update(1.5);
+        assert_eq!(detector.releases_in(1.5), Some(0.5));
+        assert!(!pass(&mut detector, packet(sweeper, "10.0.0.2", 445)));
+        detector.update(1.0);
+        assert!(!detector.is_flagged(sweeper));
+        assert!(pass(&mut detector, packet(sweeper, "10.0.0.2", 445)));
+    }
+}
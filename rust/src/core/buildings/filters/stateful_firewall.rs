@@ -0,0 +1,505 @@
+use std::collections::HashMap;
+
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::{Packet, Protocol, TcpFlags};
+use crate::logic::blocklist::Blocklists;
+use serde::{Deserialize, Serialize};
+
+fn default_tcp_timeout_ms() -> u32 {
+    10_000
+}
+
+fn default_udp_timeout_ms() -> u32 {
+    5_000
+}
+
+/// A service new connections may be opened to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FirewallService {
+    pub protocol: Protocol,
+    pub port: u16,
+}
+
+/// "Allow established, allow new only to listed services".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatefulFirewallConfig {
+    pub services: Vec<FirewallService>,
+    /// Idle time, in milliseconds of simulated time, after which a TCP session is forgotten.
+    #[serde(default = "default_tcp_timeout_ms")]
+    pub tcp_timeout_ms: u32,
+    /// Idle time after which a UDP (or other) pseudo-flow is forgotten.
+    #[serde(default = "default_udp_timeout_ms")]
+    pub udp_timeout_ms: u32,
+}
+
+impl StatefulFirewallConfig {
+    pub fn new(services: Vec<FirewallService>) -> Self {
+        Self {
+            services,
+            tcp_timeout_ms: default_tcp_timeout_ms(),
+            udp_timeout_ms: default_udp_timeout_ms(),
+        }
+    }
+
+    fn allows_new(&self, packet: &Packet) -> bool {
+        self.services
+            .iter()
+            .any(|service| service.protocol == packet.protocol && service.port == packet.dest_port)
+    }
+
+    fn timeout_secs(&self, protocol: &Protocol) -> f64 {
+        let ms = match protocol {
+            Protocol::Tcp => self.tcp_timeout_ms,
+            _ => self.udp_timeout_ms,
+        };
+        ms as f64 / 1000.0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// The client's SYN went through.
+    SynSent,
+    /// The server answered with SYN+ACK.
+    SynReceived,
+    /// Handshake completed, or any UDP flow (and TCP traffic without recorded flags).
+    Established,
+    /// A FIN went through; the session stays until it times out.
+    Closing,
+}
+
+impl SessionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SynSent => "syn_sent",
+            Self::SynReceived => "syn_received",
+            Self::Established => "established",
+            Self::Closing => "closing",
+        }
+    }
+}
+
+/// Client and server endpoints of a session, the client being the side that opened it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SessionKey {
+    pub client_ip: String,
+    pub client_port: u16,
+    pub server_ip: String,
+    pub server_port: u16,
+    pub protocol: Protocol,
+}
+
+impl SessionKey {
+    fn from_client(packet: &Packet) -> Self {
+        Self {
+            client_ip: packet.source_ip.clone(),
+            client_port: packet.source_port,
+            server_ip: packet.dest_ip.clone(),
+            server_port: packet.dest_port,
+            protocol: packet.protocol.clone(),
+        }
+    }
+
+    fn from_server(packet: &Packet) -> Self {
+        Self {
+            client_ip: packet.dest_ip.clone(),
+            client_port: packet.dest_port,
+            server_ip: packet.source_ip.clone(),
+            server_port: packet.source_port,
+            protocol: packet.protocol.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub key: SessionKey,
+    pub state: SessionState,
+    /// Firewall clock when the session last saw a packet.
+    pub last_seen: f64,
+}
+
+/// Simulation state of a `StatefulFirewall`, kept by world snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct StatefulFirewallState {
+    pub packet: Option<Packet>,
+    pub clock: f64,
+    pub sessions: Vec<Session>,
+}
+
+/// What a packet does to the session table if it goes through.
+enum Tracking {
+    Blocked,
+    Update(SessionKey, SessionState),
+    Close(SessionKey),
+}
+
+/// Tracks TCP handshakes and UDP pseudo-flows in a session table. Packets of a known
+/// session, or opening one to a listed service, go to the match output; everything else
+/// goes to the mismatch output.
+pub struct StatefulFirewall {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    buffer: Option<Packet>,
+    clock: f64,
+    sessions: HashMap<SessionKey, Session>,
+    pub config: Option<StatefulFirewallConfig>,
+}
+
+impl StatefulFirewall {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+            clock: 0.0,
+            sessions: HashMap::new(),
+            config: None,
+        }
+    }
+
+    pub fn new_with_config(
+        id: BuildingId,
+        pos: Vec2i,
+        rot: i32,
+        config: StatefulFirewallConfig,
+    ) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(id, pos, rot)
+        }
+    }
+
+    /// Whether `packet` belongs to a session or may open one.
+    pub fn filter(&self, packet: &Packet) -> bool {
+        !matches!(self.track(packet), Tracking::Blocked)
+    }
+
+    /// Changing the policy keeps the sessions already open.
+    pub fn set_config(&mut self, config: StatefulFirewallConfig) {
+        self.config = Some(config);
+    }
+
+    /// Current sessions, oldest activity first.
+    pub fn sessions(&self) -> Vec<&Session> {
+        let mut sessions: Vec<&Session> = self.sessions.values().collect();
+        sessions.sort_by(|a, b| a.last_seen.total_cmp(&b.last_seen));
+        sessions
+    }
+
+    /// Seconds until `session` times out.
+    pub fn expires_in(&self, session: &Session) -> f64 {
+        self.config.as_ref().map_or(0.0, |config| {
+            session.last_seen + config.timeout_secs(&session.key.protocol) - self.clock
+        })
+    }
+
+    fn track(&self, packet: &Packet) -> Tracking {
+        let Some(config) = &self.config else {
+            return Tracking::Blocked; // No config means no packets pass through
+        };
+        let flags = match packet.protocol {
+            Protocol::Tcp => packet.tcp_flags,
+            _ => None,
+        };
+        let is = |flag| flags.is_some_and(|flags: TcpFlags| flags.contains(flag));
+
+        let from_client = SessionKey::from_client(packet);
+        let from_server = SessionKey::from_server(packet);
+        let existing = [(from_client.clone(), true), (from_server, false)]
+            .into_iter()
+            .find_map(|(key, client)| self.sessions.get(&key).map(|s| (key, s.state, client)));
+
+        if let Some((key, state, from_client)) = existing {
+            if is(TcpFlags::RST) {
+                return Tracking::Close(key);
+            }
+            let next = match state {
+                SessionState::SynSent if !from_client && is(TcpFlags::SYN) && is(TcpFlags::ACK) => {
+                    SessionState::SynReceived
+                }
+                // Until the server's SYN+ACK, only a retransmitted SYN of the client follows.
+                SessionState::SynSent if from_client && is(TcpFlags::SYN) && !is(TcpFlags::ACK) => {
+                    SessionState::SynSent
+                }
+                SessionState::SynSent => return Tracking::Blocked,
+                _ if is(TcpFlags::FIN) => SessionState::Closing,
+                SessionState::SynReceived if from_client && is(TcpFlags::ACK) => {
+                    SessionState::Established
+                }
+                state => state,
+            };
+            return Tracking::Update(key, next);
+        }
+
+        if !config.allows_new(packet) {
+            return Tracking::Blocked;
+        }
+        match flags {
+            // Only a bare SYN opens a TCP connection.
+            Some(_) if is(TcpFlags::SYN) && !is(TcpFlags::ACK) => {
+                Tracking::Update(from_client, SessionState::SynSent)
+            }
+            Some(_) => Tracking::Blocked,
+            // Without flags the first packet of a flow opens it, as for UDP.
+            None => Tracking::Update(from_client, SessionState::Established),
+        }
+    }
+
+    /// Forget sessions idle for longer than their timeout.
+    fn expire(&mut self) {
+        let Some(config) = &self.config else {
+            return;
+        };
+        let clock = self.clock;
+        self.sessions
+            .retain(|key, session| clock - session.last_seen < config.timeout_secs(&key.protocol));
+    }
+}
+
+impl Building for StatefulFirewall {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::StatefulFirewall
+    }
+    fn filter_packet(&self, packet: &Packet, _blocklists: &Blocklists) -> Option<bool> {
+        Some(self.filter(packet))
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::Firewall)
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::Firewall(config) = config else {
+            return false;
+        };
+        self.set_config(config);
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+    }
+    fn update(&mut self, delta: f32) {
+        self.clock += delta as f64;
+        self.expire();
+    }
+    fn can_offload(&self) -> bool {
+        self.buffer.is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.buffer.is_none()
+    }
+    /// The packet leaves by the verdict of `filter` in the same tick, so the session table
+    /// is updated here.
+    fn offload(&mut self) -> Packet {
+        let packet = self.buffer.take().expect("Offload called without packet");
+        match self.track(&packet) {
+            Tracking::Blocked => {}
+            Tracking::Update(key, state) => {
+                self.sessions.insert(
+                    key.clone(),
+                    Session {
+                        key,
+                        state,
+                        last_seen: self.clock,
+                    },
+                );
+            }
+            Tracking::Close(key) => {
+                self.sessions.remove(&key);
+            }
+        }
+        packet
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        self.buffer = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.buffer.iter().cloned().collect()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.buffer.iter().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::StatefulFirewall(StatefulFirewallState {
+            packet: self.buffer.clone(),
+            clock: self.clock,
+            sessions: self.sessions.values().cloned().collect(),
+        })
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = None;
+        self.clock = 0.0;
+        self.sessions.clear();
+        match state {
+            BuildingState::StatefulFirewall(state) => {
+                self.buffer = state.packet;
+                self.clock = state.clock;
+                self.sessions = state
+                    .sessions
+                    .into_iter()
+                    .map(|session| (session.key.clone(), session))
+                    .collect();
+            }
+            BuildingState::Packets(packets) => self.buffer = packets.into_iter().next(),
+            _ => {}
+        }
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: (&str, u16) = ("10.0.0.1", 40000);
+    const SERVER: (&str, u16) = ("10.0.0.2", 22);
+
+    fn packet(from: (&str, u16), to: (&str, u16), protocol: Protocol, flags: &str) -> Packet {
+        let mut packet = Packet::new(
+            from.0.to_string(),
+            to.0.to_string(),
+            from.1,
+            to.1,
+            protocol,
+            64,
+            vec![],
+        );
+        packet.tcp_flags = TcpFlags::from_letters(flags);
+        packet
+    }
+
+    /// Feed one packet and let it leave, returning whether it went to the match output.
+    fn pass(firewall: &mut StatefulFirewall, packet: Packet) -> bool {
+        firewall.accept(packet, Vec2i { x: 0, y: 1 });
+        let allowed = firewall.filter(&firewall.get_packets()[0]);
+        firewall.offload();
+        allowed
+    }
+
+    fn ssh_firewall() -> StatefulFirewall {
+        let config = StatefulFirewallConfig::new(vec![FirewallService {
+            protocol: Protocol::Tcp,
+            port: 22,
+        }]);
+        StatefulFirewall::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config)
+    }
+
+    fn state(firewall: &StatefulFirewall) -> Option<SessionState> {
+        firewall.sessions().first().map(|session| session.state)
+    }
+
+    #[test]
+    fn tracks_the_tcp_handshake() {
+        let mut firewall = ssh_firewall();
+        // Traffic that is not part of a connection is blocked.
+        assert!(!pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Tcp, "A")
+        ));
+        assert!(!pass(
+            &mut firewall,
+            packet(SERVER, CLIENT, Protocol::Tcp, "SA")
+        ));
+
+        assert!(pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Tcp, "S")
+        ));
+        assert_eq!(state(&firewall), Some(SessionState::SynSent));
+        // Nothing but the server's SYN+ACK gets through before the handshake goes on.
+        for (from, to, flags) in [
+            (CLIENT, SERVER, "A"),
+            (SERVER, CLIENT, "PA"),
+            (CLIENT, SERVER, "F"),
+        ] {
+            assert!(!pass(&mut firewall, packet(from, to, Protocol::Tcp, flags)));
+        }
+        assert!(pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Tcp, "S")
+        ));
+        assert_eq!(state(&firewall), Some(SessionState::SynSent));
+        assert!(pass(
+            &mut firewall,
+            packet(SERVER, CLIENT, Protocol::Tcp, "SA")
+        ));
+        assert_eq!(state(&firewall), Some(SessionState::SynReceived));
+        assert!(pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Tcp, "A")
+        ));
+        assert_eq!(state(&firewall), Some(SessionState::Established));
+        assert!(pass(
+            &mut firewall,
+            packet(SERVER, CLIENT, Protocol::Tcp, "PA")
+        ));
+
+        assert!(pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Tcp, "R")
+        ));
+        assert!(firewall.sessions().is_empty());
+        assert!(!pass(
+            &mut firewall,
+            packet(SERVER, CLIENT, Protocol::Tcp, "A")
+        ));
+    }
+
+    #[test]
+    fn new_flows_only_to_listed_services_and_sessions_time_out() {
+        let mut firewall = ssh_firewall();
+        let web = ("10.0.0.2", 80);
+        assert!(!pass(
+            &mut firewall,
+            packet(CLIENT, web, Protocol::Tcp, "S")
+        ));
+        assert!(!pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Udp, "")
+        ));
+
+        firewall.set_config(StatefulFirewallConfig {
+            udp_timeout_ms: 1000,
+            ..StatefulFirewallConfig::new(vec![FirewallService {
+                protocol: Protocol::Udp,
+                port: 22,
+            }])
+        });
+        assert!(pass(
+            &mut firewall,
+            packet(CLIENT, SERVER, Protocol::Udp, "")
+        ));
+        let reply = packet(SERVER, CLIENT, Protocol::Udp, "");
+        assert!(pass(&mut firewall, reply.clone()));
+
+        firewall.update(0.6);
+        assert!(firewall.expires_in(firewall.sessions()[0]) > 0.0);
+        firewall.update(0.6);
+        assert!(firewall.sessions().is_empty());
+        assert!(!pass(&mut firewall, reply));
+    }
+}
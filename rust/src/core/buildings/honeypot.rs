@@ -0,0 +1,264 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::{Packet, PacketLabel, Protocol};
+
+/// Longest payload signature kept, in characters.
+const SIGNATURE_LEN: usize = 32;
+
+/// What a honeypot has learned about one sender.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AttackerProfile {
+    pub source_ip: String,
+    pub packets: u64,
+    pub bytes: u64,
+    /// Packets labelled as attack traffic (`PacketLabel::Incorrect`).
+    pub attack_packets: u64,
+    /// Packets per destination port probed.
+    pub dest_ports: BTreeMap<u16, u64>,
+    pub dest_ips: BTreeSet<String>,
+    pub tcp: u64,
+    pub udp: u64,
+    pub other_protocols: u64,
+    /// Packets per payload signature (see [`payload_signature`]).
+    pub signatures: BTreeMap<String, u64>,
+    /// Capture timestamps of the first and last packet, in microseconds.
+    pub first_seen: i64,
+    pub last_seen: i64,
+}
+
+impl AttackerProfile {
+    fn new(source_ip: &str) -> Self {
+        Self {
+            source_ip: source_ip.to_string(),
+            first_seen: i64::MAX,
+            last_seen: i64::MIN,
+            ..Self::default()
+        }
+    }
+
+    fn record(&mut self, packet: &Packet) {
+        self.packets += 1;
+        self.bytes += packet.length as u64;
+        if packet.label == PacketLabel::Incorrect {
+            self.attack_packets += 1;
+        }
+        *self.dest_ports.entry(packet.dest_port).or_insert(0) += 1;
+        self.dest_ips.insert(packet.dest_ip.clone());
+        match packet.protocol {
+            Protocol::Tcp => self.tcp += 1,
+            Protocol::Udp => self.udp += 1,
+            Protocol::Unknown => self.other_protocols += 1,
+        }
+        if let Some(signature) = payload_signature(&packet.payload) {
+            *self.signatures.entry(signature).or_insert(0) += 1;
+        }
+        self.first_seen = self.first_seen.min(packet.timestamp);
+        self.last_seen = self.last_seen.max(packet.timestamp);
+    }
+
+    /// Fold in what another honeypot saw from the same sender.
+    pub fn merge(&mut self, other: &AttackerProfile) {
+        self.packets += other.packets;
+        self.bytes += other.bytes;
+        self.attack_packets += other.attack_packets;
+        for (port, count) in &other.dest_ports {
+            *self.dest_ports.entry(*port).or_insert(0) += count;
+        }
+        self.dest_ips.extend(other.dest_ips.iter().cloned());
+        self.tcp += other.tcp;
+        self.udp += other.udp;
+        self.other_protocols += other.other_protocols;
+        for (signature, count) in &other.signatures {
+            *self.signatures.entry(signature.clone()).or_insert(0) += count;
+        }
+        self.first_seen = self.first_seen.min(other.first_seen);
+        self.last_seen = self.last_seen.max(other.last_seen);
+    }
+}
+
+/// Readable fingerprint of a payload: its first non-empty line, with bytes outside printable
+/// ASCII shown as `.` and cut to `SIGNATURE_LEN` characters. `None` for empty payloads.
+pub fn payload_signature(payload: &[u8]) -> Option<String> {
+    let line = payload
+        .split(|&b| b == b'\n' || b == b'\r')
+        .find(|line| !line.is_empty())?;
+    Some(
+        line.iter()
+            .take(SIGNATURE_LEN)
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect(),
+    )
+}
+
+/// Sink for traffic diverted away from real services. It keeps the packets like a
+/// `RecycleBin` and builds a profile of every sender from them.
+pub struct Honeypot {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    packets: Vec<Packet>,
+    profiles: BTreeMap<String, AttackerProfile>,
+}
+
+impl Honeypot {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            packets: Vec::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+
+    /// Profiles of the senders seen so far, by source IP.
+    pub fn profiles(&self) -> &BTreeMap<String, AttackerProfile> {
+        &self.profiles
+    }
+
+    fn record(&mut self, packet: &Packet) {
+        self.profiles
+            .entry(packet.source_ip.clone())
+            .or_insert_with(|| AttackerProfile::new(&packet.source_ip))
+            .record(packet);
+    }
+}
+
+impl Building for Honeypot {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::Honeypot
+    }
+    fn update(&mut self, _delta: f32) {}
+    fn can_offload(&self) -> bool {
+        false
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        true
+    }
+    fn offload(&mut self) -> Packet {
+        panic!("Cannot offload from honeypot")
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        self.record(&packet);
+        self.packets.push(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.packets.clone()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        vec![]
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.packets.clone())
+    }
+    fn snapshot_state(&self) -> BuildingState {
+        BuildingState::Sink {
+            received: self.packets.len(),
+            rest: Box::new(BuildingState::empty()),
+        }
+    }
+    /// Profiles are rebuilt from the restored packets.
+    fn restore_state(&mut self, state: BuildingState) {
+        let packets = match state {
+            BuildingState::Packets(packets) => packets,
+            BuildingState::Sink { received, .. } => {
+                let mut packets = std::mem::take(&mut self.packets);
+                packets.truncate(received);
+                packets
+            }
+            _ => Vec::new(),
+        };
+        self.profiles.clear();
+        for packet in &packets {
+            self.record(packet);
+        }
+        self.packets = packets;
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(dest_port: u16, payload: &[u8]) -> Packet {
+        let mut packet = Packet::new(
+            "203.0.113.9".to_string(),
+            "10.0.0.2".to_string(),
+            40000,
+            dest_port,
+            Protocol::Tcp,
+            64,
+            payload.to_vec(),
+        );
+        packet.label = PacketLabel::Incorrect;
+        packet
+    }
+
+    #[test]
+    fn profiles_senders_and_rebuilds_them_on_restore() {
+        let mut honeypot = Honeypot::new(1, Vec2i { x: 0, y: 0 }, 0);
+        let from = Vec2i { x: 0, y: 1 };
+        honeypot.accept(probe(22, b"SSH-2.0-libssh_0.9\r\n"), from);
+        honeypot.accept(probe(80, b"GET /admin HTTP/1.1\r\nHost: x\r\n"), from);
+        honeypot.accept(probe(80, b"GET /admin HTTP/1.1\r\n"), from);
+        honeypot.accept(probe(23, b""), from);
+
+        let profile = &honeypot.profiles()["203.0.113.9"];
+        assert_eq!((profile.packets, profile.attack_packets), (4, 4));
+        assert_eq!(
+            profile.dest_ports.keys().copied().collect::<Vec<_>>(),
+            [22, 23, 80]
+        );
+        assert_eq!(profile.signatures["GET /admin HTTP/1.1"], 2);
+        assert_eq!(profile.signatures["SSH-2.0-libssh_0.9"], 1);
+
+        let mut restored = Honeypot::new(1, Vec2i { x: 0, y: 0 }, 0);
+        restored.restore_state(honeypot.save_state());
+        assert_eq!(restored.profiles(), honeypot.profiles());
+    }
+
+    #[test]
+    fn signatures_are_printable_and_short() {
+        assert_eq!(payload_signature(b""), None);
+        assert_eq!(
+            payload_signature(b"\r\n\x16\x03\x01hello"),
+            Some("...hello".to_string())
+        );
+        assert_eq!(
+            payload_signature(&[b'A'; 100]).unwrap().len(),
+            SIGNATURE_LEN
+        );
+    }
+}
@@ -1,7 +1,8 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
 use crate::core::dto::{BuildingId, Vec2i};
-use crate::core::packet::{Packet, PacketLabel, Protocol};
+use crate::core::packet::{Packet, PacketId};
 use crate::packet::Traffic;
+use crate::packet::traffic_source::{TrafficResourceSource, TrafficSource};
 use godot::prelude::*;
 
 pub struct Internet {
@@ -9,9 +10,10 @@ pub struct Internet {
     pos: Vec2i,
     rot: i32,
     packets: Vec<Packet>,
-    traffic: Option<Gd<Traffic>>,
+    source: Option<Box<dyn TrafficSource>>,
+    /// Number of packets pulled from `source`; the next packet gets this as its id index.
+    emitted: u64,
     time: f32,
-    next_packet_index: usize,
 }
 
 impl Internet {
@@ -21,16 +23,37 @@ impl Internet {
             pos,
             rot,
             packets: Vec::new(),
-            traffic: None,
+            source: None,
+            emitted: 0,
             time: 0.0,
-            next_packet_index: 0,
         }
     }
 
-    pub fn set_traffic(&mut self, traffic: Gd<crate::packet::Traffic>) {
-        self.traffic = Some(traffic);
+    pub fn set_traffic(&mut self, traffic: Gd<Traffic>) {
+        self.set_source(Box::new(TrafficResourceSource::new(traffic)));
+    }
+
+    /// Emit packets pulled from `source` as the simulation clock reaches their timestamps.
+    pub fn set_source(&mut self, source: Box<dyn TrafficSource>) {
+        self.source = Some(source);
+        self.emitted = 0;
         self.time = 0.0;
-        self.next_packet_index = 0;
+    }
+
+    /// `true` once the traffic source has emitted every packet and none are waiting here.
+    pub fn is_exhausted(&self) -> bool {
+        self.packets.is_empty()
+            && self
+                .source
+                .as_ref()
+                .is_none_or(|source| source.is_exhausted())
+    }
+
+    /// Whether `restore_state` can move the traffic source back (streamed captures cannot).
+    pub fn can_rewind(&self) -> bool {
+        self.source
+            .as_ref()
+            .is_none_or(|source| source.can_rewind())
     }
 
     #[cfg(test)]
@@ -49,79 +72,25 @@ impl Building for Internet {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::Internet
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 2, y: 2 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        let mut poses = Vec::new();
-        let size = self.get_size();
-        // Top and Bottom
-        for x_offset in 0..size.x {
-            poses.push(Vec2i {
-                x: self.pos.x + x_offset,
-                y: self.pos.y - 1,
-            });
-            poses.push(Vec2i {
-                x: self.pos.x + x_offset,
-                y: self.pos.y + size.y,
-            });
-        }
-        // Left and Right
-        for y_offset in 0..size.y {
-            poses.push(Vec2i {
-                x: self.pos.x - 1,
-                y: self.pos.y + y_offset,
-            });
-            poses.push(Vec2i {
-                x: self.pos.x + size.x,
-                y: self.pos.y + y_offset,
-            });
-        }
-        poses
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        Vec::new()
-    }
     fn update(&mut self, delta: f32) {
         self.time += delta;
-        if let Some(traffic) = &self.traffic {
-            let traffic = traffic.bind();
-            let traffic_packets = traffic.packets();
-
-            while self.next_packet_index < traffic_packets.len() {
-                if let Some(packet_resource) = traffic_packets.get(self.next_packet_index) {
-                    let packet_resource = packet_resource.bind();
-                    // Packet timestamps are stored as microseconds relative to the capture start.
-                    let packet_time = (packet_resource.get_timestamp() as f64) / 1_000_000.0;
-                    if (self.time as f64) >= packet_time {
-                        // This is a simplified conversion.
-                        // A more robust solution would handle different packet types.
-                        let protocol = match packet_resource.get_protocol() {
-                            6 => Protocol::Tcp,
-                            17 => Protocol::Udp,
-                            _ => Protocol::Unknown,
-                        };
-                        let mut new_packet = Packet::new(
-                            packet_resource.get_src_ip().to_string(),
-                            packet_resource.get_dst_ip().to_string(),
-                            packet_resource.get_src_port() as u16,
-                            packet_resource.get_dst_port() as u16,
-                            protocol,
-                            packet_resource.get_packet_size() as u32,
-                            vec![], // ペイロードは今のところ空
-                        );
-                        new_packet.label = PacketLabel::from_raw(packet_resource.get_label());
-                        self.packets.push(new_packet);
-                        self.next_packet_index += 1;
-                    } else {
-                        break; // Packets are sorted by time, so we can stop.
-                    }
-                } else {
-                    break;
-                }
+        if let Some(source) = self.source.as_mut() {
+            // Packet timestamps are stored as microseconds relative to the capture start.
+            let now_us = (self.time as f64 * 1_000_000.0) as i64;
+            while let Some(mut packet) = source.pop_due(now_us) {
+                // Sources emit in `Traffic` order, so this is the packet's index in its traffic.
+                packet.id = Some(PacketId {
+                    origin: self.id,
+                    index: self.emitted,
+                });
+                self.emitted += 1;
+                self.packets.push(packet);
             }
         }
     }
@@ -146,6 +115,30 @@ impl Building for Internet {
     fn get_packet_progresses(&self) -> Vec<f32> {
         vec![]
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Internet {
+            packets: self.packets.clone(),
+            emitted: self.emitted,
+            time: self.time,
+        }
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        let (packets, emitted, time) = match state {
+            BuildingState::Internet {
+                packets,
+                emitted,
+                time,
+            } => (packets, emitted, time),
+            _ => (Vec::new(), 0, 0.0),
+        };
+        // The source only needs to hand out what had not been pulled at `emitted`.
+        if let Some(source) = self.source.as_mut() {
+            source.rewind(emitted);
+        }
+        self.packets = packets;
+        self.emitted = emitted;
+        self.time = time;
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -1,4 +1,4 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::core::packet::Packet;
 
@@ -19,17 +19,6 @@ impl Junction {
         }
     }
 
-    fn neighbor_offsets(&self) -> [(i32, i32); 4] {
-        let rotation = self.rot.rem_euclid(4);
-        match rotation {
-            0 => [(0, -1), (1, 0), (0, 1), (-1, 0)],
-            1 => [(1, 0), (0, 1), (-1, 0), (0, -1)],
-            2 => [(0, 1), (-1, 0), (0, -1), (1, 0)],
-            3 => [(-1, 0), (0, -1), (1, 0), (0, 1)],
-            _ => [(0, -1), (1, 0), (0, 1), (-1, 0)],
-        }
-    }
-
     pub fn pending_output_pos(&self) -> Option<Vec2i> {
         self.buffer.as_ref().map(|(_, source_pos)| Vec2i {
             x: self.pos.x + (self.pos.x - source_pos.x),
@@ -48,24 +37,12 @@ impl Building for Junction {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::Junction
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 1, y: 1 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        self.neighbor_offsets()
-            .into_iter()
-            .map(|(dx, dy)| Vec2i {
-                x: self.pos.x + dx,
-                y: self.pos.y + dy,
-            })
-            .collect()
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        self.get_output_poses()
-    }
     fn update(&mut self, _delta: f32) {}
     fn can_offload(&self) -> bool {
         self.buffer.is_some()
@@ -89,6 +66,15 @@ impl Building for Junction {
     fn get_packet_progresses(&self) -> Vec<f32> {
         self.buffer.iter().map(|_| 0.0).collect()
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Junction(self.buffer.clone())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Junction(buffer) => buffer,
+            _ => None,
+        };
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -0,0 +1,261 @@
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::building_layout::{PortKind, layout_of};
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::Packet;
+use serde::{Deserialize, Serialize};
+
+/// Input ports of a merger, in the order `MergerConfig` refers to them.
+pub const MERGER_INPUTS: [&str; 3] = ["in_back", "in_left", "in_right"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MergerMode {
+    /// Take turns between the inputs that have a packet waiting.
+    #[default]
+    Fair,
+    /// Always forward from `input` first; the others go when it has nothing.
+    Priority { input: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct MergerConfig {
+    pub mode: MergerMode,
+}
+
+/// Joins its back, left and right inputs into the front output. Each input has its own
+/// slot, so feeders never block each other; the rule decides which slot leaves next.
+pub struct Merger {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    /// Packet waiting at each input, in `MERGER_INPUTS` order.
+    slots: [Option<Packet>; 3],
+    /// Input the fair interleave looks at first.
+    cursor: usize,
+    pub config: Option<MergerConfig>,
+}
+
+impl Merger {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            slots: Default::default(),
+            cursor: 0,
+            config: None,
+        }
+    }
+
+    pub fn new_with_config(id: BuildingId, pos: Vec2i, rot: i32, config: MergerConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..Self::new(id, pos, rot)
+        }
+    }
+
+    pub fn set_config(&mut self, config: MergerConfig) {
+        self.config = Some(config);
+    }
+
+    /// Input a packet from `source_pos` arrives at: the input whose port tile is closest,
+    /// since larger feeders report their top-left tile. Ports are matched to `MERGER_INPUTS`
+    /// by name, whatever their order in the definition.
+    fn input_index(&self, source_pos: Vec2i) -> usize {
+        let layout = layout_of(BuildingType::Merger);
+        layout
+            .ports
+            .iter()
+            .filter(|port| port.kind == PortKind::Input)
+            .filter_map(|port| {
+                let index = MERGER_INPUTS.iter().position(|name| *name == port.name)?;
+                let pos = layout.port_pos(port, self.pos, self.rot);
+                Some((
+                    index,
+                    (pos.x - source_pos.x).abs() + (pos.y - source_pos.y).abs(),
+                ))
+            })
+            .min_by_key(|&(_, distance)| distance)
+            .map_or(0, |(index, _)| index)
+    }
+
+    /// Inputs holding a packet, the one that leaves next first.
+    fn waiting(&self) -> impl Iterator<Item = usize> + '_ {
+        let first = self.next_input();
+        first
+            .into_iter()
+            .chain((0..self.slots.len()).filter(move |&index| Some(index) != first))
+            .filter(|&index| self.slots[index].is_some())
+    }
+
+    /// Input the next packet leaves from.
+    fn next_input(&self) -> Option<usize> {
+        let mode = self.config.clone().unwrap_or_default().mode;
+        if let MergerMode::Priority { input } = mode
+            && self.slots.get(input).is_some_and(Option::is_some)
+        {
+            return Some(input);
+        }
+        (0..self.slots.len())
+            .map(|step| (self.cursor + step) % self.slots.len())
+            .find(|&index| self.slots[index].is_some())
+    }
+}
+
+impl Building for Merger {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::Merger
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::Merger)
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::Merger(config) = config else {
+            return false;
+        };
+        self.set_config(config);
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+    }
+    fn update(&mut self, _delta: f32) {}
+    fn can_offload(&self) -> bool {
+        self.next_input().is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, source_pos: Vec2i) -> bool {
+        self.slots[self.input_index(source_pos)].is_none()
+    }
+    fn offload(&mut self) -> Packet {
+        let index = self.next_input().expect("Offload called without packet");
+        self.cursor = (index + 1) % self.slots.len();
+        self.slots[index].take().expect("next input holds a packet")
+    }
+    fn accept(&mut self, packet: Packet, source_pos: Vec2i) -> BuildingAction {
+        let index = self.input_index(source_pos);
+        self.slots[index] = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    /// Waiting packets, the one that leaves next first.
+    fn get_packets(&self) -> Vec<Packet> {
+        self.waiting()
+            .map(|index| self.slots[index].clone().expect("waiting slot"))
+            .collect()
+    }
+    /// Same order as `get_packets`.
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.waiting().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Merger {
+            slots: self.slots.to_vec(),
+            cursor: self.cursor,
+        }
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.slots = Default::default();
+        self.cursor = 0;
+        if let BuildingState::Merger { slots, cursor } = state {
+            for (slot, packet) in self.slots.iter_mut().zip(slots) {
+                *slot = packet;
+            }
+            self.cursor = cursor;
+        }
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::packet::Protocol;
+
+    #[test]
+    fn inputs_are_found_by_port_name() {
+        let layout = layout_of(BuildingType::Merger);
+        for rot in 0..4 {
+            let merger = Merger::new(1, Vec2i { x: 5, y: 5 }, rot);
+            for (index, name) in MERGER_INPUTS.iter().enumerate() {
+                let port = layout.port(name).expect("merger input port");
+                let source_pos = layout.port_pos(port, merger.pos, rot);
+                assert_eq!(merger.input_index(source_pos), index, "{} at {}", name, rot);
+            }
+        }
+    }
+
+    fn packet(port: u16) -> Packet {
+        Packet::new(
+            "10.0.0.1".to_string(),
+            "10.0.0.2".to_string(),
+            port,
+            80,
+            Protocol::Tcp,
+            64,
+            vec![],
+        )
+    }
+
+    /// Merger at the origin facing east: back is west, left south, right north.
+    const BACK: Vec2i = Vec2i { x: -1, y: 0 };
+    const LEFT: Vec2i = Vec2i { x: 0, y: 1 };
+    const RIGHT: Vec2i = Vec2i { x: 0, y: -1 };
+
+    /// Fill every input, take one packet, refill the back input and take the rest.
+    fn order_with_back_refilled(merger: &mut Merger) -> Vec<u16> {
+        for (port, pos) in [(1, BACK), (2, LEFT), (3, RIGHT)] {
+            assert!(merger.can_accept(&packet(port), pos));
+            merger.accept(packet(port), pos);
+        }
+        assert!(!merger.can_accept(&packet(9), LEFT));
+
+        let mut order = vec![merger.offload().source_port];
+        merger.accept(packet(4), BACK);
+        while merger.can_offload() {
+            assert_eq!(
+                merger.get_packet_progresses().len(),
+                merger.get_packets().len()
+            );
+            let next = merger.get_packets()[0].source_port;
+            let packet = merger.offload();
+            assert_eq!(packet.source_port, next);
+            order.push(packet.source_port);
+        }
+        order
+    }
+
+    #[test]
+    fn fair_mode_interleaves_inputs() {
+        let mut merger = Merger::new(1, Vec2i { x: 0, y: 0 }, 0);
+        assert_eq!(order_with_back_refilled(&mut merger), vec![1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn priority_mode_prefers_its_input() {
+        let config = MergerConfig {
+            mode: MergerMode::Priority { input: 0 },
+        };
+        let mut merger = Merger::new_with_config(1, Vec2i { x: 0, y: 0 }, 0, config);
+        assert_eq!(order_with_back_refilled(&mut merger), vec![1, 4, 2, 3]);
+    }
+}
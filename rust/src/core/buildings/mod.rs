@@ -1,6 +1,12 @@
+pub mod analyzer;
 pub mod conveyor;
 pub mod datacenter;
 pub mod filters;
+pub mod honeypot;
 pub mod internet;
 pub mod junction;
+pub mod merger;
 pub mod recycle_bin;
+pub mod splitter;
+pub mod tap;
+pub mod tunnel;
@@ -1,4 +1,4 @@
-use crate::core::building::{Building, BuildingAction, BuildingType};
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::core::packet::Packet;
 
@@ -30,35 +30,12 @@ impl Building for RecycleBin {
     fn rotation(&self) -> i32 {
         self.rot
     }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
     fn building_type(&self) -> BuildingType {
         BuildingType::RecycleBin
     }
-    fn get_size(&self) -> Vec2i {
-        Vec2i { x: 1, y: 1 }
-    }
-    fn get_output_poses(&self) -> Vec<Vec2i> {
-        vec![]
-    }
-    fn get_input_poses(&self) -> Vec<Vec2i> {
-        vec![
-            Vec2i {
-                x: self.pos.x,
-                y: self.pos.y - 1,
-            },
-            Vec2i {
-                x: self.pos.x + 1,
-                y: self.pos.y,
-            },
-            Vec2i {
-                x: self.pos.x,
-                y: self.pos.y + 1,
-            },
-            Vec2i {
-                x: self.pos.x - 1,
-                y: self.pos.y,
-            },
-        ]
-    }
     fn update(&mut self, _delta: f32) {}
     fn can_offload(&self) -> bool {
         false
@@ -82,6 +59,26 @@ impl Building for RecycleBin {
     fn get_packet_progresses(&self) -> Vec<f32> {
         vec![]
     }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.packets.clone())
+    }
+    fn snapshot_state(&self) -> BuildingState {
+        BuildingState::Sink {
+            received: self.packets.len(),
+            rest: Box::new(BuildingState::empty()),
+        }
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.packets = match state {
+            BuildingState::Packets(packets) => packets,
+            BuildingState::Sink { received, .. } => {
+                let mut packets = std::mem::take(&mut self.packets);
+                packets.truncate(received);
+                packets
+            }
+            _ => Vec::new(),
+        };
+    }
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -0,0 +1,265 @@
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::Packet;
+use serde::{Deserialize, Serialize};
+
+/// Output ports of a splitter, in the order `SplitterConfig` refers to them.
+pub const SPLITTER_OUTPUTS: [&str; 3] = ["out_front", "out_left", "out_right"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SplitterMode {
+    /// Share packets between the outputs by their weights.
+    #[default]
+    Weighted,
+    /// Send to `output` whenever it accepts; the others share the rest by weight.
+    Priority { output: usize },
+    /// Keep `output` for overflow: it only gets packets the others cannot take.
+    Overflow { output: usize },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitterConfig {
+    /// Share of each output in `SPLITTER_OUTPUTS` order. An output weighted `0` only gets
+    /// packets as the priority or overflow output.
+    pub weights: [u32; 3],
+    #[serde(default)]
+    pub mode: SplitterMode,
+}
+
+impl Default for SplitterConfig {
+    fn default() -> Self {
+        Self {
+            weights: [1; 3],
+            mode: SplitterMode::Weighted,
+        }
+    }
+}
+
+impl SplitterConfig {
+    /// Smooth weighted round-robin over the weighted outputs allowed by `eligible`: each
+    /// gains its weight in `credit`, and the one with the most pays the total back and gets
+    /// the packet. Heavy outputs thus do not get long runs.
+    fn weighted(&self, credit: &mut [i64; 3], eligible: impl Fn(usize) -> bool) -> Option<usize> {
+        let candidates: Vec<usize> = (0..3)
+            .filter(|&i| self.weights[i] > 0 && eligible(i))
+            .collect();
+        let total: i64 = candidates.iter().map(|&i| self.weights[i] as i64).sum();
+        for &i in &candidates {
+            credit[i] += self.weights[i] as i64;
+        }
+        // The first output wins a tie.
+        let best = candidates.into_iter().rev().max_by_key(|&i| credit[i])?;
+        credit[best] -= total;
+        Some(best)
+    }
+
+    /// Output for the next packet given which outputs can take it now, moving the routing
+    /// `credit` on. `None` means the packet waits.
+    pub fn choose_output(&self, credit: &mut [i64; 3], accepting: [bool; 3]) -> Option<usize> {
+        match self.mode {
+            SplitterMode::Weighted => self.weighted(credit, |i| accepting[i]),
+            SplitterMode::Priority { output } => {
+                if accepting.get(output).copied().unwrap_or(false) {
+                    Some(output)
+                } else {
+                    self.weighted(credit, |i| i != output && accepting[i])
+                }
+            }
+            SplitterMode::Overflow { output } => self
+                .weighted(credit, |i| i != output && accepting[i])
+                .or_else(|| {
+                    accepting
+                        .get(output)
+                        .copied()
+                        .unwrap_or(false)
+                        .then_some(output)
+                }),
+        }
+    }
+}
+
+/// Sends packets from its back to the front, left and right outputs by its rule. Without
+/// a rule it alternates evenly between them.
+pub struct Splitter {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    buffer: Option<Packet>,
+    /// Weighted round-robin credit of each output (see [`SplitterConfig::choose_output`]).
+    credit: [i64; 3],
+    pub config: Option<SplitterConfig>,
+}
+
+impl Splitter {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+            credit: [0; 3],
+            config: None,
+        }
+    }
+
+    pub fn new_with_config(id: BuildingId, pos: Vec2i, rot: i32, config: SplitterConfig) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+            credit: [0; 3],
+            config: Some(config),
+        }
+    }
+
+    pub fn set_config(&mut self, config: SplitterConfig) {
+        self.config = Some(config);
+    }
+
+    /// See [`SplitterConfig::choose_output`].
+    pub fn choose_output(&mut self, accepting: [bool; 3]) -> Option<usize> {
+        match &self.config {
+            Some(config) => config.choose_output(&mut self.credit, accepting),
+            None => SplitterConfig::default().choose_output(&mut self.credit, accepting),
+        }
+    }
+}
+
+impl Building for Splitter {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::Splitter
+    }
+    fn filter_config(&self) -> Option<BuildingConfig> {
+        self.config.clone().map(BuildingConfig::Splitter)
+    }
+    fn set_filter_config(&mut self, config: BuildingConfig) -> bool {
+        let BuildingConfig::Splitter(config) = config else {
+            return false;
+        };
+        self.set_config(config);
+        true
+    }
+    fn clear_filter_config(&mut self) {
+        self.config = None;
+    }
+    fn update(&mut self, _delta: f32) {}
+    fn can_offload(&self) -> bool {
+        self.buffer.is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.buffer.is_none()
+    }
+    fn offload(&mut self) -> Packet {
+        self.buffer.take().expect("Offload called without packet")
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        self.buffer = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.buffer.iter().cloned().collect()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.buffer.iter().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Splitter {
+            packet: self.buffer.clone(),
+            credit: self.credit,
+        }
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        (self.buffer, self.credit) = match state {
+            BuildingState::Splitter { packet, credit } => (packet, credit),
+            BuildingState::Packets(packets) => (packets.into_iter().next(), [0; 3]),
+            _ => (None, [0; 3]),
+        };
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn picks(config: &SplitterConfig, accepting: [bool; 3], picks: usize) -> Vec<usize> {
+        let mut credit = [0; 3];
+        (0..picks)
+            .map(|_| config.choose_output(&mut credit, accepting).unwrap())
+            .collect()
+    }
+
+    fn run(config: &SplitterConfig, accepting: [bool; 3], picks: usize) -> [usize; 3] {
+        let mut counts = [0; 3];
+        for output in self::picks(config, accepting, picks) {
+            counts[output] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn weighted_mode_follows_ratios() {
+        let config = SplitterConfig {
+            weights: [2, 1, 0],
+            mode: SplitterMode::Weighted,
+        };
+        assert_eq!(picks(&config, [true; 3], 6), vec![0, 1, 0, 0, 1, 0]);
+        assert_eq!(run(&config, [true; 3], 30), [20, 10, 0]);
+        // A blocked output's share goes to the others.
+        assert_eq!(run(&config, [false, true, true], 5), [0, 5, 0]);
+        assert_eq!(
+            config.choose_output(&mut [0; 3], [false, false, true]),
+            None
+        );
+    }
+
+    #[test]
+    fn large_weights_keep_their_ratio() {
+        let config = SplitterConfig {
+            weights: [1_000_000, 3_000_000, 0],
+            mode: SplitterMode::Weighted,
+        };
+        assert_eq!(picks(&config, [true; 3], 4), vec![1, 0, 1, 1]);
+        assert_eq!(run(&config, [true; 3], 400), [100, 300, 0]);
+    }
+
+    #[test]
+    fn priority_and_overflow_modes() {
+        let priority = SplitterConfig {
+            weights: [1, 1, 1],
+            mode: SplitterMode::Priority { output: 2 },
+        };
+        assert_eq!(run(&priority, [true; 3], 6), [0, 0, 6]);
+        assert_eq!(run(&priority, [true, true, false], 6), [3, 3, 0]);
+
+        let overflow = SplitterConfig {
+            weights: [1, 0, 0],
+            mode: SplitterMode::Overflow { output: 1 },
+        };
+        assert_eq!(run(&overflow, [true; 3], 4), [4, 0, 0]);
+        assert_eq!(run(&overflow, [false, true, true], 4), [0, 4, 0]);
+    }
+}
@@ -0,0 +1,80 @@
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::Packet;
+
+/// Passes packets straight through its front output and sends a copy of each one to its
+/// mirror output. The copy is made when the original leaves; if the mirror side has no
+/// room, the copy is skipped so the main path never waits for it.
+pub struct Tap {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    buffer: Option<Packet>,
+}
+
+impl Tap {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+        }
+    }
+}
+
+impl Building for Tap {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::Tap
+    }
+    fn update(&mut self, _delta: f32) {}
+    fn can_offload(&self) -> bool {
+        self.buffer.is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.buffer.is_none()
+    }
+    fn offload(&mut self) -> Packet {
+        self.buffer.take().expect("Offload called without packet")
+    }
+    fn accept(&mut self, packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        self.buffer = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.buffer.iter().cloned().collect()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.buffer.iter().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.get_packets())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Packets(packets) => packets.into_iter().next(),
+            _ => None,
+        };
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
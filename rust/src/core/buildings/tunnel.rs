@@ -0,0 +1,269 @@
+use crate::core::building::{Building, BuildingAction, BuildingState, BuildingType};
+use crate::core::building_registry::registry;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::Packet;
+use std::collections::VecDeque;
+
+/// 建物定義に射程がない場合にトンネルが届く距離 (タイル)
+const DEFAULT_TUNNEL_RANGE: u32 = 4;
+/// 建物定義に速度がない場合の地下での速度 (タイル/秒)
+const DEFAULT_TUNNEL_SPEED: f32 = 2.0;
+/// 建物定義に容量がない場合に同時に地下を通れるパケット数
+const DEFAULT_TUNNEL_CAPACITY: u32 = 4;
+
+/// One tile towards the front of a building turned by `rot`.
+pub fn facing_step(rot: i32) -> Vec2i {
+    match rot.rem_euclid(4) {
+        0 => Vec2i { x: 1, y: 0 },
+        1 => Vec2i { x: 0, y: 1 },
+        2 => Vec2i { x: -1, y: 0 },
+        _ => Vec2i { x: 0, y: -1 },
+    }
+}
+
+/// Farthest distance, in tiles, at which a tunnel entrance finds its exit.
+pub fn tunnel_range() -> i32 {
+    registry()
+        .by_type(BuildingType::TunnelEntrance)
+        .range
+        .unwrap_or(DEFAULT_TUNNEL_RANGE) as i32
+}
+
+/// Takes packets from behind and sends them underground to the nearest `TunnelExit` ahead
+/// that faces the same way. The pairing is an edge of the connection graph.
+pub struct TunnelEntrance {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    buffer: Option<Packet>,
+}
+
+impl TunnelEntrance {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        Self {
+            id,
+            pos,
+            rot,
+            buffer: None,
+        }
+    }
+}
+
+impl Building for TunnelEntrance {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::TunnelEntrance
+    }
+    fn update(&mut self, _delta: f32) {}
+    fn can_offload(&self) -> bool {
+        self.buffer.is_some()
+    }
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.buffer.is_none()
+    }
+    fn offload(&mut self) -> Packet {
+        self.buffer.take().expect("Offload called without packet")
+    }
+    fn accept(&mut self, mut packet: Packet, _source_pos: Vec2i) -> BuildingAction {
+        packet.progress = 0.0;
+        self.buffer = Some(packet);
+        BuildingAction::None
+    }
+    fn get_progress(&self) -> f32 {
+        0.0
+    }
+    fn get_packets(&self) -> Vec<Packet> {
+        self.buffer.iter().cloned().collect()
+    }
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.buffer.iter().map(|_| 0.0).collect()
+    }
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Packets(self.get_packets())
+    }
+    fn restore_state(&mut self, state: BuildingState) {
+        self.buffer = match state {
+            BuildingState::Packets(packets) => packets.into_iter().next(),
+            _ => None,
+        };
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+/// Receives packets from tunnel entrances behind it. A packet spends `distance / speed`
+/// seconds underground before it can leave through the front; packets never overtake.
+pub struct TunnelExit {
+    id: BuildingId,
+    pos: Vec2i,
+    rot: i32,
+    /// タイル/秒
+    speed: f32,
+    capacity: usize,
+    /// Packets underground, front first, with the entrance each one came from.
+    in_flight: VecDeque<(Packet, Vec2i)>,
+}
+
+impl TunnelExit {
+    pub fn new(id: BuildingId, pos: Vec2i, rot: i32) -> Self {
+        let def = registry().by_type(BuildingType::TunnelExit);
+        Self {
+            id,
+            pos,
+            rot,
+            speed: def.speed.unwrap_or(DEFAULT_TUNNEL_SPEED),
+            capacity: def.capacity.unwrap_or(DEFAULT_TUNNEL_CAPACITY).max(1) as usize,
+            in_flight: VecDeque::new(),
+        }
+    }
+
+    /// Packets underground with the entrance they came from, front packet first.
+    pub fn in_flight(&self) -> impl Iterator<Item = &(Packet, Vec2i)> {
+        self.in_flight.iter()
+    }
+
+    fn distance_from(&self, entrance_pos: Vec2i) -> f32 {
+        let distance = (self.pos.x - entrance_pos.x).abs() + (self.pos.y - entrance_pos.y).abs();
+        distance.max(1) as f32
+    }
+}
+
+impl Building for TunnelExit {
+    fn id(&self) -> BuildingId {
+        self.id
+    }
+    fn position(&self) -> Vec2i {
+        self.pos
+    }
+    fn rotation(&self) -> i32 {
+        self.rot
+    }
+    fn set_rotation(&mut self, rot: i32) {
+        self.rot = rot;
+    }
+    fn building_type(&self) -> BuildingType {
+        BuildingType::TunnelExit
+    }
+
+    fn update(&mut self, delta: f32) {
+        // Progress is the fraction of the tunnel covered, so longer tunnels take longer.
+        let mut limit = 1.0;
+        for index in 0..self.in_flight.len() {
+            let distance = self.distance_from(self.in_flight[index].1);
+            let packet = &mut self.in_flight[index].0;
+            packet.progress = (packet.progress + self.speed * delta / distance).min(limit);
+            limit = packet.progress;
+        }
+    }
+
+    fn can_offload(&self) -> bool {
+        matches!(self.in_flight.front(), Some((p, _)) if p.progress >= 1.0)
+    }
+
+    fn can_accept(&self, _packet: &Packet, _source_pos: Vec2i) -> bool {
+        self.in_flight.len() < self.capacity
+    }
+
+    fn offload(&mut self) -> Packet {
+        self.in_flight
+            .pop_front()
+            .expect("Offload called without packet")
+            .0
+    }
+
+    fn accept(&mut self, mut packet: Packet, source_pos: Vec2i) -> BuildingAction {
+        packet.progress = 0.0;
+        self.in_flight.push_back((packet, source_pos));
+        BuildingAction::None
+    }
+
+    fn get_progress(&self) -> f32 {
+        self.in_flight.front().map_or(0.0, |(p, _)| p.progress)
+    }
+
+    fn get_packets(&self) -> Vec<Packet> {
+        self.in_flight.iter().map(|(p, _)| p.clone()).collect()
+    }
+
+    fn get_packet_progresses(&self) -> Vec<f32> {
+        self.in_flight.iter().map(|(p, _)| p.progress).collect()
+    }
+
+    fn save_state(&self) -> BuildingState {
+        BuildingState::Tunnel(self.in_flight.iter().cloned().collect())
+    }
+
+    fn restore_state(&mut self, state: BuildingState) {
+        self.in_flight = match state {
+            BuildingState::Tunnel(in_flight) => in_flight.into(),
+            _ => VecDeque::new(),
+        };
+    }
+
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+    fn as_any_mut(&mut self) -> &mut dyn std::any::Any {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::packet::Protocol;
+
+    fn create_test_packet() -> Packet {
+        Packet::new(
+            "10.0.0.1".to_string(),
+            "10.0.0.2".to_string(),
+            1000,
+            80,
+            Protocol::Tcp,
+            64,
+            vec![],
+        )
+    }
+
+    #[test]
+    fn transit_time_grows_with_distance() {
+        let mut near = TunnelExit::new(1, Vec2i { x: 2, y: 0 }, 0);
+        let mut far = TunnelExit::new(2, Vec2i { x: 4, y: 0 }, 0);
+        near.accept(create_test_packet(), Vec2i { x: 0, y: 0 });
+        far.accept(create_test_packet(), Vec2i { x: 0, y: 0 });
+
+        let step = 2.0 / near.speed;
+        near.update(step);
+        far.update(step);
+        assert!(near.can_offload());
+        assert!(!far.can_offload());
+        far.update(step);
+        assert!(far.can_offload());
+    }
+
+    #[test]
+    fn packets_do_not_overtake_underground() {
+        let mut exit = TunnelExit::new(1, Vec2i { x: 4, y: 0 }, 0);
+        exit.accept(create_test_packet(), Vec2i { x: 0, y: 0 });
+        exit.accept(create_test_packet(), Vec2i { x: 3, y: 0 });
+        exit.update(1.0 / exit.speed);
+
+        let progresses = exit.get_packet_progresses();
+        assert!(progresses[1] <= progresses[0]);
+    }
+}
@@ -1,8 +1,9 @@
 use crate::core::building::BuildingType;
 use crate::core::packet::Packet;
 use godot::prelude::Vector2i;
+use serde::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
 pub struct Vec2i {
     pub x: i32,
     pub y: i32,
@@ -22,6 +23,50 @@ impl From<Vec2i> for Vector2i {
 
 pub type BuildingId = u64;
 
+/// Why `World::update` discarded a packet instead of moving it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DropReason {
+    /// The building has no connected output at all.
+    NoOutputs,
+    /// Outputs accepted the packet, but none of them could be selected for its route.
+    RoutingFailed,
+    /// A datacenter refused it: it does not host the service the packet is addressed to.
+    ClosedService,
+    /// A datacenter's queue was full when it arrived, or the conveyor holding it was replaced
+    /// by a tier with fewer slots.
+    Overloaded,
+    /// The building holding it was replaced by one that cannot take its packets over.
+    Replaced,
+    /// An original packet reached an analyzer, which only takes the copies taps make.
+    Misrouted,
+}
+
+impl DropReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DropReason::NoOutputs => "no_outputs",
+            DropReason::RoutingFailed => "routing_failed",
+            DropReason::ClosedService => "closed_service",
+            DropReason::Overloaded => "overloaded",
+            DropReason::Replaced => "replaced",
+            DropReason::Misrouted => "misrouted",
+        }
+    }
+}
+
+/// How an entry of a named blocklist changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlocklistChange {
+    /// Added, or given a later expiry; `by` is the detector that added it, `None` for the
+    /// player. `ttl_ms` is `None` for entries that do not expire.
+    Added {
+        by: Option<BuildingId>,
+        ttl_ms: Option<u32>,
+    },
+    Removed,
+    Expired,
+}
+
 #[derive(Debug, Clone)]
 pub enum WorldEvent {
     BuildingPlaced {
@@ -34,6 +79,13 @@ pub enum WorldEvent {
         id: BuildingId,
         pos: Vec2i,
     },
+    /// A building was rotated or changed type in place; its id stays the same.
+    BuildingUpdated {
+        id: BuildingId,
+        pos: Vec2i,
+        building_type: BuildingType,
+        rotation: i32,
+    },
     BuildingProgressUpdated {
         id: BuildingId,
         progress: f32,
@@ -44,4 +96,14 @@ pub enum WorldEvent {
         to_id: BuildingId,
         progress_start: f32,
     },
+    PacketDropped {
+        packet: Packet,
+        building_id: BuildingId,
+        reason: DropReason,
+    },
+    BlocklistChanged {
+        list: String,
+        ip: String,
+        change: BlocklistChange,
+    },
 }
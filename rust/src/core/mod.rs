@@ -1,5 +1,7 @@
 pub mod building;
-pub mod building_defs;
+pub mod building_config;
+pub mod building_layout;
+pub mod building_registry;
 pub mod buildings;
 pub mod dto;
 pub mod filters;
@@ -1,4 +1,6 @@
+use crate::core::dto::BuildingId;
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default, Hash)]
 pub enum PacketLabel {
@@ -33,8 +35,81 @@ pub enum Protocol {
     Unknown,
 }
 
+impl Protocol {
+    /// Map an IP protocol number (`6` for TCP, `17` for UDP) to a `Protocol`.
+    pub fn from_ip_number(number: i64) -> Self {
+        match number {
+            6 => Protocol::Tcp,
+            17 => Protocol::Udp,
+            _ => Protocol::Unknown,
+        }
+    }
+}
+
+/// TCP header flags, as the low byte of the header's flag field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Hash, Serialize, Deserialize)]
+pub struct TcpFlags(pub u8);
+
+impl TcpFlags {
+    pub const FIN: TcpFlags = TcpFlags(0x01);
+    pub const SYN: TcpFlags = TcpFlags(0x02);
+    pub const RST: TcpFlags = TcpFlags(0x04);
+    pub const PSH: TcpFlags = TcpFlags(0x08);
+    pub const ACK: TcpFlags = TcpFlags(0x10);
+    pub const URG: TcpFlags = TcpFlags(0x20);
+
+    const LETTERS: [(char, TcpFlags); 6] = [
+        ('F', Self::FIN),
+        ('S', Self::SYN),
+        ('R', Self::RST),
+        ('P', Self::PSH),
+        ('A', Self::ACK),
+        ('U', Self::URG),
+    ];
+
+    /// Parse flags written as letters the way tcpdump prints them, e.g. `"SA"` for SYN+ACK.
+    /// `"."` alone means no flag set.
+    pub fn from_letters(text: &str) -> Option<Self> {
+        let mut flags = TcpFlags::default();
+        for letter in text.chars().filter(|&c| c != '.') {
+            let (_, flag) = Self::LETTERS
+                .iter()
+                .find(|(l, _)| l.eq_ignore_ascii_case(&letter))?;
+            flags.0 |= flag.0;
+        }
+        Some(flags)
+    }
+
+    pub fn to_letters(self) -> String {
+        let letters: String = Self::LETTERS
+            .iter()
+            .filter(|(_, flag)| self.contains(*flag))
+            .map(|(letter, _)| *letter)
+            .collect();
+        if letters.is_empty() {
+            ".".to_string()
+        } else {
+            letters
+        }
+    }
+
+    pub fn contains(self, other: TcpFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+/// Stable identity of a packet spawned by an `Internet`: the spawning building and the
+/// packet's index in that building's traffic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PacketId {
+    pub origin: BuildingId,
+    pub index: u64,
+}
+
 #[derive(Clone, Debug)]
 pub struct Packet {
+    /// Set when an `Internet` spawns the packet; `None` for packets created elsewhere.
+    pub id: Option<PacketId>,
     pub source_ip: String,
     pub dest_ip: String,
     pub source_port: u16,
@@ -44,6 +119,20 @@ pub struct Packet {
     pub payload: Vec<u8>,
     pub progress: f32,
     pub label: PacketLabel,
+    /// Microseconds since the capture start, as stored in the source `Traffic`.
+    pub timestamp: i64,
+    /// Reassembled byte stream of the TCP flow this segment belongs to, when the traffic was
+    /// loaded with reassembly enabled. Shared between all segments of the flow.
+    pub stream: Option<Arc<[u8]>>,
+    /// TCP header flags when the traffic recorded them (pcap captures, or JSON entries with
+    /// `tcp_flags`); `None` otherwise and for other protocols.
+    pub tcp_flags: Option<TcpFlags>,
+    /// Set on the copy a `Tap` sends to its mirror output. Copies are never scored or
+    /// counted towards stage completion, so only the original is.
+    pub mirrored_by: Option<BuildingId>,
+    /// Free-form tag from the traffic file, used by stages to split one traffic file between
+    /// several `Internet` buildings.
+    pub tag: Option<String>,
 }
 
 impl Packet {
@@ -62,6 +151,7 @@ impl Packet {
         payload: Vec<u8>,
     ) -> Self {
         Self {
+            id: None,
             source_ip,
             dest_ip,
             source_port,
@@ -71,8 +161,27 @@ impl Packet {
             payload,
             progress: 0.0,
             label: PacketLabel::default(),
+            timestamp: 0,
+            stream: None,
+            tcp_flags: None,
+            mirrored_by: None,
+            tag: None,
+        }
+    }
+
+    /// Copy of this packet made by tap `tap_id`. The copy has no `id`, so its hops are not
+    /// added to the original's trace.
+    pub fn mirror_copy(&self, tap_id: BuildingId) -> Self {
+        Self {
+            id: None,
+            mirrored_by: Some(tap_id),
+            ..self.clone()
         }
     }
+
+    pub fn is_mirror_copy(&self) -> bool {
+        self.mirrored_by.is_some()
+    }
 }
 
 /// Encode raw bytes into an ASCII string, escaping non-printable bytes as `\xHH`.
@@ -129,4 +238,17 @@ mod tests {
         );
         assert_eq!(packet.payload_to_string(), "A\\0\\n");
     }
+
+    #[test]
+    fn mirror_copy_leaves_the_id_to_the_original() {
+        use super::{Packet, PacketId, Protocol};
+
+        let mut packet = Packet::new("s".into(), "d".into(), 1, 2, Protocol::Tcp, 0, vec![]);
+        packet.id = Some(PacketId {
+            origin: 3,
+            index: 4,
+        });
+        let copy = packet.mirror_copy(7);
+        assert_eq!((copy.id, copy.mirrored_by), (None, Some(7)));
+    }
 }
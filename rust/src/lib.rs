@@ -1,3 +1,4 @@
+use godot::classes::FileAccess;
 use godot::prelude::*;
 
 pub mod core;
@@ -7,8 +8,29 @@ pub mod packet;
 
 struct RustExtension;
 
+/// Building definitions GDScript reads; the simulation loads the same file at startup.
+const BUILDINGS_PATH: &str = "res://assets/buildings.json";
+
 #[gdextension]
-unsafe impl ExtensionLibrary for RustExtension {}
+unsafe impl ExtensionLibrary for RustExtension {
+    fn on_level_init(level: InitLevel) {
+        if level != InitLevel::Scene {
+            return;
+        }
+        let text = if FileAccess::file_exists(BUILDINGS_PATH) {
+            FileAccess::get_file_as_string(BUILDINGS_PATH).to_string()
+        } else {
+            String::new()
+        };
+        if let Err(err) = core::building_registry::load_registry(&text) {
+            godot_warn!(
+                "Using the built-in building definitions, {} could not be loaded: {}",
+                BUILDINGS_PATH,
+                err
+            );
+        }
+    }
+}
 
 #[cfg(test)]
 pub mod tests;
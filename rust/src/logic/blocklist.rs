@@ -0,0 +1,135 @@
+//! Named IP blocklists kept by `World`.
+//!
+//! Detectors add the sources they flag, IP filters that reference a list by name match the
+//! addresses on it, and the player can edit entries from Godot. An entry either stays until
+//! it is removed or expires after a TTL of simulated time.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+/// One address on a blocklist.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BlocklistEntry {
+    /// Simulated time the entry expires at; `None` when it does not expire.
+    pub expires_at: Option<f64>,
+}
+
+/// Blocklists by name, each mapping an IP to its entry. Lists with no entries are not kept.
+#[derive(Debug, Clone, Default)]
+pub struct Blocklists {
+    clock: f64,
+    lists: BTreeMap<String, BTreeMap<String, BlocklistEntry>>,
+}
+
+impl Blocklists {
+    pub fn contains(&self, list: &str, ip: &str) -> bool {
+        self.lists
+            .get(list)
+            .is_some_and(|entries| entries.contains_key(ip))
+    }
+
+    /// Put `ip` on `list` for `ttl` seconds, or for good when `ttl` is `None`, replacing
+    /// any existing entry. Returns whether anything changed.
+    pub fn set(&mut self, list: &str, ip: &str, ttl: Option<f64>) -> bool {
+        let entry = BlocklistEntry {
+            expires_at: ttl.map(|ttl| self.clock + ttl),
+        };
+        self.put(list, ip, Some(entry))
+    }
+
+    /// Put `ip` on `list` for a detector. An existing entry only ever gets a later expiry.
+    pub fn extend(&mut self, list: &str, ip: &str, ttl: Option<f64>) -> bool {
+        let expires_at = ttl.map(|ttl| self.clock + ttl);
+        let entries = self.lists.entry(list.to_string()).or_default();
+        let Some(current) = entries.get_mut(ip) else {
+            entries.insert(ip.to_string(), BlocklistEntry { expires_at });
+            return true;
+        };
+        match (current.expires_at, expires_at) {
+            (None, _) => false,
+            (Some(_), None) => {
+                current.expires_at = None;
+                true
+            }
+            (Some(old), Some(new)) if new > old => {
+                current.expires_at = Some(new);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Entry of `ip` on `list`, if it is on it.
+    pub fn entry(&self, list: &str, ip: &str) -> Option<BlocklistEntry> {
+        self.lists.get(list)?.get(ip).copied()
+    }
+
+    /// Make the entry of `ip` on `list` exactly `entry`, taking it off when `None`. Returns
+    /// whether anything changed.
+    pub fn put(&mut self, list: &str, ip: &str, entry: Option<BlocklistEntry>) -> bool {
+        let Some(entry) = entry else {
+            return self.remove(list, ip);
+        };
+        let entries = self.lists.entry(list.to_string()).or_default();
+        entries.insert(ip.to_string(), entry) != Some(entry)
+    }
+
+    /// Remove `ip` from `list`. Returns whether it was on it.
+    pub fn remove(&mut self, list: &str, ip: &str) -> bool {
+        let Some(entries) = self.lists.get_mut(list) else {
+            return false;
+        };
+        let removed = entries.remove(ip).is_some();
+        if entries.is_empty() {
+            self.lists.remove(list);
+        }
+        removed
+    }
+
+    /// Seconds left for `ip` on `list`: `None` when it is not on the list, `Some(None)` when
+    /// its entry does not expire.
+    pub fn expires_in(&self, list: &str, ip: &str) -> Option<Option<f64>> {
+        let entry = self.entry(list, ip)?;
+        Some(self.left(entry))
+    }
+
+    /// Seconds `entry` has left, `None` when it does not expire.
+    pub fn left(&self, entry: BlocklistEntry) -> Option<f64> {
+        entry.expires_at.map(|at| (at - self.clock).max(0.0))
+    }
+
+    /// Names of the lists that have entries, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.lists.keys().map(String::as_str)
+    }
+
+    /// Entries of `list` in address order, with the seconds each has left (`None` when it
+    /// does not expire).
+    pub fn entries(&self, list: &str) -> Vec<(&str, Option<f64>)> {
+        self.lists
+            .get(list)
+            .into_iter()
+            .flatten()
+            .map(|(ip, entry)| (ip.as_str(), self.left(*entry)))
+            .collect()
+    }
+
+    /// Advance the clock by `delta` seconds and drop the entries that expired, returning
+    /// them as `(list, ip)` pairs.
+    pub fn advance(&mut self, delta: f64) -> Vec<(String, String)> {
+        self.clock += delta;
+        let clock = self.clock;
+        let mut expired = Vec::new();
+        self.lists.retain(|list, entries| {
+            entries.retain(|ip, entry| {
+                let keep = entry.expires_at.is_none_or(|at| at > clock);
+                if !keep {
+                    expired.push((list.clone(), ip.clone()));
+                }
+                keep
+            });
+            !entries.is_empty()
+        });
+        expired
+    }
+}
@@ -2,10 +2,12 @@ use crate::core::building::Building;
 use crate::core::buildings::datacenter::Datacenter;
 use crate::core::buildings::internet::Internet;
 use crate::core::dto::BuildingId;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 
+/// Buildings keyed by id. Iteration is in ascending id (placement) order, so every
+/// `World::update` visits buildings in the same order.
 pub struct BuildingStorage {
-    buildings: HashMap<BuildingId, Box<dyn Building>>,
+    buildings: BTreeMap<BuildingId, Box<dyn Building>>,
 }
 
 impl Default for BuildingStorage {
@@ -17,7 +19,7 @@ impl Default for BuildingStorage {
 impl BuildingStorage {
     pub fn new() -> Self {
         Self {
-            buildings: HashMap::new(),
+            buildings: BTreeMap::new(),
         }
     }
 
@@ -1,9 +1,12 @@
 use crate::core::building::{Building, BuildingType};
+pub use crate::core::building_layout::OutputRole;
+use crate::core::buildings::tunnel::{facing_step, tunnel_range};
 use crate::core::dto::{BuildingId, Vec2i};
 use crate::logic::building_map::BuildingMap;
 use crate::logic::building_storage::BuildingStorage;
 use std::collections::HashMap;
 
+#[derive(Debug, PartialEq, Eq)]
 pub struct ConnectionGraph {
     outputs: HashMap<BuildingId, Vec<ConnectionEdge>>, // from_id -> to_id with role
 }
@@ -21,22 +24,94 @@ impl ConnectionGraph {
         }
     }
 
+    /// `pos` に置かれた建物について接続を差分更新し、出力を再評価した建物の ID を返す。
+    ///
+    /// エッジ A→B は A の出力タイルに B があり、B の入力タイルに A がある場合にのみ張られる。
+    /// そのため新しい建物 X が影響するのは X 自身の出力と、X の入力タイルにいる建物の出力だけで、
+    /// それ以外のエッジは変わらない。
     pub fn update_connections_at(
         &mut self,
-        _pos: Vec2i,
-        _map: &BuildingMap,
-        _storage: &BuildingStorage,
+        pos: Vec2i,
+        map: &BuildingMap,
+        storage: &BuildingStorage,
+    ) -> Vec<BuildingId> {
+        let Some(building) = map.get(&pos).and_then(|id| storage.get(id)) else {
+            return Vec::new();
+        };
+
+        let mut affected = vec![building.id()];
+        for input_pos in building.get_input_poses() {
+            if let Some(source_id) = map.get(&input_pos)
+                && !affected.contains(&source_id)
+            {
+                affected.push(source_id);
+            }
+        }
+        // トンネル出口は入力タイルを持たず、後方の入口から届くエッジで繋がる。
+        for entrance_id in tunnel_entrances_behind(building, map, storage) {
+            if !affected.contains(&entrance_id) {
+                affected.push(entrance_id);
+            }
+        }
+
+        self.recompute_outputs(&affected, map, storage);
+        affected
+    }
+
+    /// `ids` の建物の出力を現在の配置から計算し直す。ストレージにない建物はノードごと消す。
+    pub fn recompute_outputs(
+        &mut self,
+        ids: &[BuildingId],
+        map: &BuildingMap,
+        storage: &BuildingStorage,
     ) {
-        // Placeholder for incremental update
+        for &id in ids {
+            match storage.get(id) {
+                Some(from_building) => {
+                    self.set_outputs(id, compute_outputs(from_building, map, storage))
+                }
+                None => {
+                    self.outputs.remove(&id);
+                }
+            }
+        }
     }
 
-    pub fn remove_node(&mut self, id: BuildingId) {
+    /// 撤去する建物 `building` のノードを取り除き、エッジを失った建物の ID を返す。
+    ///
+    /// `building` へのエッジを持ちうるのはその入力タイルにいる建物 (トンネル出口なら後方の入口)
+    /// だけなので、そこだけを見る。ストレージから外す前に呼ぶこと。入口は別の出口と組み直せる
+    /// ため、外した後で返り値を `recompute_outputs` に渡す。
+    pub fn remove_node(
+        &mut self,
+        building: &dyn Building,
+        map: &BuildingMap,
+        storage: &BuildingStorage,
+    ) -> Vec<BuildingId> {
+        let id = building.id();
         self.outputs.remove(&id);
 
-        self.outputs.retain(|_, edges| {
-            edges.retain(|edge| edge.to_id != id);
-            !edges.is_empty()
-        });
+        let mut affected = Vec::new();
+        let input_poses = building.get_input_poses().into_iter();
+        let entrance_poses = tunnel_entrances_behind(building, map, storage)
+            .into_iter()
+            .filter_map(|entrance_id| storage.get(entrance_id).map(|b| b.position()));
+        for input_pos in input_poses.chain(entrance_poses) {
+            let Some(source_id) = map.get(&input_pos) else {
+                continue;
+            };
+            if source_id == id || affected.contains(&source_id) {
+                continue;
+            }
+            if let Some(edges) = self.outputs.get_mut(&source_id) {
+                edges.retain(|edge| edge.to_id != id);
+                if edges.is_empty() {
+                    self.outputs.remove(&source_id);
+                }
+                affected.push(source_id);
+            }
+        }
+        affected
     }
 
     pub fn get_outputs(&self, from_id: BuildingId) -> Option<&Vec<ConnectionEdge>> {
@@ -51,45 +126,107 @@ impl ConnectionGraph {
     pub fn rebuild(&mut self, map: &BuildingMap, storage: &BuildingStorage) {
         self.outputs.clear();
         for from_building in storage.iter() {
-            let from_id = from_building.id();
-
-            let mut connections = Vec::new();
-
-            for out_pos in from_building.get_output_poses() {
-                if let Some(to_id) = map.get(&out_pos) {
-                    if to_id == from_id {
-                        continue;
-                    }
-
-                    if let Some(target_building) = storage.get(to_id) {
-                        let accepts_from_source = target_building
-                            .get_input_poses()
-                            .into_iter()
-                            .any(|input_pos| map.get(&input_pos) == Some(from_id));
-
-                        if accepts_from_source {
-                            let role = classify_output_role(from_building, out_pos);
-                            connections.push(ConnectionEdge { to_id, role });
-                        }
-                    }
-                }
+            let connections = compute_outputs(from_building, map, storage);
+            self.set_outputs(from_building.id(), connections);
+        }
+    }
+
+    fn set_outputs(&mut self, from_id: BuildingId, connections: Vec<ConnectionEdge>) {
+        if connections.is_empty() {
+            self.outputs.remove(&from_id);
+        } else {
+            self.outputs.insert(from_id, connections);
+        }
+    }
+}
+
+/// `from_building` の出力エッジ。重複を排除し、ソート済みで返す。
+fn compute_outputs(
+    from_building: &dyn Building,
+    map: &BuildingMap,
+    storage: &BuildingStorage,
+) -> Vec<ConnectionEdge> {
+    let from_id = from_building.id();
+    let mut connections = Vec::new();
+
+    for out_pos in from_building.get_output_poses() {
+        if let Some(to_id) = map.get(&out_pos) {
+            if to_id == from_id {
+                continue;
             }
 
-            // 重複を排除し, ソートしてから追加
-            if !connections.is_empty() {
-                connections.sort_unstable_by_key(|a| edge_sort_key(a));
-                connections.dedup_by(|a, b| edge_sort_key(a) == edge_sort_key(b));
-                self.outputs.insert(from_id, connections);
+            if let Some(target_building) = storage.get(to_id) {
+                let accepts_from_source = target_building
+                    .get_input_poses()
+                    .into_iter()
+                    .any(|input_pos| map.get(&input_pos) == Some(from_id));
+
+                if accepts_from_source {
+                    let role = from_building.output_role(out_pos);
+                    connections.push(ConnectionEdge { to_id, role });
+                }
             }
         }
     }
+
+    if let Some(to_id) = paired_tunnel_exit(from_building, map, storage) {
+        connections.push(ConnectionEdge {
+            to_id,
+            role: OutputRole::Default,
+        });
+    }
+
+    connections.sort_unstable_by_key(|a| edge_sort_key(a));
+    connections.dedup_by(|a, b| edge_sort_key(a) == edge_sort_key(b));
+    connections
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub enum OutputRole {
-    Default,
-    FilterMatch,
-    FilterMismatch,
+/// トンネル入口 `entrance` の正面 `tunnel_range` タイル以内で、同じ向きの最も近い出口。
+fn paired_tunnel_exit(
+    entrance: &dyn Building,
+    map: &BuildingMap,
+    storage: &BuildingStorage,
+) -> Option<BuildingId> {
+    if entrance.building_type() != BuildingType::TunnelEntrance {
+        return None;
+    }
+    tiles_along(entrance.position(), entrance.rotation(), 1).find_map(|pos| {
+        map.get(&pos)
+            .and_then(|id| storage.get(id))
+            .filter(|b| is_tunnel_pair(entrance, *b))
+            .map(|b| b.id())
+    })
+}
+
+/// トンネル出口 `exit` と組みうる、後方 `tunnel_range` タイル以内の同じ向きの入口。
+fn tunnel_entrances_behind(
+    exit: &dyn Building,
+    map: &BuildingMap,
+    storage: &BuildingStorage,
+) -> Vec<BuildingId> {
+    if exit.building_type() != BuildingType::TunnelExit {
+        return Vec::new();
+    }
+    tiles_along(exit.position(), exit.rotation(), -1)
+        .filter_map(|pos| map.get(&pos).and_then(|id| storage.get(id)))
+        .filter(|b| is_tunnel_pair(*b, exit))
+        .map(|b| b.id())
+        .collect()
+}
+
+fn is_tunnel_pair(entrance: &dyn Building, exit: &dyn Building) -> bool {
+    entrance.building_type() == BuildingType::TunnelEntrance
+        && exit.building_type() == BuildingType::TunnelExit
+        && entrance.rotation().rem_euclid(4) == exit.rotation().rem_euclid(4)
+}
+
+/// `pos` から向き `rotation` に `direction` (1 で前方、-1 で後方) へ `tunnel_range` タイル分。
+fn tiles_along(pos: Vec2i, rotation: i32, direction: i32) -> impl Iterator<Item = Vec2i> {
+    let step = facing_step(rotation);
+    (1..=tunnel_range()).map(move |distance| Vec2i {
+        x: pos.x + step.x * distance * direction,
+        y: pos.y + step.y * distance * direction,
+    })
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -107,50 +244,6 @@ fn role_index(role: OutputRole) -> i32 {
         OutputRole::Default => 0,
         OutputRole::FilterMatch => 1,
         OutputRole::FilterMismatch => 2,
-    }
-}
-
-fn classify_output_role(building: &dyn Building, out_pos: Vec2i) -> OutputRole {
-    match building.building_type() {
-        BuildingType::IpFilter
-        | BuildingType::PortFilter
-        | BuildingType::LengthFilter
-        | BuildingType::ProtocolFilter
-        | BuildingType::ContentFilter => classify_filter_output(building, out_pos),
-        _ => OutputRole::Default,
-    }
-}
-
-fn classify_filter_output(building: &dyn Building, out_pos: Vec2i) -> OutputRole {
-    let pos = building.position();
-    let rot = building.rotation().rem_euclid(4);
-
-    let front = match rot {
-        0 => Vec2i {
-            x: pos.x,
-            y: pos.y - 1,
-        },
-        1 => Vec2i {
-            x: pos.x + 1,
-            y: pos.y,
-        },
-        2 => Vec2i {
-            x: pos.x,
-            y: pos.y + 1,
-        },
-        3 => Vec2i {
-            x: pos.x - 1,
-            y: pos.y,
-        },
-        _ => Vec2i {
-            x: pos.x,
-            y: pos.y - 1,
-        },
-    };
-
-    if out_pos == front {
-        OutputRole::FilterMismatch
-    } else {
-        OutputRole::FilterMatch
+        OutputRole::Mirror => 3,
     }
 }
@@ -0,0 +1,114 @@
+//! Undo/redo history of layout edits.
+//!
+//! Every edit applied through `MapController` is recorded as an [`AppliedEdit`], which knows
+//! how to revert and repeat itself (a removed building is put back with its id and rule).
+//! Edits made between [`EditHistory::begin_group`] and [`EditHistory::end_group`], such as
+//! the conveyors of one dragged line, form a single undo step.
+use std::collections::VecDeque;
+
+use crate::map_controller::{AppliedEdit, LayoutEdit, World};
+
+/// Undo steps kept by default.
+pub const DEFAULT_HISTORY_LIMIT: usize = 100;
+
+#[derive(Debug)]
+pub struct EditHistory {
+    undo: VecDeque<Vec<AppliedEdit>>,
+    redo: Vec<Vec<AppliedEdit>>,
+    group: Option<Vec<AppliedEdit>>,
+    limit: usize,
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTORY_LIMIT)
+    }
+}
+
+impl EditHistory {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            group: None,
+            limit: limit.max(1),
+        }
+    }
+
+    /// Change the number of undo steps kept, dropping the oldest ones if needed.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit.max(1);
+        while self.undo.len() > self.limit {
+            self.undo.pop_front();
+        }
+    }
+
+    /// Record an edit the player made. Any redo steps are discarded.
+    pub fn record(&mut self, edit: AppliedEdit) {
+        self.redo.clear();
+        match self.group.as_mut() {
+            Some(group) => group.push(edit),
+            None => self.push_undo(vec![edit]),
+        }
+    }
+
+    /// Start collecting edits into one undo step. Nested calls join the open group.
+    pub fn begin_group(&mut self) {
+        self.group.get_or_insert_with(Vec::new);
+    }
+
+    /// Close the group opened by `begin_group`; an empty group leaves no step behind.
+    pub fn end_group(&mut self) {
+        if let Some(group) = self.group.take()
+            && !group.is_empty()
+        {
+            self.push_undo(group);
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty() || self.group.as_ref().is_some_and(|g| !g.is_empty())
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Revert the latest step and return the edits that were applied to do so. An open group
+    /// is closed first.
+    pub fn undo(&mut self, world: &mut World) -> Option<Vec<LayoutEdit>> {
+        self.end_group();
+        let step = self.undo.pop_back()?;
+        let applied = step
+            .iter()
+            .rev()
+            .filter_map(|edit| world.apply_edit(&edit.undo).map(|_| edit.undo.clone()))
+            .collect();
+        self.redo.push(step);
+        Some(applied)
+    }
+
+    /// Repeat the latest undone step and return the edits that were applied to do so.
+    pub fn redo(&mut self, world: &mut World) -> Option<Vec<LayoutEdit>> {
+        let step = self.redo.pop()?;
+        let applied = step
+            .iter()
+            .filter_map(|edit| world.apply_edit(&edit.redo).map(|_| edit.redo.clone()))
+            .collect();
+        self.push_undo(step);
+        Some(applied)
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.group = None;
+    }
+
+    fn push_undo(&mut self, step: Vec<AppliedEdit>) {
+        if self.undo.len() == self.limit {
+            self.undo.pop_front();
+        }
+        self.undo.push_back(step);
+    }
+}
@@ -0,0 +1,77 @@
+//! Fixed-timestep driver for `World::update`.
+//!
+//! Frame deltas are accumulated and converted into whole ticks of [`TICK_SECONDS`], so the
+//! simulation advances identically regardless of frame rate or `over_clock` speed; those
+//! only change how many ticks run per frame.
+
+/// Simulated seconds per tick.
+pub const TICK_SECONDS: f32 = 1.0 / 60.0;
+
+/// Upper bound of ticks run for one frame. Prevents a long hitch (or a very high
+/// `over_clock`) from stalling the game while it catches up; the remainder is carried over.
+pub const MAX_TICKS_PER_FRAME: u32 = 240;
+
+#[derive(Debug, Clone, Default)]
+pub struct FixedStep {
+    accumulator: f64,
+}
+
+impl FixedStep {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `delta` seconds and return how many ticks are due now.
+    pub fn advance(&mut self, delta: f64) -> u32 {
+        if delta.is_finite() && delta > 0.0 {
+            self.accumulator += delta;
+        }
+        let tick = TICK_SECONDS as f64;
+        let due = (self.accumulator / tick)
+            .floor()
+            .min(MAX_TICKS_PER_FRAME as f64) as u32;
+        self.accumulator -= due as f64 * tick;
+        due
+    }
+
+    pub fn reset(&mut self) {
+        self.accumulator = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accumulates_partial_frames() {
+        let mut step = FixedStep::new();
+        let tick = TICK_SECONDS as f64;
+
+        assert_eq!(step.advance(tick * 0.6), 0);
+        assert_eq!(step.advance(tick * 0.6), 1);
+        assert_eq!(step.advance(tick * 2.0), 2);
+    }
+
+    #[test]
+    fn frame_rate_does_not_change_tick_count() {
+        let mut slow = FixedStep::new();
+        let mut fast = FixedStep::new();
+
+        let slow_ticks: u32 = (0..30).map(|_| slow.advance(1.0 / 30.0)).sum();
+        let fast_ticks: u32 = (0..144).map(|_| fast.advance(1.0 / 144.0)).sum();
+        // One simulated second either way; at most one tick is still in the accumulator.
+        assert!((59..=60).contains(&slow_ticks));
+        assert!((59..=60).contains(&fast_ticks));
+    }
+
+    #[test]
+    fn caps_ticks_per_frame_and_carries_the_rest() {
+        let mut step = FixedStep::new();
+        let tick = TICK_SECONDS as f64;
+
+        assert_eq!(step.advance(tick * 300.5), MAX_TICKS_PER_FRAME);
+        assert_eq!(step.advance(0.0), 60);
+        assert_eq!(step.advance(f64::NAN), 0);
+    }
+}
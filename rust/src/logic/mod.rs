@@ -1,4 +1,9 @@
+pub mod blocklist;
 pub mod building_map;
 pub mod building_storage;
 pub mod connection_graph;
+pub mod edit_history;
+pub mod fixed_step;
 pub mod packet_completion;
+pub mod replay;
+pub mod stage;
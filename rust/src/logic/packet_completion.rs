@@ -1,108 +1,71 @@
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::collections::{BTreeMap, HashSet};
 
 use godot::prelude::*;
 
 use crate::core::building::BuildingType;
-use crate::core::dto::BuildingId;
-use crate::core::packet::{Packet as CorePacket, PacketLabel, Protocol};
+use crate::core::buildings::datacenter::Datacenter;
+use crate::core::buildings::internet::Internet;
+use crate::core::dto::{BuildingId, DropReason};
+use crate::core::packet::{Packet as CorePacket, PacketId};
 use crate::logic::building_storage::BuildingStorage;
-use crate::map_controller::World;
-use crate::packet::{Packet as TrafficPacket, Traffic};
-
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
-struct PacketKey {
-    source_ip: String,
-    dest_ip: String,
-    source_port: u16,
-    dest_port: u16,
-    protocol: Protocol,
-    length: u32,
-    label: PacketLabel,
-}
-
-impl PacketKey {
-    fn from_core(packet: &CorePacket) -> Self {
-        Self {
-            source_ip: packet.source_ip.clone(),
-            dest_ip: packet.dest_ip.clone(),
-            source_port: packet.source_port,
-            dest_port: packet.dest_port,
-            protocol: packet.protocol.clone(),
-            length: packet.length,
-            label: packet.label,
-        }
-    }
-
-    fn from_resource(packet: &Gd<TrafficPacket>) -> Option<Self> {
-        let packet = packet.bind();
-        let protocol = match packet.get_protocol() {
-            6 => Protocol::Tcp,
-            17 => Protocol::Udp,
-            _ => Protocol::Unknown,
-        };
-        let label = PacketLabel::from_raw(packet.get_label());
-        Some(Self {
-            source_ip: packet.get_src_ip().to_string(),
-            dest_ip: packet.get_dst_ip().to_string(),
-            source_port: packet.get_src_port() as u16,
-            dest_port: packet.get_dst_port() as u16,
-            protocol,
-            length: packet.get_packet_size() as u32,
-            label,
-        })
-    }
-}
+use crate::map_controller::{World, set_packet_id};
 
 #[derive(Clone, Debug)]
 struct PacketReport {
     building_type: BuildingType,
     building_id: BuildingId,
     packet: CorePacket,
+    /// Set when routing discarded the packet in `building_id` instead of delivering it.
+    dropped: Option<DropReason>,
 }
 
-static PLANNED_PACKETS: Mutex<Option<Vec<PacketKey>>> = Mutex::new(None);
-
-pub fn register_planned_traffic(traffic: &Gd<Traffic>) {
-    let traffic = traffic.bind();
-    let packets = traffic.packets();
-
-    let mut planned = Vec::with_capacity(packets.len());
-    for idx in 0..packets.len() {
-        if let Some(packet) = packets.get(idx) {
-            if let Some(key) = PacketKey::from_resource(&packet) {
-                planned.push(key);
-            }
-        }
-    }
-
-    set_planned_packet_keys(planned);
+/// What a stage expects one traffic to deliver.
+#[derive(Clone, Debug)]
+enum PlannedTraffic {
+    /// Every planned packet is known up front by the id its `Internet` gives it; completion
+    /// waits until each of them has been delivered or dropped.
+    Ids(HashSet<PacketId>),
+    /// Traffic is streamed from a source that is not materialized; completion is reached once
+    /// every `Internet` source is exhausted and no packet is left in transit.
+    Streaming,
 }
 
-pub fn clear_planned_packets() {
-    let mut guard = PLANNED_PACKETS
-        .lock()
-        .expect("PLANNED_PACKETS mutex poisoned in clear_planned_packets");
-    *guard = None;
+/// What the current stage expects from each `Internet`, told apart by the origin in each
+/// packet's id. Kept by `World` and restored with its snapshots.
+#[derive(Clone, Debug, Default)]
+pub struct TrafficPlan {
+    sources: BTreeMap<BuildingId, PlannedTraffic>,
 }
 
-fn set_planned_packet_keys(keys: Vec<PacketKey>) {
-    let mut guard = PLANNED_PACKETS
-        .lock()
-        .expect("PLANNED_PACKETS mutex poisoned in set_planned_packet_keys");
-    *guard = Some(keys);
+impl TrafficPlan {
+    /// Whether no traffic is planned; such a stage never completes.
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
 }
 
-#[cfg(test)]
-pub fn register_planned_core_packets<'a, I>(packets: I)
+/// Plan `packets` for the `Internet` `source`, which numbers them in emission order; the stage
+/// completes once every registered source has.
+pub fn register_source_core_packets<'a, I>(world: &mut World, source: BuildingId, packets: I)
 where
     I: IntoIterator<Item = &'a CorePacket>,
 {
-    let planned = packets
-        .into_iter()
-        .map(PacketKey::from_core)
-        .collect::<Vec<_>>();
-    set_planned_packet_keys(planned);
+    let ids = (0..packets.into_iter().count() as u64)
+        .map(|index| PacketId {
+            origin: source,
+            index,
+        })
+        .collect();
+    add_source_plan(world, source, PlannedTraffic::Ids(ids));
+}
+
+/// Plan a streamed traffic for the `Internet` `source`.
+pub fn register_streaming_source(world: &mut World, source: BuildingId) {
+    add_source_plan(world, source, PlannedTraffic::Streaming);
+}
+
+fn add_source_plan(world: &mut World, source: BuildingId, planned: PlannedTraffic) {
+    world.traffic_plan_mut().sources.insert(source, planned);
 }
 
 pub fn completed_packets_variant(world: &World) -> Variant {
@@ -119,43 +82,102 @@ pub fn completed_packets_variant(world: &World) -> Variant {
     }
 }
 
+/// Whether every planned packet has reached a sink or been dropped.
+pub fn all_packets_resolved(world: &World) -> bool {
+    check_all_packets_transferred(world).is_some()
+}
+
 #[cfg(test)]
 pub fn completed_packet_count_for_test(world: &World) -> Option<usize> {
     check_all_packets_transferred(world).map(|reports| reports.len())
 }
 
 fn check_all_packets_transferred(world: &World) -> Option<Vec<PacketReport>> {
-    let planned_keys = {
-        let guard = PLANNED_PACKETS
-            .lock()
-            .expect("PLANNED_PACKETS mutex poisoned in check_all_packets_transferred");
-        guard.clone()?
-    };
-
-    let reports = collect_delivered_packets(&world.storage);
-    if reports.is_empty() && planned_keys.is_empty() {
-        return Some(reports);
+    let plan = world.traffic_plan();
+    if plan.is_empty() {
+        return None;
     }
 
-    let delivered_counts = to_counts(
-        reports
+    // Dropped packets will never reach a sink, so they count as resolved.
+    let mut reports = collect_delivered_packets(&world.storage);
+    reports.extend(collect_dropped_packets(world));
+    let finished = plan.sources.iter().all(|(&source, planned)| {
+        let reports: Vec<PacketReport> = reports
             .iter()
-            .map(|report| PacketKey::from_core(&report.packet)),
-    );
-    let planned_counts = to_counts(planned_keys.into_iter());
+            .filter(|report| report_origin(report) == Some(source))
+            .cloned()
+            .collect();
+        traffic_finished(planned, source, &reports, &world.storage)
+    });
+    finished.then_some(reports)
+}
 
-    if delivered_counts == planned_counts {
-        Some(reports)
-    } else {
-        None
-    }
+/// Progress of one `Internet` of the stage.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SourceProgress {
+    pub source: BuildingId,
+    /// Packets the source is planned to emit; `None` when its traffic is streamed.
+    pub planned: Option<usize>,
+    /// Its packets that reached a sink or were dropped.
+    pub resolved: usize,
+    pub finished: bool,
+}
+
+/// Per-source progress, in building id order.
+pub fn source_progress(world: &World) -> Vec<SourceProgress> {
+    let mut reports = collect_delivered_packets(&world.storage);
+    reports.extend(collect_dropped_packets(world));
+    world
+        .traffic_plan()
+        .sources
+        .iter()
+        .map(|(&source, planned)| {
+            let reports: Vec<PacketReport> = reports
+                .iter()
+                .filter(|report| report_origin(report) == Some(source))
+                .cloned()
+                .collect();
+            SourceProgress {
+                source,
+                planned: match planned {
+                    PlannedTraffic::Ids(ids) => Some(ids.len()),
+                    PlannedTraffic::Streaming => None,
+                },
+                resolved: reports.len(),
+                finished: traffic_finished(planned, source, &reports, &world.storage),
+            }
+        })
+        .collect()
+}
+
+fn report_origin(report: &PacketReport) -> Option<BuildingId> {
+    report.packet.id.map(|id| id.origin)
+}
+
+fn traffic_finished(
+    planned: &PlannedTraffic,
+    source: BuildingId,
+    reports: &[PacketReport],
+    storage: &BuildingStorage,
+) -> bool {
+    let planned_ids = match planned {
+        PlannedTraffic::Ids(ids) => ids,
+        PlannedTraffic::Streaming => return streaming_finished(storage, source),
+    };
+    // Packets are told apart by id, so one with the same headers as a planned packet cannot
+    // stand in for it.
+    let resolved_ids: HashSet<PacketId> = reports
+        .iter()
+        .filter_map(|report| report.packet.id)
+        .collect();
+    planned_ids.is_subset(&resolved_ids)
 }
 
 fn collect_delivered_packets(storage: &BuildingStorage) -> Vec<PacketReport> {
     let mut reports = Vec::new();
     for building in storage.iter() {
         match building.building_type() {
-            BuildingType::Datacenter | BuildingType::RecycleBin => {
+            BuildingType::Datacenter | BuildingType::RecycleBin | BuildingType::Honeypot => {
                 let building_type = building.building_type();
                 let building_id = building.id();
                 for packet in building.get_packets() {
@@ -163,6 +185,7 @@ fn collect_delivered_packets(storage: &BuildingStorage) -> Vec<PacketReport> {
                         building_type,
                         building_id,
                         packet,
+                        dropped: None,
                     });
                 }
             }
@@ -172,15 +195,35 @@ fn collect_delivered_packets(storage: &BuildingStorage) -> Vec<PacketReport> {
     reports
 }
 
-fn to_counts<I>(iter: I) -> HashMap<PacketKey, usize>
-where
-    I: IntoIterator<Item = PacketKey>,
-{
-    let mut counts = HashMap::new();
-    for key in iter {
-        *counts.entry(key).or_insert(0) += 1;
-    }
-    counts
+fn collect_dropped_packets(world: &World) -> impl Iterator<Item = PacketReport> + '_ {
+    world.dropped_packets().iter().map(|dropped| PacketReport {
+        building_type: dropped.building_type,
+        building_id: dropped.building_id,
+        packet: dropped.packet.clone(),
+        dropped: Some(dropped.reason),
+    })
+}
+
+/// Whether the `Internet` `source` is exhausted and none of its packets is left in transit.
+fn streaming_finished(storage: &BuildingStorage, source: BuildingId) -> bool {
+    let from_source = |packet: &CorePacket| packet.id.is_some_and(|id| id.origin == source);
+    storage
+        .iter()
+        .all(|building| match building.building_type() {
+            BuildingType::RecycleBin | BuildingType::Honeypot => true,
+            // Packets still queued for processing are not delivered yet.
+            BuildingType::Datacenter => building
+                .as_any()
+                .downcast_ref::<Datacenter>()
+                .is_none_or(|datacenter| !datacenter.queued_packets().any(from_source)),
+            BuildingType::Internet if building.id() == source => building
+                .as_any()
+                .downcast_ref::<Internet>()
+                .is_some_and(|internet| internet.is_exhausted()),
+            // Copies still on their way to an analyzer carry no id and do not hold up
+            // completion.
+            _ => !building.get_packets().iter().any(from_source),
+        })
 }
 
 fn packet_report_to_variant(report: PacketReport) -> Variant {
@@ -197,5 +240,10 @@ fn packet_report_to_variant(report: PacketReport) -> Variant {
     dict.set("protocol", (report.packet.protocol as i32).to_variant());
     dict.set("length", (report.packet.length as i32).to_variant());
     dict.set("label", report.packet.label.to_raw().to_variant());
+    set_packet_id(&mut dict, report.packet.id);
+    dict.set("dropped", report.dropped.is_some().to_variant());
+    if let Some(reason) = report.dropped {
+        dict.set("drop_reason", reason.as_str().to_variant());
+    }
     dict.to_variant()
 }
@@ -0,0 +1,236 @@
+//! Replay files: a stage plus the player's layout edits, each stamped with the tick it was
+//! made at.
+//!
+//! The simulation runs on a fixed timestep (see [`fixed_step`](super::fixed_step)) and visits
+//! buildings in id order, so applying the same edits at the same ticks reproduces a run
+//! exactly. [`run_replay`] does that without Godot; `MapController::save_replay` writes the
+//! file from a live session.
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use super::fixed_step::TICK_SECONDS;
+use super::packet_completion;
+use super::stage;
+use crate::core::building::BuildingType;
+use crate::core::buildings::honeypot::AttackerProfile;
+use crate::core::dto::BuildingId;
+use crate::core::packet::{Packet, PacketLabel};
+use crate::map_controller::{DropStats, LayoutEdit, World};
+
+pub const REPLAY_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayEdit {
+    /// `World::tick` at which the edit was applied, i.e. the number of updates before it.
+    pub tick: u64,
+    pub edit: LayoutEdit,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    /// Stage map path as passed to `MapController::load_map` (usually `res://...`).
+    pub stage: String,
+    pub tick_seconds: f32,
+    /// Tick the recording ended at.
+    pub end_tick: u64,
+    pub edits: Vec<ReplayEdit>,
+}
+
+impl Replay {
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let replay: Replay =
+            serde_json::from_str(text).map_err(|err| format!("リプレイが不正です: {}", err))?;
+        if replay.version != REPLAY_VERSION {
+            return Err(format!("未対応のリプレイバージョン: {}", replay.version));
+        }
+        if replay.tick_seconds != TICK_SECONDS {
+            return Err(format!(
+                "tick_seconds が一致しません: {} (期待値 {})",
+                replay.tick_seconds, TICK_SECONDS
+            ));
+        }
+        Ok(replay)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("replay serialization cannot fail")
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("ファイル '{}' を読み込めません: {}", path.display(), err))?;
+        Self::from_json(&text)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_json())
+            .map_err(|err| format!("ファイル '{}' に書き込めません: {}", path.display(), err))
+    }
+}
+
+/// Collects the edits of a session. Restarted whenever a stage is loaded.
+#[derive(Debug, Default)]
+pub struct ReplayRecorder {
+    stage: Option<String>,
+    edits: Vec<ReplayEdit>,
+}
+
+impl ReplayRecorder {
+    pub fn start(&mut self, stage: String) {
+        self.stage = Some(stage);
+        self.edits.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.stage = None;
+        self.edits.clear();
+    }
+
+    pub fn record(&mut self, tick: u64, edit: LayoutEdit) {
+        self.edits.push(ReplayEdit { tick, edit });
+    }
+
+    /// Called when the world was rewound by restoring its snapshot at `tick`. Edits made after
+    /// it stay in the layout and take effect from the snapshot on, so they are moved to `tick`;
+    /// the replay then reproduces the rewound run from there.
+    pub fn rewind_to(&mut self, tick: u64) {
+        for edit in self.edits.iter_mut() {
+            edit.tick = edit.tick.min(tick);
+        }
+    }
+
+    /// The recording so far, or `None` when no stage was loaded.
+    pub fn to_replay(&self, end_tick: u64) -> Option<Replay> {
+        Some(Replay {
+            version: REPLAY_VERSION,
+            stage: self.stage.clone()?,
+            tick_seconds: TICK_SECONDS,
+            end_tick,
+            edits: self.edits.clone(),
+        })
+    }
+}
+
+/// Packets a sink received, by label.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SinkSummary {
+    pub building_id: BuildingId,
+    pub building_type: BuildingType,
+    pub correct: usize,
+    pub incorrect: usize,
+    pub unknown: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ReplayOutcome {
+    pub ticks: u64,
+    pub score: u32,
+    /// Whether every planned packet was delivered or dropped by the end of the replay.
+    pub completed: bool,
+    pub sinks: Vec<SinkSummary>,
+    pub drops: DropStats,
+    /// Senders caught by honeypots (see [`World::attacker_profiles`]).
+    pub attackers: Vec<AttackerProfile>,
+    /// Fingerprint of the final world state; equal digests mean identical runs.
+    pub digest: String,
+}
+
+/// Load the replay's stage from `root` and play it back.
+pub fn run_replay(replay: &Replay, root: &Path) -> Result<ReplayOutcome, String> {
+    let mut world = stage::load_stage(&replay.stage, root)?;
+    play(&mut world, &replay.edits, replay.end_tick);
+    Ok(outcome(&world))
+}
+
+/// Advance `world` to `end_tick`, applying every edit before the update of its tick.
+pub fn play(world: &mut World, edits: &[ReplayEdit], end_tick: u64) {
+    let mut pending = edits.iter().peekable();
+    while world.tick() < end_tick {
+        while let Some(edit) = pending.next_if(|edit| edit.tick <= world.tick()) {
+            world.apply_edit(&edit.edit);
+        }
+        world.update(TICK_SECONDS);
+    }
+}
+
+pub fn outcome(world: &World) -> ReplayOutcome {
+    let sinks = world
+        .storage
+        .iter()
+        .filter(|building| {
+            matches!(
+                building.building_type(),
+                BuildingType::Datacenter | BuildingType::RecycleBin | BuildingType::Honeypot
+            )
+        })
+        .map(|building| {
+            let packets = building.get_packets();
+            let count = |label| packets.iter().filter(|p| p.label == label).count();
+            SinkSummary {
+                building_id: building.id(),
+                building_type: building.building_type(),
+                correct: count(PacketLabel::Correct),
+                incorrect: count(PacketLabel::Incorrect),
+                unknown: count(PacketLabel::Unknown),
+            }
+        })
+        .collect();
+
+    ReplayOutcome {
+        ticks: world.tick(),
+        score: world.score,
+        completed: packet_completion::all_packets_resolved(world),
+        sinks,
+        drops: world.drop_stats(),
+        attackers: world.attacker_profiles(),
+        digest: format!("{:016x}", digest(world)),
+    }
+}
+
+/// FNV-1a over every building's packets (with progress) and the dropped packets.
+pub fn digest(world: &World) -> u64 {
+    let mut hash = Fnv(0xcbf2_9ce4_8422_2325);
+    hash.write(&world.tick().to_le_bytes());
+    hash.write(&world.score.to_le_bytes());
+    for building in world.storage.iter() {
+        hash.write(&building.id().to_le_bytes());
+        hash.write(&[building.building_type() as u8]);
+        for packet in building.get_packets() {
+            hash_packet(&mut hash, &packet);
+        }
+    }
+    for dropped in world.dropped_packets() {
+        hash.write(&dropped.building_id.to_le_bytes());
+        hash.write(&dropped.tick.to_le_bytes());
+        hash_packet(&mut hash, &dropped.packet);
+    }
+    hash.0
+}
+
+fn hash_packet(hash: &mut Fnv, packet: &Packet) {
+    if let Some(id) = packet.id {
+        hash.write(&id.origin.to_le_bytes());
+        hash.write(&id.index.to_le_bytes());
+    }
+    hash.write(packet.source_ip.as_bytes());
+    hash.write(packet.dest_ip.as_bytes());
+    hash.write(&packet.source_port.to_le_bytes());
+    hash.write(&packet.dest_port.to_le_bytes());
+    hash.write(&packet.length.to_le_bytes());
+    hash.write(&packet.progress.to_bits().to_le_bytes());
+    hash.write(&packet.label.to_raw().to_le_bytes());
+}
+
+struct Fnv(u64);
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+}
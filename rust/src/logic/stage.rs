@@ -0,0 +1,353 @@
+//! Godot-independent loading of stage map files, used by `MapController::load_map` and the
+//! headless runner.
+//!
+//! Buildings are placed in file order and the `meta` keys `packetsType`, `packetsPath`,
+//! `reassembleTcp`, `flowOptions`, `dropPenalty`, `honeypotReward` and `sources` are honoured.
+//! `res://` paths are resolved against the project root passed in.
+//!
+//! `sources` gives each `Internet` its own traffic, and completion is then tracked per source:
+//!
+//! ```json
+//! "sources": [
+//!   {"x": 0, "y": 2, "cidr": "203.0.113.0/24"},
+//!   {"x": 0, "y": 8, "tag": "office"},
+//!   {"x": 0, "y": 12, "packetsType": "pcap", "packetsPath": "res://assets/packets/dns.pcap"}
+//! ]
+//! ```
+//!
+//! Entries without `packetsPath` split the shared stage traffic; Internets not listed emit
+//! nothing. An entry that names no Internet or has an invalid `cidr` fails the whole stage.
+//! Without `sources` the stage traffic goes to the first Internet placed, so no packet is
+//! emitted twice.
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pcap_file::pcap::PcapReader;
+use serde_json::{Map, Value};
+
+use crate::core::building::{Building, BuildingType};
+use crate::core::building_registry::registry;
+use crate::core::buildings::internet::Internet;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::core::packet::{Packet, Protocol};
+use crate::logic::packet_completion;
+use crate::map_controller::World;
+use crate::packet::flow_import::{self, FlowImportOptions};
+use crate::packet::normalize_timestamp;
+use crate::packet::pcap_frame::parse_packet_from_bytes;
+use crate::packet::reassembly::{TcpReassembler, attach_streams};
+use crate::packet::traffic_schema::{self, ParseOptions, TrafficEntry};
+use crate::packet::traffic_selector::{Cidr, TrafficSelector};
+use crate::packet::traffic_source::{
+    PcapTrafficSource, SelectedTrafficSource, TrafficSource, VecTrafficSource,
+};
+
+/// Resolve a stage-relative path: `res://` and relative paths are joined onto `root`.
+pub fn resolve_path(path: &str, root: &Path) -> PathBuf {
+    match path.strip_prefix("res://") {
+        Some(rest) => root.join(rest),
+        None => root.join(path),
+    }
+}
+
+/// Build a `World` from the stage map at `path` and register its planned traffic for
+/// completion checks.
+pub fn load_stage(path: &str, root: &Path) -> Result<World, String> {
+    let file = resolve_path(path, root);
+    let text = fs::read_to_string(&file)
+        .map_err(|err| format!("ステージ '{}' を読み込めません: {}", file.display(), err))?;
+    let data: Value = serde_json::from_str(&text)
+        .map_err(|err| format!("ステージ '{}' が不正です: {}", file.display(), err))?;
+    Ok(build_stage(&data, root)?.world)
+}
+
+/// A stage built by [`build_stage`].
+pub struct LoadedStage {
+    pub world: World,
+    /// Building entries that were skipped, described for the log.
+    pub skipped: Vec<String>,
+}
+
+/// Build a `World` from parsed stage map `data` and register its planned traffic for
+/// completion checks. Building entries that are incomplete or name an unknown `blockId` are
+/// skipped; a `meta` that cannot be honoured fails the whole stage.
+pub fn build_stage(data: &Value, root: &Path) -> Result<LoadedStage, String> {
+    let buildings = data
+        .get("buildings")
+        .and_then(Value::as_array)
+        .ok_or_else(|| "'buildings' 配列がありません".to_string())?;
+
+    let mut world = World::new();
+    let mut skipped = Vec::new();
+    for building in buildings {
+        let field = |key: &str| building.get(key).and_then(as_i32);
+        let (Some(x), Some(y), Some(block_id), Some(rotation)) =
+            (field("x"), field("y"), field("blockId"), field("rotation"))
+        else {
+            skipped.push(format!("建物データが不正です: {}", building));
+            continue;
+        };
+        if registry().by_id(block_id).is_some() {
+            world.place_block(Vec2i { x, y }, block_id, rotation);
+        } else {
+            skipped.push(format!("不明な blockId: {}", block_id));
+        }
+    }
+    world.rebuild_connections();
+
+    if let Some(meta) = data.get("meta").and_then(Value::as_object) {
+        apply_meta(&mut world, meta, root)?;
+    }
+    Ok(LoadedStage { world, skipped })
+}
+
+fn as_i32(value: &Value) -> Option<i32> {
+    match value {
+        Value::Number(n) => n
+            .as_i64()
+            .map(|i| i as i32)
+            .or_else(|| n.as_f64().map(|f| f.round() as i32)),
+        Value::String(s) => s
+            .parse::<i32>()
+            .ok()
+            .or_else(|| s.parse::<f64>().ok().map(|f| f.round() as i32)),
+        _ => None,
+    }
+}
+
+fn apply_meta(world: &mut World, meta: &Map<String, Value>, root: &Path) -> Result<(), String> {
+    if let Some(penalty) = meta.get("dropPenalty").and_then(as_i32) {
+        world.set_drop_penalty(penalty.max(0) as u32);
+    }
+    if let Some(reward) = meta.get("honeypotReward").and_then(as_i32) {
+        world.set_honeypot_reward(reward.max(0) as u32);
+    }
+
+    let specs = match meta.get("sources") {
+        Some(sources) => source_specs(world, sources)?,
+        // Without `sources` only the first Internet emits the stage traffic.
+        None => world
+            .storage
+            .get_internet_buildings_mut()
+            .iter()
+            .map(|internet| internet.id())
+            .min()
+            .map(|id| SourceSpec {
+                id,
+                own: None,
+                selector: TrafficSelector::default(),
+            })
+            .into_iter()
+            .collect(),
+    };
+
+    let mut shared: Option<Vec<Packet>> = None;
+    for spec in specs {
+        let traffic = spec.own.unwrap_or(meta);
+        let packets_type = traffic.get("packetsType").and_then(Value::as_str);
+        let packets_path = traffic.get("packetsPath").and_then(Value::as_str);
+        let (Some(packets_type), Some(packets_path)) = (packets_type, packets_path) else {
+            continue;
+        };
+        let file = resolve_path(packets_path, root);
+
+        let source: Box<dyn TrafficSource> = if packets_type == "pcap_stream" {
+            let stream = Box::new(PcapTrafficSource::open(&file)?);
+            packet_completion::register_streaming_source(world, spec.id);
+            if spec.selector.takes_everything() {
+                stream
+            } else {
+                Box::new(SelectedTrafficSource::new(stream, spec.selector))
+            }
+        } else {
+            let packets = match spec.own {
+                Some(own) => read_packets(own, packets_type, &file)?,
+                None => {
+                    if shared.is_none() {
+                        shared = Some(read_packets(meta, packets_type, &file)?);
+                    }
+                    shared.clone().unwrap_or_default()
+                }
+            };
+            let packets: Vec<Packet> = packets
+                .into_iter()
+                .filter(|packet| spec.selector.matches(packet))
+                .collect();
+            packet_completion::register_source_core_packets(world, spec.id, &packets);
+            Box::new(VecTrafficSource::new(packets))
+        };
+        if let Some(internet) = world
+            .storage
+            .get_mut(spec.id)
+            .and_then(|b| b.as_any_mut().downcast_mut::<Internet>())
+        {
+            internet.set_source(source);
+        }
+    }
+    Ok(())
+}
+
+/// Traffic of one `Internet`: `own` is the `sources` entry when it names its own
+/// `packetsType`/`packetsPath` (and `reassembleTcp`/`flowOptions`); `None` takes the shared
+/// stage traffic from the meta.
+struct SourceSpec<'a> {
+    id: BuildingId,
+    own: Option<&'a Map<String, Value>>,
+    selector: TrafficSelector,
+}
+
+/// `meta.sources`: one entry per `Internet`, found by its `x`/`y`. An entry with its own
+/// `packetsPath` loads that file; otherwise it takes the shared stage traffic. `cidr` and
+/// `tag` narrow it to the packets from those source addresses or with that tag.
+fn source_specs<'a>(world: &World, sources: &'a Value) -> Result<Vec<SourceSpec<'a>>, String> {
+    let entries = sources
+        .as_array()
+        .ok_or_else(|| "'sources' は配列である必要があります".to_string())?;
+    entries
+        .iter()
+        .map(|entry| {
+            let entry = entry
+                .as_object()
+                .ok_or_else(|| "'sources' の要素はオブジェクトである必要があります".to_string())?;
+            let (Some(x), Some(y)) = (
+                entry.get("x").and_then(as_i32),
+                entry.get("y").and_then(as_i32),
+            ) else {
+                return Err("'sources' の要素に x / y がありません".to_string());
+            };
+            let id = world
+                .get_building_id_at(&Vec2i { x, y })
+                .filter(|&id| {
+                    world
+                        .get_building(id)
+                        .is_some_and(|b| b.building_type() == BuildingType::Internet)
+                })
+                .ok_or_else(|| format!("({}, {}) に Internet がありません", x, y))?;
+            let cidr = entry
+                .get("cidr")
+                .and_then(Value::as_str)
+                .map(Cidr::parse)
+                .transpose()?;
+            let tag = entry.get("tag").and_then(Value::as_str).map(String::from);
+            Ok(SourceSpec {
+                id,
+                own: entry.contains_key("packetsPath").then_some(entry),
+                selector: TrafficSelector { cidr, tag },
+            })
+        })
+        .collect()
+}
+
+/// Packets of a non-streamed traffic file, with `options` holding `reassembleTcp` and
+/// `flowOptions`.
+fn read_packets(
+    options: &Map<String, Value>,
+    packets_type: &str,
+    file: &Path,
+) -> Result<Vec<Packet>, String> {
+    let packets = match packets_type {
+        "json" => {
+            let text = read(file)?;
+            let entries = traffic_schema::parse_traffic_json(&text, ParseOptions::default())
+                .map_err(|err| err.to_string())?;
+            packets_from_entries(entries)
+        }
+        "pcap" => {
+            let reassemble = options
+                .get("reassembleTcp")
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            read_pcap(file, reassemble)?
+        }
+        "zeek" | "flow_csv" => {
+            let default_preset = if packets_type == "zeek" {
+                "zeek"
+            } else {
+                "generic"
+            };
+            let options = FlowImportOptions::from_json(options.get("flowOptions"), default_preset)?;
+            let text = read(file)?;
+            let entries = if packets_type == "zeek" {
+                flow_import::parse_zeek_conn(&text, &options)
+            } else {
+                flow_import::parse_flow_csv(&text, &options)
+            }
+            .map_err(|err| err.to_string())?;
+            packets_from_entries(entries)
+        }
+        other => return Err(format!("不明な packetsType: '{}'", other)),
+    };
+    Ok(packets)
+}
+
+fn read(file: &Path) -> Result<String, String> {
+    fs::read_to_string(file)
+        .map_err(|err| format!("ファイル '{}' を読み込めません: {}", file.display(), err))
+}
+
+/// Same conversion as `JsonLoader`: sorted by timestamp and rebased to start at `0`.
+fn packets_from_entries(mut entries: Vec<TrafficEntry>) -> Vec<Packet> {
+    traffic_schema::sort_and_normalize(&mut entries);
+    entries
+        .into_iter()
+        .map(|entry| {
+            let mut packet = Packet::new(
+                entry.src_ip,
+                entry.dst_ip,
+                entry.src_port,
+                entry.dst_port,
+                Protocol::from_ip_number(entry.protocol as i64),
+                entry.size,
+                entry.payload,
+            );
+            packet.label = entry.label;
+            packet.timestamp = entry.timestamp.unwrap_or(0);
+            packet.tag = entry.tag;
+            packet
+        })
+        .collect()
+}
+
+/// Same conversion as `PcapCapture::to_traffic` (and `to_traffic_reassembled`).
+fn read_pcap(file: &Path, reassemble: bool) -> Result<Vec<Packet>, String> {
+    let handle = fs::File::open(file)
+        .map_err(|err| format!("ファイル '{}' を開けません: {}", file.display(), err))?;
+    let mut reader =
+        PcapReader::new(handle).map_err(|err| format!("pcapヘッダーが不正です: {}", err))?;
+
+    let mut reassembler = TcpReassembler::new();
+    let mut packets = Vec::new();
+    while let Some(frame) = reader.next_packet() {
+        // The reader cannot skip past a broken record, so stop at the first one.
+        let Ok(frame) = frame else {
+            break;
+        };
+        if reassemble {
+            reassembler.push_frame(&frame.data);
+        }
+        let Some(parsed) = parse_packet_from_bytes(&frame.data) else {
+            continue;
+        };
+        let mut packet = Packet::new(
+            parsed.src_ip,
+            parsed.dst_ip,
+            parsed.src_port,
+            parsed.dst_port,
+            Protocol::from_ip_number(parsed.protocol as i64),
+            frame.orig_len,
+            parsed.payload,
+        );
+        packet.timestamp = frame.timestamp.as_micros() as i64;
+        packets.push(packet);
+    }
+
+    packets.sort_by_key(|packet| packet.timestamp);
+    let baseline = packets.first().map(|packet| packet.timestamp);
+    for packet in packets.iter_mut() {
+        packet.timestamp = normalize_timestamp(packet.timestamp, baseline);
+    }
+    if reassemble {
+        attach_streams(&mut packets, &reassembler.finish());
+    }
+    Ok(packets)
+}
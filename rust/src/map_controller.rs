@@ -1,26 +1,37 @@
-use godot::classes::{INode, Json, Node, ResourceLoader};
+use godot::classes::{FileAccess, INode, Json, Node, ProjectSettings};
 use godot::prelude::*;
 use std::cell::RefCell;
 use std::collections::HashMap;
 
 use crate::core::building::BuildingType;
-use crate::core::building_defs::building;
+use crate::core::building_registry::{self, building_type_from_id, registry};
 use crate::core::dto::Vec2i as CoreVec2i;
 use crate::logic::building_map::BuildingMap;
 use crate::logic::connection_graph::{ConnectionEdge, ConnectionGraph, OutputRole};
-use crate::packet::{JsonLoader, PcapLoader};
 
 use crate::core::building::Building;
+use crate::core::buildings::analyzer::Analyzer;
 use crate::core::buildings::conveyor::{Conveyor, EntrySide};
-use crate::core::buildings::datacenter::Datacenter;
-use crate::core::buildings::internet::Internet;
-use crate::core::buildings::recycle_bin::RecycleBin;
-use crate::core::dto::{BuildingId, WorldEvent};
+use crate::core::buildings::datacenter::{Datacenter, DatacenterConfig, HostedService};
+use crate::core::buildings::honeypot::AttackerProfile;
+use crate::core::buildings::merger::{MERGER_INPUTS, Merger, MergerConfig, MergerMode};
+use crate::core::buildings::splitter::{SPLITTER_OUTPUTS, Splitter, SplitterConfig, SplitterMode};
+use crate::core::buildings::tunnel::{TunnelExit, facing_step};
+use crate::core::dto::{BuildingId, DropReason, WorldEvent};
+use crate::logic::blocklist::Blocklists;
 use crate::logic::building_storage::BuildingStorage;
-use crate::logic::packet_completion;
-
-use crate::core::buildings::filters::content_filter::{ContentFilter, ContentFilterConfig};
-use crate::core::buildings::filters::ip_filter::{IpFilter, IpFilterConfig, IpFilterDirection};
+use crate::logic::edit_history::EditHistory;
+use crate::logic::fixed_step::{FixedStep, TICK_SECONDS};
+use crate::logic::packet_completion::{self, TrafficPlan};
+use crate::logic::replay::ReplayRecorder;
+use crate::logic::stage;
+
+use crate::core::buildings::filters::content_filter::{
+    ContentFilter, ContentFilterConfig, ContentMatchScope,
+};
+use crate::core::buildings::filters::ip_filter::{
+    IpBlocklistConfig, IpFilter, IpFilterConfig, IpFilterDirection,
+};
 use crate::core::buildings::filters::length_filter::{
     LengthFilter, LengthFilterConfig, LengthFilterDirection,
 };
@@ -28,6 +39,13 @@ use crate::core::buildings::filters::port_filter::{
     PortFilter, PortFilterConfig, PortFilterDirection,
 };
 use crate::core::buildings::filters::protocol_filter::{ProtocolFilter, ProtocolFilterConfig};
+use crate::core::buildings::filters::rate_limiter::{RateLimitKey, RateLimiter, RateLimiterConfig};
+use crate::core::buildings::filters::scan_detector::{
+    BlocklistFeed, ScanDetector, ScanDetectorConfig,
+};
+use crate::core::buildings::filters::stateful_firewall::{
+    FirewallService, StatefulFirewall, StatefulFirewallConfig,
+};
 use crate::core::packet::Protocol;
 fn variant_to_i32(value: &Variant) -> Option<i32> {
     if let Ok(i) = value.try_to::<i32>() {
@@ -55,7 +73,25 @@ fn variant_to_i32(value: &Variant) -> Option<i32> {
     None
 }
 
+mod blocklists;
+mod building_update;
+mod honeypots;
+mod layout_edit;
+mod packet_drops;
 mod packet_export;
+mod packet_trace;
+mod snapshots;
+
+pub use crate::core::building_config::BuildingConfig;
+use building_update::new_building;
+pub use layout_edit::{AppliedEdit, LayoutEdit};
+pub use packet_drops::{DropStats, DroppedPacket};
+pub use packet_export::PcapExportScope;
+pub use packet_trace::PacketHop;
+use packet_trace::PacketTraces;
+pub(crate) use packet_trace::set_packet_id;
+use snapshots::Snapshots;
+pub use snapshots::{MAX_SNAPSHOTS, SNAPSHOT_INTERVAL};
 
 pub struct World {
     pub storage: BuildingStorage,
@@ -64,6 +100,18 @@ pub struct World {
     next_id: u64,
     events: Vec<WorldEvent>,
     route_counters: HashMap<(BuildingId, OutputRole), usize>,
+    /// Number of `update` calls so far.
+    tick: u64,
+    traces: PacketTraces,
+    dropped: Vec<DroppedPacket>,
+    drop_penalty: u32,
+    drop_penalty_total: u64,
+    honeypot_reward: u32,
+    /// Traffic the stage expects its `Internet`s to deliver, for completion.
+    plan: TrafficPlan,
+    snapshots: Snapshots,
+    /// Named IP blocklists filled by detectors and referenced by IP filters.
+    blocklists: Blocklists,
     pub score: u32,
 }
 
@@ -82,25 +130,120 @@ impl World {
             next_id: 0,
             events: Vec::new(),
             route_counters: HashMap::new(),
+            tick: 0,
+            traces: PacketTraces::default(),
+            dropped: Vec::new(),
+            drop_penalty: 0,
+            drop_penalty_total: 0,
+            honeypot_reward: 0,
+            plan: TrafficPlan::default(),
+            snapshots: Snapshots::default(),
+            blocklists: Blocklists::default(),
             score: 0,
         }
     }
 
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn traffic_plan(&self) -> &TrafficPlan {
+        &self.plan
+    }
+
+    pub fn traffic_plan_mut(&mut self) -> &mut TrafficPlan {
+        &mut self.plan
+    }
+
+    /// Place the first block of `building_type` (see `BuildingRegistry::by_type`).
     pub fn place_building(&mut self, pos: CoreVec2i, building_type: BuildingType, rotation: i32) {
+        let def = registry().by_type(building_type);
+        self.add_placed_building(new_building(self.next_id, pos, def, rotation));
+    }
+
+    /// Place block `block_id` of the building registry; unknown ids place nothing.
+    pub fn place_block(&mut self, pos: CoreVec2i, block_id: i32, rotation: i32) {
+        if let Some(def) = registry().by_id(block_id) {
+            self.add_placed_building(new_building(self.next_id, pos, def, rotation));
+        }
+    }
+
+    pub fn place_rate_limiter_with_config(
+        &mut self,
+        pos: CoreVec2i,
+        rotation: i32,
+        config: RateLimiterConfig,
+    ) {
         let id = self.next_id;
+        self.add_placed_building(Box::new(RateLimiter::new_with_config(
+            id, pos, rotation, config,
+        )));
+    }
 
-        let building: Box<dyn Building> = match building_type {
-            BuildingType::Conveyor => Box::new(Conveyor::new(id, pos, rotation)),
-            BuildingType::Internet => Box::new(Internet::new(id, pos, rotation)),
-            BuildingType::Datacenter => Box::new(Datacenter::new(id, pos, rotation)),
-            BuildingType::RecycleBin => Box::new(RecycleBin::new(id, pos, rotation)),
-            BuildingType::IpFilter => Box::new(IpFilter::new(id, pos, rotation)),
-            BuildingType::PortFilter => Box::new(PortFilter::new(id, pos, rotation)),
-            BuildingType::LengthFilter => Box::new(LengthFilter::new(id, pos, rotation)),
-            BuildingType::ProtocolFilter => Box::new(ProtocolFilter::new(id, pos, rotation)),
-            BuildingType::ContentFilter => Box::new(ContentFilter::new(id, pos, rotation)),
-            _ => return, // or handle error
-        };
+    pub fn place_stateful_firewall_with_config(
+        &mut self,
+        pos: CoreVec2i,
+        rotation: i32,
+        config: StatefulFirewallConfig,
+    ) {
+        let id = self.next_id;
+        self.add_placed_building(Box::new(StatefulFirewall::new_with_config(
+            id, pos, rotation, config,
+        )));
+    }
+
+    pub fn place_scan_detector_with_config(
+        &mut self,
+        pos: CoreVec2i,
+        rotation: i32,
+        config: ScanDetectorConfig,
+    ) {
+        let id = self.next_id;
+        self.add_placed_building(Box::new(ScanDetector::new_with_config(
+            id, pos, rotation, config,
+        )));
+    }
+
+    pub fn place_datacenter_with_config(
+        &mut self,
+        pos: CoreVec2i,
+        rotation: i32,
+        config: DatacenterConfig,
+    ) {
+        let id = self.next_id;
+        self.add_placed_building(Box::new(Datacenter::new_with_config(
+            id, pos, rotation, config,
+        )));
+    }
+
+    pub fn place_splitter_with_config(
+        &mut self,
+        pos: CoreVec2i,
+        rotation: i32,
+        config: SplitterConfig,
+    ) {
+        let id = self.next_id;
+        self.add_placed_building(Box::new(Splitter::new_with_config(
+            id, pos, rotation, config,
+        )));
+    }
+
+    pub fn place_merger_with_config(
+        &mut self,
+        pos: CoreVec2i,
+        rotation: i32,
+        config: MergerConfig,
+    ) {
+        let id = self.next_id;
+        self.add_placed_building(Box::new(Merger::new_with_config(id, pos, rotation, config)));
+    }
+
+    /// `building` (created with id `next_id`) を配置する。他の建物と重なる場合は何もしない。
+    fn add_placed_building(&mut self, building: Box<dyn Building>) {
+        let id = building.id();
+        let pos = building.position();
+        let rotation = building.rotation();
+        let building_type = building.building_type();
         let size = building.get_size();
 
         // 衝突検知
@@ -132,7 +275,7 @@ impl World {
             }
         }
 
-        self.rebuild_connections();
+        self.update_connections_at(pos);
 
         self.events.push(WorldEvent::BuildingPlaced {
             id,
@@ -183,8 +326,8 @@ impl World {
             }
         }
 
-        // 接続を再構築
-        self.rebuild_connections();
+        // 接続を差分更新
+        self.update_connections_at(pos);
 
         self.events.push(WorldEvent::BuildingPlaced {
             id,
@@ -232,7 +375,7 @@ impl World {
             }
         }
 
-        self.rebuild_connections();
+        self.update_connections_at(pos);
 
         self.events.push(WorldEvent::BuildingPlaced {
             id,
@@ -280,7 +423,7 @@ impl World {
             }
         }
 
-        self.rebuild_connections();
+        self.update_connections_at(pos);
 
         self.events.push(WorldEvent::BuildingPlaced {
             id,
@@ -331,7 +474,7 @@ impl World {
             }
         }
 
-        self.rebuild_connections();
+        self.update_connections_at(pos);
 
         self.events.push(WorldEvent::BuildingPlaced {
             id,
@@ -382,7 +525,7 @@ impl World {
             }
         }
 
-        self.rebuild_connections();
+        self.update_connections_at(pos);
 
         self.events.push(WorldEvent::BuildingPlaced {
             id,
@@ -394,6 +537,7 @@ impl World {
 
     pub fn remove_building(&mut self, pos: &CoreVec2i) {
         if let Some(id) = self.map.get(pos) {
+            let mut affected = Vec::new();
             if let Some(building) = self.storage.get(id) {
                 let size = building.get_size();
                 let base_pos = building.position();
@@ -407,10 +551,14 @@ impl World {
                         self.map.remove(&tile_pos);
                     }
                 }
+
+                affected = self.graph.remove_node(building, &self.map, &self.storage);
             }
 
             self.storage.remove(id);
-            self.graph.remove_node(id);
+            self.graph
+                .recompute_outputs(&affected, &self.map, &self.storage);
+            self.prune_route_counters(&affected);
             self.route_counters
                 .retain(|(tracked_id, _), _| *tracked_id != id);
 
@@ -420,10 +568,21 @@ impl World {
     }
 
     pub fn update(&mut self, delta: f32) {
+        self.take_snapshot_if_due();
+        self.tick += 1;
+
         // 1. 内部状態更新フェーズ
         for building in self.storage.iter_mut() {
             let old_progress = building.get_progress();
             building.update(delta);
+            if building.building_type() == BuildingType::Internet {
+                let origin = (building.id(), BuildingType::Internet);
+                for packet in building.get_packets() {
+                    if let Some(packet_id) = packet.id {
+                        self.traces.record_spawn(packet_id, self.tick, origin);
+                    }
+                }
+            }
             let new_progress = building.get_progress();
             if (new_progress - old_progress).abs() > f32::EPSILON {
                 self.events.push(WorldEvent::BuildingProgressUpdated {
@@ -432,9 +591,10 @@ impl World {
                 });
             }
         }
+        self.expire_blocklists(delta);
 
         // 2. 転送決定フェーズ (不変)
-        let mut decisions: Vec<(BuildingId, ConnectionEdge)> = Vec::new();
+        let mut decisions: Vec<(BuildingId, ConnectionEdge, Option<bool>)> = Vec::new();
         let mut packets_to_drop = Vec::new();
 
         let potential_sources: Vec<BuildingId> = self.storage.iter().map(|b| b.id()).collect();
@@ -447,7 +607,7 @@ impl World {
             };
 
             let Some(edges) = self.graph.get_outputs(from_id) else {
-                packets_to_drop.push(from_id);
+                packets_to_drop.push((from_id, DropReason::NoOutputs));
                 continue;
             };
             let mut default_edges = Vec::new();
@@ -457,6 +617,10 @@ impl World {
             let mut has_any_accepting_targets = false;
 
             for edge in edges.iter() {
+                // Mirror outputs only ever get copies, sent when the original leaves.
+                if edge.role == OutputRole::Mirror {
+                    continue;
+                }
                 if let Some(to_building) = self.storage.get(edge.to_id) {
                     has_any_targets = true;
                     if !to_building.can_accept(&packet_to_offload, source_pos) {
@@ -471,64 +635,140 @@ impl World {
                     OutputRole::Default => default_edges.push(*edge),
                     OutputRole::FilterMatch => match_edges.push(*edge),
                     OutputRole::FilterMismatch => mismatch_edges.push(*edge),
+                    OutputRole::Mirror => {}
                 }
             }
 
-            let selected_edge = select_edge_for_building(
-                from_id,
-                building_type,
-                filter_result,
-                &mut self.route_counters,
-                &default_edges,
-                &match_edges,
-                &mismatch_edges,
-            );
+            let selected_edge = if building_type == BuildingType::Splitter {
+                self.storage
+                    .get_mut(from_id)
+                    .and_then(|splitter| select_splitter_edge(splitter, &self.map, &default_edges))
+            } else {
+                select_edge_for_building(
+                    from_id,
+                    filter_result,
+                    &mut self.route_counters,
+                    &default_edges,
+                    &match_edges,
+                    &mismatch_edges,
+                )
+            };
 
             if let Some(edge) = selected_edge {
-                decisions.push((from_id, edge));
+                decisions.push((from_id, edge, filter_result));
             } else if !has_any_targets {
-                packets_to_drop.push(from_id);
-            } else if !has_any_accepting_targets {
-                // All targets are temporarily full; keep packet queued for a later tick.
+                packets_to_drop.push((from_id, DropReason::NoOutputs));
+            } else if !has_any_accepting_targets || building_type == BuildingType::Splitter {
+                // All targets are temporarily full, or a splitter's rule is waiting for an
+                // output it allows; keep packet queued for a later tick.
                 continue;
             } else {
                 // Reaching here means there were accepting targets but routing failed; drop as a safeguard.
-                packets_to_drop.push(from_id);
+                packets_to_drop.push((from_id, DropReason::RoutingFailed));
             }
         }
 
         // 3. 転送実行フェーズ (可変)
-        for (from_id, edge) in decisions {
+        for (from_id, edge, filter_result) in decisions {
             if let Some((from_building, to_building)) =
                 self.storage.get_two_mut(from_id, edge.to_id)
             {
                 let packet = from_building.offload();
+                if let Some(packet_id) = packet.id {
+                    self.traces.record_transfer(
+                        packet_id,
+                        self.tick,
+                        (from_id, from_building.building_type()),
+                        filter_result,
+                        (edge.to_id, to_building.building_type()),
+                    );
+                }
                 let progress_start = packet.progress;
                 let source_pos = from_building.position();
                 let event_packet = packet.clone();
-                to_building.accept(packet, source_pos);
+                let to_honeypot = to_building.building_type() == BuildingType::Honeypot;
+                // Datacenters and analyzers hand back the packets they could not take.
+                let may_reject = matches!(
+                    to_building.building_type(),
+                    BuildingType::Datacenter | BuildingType::Analyzer
+                );
+                deliver(to_building, packet, source_pos);
+                if to_honeypot {
+                    self.reward_honeypot_catch(&event_packet);
+                }
 
                 self.events.push(WorldEvent::PacketMoved {
-                    packet: event_packet,
+                    packet: event_packet.clone(),
                     from_id,
                     to_id: edge.to_id,
                     progress_start,
                 });
+                if may_reject {
+                    self.drop_rejected_packets(edge.to_id);
+                }
+                match self.storage.get(from_id).map(|b| b.building_type()) {
+                    Some(BuildingType::Tap) => self.send_mirror_copy(from_id, &event_packet),
+                    Some(BuildingType::ScanDetector) => self.publish_detections(from_id),
+                    _ => {}
+                }
             }
         }
 
         // 4. パケット破棄フェーズ
-        for id in packets_to_drop {
+        for (id, reason) in packets_to_drop {
             if let Some(building) = self.storage.get_mut(id)
                 && building.building_type() != BuildingType::RecycleBin
                 && building.can_offload()
             {
-                building.offload(); // パケットを破棄
-                // ここでイベントを発行することも可能
+                let building_type = building.building_type();
+                let packet = building.offload(); // パケットを破棄
+                self.record_drop(packet, id, building_type, reason);
+                self.publish_detections(id);
             }
         }
     }
 
+    /// Sends a copy of `packet` to one of the tap's mirror outputs, taking turns between
+    /// them. The copy is skipped when the chosen output is full, so the tap never waits.
+    fn send_mirror_copy(&mut self, tap_id: BuildingId, packet: &crate::core::packet::Packet) {
+        let mirror_edges: Vec<ConnectionEdge> = self
+            .graph
+            .get_outputs(tap_id)
+            .map(|edges| {
+                edges
+                    .iter()
+                    .filter(|edge| edge.role == OutputRole::Mirror)
+                    .copied()
+                    .collect()
+            })
+            .unwrap_or_default();
+        let target = select_edge_round_robin(
+            tap_id,
+            OutputRole::Mirror,
+            &mut self.route_counters,
+            &mirror_edges,
+        );
+        let Some(edge) = target else {
+            return;
+        };
+        let Some((tap, to_building)) = self.storage.get_two_mut(tap_id, edge.to_id) else {
+            return;
+        };
+        let mut copy = packet.mirror_copy(tap_id);
+        copy.progress = 0.0;
+        let source_pos = tap.position();
+        if !to_building.can_accept(&copy, source_pos) {
+            return;
+        }
+        deliver(to_building, copy.clone(), source_pos);
+        self.events.push(WorldEvent::PacketMoved {
+            packet: copy,
+            from_id: tap_id,
+            to_id: edge.to_id,
+            progress_start: 0.0,
+        });
+    }
+
     pub fn drain_events(&mut self) -> Vec<WorldEvent> {
         std::mem::take(&mut self.events)
     }
@@ -543,12 +783,42 @@ impl World {
             .retain(|(id, _), _| self.graph.get_outputs(*id).is_some());
     }
 
+    /// 建物 `pos` の配置に合わせて接続を差分更新する。
+    fn update_connections_at(&mut self, pos: CoreVec2i) {
+        let affected = self
+            .graph
+            .update_connections_at(pos, &self.map, &self.storage);
+        self.prune_route_counters(&affected);
+    }
+
+    /// 出力を失った建物の振り分けカウンタを捨てる (`rebuild_connections` と同じ規則)。
+    fn prune_route_counters(&mut self, ids: &[BuildingId]) {
+        for &id in ids {
+            if self.graph.get_outputs(id).is_none() {
+                self.route_counters
+                    .retain(|(tracked_id, _), _| *tracked_id != id);
+            }
+        }
+    }
+
+    /// 差分更新の結果と突き合わせるための、現在の配置から作り直した接続グラフ。
+    #[cfg(test)]
+    pub fn rebuilt_connection_graph(&self) -> ConnectionGraph {
+        let mut graph = ConnectionGraph::new();
+        graph.rebuild(&self.map, &self.storage);
+        graph
+    }
+
+    #[cfg(test)]
+    pub fn connection_graph(&self) -> &ConnectionGraph {
+        &self.graph
+    }
+
     #[cfg(test)]
     pub fn get_output_connections(&self, from_id: BuildingId) -> Option<&Vec<ConnectionEdge>> {
         self.graph.get_outputs(from_id)
     }
 
-    #[cfg(test)]
     pub fn get_building_id_at(&self, pos: &CoreVec2i) -> Option<BuildingId> {
         self.map.get(pos)
     }
@@ -571,44 +841,34 @@ impl World {
         let packet = packets.into_iter().next()?;
         let building_type = building.building_type();
         let source_pos = building.position();
-        let filter_result = filter_packet(building, &packet);
+        let filter_result = building.filter_packet(&packet, &self.blocklists);
 
         Some((building_type, source_pos, filter_result, packet))
     }
 }
 
+/// Edge for a packet leaving `from_id`: filters send it to their match or mismatch outputs
+/// by `filter_result`, other buildings to their default outputs, each round-robin. When the
+/// preferred outputs have no edge, the others are tried in turn.
 fn select_edge_for_building(
     from_id: BuildingId,
-    building_type: BuildingType,
     filter_result: Option<bool>,
     counters: &mut HashMap<(BuildingId, OutputRole), usize>,
     default_edges: &[ConnectionEdge],
     match_edges: &[ConnectionEdge],
     mismatch_edges: &[ConnectionEdge],
 ) -> Option<ConnectionEdge> {
-    let primary_choice = match (building_type, filter_result) {
-        (
-            BuildingType::IpFilter
-            | BuildingType::PortFilter
-            | BuildingType::LengthFilter
-            | BuildingType::ProtocolFilter
-            | BuildingType::ContentFilter,
-            Some(true),
-        ) => select_edge_round_robin(from_id, OutputRole::FilterMatch, counters, match_edges),
-        (
-            BuildingType::IpFilter
-            | BuildingType::PortFilter
-            | BuildingType::LengthFilter
-            | BuildingType::ProtocolFilter
-            | BuildingType::ContentFilter,
-            Some(false),
-        ) => select_edge_round_robin(
+    let primary_choice = match filter_result {
+        Some(true) => {
+            select_edge_round_robin(from_id, OutputRole::FilterMatch, counters, match_edges)
+        }
+        Some(false) => select_edge_round_robin(
             from_id,
             OutputRole::FilterMismatch,
             counters,
             mismatch_edges,
         ),
-        _ => select_edge_round_robin(from_id, OutputRole::Default, counters, default_edges),
+        None => select_edge_round_robin(from_id, OutputRole::Default, counters, default_edges),
     };
 
     primary_choice
@@ -626,6 +886,29 @@ fn select_edge_for_building(
         })
 }
 
+/// Edge for a splitter's next packet by its rule. `accepting_edges` are the outputs that can
+/// take the packet now; the rule's round-robin state lives in the splitter.
+fn select_splitter_edge(
+    building: &mut dyn Building,
+    map: &BuildingMap,
+    accepting_edges: &[ConnectionEdge],
+) -> Option<ConnectionEdge> {
+    let layout = building.layout();
+    let (pos, rotation) = (building.position(), building.rotation());
+    let targets = SPLITTER_OUTPUTS.map(|name| {
+        let port = layout.port(name)?;
+        let to_id = map.get(&layout.port_pos(port, pos, rotation))?;
+        accepting_edges
+            .iter()
+            .find(|edge| edge.to_id == to_id)
+            .copied()
+    });
+
+    let splitter = building.as_any_mut().downcast_mut::<Splitter>()?;
+    let output = splitter.choose_output(targets.map(|edge| edge.is_some()))?;
+    targets[output]
+}
+
 fn select_edge_round_robin(
     from_id: BuildingId,
     role: OutputRole,
@@ -643,32 +926,102 @@ fn select_edge_round_robin(
     Some(edge)
 }
 
-fn filter_packet(building: &dyn Building, packet: &crate::core::packet::Packet) -> Option<bool> {
-    match building.building_type() {
-        BuildingType::IpFilter => building
-            .as_any()
-            .downcast_ref::<IpFilter>()
-            .map(|f| f.filter(packet)),
-        BuildingType::PortFilter => building
-            .as_any()
-            .downcast_ref::<PortFilter>()
-            .map(|f| f.filter(packet)),
-        BuildingType::LengthFilter => building
-            .as_any()
-            .downcast_ref::<LengthFilter>()
-            .map(|f| f.filter(packet)),
-        BuildingType::ProtocolFilter => building
-            .as_any()
-            .downcast_ref::<ProtocolFilter>()
-            .map(|f| f.filter(packet)),
-        BuildingType::ContentFilter => building
-            .as_any()
-            .downcast_ref::<ContentFilter>()
-            .map(|f| f.filter(packet)),
-        _ => None,
+/// Hand `packet` over to `to`. Tap copies are only for analyzers, so the sinks that serve,
+/// score or profile traffic discard them; the original is delivered on its own.
+fn deliver(to: &mut dyn Building, packet: crate::core::packet::Packet, source_pos: CoreVec2i) {
+    let discards_copies = matches!(
+        to.building_type(),
+        BuildingType::Datacenter | BuildingType::Honeypot | BuildingType::RecycleBin
+    );
+    if packet.is_mirror_copy() && discards_copies {
+        return;
+    }
+    to.accept(packet, source_pos);
+}
+
+/// Services of a firewall rule, written as `[{"protocol": "tcp", "port": 22}, ...]`.
+fn firewall_services_from_variant(services: &VariantArray) -> Option<Vec<FirewallService>> {
+    services
+        .iter_shared()
+        .map(|entry| {
+            let entry = entry.try_to::<Dictionary>().ok()?;
+            let protocol = match entry
+                .get("protocol")?
+                .try_to::<GString>()
+                .ok()?
+                .to_string()
+                .as_str()
+            {
+                "tcp" => Protocol::Tcp,
+                "udp" => Protocol::Udp,
+                "unknown" => Protocol::Unknown,
+                _ => return None,
+            };
+            let port = u16::try_from(entry.get("port")?.try_to::<i64>().ok()?).ok()?;
+            Some(FirewallService { protocol, port })
+        })
+        .collect()
+}
+
+/// Services of a datacenter rule, written as `[{"protocol": "tcp", "port": 443}, ...]`. An
+/// entry may add `"ip"` to open the port on that address only.
+fn hosted_services_from_variant(services: &VariantArray) -> Option<Vec<HostedService>> {
+    let firewall_services = firewall_services_from_variant(services)?;
+    services
+        .iter_shared()
+        .zip(firewall_services)
+        .map(|(entry, service)| {
+            let ip = match entry.try_to::<Dictionary>().ok()?.get("ip") {
+                Some(ip) => Some(ip.try_to::<GString>().ok()?.to_string()),
+                None => None,
+            };
+            Some(HostedService {
+                protocol: service.protocol,
+                port: service.port,
+                ip: ip.filter(|ip| !ip.is_empty()),
+            })
+        })
+        .collect()
+}
+
+fn protocol_name(protocol: &Protocol) -> &'static str {
+    match protocol {
+        Protocol::Tcp => "tcp",
+        Protocol::Udp => "udp",
+        Protocol::Unknown => "unknown",
     }
 }
 
+fn attacker_profile_to_dictionary(profile: &AttackerProfile) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("source_ip", profile.source_ip.as_str());
+    dict.set("packets", profile.packets as i64);
+    dict.set("attack_packets", profile.attack_packets as i64);
+    dict.set("bytes", profile.bytes as i64);
+    let mut dest_ports = Dictionary::new();
+    for (port, count) in &profile.dest_ports {
+        dest_ports.set(*port as i64, *count as i64);
+    }
+    dict.set("dest_ports", dest_ports);
+    let dest_ips: VariantArray = profile
+        .dest_ips
+        .iter()
+        .map(|ip| ip.as_str().to_variant())
+        .collect();
+    dict.set("dest_ips", dest_ips);
+    dict.set("tcp", profile.tcp as i64);
+    dict.set("udp", profile.udp as i64);
+    dict.set("other_protocols", profile.other_protocols as i64);
+    let mut signatures = Dictionary::new();
+    for (signature, count) in &profile.signatures {
+        signatures.set(signature.as_str(), *count as i64);
+    }
+    dict.set("signatures", signatures);
+    dict.set("first_seen", profile.first_seen);
+    dict.set("last_seen", profile.last_seen);
+    dict
+}
+
 pub(crate) fn conveyor_packet_position(
     tile_pos: CoreVec2i,
     rotation: i32,
@@ -719,20 +1072,63 @@ pub(crate) fn conveyor_packet_position(
     }
 }
 
-fn building_type_from_id(building_type_id: i32) -> Option<BuildingType> {
-    match building_type_id {
-        building::INTERNET => Some(BuildingType::Internet),
-        building::DATACENTER => Some(BuildingType::Datacenter),
-        building::CONVEYOR => Some(BuildingType::Conveyor),
-        building::IP_FILTER => Some(BuildingType::IpFilter),
-        building::PORT_FILTER => Some(BuildingType::PortFilter),
-        building::LENGTH_FILTER => Some(BuildingType::LengthFilter),
-        building::PROTOCOL_FILTER => Some(BuildingType::ProtocolFilter),
-        building::CONTENT_FILTER => Some(BuildingType::ContentFilter),
-        building::JUNCTION => Some(BuildingType::Junction),
-        building::RECYCLE_BIN => Some(BuildingType::RecycleBin),
-        _ => std::option::Option::None,
-    }
+/// Position of a packet underground between the front edges of its entrance and of the
+/// exit, so it comes out where the next conveyor picks it up.
+pub(crate) fn tunnel_packet_position(
+    entrance_pos: CoreVec2i,
+    exit_pos: CoreVec2i,
+    rotation: i32,
+    progress: f32,
+    tile_size: f32,
+) -> Vector2 {
+    let step = facing_step(rotation);
+    let front_dir = Vector2::new(step.x as f32, step.y as f32);
+    let front_edge = |tile: CoreVec2i| {
+        let center = Vector2::new(
+            (tile.x as f32 + 0.5) * tile_size,
+            (tile.y as f32 + 0.5) * tile_size,
+        );
+        center + front_dir * (tile_size / 2.0)
+    };
+    front_edge(entrance_pos).lerp(front_edge(exit_pos), progress.clamp(0.0, 1.0))
+}
+
+/// Fields of a scan detector rule as `get_filter_rule` reports them.
+fn scan_detector_rule(result: &mut Dictionary, config: &ScanDetectorConfig) {
+    result.set("window_ms", config.window_ms as i64);
+    result.set("port_threshold", config.port_threshold as i64);
+    result.set("host_threshold", config.host_threshold as i64);
+    result.set("grace_ms", config.grace_ms as i64);
+}
+
+/// Row of `get_all_packet_positions` for `packet` drawn at `pos`.
+fn packet_position_entry(packet: &crate::core::packet::Packet, pos: Vector2) -> Variant {
+    let mut dict = Dictionary::new();
+    dict.set("source_ip", packet.source_ip.to_variant());
+    dict.set("dest_ip", packet.dest_ip.to_variant());
+    dict.set("source_port", (packet.source_port as i32).to_variant());
+    dict.set("dest_port", (packet.dest_port as i32).to_variant());
+    dict.set("protocol", (packet.protocol.clone() as i32).to_variant());
+    dict.set("length", (packet.length as i32).to_variant());
+    dict.set("position", pos.to_variant());
+    dict.set("label", packet.label.to_raw().to_variant());
+    set_packet_id(&mut dict, packet.id);
+    dict.to_variant()
+}
+
+/// Index in `ports` of the port named `prefix` + `name`, e.g. `"left"` for `"out_left"`.
+fn port_index(ports: &[&str], prefix: &str, name: &str) -> Option<usize> {
+    ports
+        .iter()
+        .position(|port| port.strip_prefix(prefix) == Some(name))
+}
+
+/// Name of a unit enum variant as written in JSON files.
+fn serde_name<T: serde::Serialize>(value: &T) -> String {
+    serde_json::to_value(value)
+        .ok()
+        .and_then(|value| value.as_str().map(String::from))
+        .unwrap_or_default()
 }
 
 #[derive(GodotClass)]
@@ -742,6 +1138,9 @@ pub struct MapController {
     simulation_started: bool,
     simulation_paused: bool,
     simulation_speed: f32,
+    stepper: FixedStep,
+    recorder: ReplayRecorder,
+    history: EditHistory,
     #[base]
     base: Base<Node>,
 }
@@ -754,23 +1153,59 @@ impl INode for MapController {
             simulation_started: false,
             simulation_paused: false,
             simulation_speed: 1.0,
+            stepper: FixedStep::new(),
+            recorder: ReplayRecorder::default(),
+            history: EditHistory::default(),
             base,
         }
     }
 
     fn process(&mut self, delta: f64) {
-        let _events = {
+        let events = {
             let mut world = self.world.borrow_mut();
             if self.simulation_started && !self.simulation_paused {
-                let scaled_delta = (delta as f32) * self.simulation_speed;
-                world.update(scaled_delta);
+                // Speed changes how many fixed ticks run per frame, never the tick length.
+                let ticks = self.stepper.advance(delta * self.simulation_speed as f64);
+                for _ in 0..ticks {
+                    world.update(TICK_SECONDS);
+                }
             }
             world.drain_events()
         };
+        for event in events {
+            if let WorldEvent::BuildingUpdated {
+                id,
+                pos,
+                building_type,
+                rotation,
+            } = event
+            {
+                let mut info = Dictionary::new();
+                info.set("id", id.to_variant());
+                info.set("pos", Vector2i::from(pos).to_variant());
+                info.set("type", (building_type as i32).to_variant());
+                info.set("rotation", rotation.to_variant());
+                self.base_mut()
+                    .emit_signal("building_updated", &[info.to_variant()]);
+            }
+        }
     }
 }
 
 impl MapController {
+    /// Apply a layout edit and record it for `undo` and `save_replay`.
+    /// Returns whether the edit changed the world.
+    fn apply_edit(&mut self, edit: LayoutEdit) -> bool {
+        let mut world = self.world.borrow_mut();
+        let applied = world.apply_edit(&edit);
+        let changed = applied.is_some();
+        if let Some(applied) = applied {
+            self.history.record(applied);
+        }
+        self.recorder.record(world.tick(), edit);
+        changed
+    }
+
     #[cfg(test)]
     pub fn packet_to_export_row_for_test(
         packet: &crate::core::packet::Packet,
@@ -786,143 +1221,66 @@ impl MapController {
     fn building_placed(info: Variant);
     #[signal]
     fn building_removed(id: i64, pos: Vector2i);
+    /// A building was rotated or changed type in place: `id`, `pos`, `type` and `rotation`.
     #[signal]
-    fn building_updated(updates: VariantArray);
+    fn building_updated(info: Variant);
     #[signal]
     fn packet_moved(info: Variant);
 
+    /// Build the world from the stage map at `map_path` (see `logic::stage` for the format)
+    /// and return its `meta` dictionary, or nil when the stage cannot be loaded.
     #[func]
     pub fn load_map(&mut self, map_path: GString) -> Variant {
         self.reset_world();
+        self.recorder.start(map_path.to_string());
 
-        let mut loader = ResourceLoader::singleton();
-
-        if !loader.exists(&map_path) {
+        if !FileAccess::file_exists(&map_path) {
             godot_error!("Map file not found at path: {}", map_path);
             return Variant::nil();
         }
-
-        let Some(resource) = loader.load(&map_path) else {
-            godot_error!("Failed to load resource from path: {}", map_path);
-            return Variant::nil();
-        };
-
-        let Ok(json_resource) = resource.try_cast::<Json>() else {
-            godot_error!("Loaded resource is not a valid JSON resource: {}", map_path);
-            return Variant::nil();
-        };
-
-        let data = json_resource.get_data();
-        let dict = match data.try_to::<Dictionary>() {
-            Ok(d) => d,
-            Err(_) => {
-                godot_error!("Map data is not a dictionary");
-                return Variant::nil();
-            }
-        };
-
-        let buildings_variant = match dict.get("buildings") {
-            Some(b) => b,
-            None => {
-                godot_error!("'buildings' key not found in map data");
+        let text = FileAccess::get_file_as_string(&map_path);
+        let data: serde_json::Value = match serde_json::from_str(&text.to_string()) {
+            Ok(data) => data,
+            Err(err) => {
+                godot_error!("Map data is not valid JSON: {}", err);
                 return Variant::nil();
             }
         };
 
-        let buildings_array = match buildings_variant.try_to::<VariantArray>() {
-            Ok(a) => a,
-            Err(_) => {
-                godot_error!("'buildings' is not an array");
+        let root = ProjectSettings::singleton().globalize_path("res://");
+        let loaded = match stage::build_stage(&data, std::path::Path::new(&root.to_string())) {
+            Ok(loaded) => loaded,
+            Err(err) => {
+                godot_error!("Failed to load map {}: {}", map_path, err);
                 return Variant::nil();
             }
         };
-
-        let mut world = self.world.borrow_mut();
-        for building_variant in buildings_array.iter_shared() {
-            let building_dict = match building_variant.try_to::<Dictionary>() {
-                Ok(d) => d,
-                Err(_) => {
-                    godot_warn!("Building data is not a dictionary, skipping");
-                    continue;
-                }
-            };
-
-            let x = building_dict.get("x").and_then(|v| variant_to_i32(&v));
-            let y = building_dict.get("y").and_then(|v| variant_to_i32(&v));
-            let block_id = building_dict
-                .get("blockId")
-                .and_then(|v| variant_to_i32(&v));
-            let rotation = building_dict
-                .get("rotation")
-                .and_then(|v| variant_to_i32(&v));
-
-            if let (Some(x), Some(y), Some(block_id), Some(rotation)) = (x, y, block_id, rotation) {
-                if let Some(building_type) = building_type_from_id(block_id) {
-                    world.place_building(CoreVec2i { x, y }, building_type, rotation);
-                } else {
-                    godot_warn!("Invalid blockId: {}, skipping", block_id);
-                }
-            } else {
-                godot_warn!(
-                    "Missing or invalid building data, skipping: {:?}",
-                    building_dict
-                );
-            }
-        }
-
-        world.rebuild_connections();
-
-        let mut return_meta = Dictionary::new();
-        if let Some(meta_var) = dict.get("meta")
-            && let Ok(meta_dict) = meta_var.try_to::<Dictionary>()
-        {
-            return_meta = meta_dict.clone(); // Clone all meta fields to return
-
-            let packets_type = meta_dict
-                .get("packetsType")
-                .and_then(|v| v.try_to::<GString>().ok());
-            let packets_path = meta_dict
-                .get("packetsPath")
-                .and_then(|v| v.try_to::<GString>().ok());
-
-            if let (Some(ptype), Some(ppath)) = (packets_type, packets_path) {
-                let traffic = if ptype.to_string() == "json" {
-                    let mut loader = JsonLoader::new_alloc();
-                    loader.call("load_traffic", &[ppath.to_variant()])
-                } else if ptype.to_string() == "pcap" {
-                    let mut loader = PcapLoader::new_alloc();
-                    loader.call("load_traffic", &[ppath.to_variant()])
-                } else {
-                    Variant::nil()
-                };
-
-                if let Ok(traffic) = traffic.try_to::<Gd<crate::packet::Traffic>>() {
-                    for internet in world.storage.get_internet_buildings_mut() {
-                        internet.set_traffic(traffic.clone());
-                    }
-                    packet_completion::register_planned_traffic(&traffic);
-
-                    //for datacenter in world.storage.get_datacenter_buildings_mut() {
-                    //    datacenter.set_traffic(traffic.clone());
-                    //}  //どのタイミングでコンタミしたかは不明　仕様変更が怖いので一旦CO
-                } else {
-                    godot_warn!("Failed to load traffic data from path: {}", ppath);
-                }
-            }
+        for skipped in &loaded.skipped {
+            godot_warn!("{}, skipping", skipped);
         }
-
-        return_meta.to_variant()
+        self.world.replace(loaded.world);
+
+        // Every meta field goes back to GDScript as written.
+        Json::parse_string(&text)
+            .try_to::<Dictionary>()
+            .ok()
+            .and_then(|dict| dict.get("meta"))
+            .and_then(|meta| meta.try_to::<Dictionary>().ok())
+            .unwrap_or_default()
+            .to_variant()
     }
 
     #[func]
     pub fn place_building(&mut self, pos: Vector2i, building_type_id: i32, rotation: i32) {
-        let Some(building_type) = building_type_from_id(building_type_id) else {
+        if registry().by_id(building_type_id).is_none() {
             godot_warn!("Invalid building_type_id: {}", building_type_id);
             return;
-        };
-        self.world
-            .borrow_mut()
-            .place_building(pos.into(), building_type, rotation);
+        }
+        self.apply_edit(LayoutEdit::Place {
+            pos: pos.into(),
+            block_id: building_type_id,
+            rotation,
+        });
     }
 
     #[func]
@@ -950,9 +1308,11 @@ impl MapController {
             direction: direction_enum,
         };
 
-        self.world
-            .borrow_mut()
-            .place_ip_filter_with_config(pos.into(), rotation, config);
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Ip(config),
+        });
     }
 
     #[func]
@@ -981,9 +1341,11 @@ impl MapController {
             direction: direction_enum,
         };
 
-        self.world
-            .borrow_mut()
-            .place_length_filter_with_config(pos.into(), rotation, config);
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Length(config),
+        });
     }
 
     #[func]
@@ -1002,9 +1364,11 @@ impl MapController {
             protocol: protocol_enum,
         };
 
-        self.world
-            .borrow_mut()
-            .place_protocol_filter_with_config(pos.into(), rotation, config);
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Protocol(config),
+        });
     }
 
     #[func]
@@ -1032,86 +1396,403 @@ impl MapController {
             direction: direction_enum,
         };
 
-        self.world
-            .borrow_mut()
-            .place_port_filter_with_config(pos.into(), rotation, config);
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Port(config),
+        });
     }
 
     #[func]
     pub fn place_content_filter(&mut self, pos: Vector2i, rotation: i32, pattern: GString) {
         let config = ContentFilterConfig {
             pattern: pattern.to_string(),
+            scope: ContentMatchScope::Segment,
         };
 
-        self.world
-            .borrow_mut()
-            .place_content_filter_with_config(pos.into(), rotation, config);
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Content(config),
+        });
     }
 
+    /// Place a rate limiter letting `limit` packets per `key` ("source_ip",
+    /// "destination_port" or "flow") through every `window_ms` milliseconds.
     #[func]
-    pub fn remove_building(&mut self, pos: Vector2i) {
-        let core_pos: CoreVec2i = pos.into();
-        self.world.borrow_mut().remove_building(&core_pos);
-    }
+    pub fn place_rate_limiter(
+        &mut self,
+        pos: Vector2i,
+        rotation: i32,
+        limit: i32,
+        window_ms: i32,
+        key: GString,
+    ) {
+        let Some(key) = RateLimitKey::from_name(&key.to_string()) else {
+            godot_warn!(
+                "Invalid key: {}. Use 'source_ip', 'destination_port' or 'flow'",
+                key
+            );
+            return;
+        };
+        if limit < 0 || window_ms <= 0 {
+            godot_warn!("Invalid rate limit: {} packets per {} ms", limit, window_ms);
+            return;
+        }
 
-    #[func]
-    pub fn reset_world(&mut self) {
-        self.world.replace(World::new());
-        self.simulation_started = false;
-        self.simulation_paused = false;
-        self.simulation_speed = 1.0;
-        packet_completion::clear_planned_packets();
-    }
+        let config = RateLimiterConfig {
+            limit: limit as u32,
+            window_ms: window_ms as u32,
+            key,
+        };
 
-    #[func]
-    pub fn start_heart_beat(&mut self) {
-        self.simulation_started = true;
-        self.simulation_paused = false;
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::RateLimit(config),
+        });
     }
 
+    /// Place a stateful firewall that lets established sessions through and opens new ones
+    /// only to `services` (`[{"protocol": "tcp", "port": 22}, ...]`).
     #[func]
-    pub fn stop_the_world(&mut self) {
-        if self.simulation_started {
-            self.simulation_paused = true;
-        }
-    }
+    pub fn place_stateful_firewall(
+        &mut self,
+        pos: Vector2i,
+        rotation: i32,
+        services: VariantArray,
+    ) {
+        let Some(services) = firewall_services_from_variant(&services) else {
+            godot_warn!("Invalid services. Use [{{\"protocol\": \"tcp\", \"port\": 22}}]");
+            return;
+        };
 
-    #[func]
-    pub fn resume(&mut self) {
-        if self.simulation_started {
-            self.simulation_paused = false;
-        }
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Firewall(StatefulFirewallConfig::new(services)),
+        });
     }
 
+    /// Place a scan detector that flags a source once it reaches more than `port_threshold`
+    /// distinct destination ports or `host_threshold` distinct hosts within `window_ms`
+    /// milliseconds (`0` turns a check off). Flagged sources go to the reject output for
+    /// `grace_ms` milliseconds, or for good when it is `0`.
     #[func]
-    pub fn over_clock(&mut self, speed: f32) {
-        if speed > 0.0 {
-            self.simulation_speed = speed;
-        } else {
+    pub fn place_scan_detector(
+        &mut self,
+        pos: Vector2i,
+        rotation: i32,
+        window_ms: i32,
+        port_threshold: i32,
+        host_threshold: i32,
+        grace_ms: i32,
+    ) {
+        if window_ms <= 0 || port_threshold < 0 || host_threshold < 0 || grace_ms < 0 {
             godot_warn!(
-                "Invalid speed '{}'. Keeping previous value {}",
-                speed,
-                self.simulation_speed
+                "Invalid scan detector rule: window {} ms, {} ports, {} hosts, grace {} ms",
+                window_ms,
+                port_threshold,
+                host_threshold,
+                grace_ms
             );
+            return;
         }
-    }
+
+        let config = ScanDetectorConfig {
+            window_ms: window_ms as u32,
+            port_threshold: port_threshold as u32,
+            host_threshold: host_threshold as u32,
+            grace_ms: grace_ms as u32,
+        };
+
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::ScanDetector(config),
+        });
+    }
+
+    /// Place a datacenter that hosts only `services` (`[{"protocol": "tcp", "port": 443}]`,
+    /// optionally with an `"ip"`; empty hosts everything) and processes `capacity_pps`
+    /// packets per second with room for `queue_size` waiting ones. `capacity_pps` of `0`
+    /// processes packets on arrival.
+    #[func]
+    pub fn place_datacenter(
+        &mut self,
+        pos: Vector2i,
+        rotation: i32,
+        services: VariantArray,
+        capacity_pps: i32,
+        queue_size: i32,
+    ) {
+        let Some(services) = hosted_services_from_variant(&services) else {
+            godot_warn!("Invalid services. Use [{{\"protocol\": \"tcp\", \"port\": 443}}]");
+            return;
+        };
+        if capacity_pps < 0 || queue_size < 0 {
+            godot_warn!(
+                "Invalid datacenter capacity: {} packets/s, queue of {}",
+                capacity_pps,
+                queue_size
+            );
+            return;
+        }
+
+        let config = DatacenterConfig {
+            services,
+            capacity_pps: capacity_pps as u32,
+            queue_size: queue_size as u32,
+        };
+
+        self.apply_edit(LayoutEdit::PlaceFilter {
+            pos: pos.into(),
+            rotation,
+            config: BuildingConfig::Datacenter(config),
+        });
+    }
+
+    #[func]
+    pub fn remove_building(&mut self, pos: Vector2i) {
+        self.apply_edit(LayoutEdit::Remove { pos: pos.into() });
+    }
+
+    /// Turn a building in place, keeping its id and the packets it holds.
+    #[func]
+    pub fn rotate_building(&mut self, building_id: i64, rotation: i32) -> bool {
+        self.apply_edit(LayoutEdit::Rotate {
+            building_id: building_id as u64,
+            rotation,
+        })
+    }
+
+    /// Change a building's block in place, keeping its id. The old rule carries over where
+    /// it fits the new type (see `BuildingConfig::migrate_to`); otherwise a new filter starts
+    /// without a rule, set one with `set_filter_rules`.
+    #[func]
+    pub fn replace_building_type(&mut self, building_id: i64, building_type_id: i32) -> bool {
+        let Some(building_type) = building_type_from_id(building_type_id) else {
+            godot_warn!("Invalid building_type_id: {}", building_type_id);
+            return false;
+        };
+        let building_id = building_id as u64;
+        let config = self
+            .world
+            .borrow()
+            .filter_config(building_id)
+            .and_then(|config| config.migrate_to(building_type));
+        self.apply_edit(LayoutEdit::ReplaceType {
+            building_id,
+            block_id: building_type_id,
+            config,
+        })
+    }
+
+    /// Put `ip` on blocklist `list` for `ttl_ms` milliseconds, or for good when it is 0 or
+    /// less. An existing entry gets the new TTL.
+    #[func]
+    pub fn set_blocklist_entry(&mut self, list: GString, ip: GString, ttl_ms: i64) -> bool {
+        if list.is_empty() || ip.is_empty() {
+            godot_warn!("Blocklist name and IP must not be empty");
+            return false;
+        }
+        self.apply_edit(LayoutEdit::SetBlocklistEntry {
+            list: list.to_string(),
+            ip: ip.to_string(),
+            ttl_ms: (ttl_ms > 0).then(|| ttl_ms.min(u32::MAX as i64) as u32),
+        })
+    }
+
+    #[func]
+    pub fn remove_blocklist_entry(&mut self, list: GString, ip: GString) -> bool {
+        self.apply_edit(LayoutEdit::RemoveBlocklistEntry {
+            list: list.to_string(),
+            ip: ip.to_string(),
+        })
+    }
+
+    /// Names of the blocklists that have entries.
+    #[func]
+    pub fn get_blocklist_names(&self) -> VariantArray {
+        let world = self.world.borrow();
+        world
+            .blocklists()
+            .names()
+            .map(|name| name.to_variant())
+            .collect()
+    }
+
+    /// Entries of blocklist `list` in address order. Each has `ip` and `expires_in_ms`, which
+    /// is -1 for entries that do not expire.
+    #[func]
+    pub fn get_blocklist(&self, list: GString) -> VariantArray {
+        let world = self.world.borrow();
+        world
+            .blocklists()
+            .entries(&list.to_string())
+            .into_iter()
+            .map(|(ip, left)| {
+                let mut entry = Dictionary::new();
+                entry.set("ip", ip);
+                entry.set(
+                    "expires_in_ms",
+                    left.map_or(-1, |secs| (secs * 1000.0) as i64),
+                );
+                entry.to_variant()
+            })
+            .collect()
+    }
+
+    #[func]
+    pub fn reset_world(&mut self) {
+        self.world.replace(World::new());
+        self.simulation_started = false;
+        self.simulation_paused = false;
+        self.simulation_speed = 1.0;
+        self.stepper.reset();
+        self.recorder.clear();
+        self.history.clear();
+    }
+
+    #[func]
+    pub fn start_heart_beat(&mut self) {
+        self.simulation_started = true;
+        self.simulation_paused = false;
+    }
+
+    #[func]
+    pub fn stop_the_world(&mut self) {
+        if self.simulation_started {
+            self.simulation_paused = true;
+        }
+    }
+
+    #[func]
+    pub fn resume(&mut self) {
+        if self.simulation_started {
+            self.simulation_paused = false;
+        }
+    }
+
+    /// Run `ticks` fixed ticks immediately, e.g. to single-step while paused. Returns the
+    /// new tick.
+    #[func]
+    pub fn step(&mut self, ticks: i64) -> i64 {
+        let mut world = self.world.borrow_mut();
+        for _ in 0..ticks.max(0) {
+            world.update(TICK_SECONDS);
+        }
+        world.tick() as i64
+    }
+
+    /// Rewind the simulation to `tick` (see `World::rewind_to`). Returns `false` when no
+    /// snapshot reaches back that far.
+    #[func]
+    pub fn rewind_to(&mut self, tick: i64) -> bool {
+        let tick = tick.max(0) as u64;
+        let result = self.world.borrow_mut().rewind_to(tick);
+        match result {
+            Ok(restored) => {
+                self.stepper.reset();
+                self.recorder.rewind_to(restored);
+                true
+            }
+            Err(err) => {
+                godot_warn!("Cannot rewind: {}", err);
+                false
+            }
+        }
+    }
+
+    #[func]
+    pub fn snapshot_count(&self) -> i64 {
+        self.world.borrow().snapshot_count() as i64
+    }
+
+    #[func]
+    pub fn over_clock(&mut self, speed: f32) {
+        if speed > 0.0 {
+            self.simulation_speed = speed;
+        } else {
+            godot_warn!(
+                "Invalid speed '{}'. Keeping previous value {}",
+                speed,
+                self.simulation_speed
+            );
+        }
+    }
+
+    /// Every block of the building registry, in file order, for the palette UI. Each has its
+    /// `building_type` as the number packet reports use.
+    #[func]
+    pub fn get_building_definitions(&self) -> VariantArray {
+        building_registry::registry()
+            .defs()
+            .iter()
+            .map(|def| {
+                let mut dict = Dictionary::new();
+                dict.set("id", def.id.to_variant());
+                dict.set("key", def.key.to_variant());
+                dict.set("name", def.name.to_variant());
+                dict.set("category", def.category.to_variant());
+                dict.set("behaviour", serde_name(&def.behaviour).to_variant());
+                dict.set("building_type", (def.behaviour as i32).to_variant());
+                dict.set("cost", def.cost.to_variant());
+                dict.set("size", Vector2i::from(def.layout.size).to_variant());
+                dict.set(
+                    "speed",
+                    def.speed.map_or(Variant::nil(), |speed| speed.to_variant()),
+                );
+                dict.set(
+                    "capacity",
+                    def.capacity
+                        .map_or(Variant::nil(), |capacity| capacity.to_variant()),
+                );
+                dict.set(
+                    "range",
+                    def.range.map_or(Variant::nil(), |range| range.to_variant()),
+                );
+                dict.set(
+                    "texture",
+                    def.texture.clone().unwrap_or_default().to_variant(),
+                );
+                dict.set("palette", def.palette.to_variant());
+                let ports: VariantArray = def
+                    .layout
+                    .ports
+                    .iter()
+                    .map(|port| {
+                        let mut port_dict = Dictionary::new();
+                        port_dict.set("name", port.name.to_variant());
+                        port_dict.set("kind", serde_name(&port.kind).to_variant());
+                        port_dict.set("side", serde_name(&port.side).to_variant());
+                        port_dict.set("offset", port.offset.to_variant());
+                        port_dict.set("role", serde_name(&port.role).to_variant());
+                        port_dict.to_variant()
+                    })
+                    .collect();
+                dict.set("ports", ports.to_variant());
+                dict.to_variant()
+            })
+            .collect()
+    }
 
     #[func]
     pub fn get_all_buildings_by_typeid(&self, building_type_id: i32) -> VariantArray {
         let world = self.world.borrow();
 
-        let Some(target_type) = building_type_from_id(building_type_id) else {
+        if registry().by_id(building_type_id).is_none() {
             godot_warn!(
                 "Invalid building_type_id for get_all_buildings_by_typeid: {}",
                 building_type_id
             );
             return VariantArray::new();
-        };
+        }
 
         world
             .storage
             .iter()
-            .filter(|building| building.building_type() == target_type)
+            .filter(|building| building.def_id() == building_type_id)
             .map(|building| {
                 let mut dict = Dictionary::new();
                 let pos = building.position();
@@ -1166,6 +1847,7 @@ impl MapController {
                     dict.set("tile_pos", Vector2i::from(tile_pos).to_variant());
                     dict.set("rotation", rotation.to_variant());
                     dict.set("label", packet.label.to_raw().to_variant());
+                    set_packet_id(&mut dict, packet.id);
                     dict.to_variant()
                 })
             })
@@ -1179,37 +1861,41 @@ impl MapController {
         let mut result = VariantArray::new();
 
         for building in world.storage.iter() {
-            if building.building_type() != BuildingType::Conveyor {
+            if building.building_type() == BuildingType::TunnelExit {
+                let Some(exit) = building.as_any().downcast_ref::<TunnelExit>() else {
+                    continue;
+                };
+                for (packet, entrance_pos) in exit.in_flight() {
+                    let pos = tunnel_packet_position(
+                        *entrance_pos,
+                        exit.position(),
+                        exit.rotation(),
+                        packet.progress,
+                        tile_size,
+                    );
+                    result.push(&packet_position_entry(packet, pos));
+                }
                 continue;
             }
 
-            let Some(conveyor) = building.as_any().downcast_ref::<Conveyor>() else {
+            if building.building_type() != BuildingType::Conveyor {
                 continue;
-            };
+            }
 
-            let Some((packet, entry_side)) = conveyor.buffer_state() else {
+            let Some(conveyor) = building.as_any().downcast_ref::<Conveyor>() else {
                 continue;
             };
 
-            let pos = conveyor_packet_position(
-                conveyor.position(),
-                conveyor.rotation(),
-                entry_side,
-                packet.progress,
-                tile_size,
-            );
-
-            let mut dict = Dictionary::new();
-            dict.set("source_ip", packet.source_ip.to_variant());
-            dict.set("dest_ip", packet.dest_ip.to_variant());
-            dict.set("source_port", (packet.source_port as i32).to_variant());
-            dict.set("dest_port", (packet.dest_port as i32).to_variant());
-            dict.set("protocol", (packet.protocol as i32).to_variant());
-            dict.set("length", (packet.length as i32).to_variant());
-            dict.set("position", pos.to_variant());
-            dict.set("label", packet.label.to_raw().to_variant());
-            let variant = dict.to_variant();
-            result.push(&variant);
+            for (packet, entry_side) in conveyor.slots() {
+                let pos = conveyor_packet_position(
+                    conveyor.position(),
+                    conveyor.rotation(),
+                    *entry_side,
+                    packet.progress,
+                    tile_size,
+                );
+                result.push(&packet_position_entry(packet, pos));
+            }
         }
 
         result
@@ -1227,19 +1913,334 @@ impl MapController {
         packet_export::recyclebin_packets(&world)
     }
 
+    /// Write the packets held by the selected sinks (`"datacenter"`, `"recyclebin"`,
+    /// `"honeypot"` or `"all"`) to a pcap file. Returns the number of frames written, or `-1` on failure.
+    #[func]
+    pub fn export_pcap(&self, path: GString, scope: GString) -> i64 {
+        let Some(scope_enum) = PcapExportScope::from_name(&scope.to_string()) else {
+            godot_warn!(
+                "Invalid scope: {}. Use 'datacenter', 'recyclebin', 'honeypot' or 'all'",
+                scope
+            );
+            return -1;
+        };
+
+        let abs = ProjectSettings::singleton().globalize_path(&path);
+        let world = self.world.borrow();
+        match world.export_pcap(scope_enum, std::path::Path::new(&abs.to_string())) {
+            Ok(written) => written as i64,
+            Err(err) => {
+                godot_error!("Failed to export pcap: {}", err);
+                -1
+            }
+        }
+    }
+
+    /// Hops of the packet spawned by Internet `origin_id` as its `packet_id`-th packet. Each
+    /// entry has `tick`, `building_id`, `building_type` and `verdict` (`null` unless the hop is
+    /// a filter the packet has already left).
+    #[func]
+    pub fn get_packet_trace(&self, origin_id: i64, packet_id: i64) -> VariantArray {
+        let id = crate::core::packet::PacketId {
+            origin: origin_id as u64,
+            index: packet_id as u64,
+        };
+        let world = self.world.borrow();
+        world
+            .packet_trace(id)
+            .map(packet_trace::trace_to_variant)
+            .unwrap_or_default()
+    }
+
     #[func]
     pub fn completed_packets(&self) -> Variant {
         let world = self.world.borrow();
         packet_completion::completed_packets_variant(&world)
     }
 
+    /// Progress of each Internet of the stage: `building_id`, `planned` packets (-1 for a
+    /// streamed traffic), `resolved` ones (delivered or dropped) and `finished`.
+    #[func]
+    pub fn get_source_progress(&self) -> VariantArray {
+        let world = self.world.borrow();
+        packet_completion::source_progress(&world)
+            .into_iter()
+            .map(|progress| {
+                let mut entry = Dictionary::new();
+                entry.set("building_id", progress.source as i64);
+                entry.set("planned", progress.planned.map_or(-1, |n| n as i64));
+                entry.set("resolved", progress.resolved as i64);
+                entry.set("finished", progress.finished);
+                entry.to_variant()
+            })
+            .collect()
+    }
+
     #[func]
     pub fn get_score(&self) -> u32 {
         self.world.borrow().score
     }
 
+    /// Number of fixed simulation ticks run since the stage was loaded.
+    #[func]
+    pub fn get_tick(&self) -> i64 {
+        self.world.borrow().tick() as i64
+    }
+
+    /// Write the loaded stage and every layout edit made so far to a replay file, which the
+    /// headless runner (`headless replay <file>`) plays back exactly.
+    #[func]
+    pub fn save_replay(&self, path: GString) -> bool {
+        let Some(replay) = self.recorder.to_replay(self.world.borrow().tick()) else {
+            godot_error!("No stage loaded; nothing to save as replay");
+            return false;
+        };
+        let abs = ProjectSettings::singleton().globalize_path(&path);
+        match replay.save(std::path::Path::new(&abs.to_string())) {
+            Ok(()) => true,
+            Err(err) => {
+                godot_error!("Failed to save replay: {}", err);
+                false
+            }
+        }
+    }
+
+    /// Counters of discarded packets: `total`, per reason (`no_outputs`, `routing_failed`,
+    /// `closed_service`, `overloaded`, `replaced`, `misrouted`), `denial_of_service`, per label (`correct`,
+    /// `incorrect`, `unknown`) and `penalty`.
+    #[func]
+    pub fn get_drop_stats(&self) -> Dictionary {
+        let world = self.world.borrow();
+        packet_drops::drop_stats_to_dictionary(&world.drop_stats())
+    }
+
+    #[func]
+    pub fn set_drop_penalty(&mut self, penalty: i64) {
+        let penalty = penalty.clamp(0, u32::MAX as i64) as u32;
+        self.world.borrow_mut().set_drop_penalty(penalty);
+    }
+
+    #[func]
+    pub fn set_honeypot_reward(&mut self, reward: i64) {
+        let reward = reward.clamp(0, u32::MAX as i64) as u32;
+        self.world.borrow_mut().set_honeypot_reward(reward);
+    }
+
+    /// Profiles of the senders caught by honeypots, most attack packets first, for the
+    /// end-of-stage report. Each has `source_ip`, `packets`, `attack_packets`, `bytes`,
+    /// `dest_ports` (port -> packets), `dest_ips`, `tcp`, `udp`, `other_protocols`,
+    /// `signatures` (payload signature -> packets), `first_seen` and `last_seen`.
+    #[func]
+    pub fn get_attacker_profiles(&self) -> VariantArray {
+        let world = self.world.borrow();
+        world
+            .attacker_profiles()
+            .iter()
+            .map(|profile| attacker_profile_to_dictionary(profile).to_variant())
+            .collect()
+    }
+
+    /// What the analyzer `building_id` has counted from tap copies. Empty when the id is not
+    /// an analyzer.
+    #[func]
+    pub fn get_analyzer_stats(&self, building_id: i64) -> Dictionary {
+        let mut dict = Dictionary::new();
+        let world = self.world.borrow();
+        let Some(analyzer) = world
+            .get_building(building_id as u64)
+            .and_then(|b| b.as_any().downcast_ref::<Analyzer>())
+        else {
+            return dict;
+        };
+        let stats = analyzer.stats();
+        dict.set("packets", stats.packets as i64);
+        dict.set("bytes", stats.bytes as i64);
+        dict.set("tcp", stats.tcp as i64);
+        dict.set("udp", stats.udp as i64);
+        dict.set("other_protocols", stats.other_protocols as i64);
+        dict.set("correct", stats.correct as i64);
+        dict.set("incorrect", stats.incorrect as i64);
+        dict.set("unknown", stats.unknown as i64);
+        let mut dest_ports = Dictionary::new();
+        for (port, count) in &stats.dest_ports {
+            dest_ports.set(*port as i64, *count as i64);
+        }
+        dict.set("dest_ports", dest_ports);
+        let mut source_ips = Dictionary::new();
+        for (ip, count) in &stats.source_ips {
+            source_ips.set(ip.as_str(), *count as i64);
+        }
+        dict.set("source_ips", source_ips);
+        dict
+    }
+
+    /// Session table of the stateful firewall `building_id`, least recently active first.
+    /// Each entry has the client and server endpoints, `protocol`, `state` ("syn_sent",
+    /// "syn_received", "established" or "closing") and `expires_in_ms`.
+    #[func]
+    pub fn get_firewall_sessions(&self, building_id: i64) -> VariantArray {
+        let world = self.world.borrow();
+        let Some(firewall) = world
+            .get_building(building_id as u64)
+            .and_then(|b| b.as_any().downcast_ref::<StatefulFirewall>())
+        else {
+            return VariantArray::new();
+        };
+        firewall
+            .sessions()
+            .into_iter()
+            .map(|session| {
+                let mut entry = Dictionary::new();
+                entry.set("client_ip", session.key.client_ip.as_str());
+                entry.set("client_port", session.key.client_port as i64);
+                entry.set("server_ip", session.key.server_ip.as_str());
+                entry.set("server_port", session.key.server_port as i64);
+                entry.set("protocol", protocol_name(&session.key.protocol));
+                entry.set("state", session.state.as_str());
+                let expires_in_ms = (firewall.expires_in(session) * 1000.0).max(0.0);
+                entry.set("expires_in_ms", expires_in_ms as i64);
+                entry.to_variant()
+            })
+            .collect()
+    }
+
+    /// Sources flagged by the scan detector `building_id`, earliest first. Each entry has
+    /// `source_ip`, `flagged_ms` (time since it was flagged) and `releases_in_ms`, which is
+    /// -1 while the source stays flagged for good.
+    #[func]
+    pub fn get_flagged_sources(&self, building_id: i64) -> VariantArray {
+        let world = self.world.borrow();
+        let Some(detector) = world
+            .get_building(building_id as u64)
+            .and_then(|b| b.as_any().downcast_ref::<ScanDetector>())
+        else {
+            return VariantArray::new();
+        };
+        detector
+            .flagged_sources()
+            .into_iter()
+            .map(|(source_ip, age)| {
+                let mut entry = Dictionary::new();
+                entry.set("source_ip", source_ip);
+                entry.set("flagged_ms", (age * 1000.0) as i64);
+                let releases_in_ms = detector
+                    .releases_in(age)
+                    .map_or(-1, |secs| (secs * 1000.0) as i64);
+                entry.set("releases_in_ms", releases_in_ms);
+                entry.to_variant()
+            })
+            .collect()
+    }
+
+    /// Load of the datacenter `building_id`: `processed` and `queued` packets, plus
+    /// `capacity_pps` and `queue_size` of its rule (`0` without one). Empty when it is not
+    /// a datacenter.
+    #[func]
+    pub fn get_datacenter_load(&self, building_id: i64) -> Dictionary {
+        let world = self.world.borrow();
+        let mut result = Dictionary::new();
+        let Some(datacenter) = world
+            .get_building(building_id as u64)
+            .and_then(|b| b.as_any().downcast_ref::<Datacenter>())
+        else {
+            return result;
+        };
+        result.set("processed", datacenter.get_packets().len() as i64);
+        result.set("queued", datacenter.queued() as i64);
+        let (capacity_pps, queue_size) = datacenter
+            .config
+            .as_ref()
+            .map_or((0, 0), |config| (config.capacity_pps, config.queue_size));
+        result.set("capacity_pps", capacity_pps as i64);
+        result.set("queue_size", queue_size as i64);
+        result
+    }
+
     #[func]
     pub fn set_filter_rules(&mut self, building_id: i64, rule: Dictionary) {
+        let id = building_id as u64;
+        let previous = self.world.borrow().filter_config(id);
+        self.update_filter_rules(building_id, rule);
+
+        // Record the resulting rule so undo and replays do not depend on dictionary parsing.
+        let world = self.world.borrow();
+        if let Some(config) = world.filter_config(id)
+            && previous.as_ref() != Some(&config)
+        {
+            let edit = LayoutEdit::SetFilterConfig {
+                building_id: id,
+                config,
+            };
+            let undo = match previous {
+                Some(config) => LayoutEdit::SetFilterConfig {
+                    building_id: id,
+                    config,
+                },
+                None => LayoutEdit::ClearFilterConfig { building_id: id },
+            };
+            self.history.record(AppliedEdit {
+                redo: edit.clone(),
+                undo,
+            });
+            self.recorder.record(world.tick(), edit);
+        }
+    }
+
+    /// Revert the latest layout edit (or edit group). Returns `false` when there is nothing
+    /// to undo.
+    #[func]
+    pub fn undo(&mut self) -> bool {
+        let mut world = self.world.borrow_mut();
+        let Some(edits) = self.history.undo(&mut world) else {
+            return false;
+        };
+        for edit in edits {
+            self.recorder.record(world.tick(), edit);
+        }
+        true
+    }
+
+    #[func]
+    pub fn redo(&mut self) -> bool {
+        let mut world = self.world.borrow_mut();
+        let Some(edits) = self.history.redo(&mut world) else {
+            return false;
+        };
+        for edit in edits {
+            self.recorder.record(world.tick(), edit);
+        }
+        true
+    }
+
+    #[func]
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    #[func]
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Group the following edits into one undo step until `end_edit_group`, e.g. while the
+    /// player drags out a conveyor line.
+    #[func]
+    pub fn begin_edit_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    #[func]
+    pub fn end_edit_group(&mut self) {
+        self.history.end_group();
+    }
+
+    /// Number of undo steps kept (default 100).
+    #[func]
+    pub fn set_history_limit(&mut self, limit: i64) {
+        self.history.set_limit(limit.max(1) as usize);
+    }
+
+    fn update_filter_rules(&mut self, building_id: i64, rule: Dictionary) {
         let mut world = self.world.borrow_mut();
         let Some(building) = world.storage.get_mut(building_id as u64) else {
             godot_warn!("Building with id {} not found", building_id);
@@ -1250,150 +2251,362 @@ impl MapController {
 
         match building_type {
             BuildingType::IpFilter => {
-                if let Some(filter) = building.as_any_mut().downcast_mut::<IpFilter>() {
-                    let target_ip = rule
-                        .get("target_ip")
-                        .and_then(|v| v.try_to::<GString>().ok())
-                        .map(|s| s.to_string());
-                    let direction_str = rule
-                        .get("direction")
-                        .and_then(|v| v.try_to::<GString>().ok())
-                        .map(|s| s.to_string());
-
-                    if let (Some(target_ip), Some(direction_str)) = (target_ip, direction_str) {
-                        let direction = match direction_str.as_str() {
-                            "source" => IpFilterDirection::Source,
-                            "destination" => IpFilterDirection::Destination,
-                            _ => {
-                                godot_warn!(
-                                    "Invalid direction: {}. Use 'source' or 'destination'",
-                                    direction_str
-                                );
-                                return;
-                            }
-                        };
+                // A rule naming a "blocklist" matches the addresses on it and needs no
+                // target_ip.
+                let blocklist = rule
+                    .get("blocklist")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string())
+                    .filter(|name| !name.is_empty());
+                let target_ip = rule
+                    .get("target_ip")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string())
+                    .or_else(|| blocklist.as_ref().map(|_| String::new()));
+                let direction_str = rule
+                    .get("direction")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string());
+
+                if let (Some(target_ip), Some(direction_str)) = (target_ip, direction_str) {
+                    let direction = match direction_str.as_str() {
+                        "source" => IpFilterDirection::Source,
+                        "destination" => IpFilterDirection::Destination,
+                        _ => {
+                            godot_warn!(
+                                "Invalid direction: {}. Use 'source' or 'destination'",
+                                direction_str
+                            );
+                            return;
+                        }
+                    };
 
-                        let config = IpFilterConfig {
+                    let config = match blocklist {
+                        Some(list) => {
+                            BuildingConfig::IpBlocklist(IpBlocklistConfig { list, direction })
+                        }
+                        None => BuildingConfig::Ip(IpFilterConfig {
                             target_ip,
                             direction,
-                        };
-                        filter.set_config(config);
-                    } else {
-                        godot_warn!("Missing target_ip or direction in IP filter rule");
-                    }
+                        }),
+                    };
+                    building.set_filter_config(config);
+                } else {
+                    godot_warn!("Missing target_ip or direction in IP filter rule");
                 }
             }
             BuildingType::PortFilter => {
-                if let Some(filter) = building.as_any_mut().downcast_mut::<PortFilter>() {
-                    let target_port = rule
-                        .get("target_port")
-                        .and_then(|v| v.try_to::<i32>().ok())
-                        .map(|p| p as u16);
-                    let direction_str = rule
-                        .get("direction")
-                        .and_then(|v| v.try_to::<GString>().ok())
-                        .map(|s| s.to_string());
-
-                    if let (Some(target_port), Some(direction_str)) = (target_port, direction_str) {
-                        let direction = match direction_str.as_str() {
-                            "source" => PortFilterDirection::Source,
-                            "destination" => PortFilterDirection::Destination,
-                            _ => {
-                                godot_warn!(
-                                    "Invalid direction: {}. Use 'source' or 'destination'",
-                                    direction_str
-                                );
-                                return;
-                            }
-                        };
+                let target_port = rule
+                    .get("target_port")
+                    .and_then(|v| v.try_to::<i32>().ok())
+                    .map(|p| p as u16);
+                let direction_str = rule
+                    .get("direction")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string());
+
+                if let (Some(target_port), Some(direction_str)) = (target_port, direction_str) {
+                    let direction = match direction_str.as_str() {
+                        "source" => PortFilterDirection::Source,
+                        "destination" => PortFilterDirection::Destination,
+                        _ => {
+                            godot_warn!(
+                                "Invalid direction: {}. Use 'source' or 'destination'",
+                                direction_str
+                            );
+                            return;
+                        }
+                    };
 
-                        let config = PortFilterConfig {
-                            target_port,
-                            direction,
-                        };
-                        filter.set_config(config);
-                    } else {
-                        godot_warn!("Missing target_port or direction in Port filter rule");
-                    }
+                    let config = PortFilterConfig {
+                        target_port,
+                        direction,
+                    };
+                    building.set_filter_config(BuildingConfig::Port(config));
+                } else {
+                    godot_warn!("Missing target_port or direction in Port filter rule");
                 }
             }
             BuildingType::LengthFilter => {
-                if let Some(filter) = building.as_any_mut().downcast_mut::<LengthFilter>() {
-                    let threshold = rule
-                        .get("threshold")
-                        .and_then(|v| v.try_to::<i32>().ok())
-                        .map(|t| t as u32);
-                    let direction_str = rule
-                        .get("direction")
-                        .and_then(|v| v.try_to::<GString>().ok())
-                        .map(|s| s.to_string());
-
-                    if let (Some(threshold), Some(direction_str)) = (threshold, direction_str) {
-                        let direction = match direction_str.as_str() {
-                            "exact" => LengthFilterDirection::Exact,
-                            "less_than" => LengthFilterDirection::LessThan,
-                            "greater_than" => LengthFilterDirection::GreaterThan,
-                            _ => {
-                                godot_warn!(
-                                    "Invalid direction: {}. Use 'exact', 'less_than', or 'greater_than'",
-                                    direction_str
-                                );
-                                return;
-                            }
-                        };
+                let threshold = rule
+                    .get("threshold")
+                    .and_then(|v| v.try_to::<i32>().ok())
+                    .map(|t| t as u32);
+                let direction_str = rule
+                    .get("direction")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string());
+
+                if let (Some(threshold), Some(direction_str)) = (threshold, direction_str) {
+                    let direction = match direction_str.as_str() {
+                        "exact" => LengthFilterDirection::Exact,
+                        "less_than" => LengthFilterDirection::LessThan,
+                        "greater_than" => LengthFilterDirection::GreaterThan,
+                        _ => {
+                            godot_warn!(
+                                "Invalid direction: {}. Use 'exact', 'less_than', or 'greater_than'",
+                                direction_str
+                            );
+                            return;
+                        }
+                    };
 
-                        let config = LengthFilterConfig {
-                            threshold,
-                            direction,
-                        };
-                        filter.set_config(config);
-                    } else {
-                        godot_warn!("Missing threshold or direction in Length filter rule");
-                    }
+                    let config = LengthFilterConfig {
+                        threshold,
+                        direction,
+                    };
+                    building.set_filter_config(BuildingConfig::Length(config));
+                } else {
+                    godot_warn!("Missing threshold or direction in Length filter rule");
                 }
             }
             BuildingType::ProtocolFilter => {
-                if let Some(filter) = building.as_any_mut().downcast_mut::<ProtocolFilter>() {
-                    let protocol_str = rule
-                        .get("protocol")
-                        .and_then(|v| v.try_to::<GString>().ok())
-                        .map(|s| s.to_string());
-
-                    if let Some(protocol_str) = protocol_str {
-                        let protocol = match protocol_str.as_str() {
-                            "tcp" => Protocol::Tcp,
-                            "udp" => Protocol::Udp,
-                            "unknown" => Protocol::Unknown,
-                            _ => {
-                                godot_warn!(
-                                    "Invalid protocol: {}. Use 'tcp', 'udp' or 'unknown'",
-                                    protocol_str
-                                );
-                                return;
-                            }
-                        };
+                let protocol_str = rule
+                    .get("protocol")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string());
+
+                if let Some(protocol_str) = protocol_str {
+                    let protocol = match protocol_str.as_str() {
+                        "tcp" => Protocol::Tcp,
+                        "udp" => Protocol::Udp,
+                        "unknown" => Protocol::Unknown,
+                        _ => {
+                            godot_warn!(
+                                "Invalid protocol: {}. Use 'tcp', 'udp' or 'unknown'",
+                                protocol_str
+                            );
+                            return;
+                        }
+                    };
 
-                        let config = ProtocolFilterConfig { protocol };
-                        filter.set_config(config);
-                    } else {
-                        godot_warn!("Missing protocol in Protocol filter rule");
-                    }
+                    let config = ProtocolFilterConfig { protocol };
+                    building.set_filter_config(BuildingConfig::Protocol(config));
+                } else {
+                    godot_warn!("Missing protocol in Protocol filter rule");
                 }
             }
             BuildingType::ContentFilter => {
-                if let Some(filter) = building.as_any_mut().downcast_mut::<ContentFilter>() {
-                    let pattern = rule
-                        .get("pattern")
-                        .and_then(|v| v.try_to::<GString>().ok())
-                        .map(|s| s.to_string());
-
-                    if let Some(pattern) = pattern {
-                        let config = ContentFilterConfig { pattern };
-                        filter.set_config(config);
-                    } else {
-                        godot_warn!("Missing pattern in Content filter rule");
+                let pattern = rule
+                    .get("pattern")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string());
+
+                // "scope" is optional; unknown values keep per-segment matching.
+                let scope = rule
+                    .get("scope")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .and_then(|s| ContentMatchScope::from_name(&s.to_string()))
+                    .unwrap_or_default();
+
+                if let Some(pattern) = pattern {
+                    let config = ContentFilterConfig { pattern, scope };
+                    building.set_filter_config(BuildingConfig::Content(config));
+                } else {
+                    godot_warn!("Missing pattern in Content filter rule");
+                }
+            }
+            BuildingType::RateLimiter => {
+                let limit = rule
+                    .get("limit")
+                    .and_then(|v| v.try_to::<i64>().ok())
+                    .filter(|&l| l >= 0);
+                let window_ms = rule
+                    .get("window_ms")
+                    .and_then(|v| v.try_to::<i64>().ok())
+                    .filter(|&w| w > 0);
+                // "key" is optional and counts per source IP by default.
+                let key = match rule.get("key").and_then(|v| v.try_to::<GString>().ok()) {
+                    Some(name) => match RateLimitKey::from_name(&name.to_string()) {
+                        Some(key) => key,
+                        None => {
+                            godot_warn!(
+                                "Invalid key: {}. Use 'source_ip', 'destination_port' or 'flow'",
+                                name
+                            );
+                            return;
+                        }
+                    },
+                    None => RateLimitKey::default(),
+                };
+
+                if let (Some(limit), Some(window_ms)) = (limit, window_ms) {
+                    building.set_filter_config(BuildingConfig::RateLimit(RateLimiterConfig {
+                        limit: limit.min(u32::MAX as i64) as u32,
+                        window_ms: window_ms.min(u32::MAX as i64) as u32,
+                        key,
+                    }));
+                } else {
+                    godot_warn!("Missing or invalid limit or window_ms in Rate limiter rule");
+                }
+            }
+            BuildingType::StatefulFirewall => {
+                let Some(services) = rule
+                    .get("services")
+                    .and_then(|v| v.try_to::<VariantArray>().ok())
+                    .and_then(|services| firewall_services_from_variant(&services))
+                else {
+                    godot_warn!(
+                        "Missing or invalid services in Stateful firewall rule. Use [{{\"protocol\": \"tcp\", \"port\": 22}}]"
+                    );
+                    return;
+                };
+                let mut config = StatefulFirewallConfig::new(services);
+                // Timeouts are optional and keep their defaults when missing.
+                let timeout = |key: &str| {
+                    rule.get(key)
+                        .and_then(|v| v.try_to::<i64>().ok())
+                        .filter(|&ms| ms > 0)
+                        .map(|ms| ms.min(u32::MAX as i64) as u32)
+                };
+                if let Some(ms) = timeout("tcp_timeout_ms") {
+                    config.tcp_timeout_ms = ms;
+                }
+                if let Some(ms) = timeout("udp_timeout_ms") {
+                    config.udp_timeout_ms = ms;
+                }
+                building.set_filter_config(BuildingConfig::Firewall(config));
+            }
+            BuildingType::ScanDetector => {
+                let value = |key: &str| {
+                    rule.get(key)
+                        .and_then(|v| v.try_to::<i64>().ok())
+                        .filter(|&n| n >= 0)
+                        .map(|n| n.min(u32::MAX as i64) as u32)
+                };
+                let Some(window_ms) = value("window_ms").filter(|&ms| ms > 0) else {
+                    godot_warn!("Missing or invalid window_ms in Scan detector rule");
+                    return;
+                };
+                // "blocklist" names the world blocklist flagged sources go on.
+                let blocklist = rule
+                    .get("blocklist")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string())
+                    .filter(|name| !name.is_empty());
+                // Thresholds, the grace period and the TTL are optional; 0 turns them off.
+                let detector = ScanDetectorConfig {
+                    window_ms,
+                    port_threshold: value("port_threshold").unwrap_or(0),
+                    host_threshold: value("host_threshold").unwrap_or(0),
+                    grace_ms: value("grace_ms").unwrap_or(0),
+                };
+                building.set_filter_config(match blocklist {
+                    Some(list) => BuildingConfig::ScanDetectorFeed {
+                        detector,
+                        feed: BlocklistFeed {
+                            list,
+                            ttl_ms: value("blocklist_ttl_ms").unwrap_or(0),
+                        },
+                    },
+                    None => BuildingConfig::ScanDetector(detector),
+                });
+            }
+            BuildingType::Datacenter => {
+                // Missing services host everything.
+                let services = match rule.get("services") {
+                    Some(v) => {
+                        let Some(services) = v
+                            .try_to::<VariantArray>()
+                            .ok()
+                            .and_then(|services| hosted_services_from_variant(&services))
+                        else {
+                            godot_warn!(
+                                "Invalid services in Datacenter rule. Use [{{\"protocol\": \"tcp\", \"port\": 443}}]"
+                            );
+                            return;
+                        };
+                        services
+                    }
+                    None => Vec::new(),
+                };
+                let value = |key: &str| {
+                    rule.get(key)
+                        .and_then(|v| v.try_to::<i64>().ok())
+                        .filter(|&n| n >= 0)
+                        .map(|n| n.min(u32::MAX as i64) as u32)
+                };
+                building.set_filter_config(BuildingConfig::Datacenter(DatacenterConfig {
+                    services,
+                    capacity_pps: value("capacity_pps").unwrap_or(0),
+                    queue_size: value("queue_size").unwrap_or(0),
+                }));
+            }
+            BuildingType::Splitter => {
+                let mut weights = [1; 3];
+                if let Some(values) = rule
+                    .get("weights")
+                    .and_then(|v| v.try_to::<VariantArray>().ok())
+                {
+                    if values.len() != weights.len() {
+                        godot_warn!("Splitter weights need one value per output (3)");
+                        return;
+                    }
+                    for (weight, value) in weights.iter_mut().zip(values.iter_shared()) {
+                        let Some(value) = variant_to_i32(&value) else {
+                            godot_warn!("Invalid splitter weight: {}", value);
+                            return;
+                        };
+                        *weight = value.max(0) as u32;
                     }
                 }
+                let mode_str = rule
+                    .get("mode")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "weighted".to_string());
+                let output = rule
+                    .get("output")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .and_then(|s| port_index(&SPLITTER_OUTPUTS, "out_", &s.to_string()));
+
+                let mode = match (mode_str.as_str(), output) {
+                    ("weighted", _) => SplitterMode::Weighted,
+                    ("priority", Some(output)) => SplitterMode::Priority { output },
+                    ("overflow", Some(output)) => SplitterMode::Overflow { output },
+                    ("priority" | "overflow", None) => {
+                        godot_warn!(
+                            "Missing or invalid output in Splitter rule. Use 'front', 'left' or 'right'"
+                        );
+                        return;
+                    }
+                    _ => {
+                        godot_warn!(
+                            "Invalid mode: {}. Use 'weighted', 'priority' or 'overflow'",
+                            mode_str
+                        );
+                        return;
+                    }
+                };
+                building
+                    .set_filter_config(BuildingConfig::Splitter(SplitterConfig { weights, mode }));
+            }
+            BuildingType::Merger => {
+                let mode_str = rule
+                    .get("mode")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "fair".to_string());
+                let input = rule
+                    .get("input")
+                    .and_then(|v| v.try_to::<GString>().ok())
+                    .and_then(|s| port_index(&MERGER_INPUTS, "in_", &s.to_string()));
+
+                let mode = match (mode_str.as_str(), input) {
+                    ("fair", _) => MergerMode::Fair,
+                    ("priority", Some(input)) => MergerMode::Priority { input },
+                    ("priority", None) => {
+                        godot_warn!(
+                            "Missing or invalid input in Merger rule. Use 'back', 'left' or 'right'"
+                        );
+                        return;
+                    }
+                    _ => {
+                        godot_warn!("Invalid mode: {}. Use 'fair' or 'priority'", mode_str);
+                        return;
+                    }
+                };
+                building.set_filter_config(BuildingConfig::Merger(MergerConfig { mode }));
             }
             _ => {
                 godot_warn!(
@@ -1402,8 +2615,11 @@ impl MapController {
                 );
             }
         }
+        // A datacenter whose queue shrank loses the packets that no longer fit.
+        world.drop_rejected_packets(building_id as u64);
     }
 
+    /// Rule of a building in the form `set_filter_rules` takes; empty when it has none.
     #[func]
     pub fn get_filter_rule(&self, building_id: i64) -> Dictionary {
         let world = self.world.borrow();
@@ -1414,71 +2630,126 @@ impl MapController {
             return result;
         };
 
-        let building_type = building.building_type();
-
-        match building_type {
-            BuildingType::IpFilter => {
-                if let Some(filter) = building.as_any().downcast_ref::<IpFilter>()
-                    && let Some(config) = &filter.config
-                {
-                    result.set("target_ip", config.target_ip.clone());
-                    let direction_str = match config.direction {
-                        IpFilterDirection::Source => "source",
-                        IpFilterDirection::Destination => "destination",
-                    };
-                    result.set("direction", direction_str);
-                }
+        match building.filter_config() {
+            Some(BuildingConfig::Ip(config)) => {
+                result.set("target_ip", config.target_ip.clone());
+                let direction_str = match config.direction {
+                    IpFilterDirection::Source => "source",
+                    IpFilterDirection::Destination => "destination",
+                };
+                result.set("direction", direction_str);
             }
-            BuildingType::PortFilter => {
-                if let Some(filter) = building.as_any().downcast_ref::<PortFilter>()
-                    && let Some(config) = &filter.config
-                {
-                    result.set("target_port", config.target_port as i32);
-                    let direction_str = match config.direction {
-                        PortFilterDirection::Source => "source",
-                        PortFilterDirection::Destination => "destination",
-                    };
-                    result.set("direction", direction_str);
-                }
+            Some(BuildingConfig::IpBlocklist(config)) => {
+                result.set("target_ip", "");
+                let direction_str = match config.direction {
+                    IpFilterDirection::Source => "source",
+                    IpFilterDirection::Destination => "destination",
+                };
+                result.set("direction", direction_str);
+                result.set("blocklist", config.list.as_str());
             }
-            BuildingType::LengthFilter => {
-                if let Some(filter) = building.as_any().downcast_ref::<LengthFilter>()
-                    && let Some(config) = &filter.config
-                {
-                    result.set("threshold", config.threshold as i32);
-                    let direction_str = match config.direction {
-                        LengthFilterDirection::Exact => "exact",
-                        LengthFilterDirection::LessThan => "less_than",
-                        LengthFilterDirection::GreaterThan => "greater_than",
-                    };
-                    result.set("direction", direction_str);
-                }
+            Some(BuildingConfig::Port(config)) => {
+                result.set("target_port", config.target_port as i32);
+                let direction_str = match config.direction {
+                    PortFilterDirection::Source => "source",
+                    PortFilterDirection::Destination => "destination",
+                };
+                result.set("direction", direction_str);
             }
-            BuildingType::ProtocolFilter => {
-                if let Some(filter) = building.as_any().downcast_ref::<ProtocolFilter>()
-                    && let Some(config) = &filter.config
-                {
-                    let protocol_str = match config.protocol {
-                        Protocol::Tcp => "tcp",
-                        Protocol::Udp => "udp",
-                        Protocol::Unknown => "unknown",
-                    };
-                    result.set("protocol", protocol_str);
-                }
+            Some(BuildingConfig::Length(config)) => {
+                result.set("threshold", config.threshold as i32);
+                let direction_str = match config.direction {
+                    LengthFilterDirection::Exact => "exact",
+                    LengthFilterDirection::LessThan => "less_than",
+                    LengthFilterDirection::GreaterThan => "greater_than",
+                };
+                result.set("direction", direction_str);
             }
-            BuildingType::ContentFilter => {
-                if let Some(filter) = building.as_any().downcast_ref::<ContentFilter>()
-                    && let Some(config) = &filter.config
-                {
-                    result.set("pattern", config.pattern.clone());
-                }
+            Some(BuildingConfig::Protocol(config)) => {
+                let protocol_str = match config.protocol {
+                    Protocol::Tcp => "tcp",
+                    Protocol::Udp => "udp",
+                    Protocol::Unknown => "unknown",
+                };
+                result.set("protocol", protocol_str);
             }
-            _ => {
-                godot_warn!(
-                    "Building with id {} is not a filter or does not support rule getting",
-                    building_id
-                );
+            Some(BuildingConfig::Content(config)) => {
+                result.set("pattern", config.pattern.clone());
+                result.set("scope", config.scope.as_str());
+            }
+            Some(BuildingConfig::RateLimit(config)) => {
+                result.set("limit", config.limit as i64);
+                result.set("window_ms", config.window_ms as i64);
+                result.set("key", config.key.as_str());
+            }
+            Some(BuildingConfig::Firewall(config)) => {
+                let services: VariantArray = config
+                    .services
+                    .iter()
+                    .map(|service| {
+                        let mut entry = Dictionary::new();
+                        entry.set("protocol", protocol_name(&service.protocol));
+                        entry.set("port", service.port as i64);
+                        entry.to_variant()
+                    })
+                    .collect();
+                result.set("services", services);
+                result.set("tcp_timeout_ms", config.tcp_timeout_ms as i64);
+                result.set("udp_timeout_ms", config.udp_timeout_ms as i64);
+            }
+            Some(BuildingConfig::ScanDetector(config)) => {
+                scan_detector_rule(&mut result, &config);
+            }
+            Some(BuildingConfig::ScanDetectorFeed { detector, feed }) => {
+                scan_detector_rule(&mut result, &detector);
+                result.set("blocklist", feed.list.as_str());
+                result.set("blocklist_ttl_ms", feed.ttl_ms as i64);
+            }
+            Some(BuildingConfig::Datacenter(config)) => {
+                let services: VariantArray = config
+                    .services
+                    .iter()
+                    .map(|service| {
+                        let mut entry = Dictionary::new();
+                        entry.set("protocol", protocol_name(&service.protocol));
+                        entry.set("port", service.port as i64);
+                        if let Some(ip) = &service.ip {
+                            entry.set("ip", ip.as_str());
+                        }
+                        entry.to_variant()
+                    })
+                    .collect();
+                result.set("services", services);
+                result.set("capacity_pps", config.capacity_pps as i64);
+                result.set("queue_size", config.queue_size as i64);
             }
+            Some(BuildingConfig::Splitter(config)) => {
+                let weights: VariantArray = config
+                    .weights
+                    .iter()
+                    .map(|weight| (*weight as i64).to_variant())
+                    .collect();
+                result.set("weights", weights);
+                let (mode_str, output) = match config.mode {
+                    SplitterMode::Weighted => ("weighted", None),
+                    SplitterMode::Priority { output } => ("priority", Some(output)),
+                    SplitterMode::Overflow { output } => ("overflow", Some(output)),
+                };
+                result.set("mode", mode_str);
+                if let Some(name) = output.and_then(|i| SPLITTER_OUTPUTS.get(i)) {
+                    result.set("output", name.trim_start_matches("out_"));
+                }
+            }
+            Some(BuildingConfig::Merger(config)) => match config.mode {
+                MergerMode::Fair => result.set("mode", "fair"),
+                MergerMode::Priority { input } => {
+                    result.set("mode", "priority");
+                    if let Some(name) = MERGER_INPUTS.get(input) {
+                        result.set("input", name.trim_start_matches("in_"));
+                    }
+                }
+            },
+            None => {}
         }
 
         result
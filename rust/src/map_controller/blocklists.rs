@@ -0,0 +1,98 @@
+use super::World;
+use crate::core::buildings::filters::scan_detector::ScanDetector;
+use crate::core::dto::{BlocklistChange, BuildingId, WorldEvent};
+use crate::logic::blocklist::{BlocklistEntry, Blocklists};
+
+impl World {
+    pub fn blocklists(&self) -> &Blocklists {
+        &self.blocklists
+    }
+
+    /// Put `ip` on blocklist `list` for `ttl_ms` milliseconds of simulated time, or for good
+    /// when it is `None`, replacing any existing entry. Returns whether the list changed.
+    pub fn set_blocklist_entry(&mut self, list: &str, ip: &str, ttl_ms: Option<u32>) -> bool {
+        let ttl = ttl_ms.map(|ms| ms as f64 / 1000.0);
+        if !self.blocklists.set(list, ip, ttl) {
+            return false;
+        }
+        self.push_blocklist_added(list, ip, ttl_ms, None);
+        true
+    }
+
+    /// Put `entry` back on blocklist `list` for `ip`, replacing any existing entry. Returns
+    /// whether the list changed.
+    pub fn restore_blocklist_entry(&mut self, list: &str, ip: &str, entry: BlocklistEntry) -> bool {
+        if !self.blocklists.put(list, ip, Some(entry)) {
+            return false;
+        }
+        let ttl_ms = self
+            .blocklists
+            .left(entry)
+            .map(|secs| (secs * 1000.0).round() as u32);
+        self.push_blocklist_added(list, ip, ttl_ms, None);
+        true
+    }
+
+    /// Take `ip` off blocklist `list`. Returns whether it was on it.
+    pub fn remove_from_blocklist(&mut self, list: &str, ip: &str) -> bool {
+        if !self.blocklists.remove(list, ip) {
+            return false;
+        }
+        self.events.push(WorldEvent::BlocklistChanged {
+            list: list.to_string(),
+            ip: ip.to_string(),
+            change: BlocklistChange::Removed,
+        });
+        true
+    }
+
+    /// Advance blocklist TTLs by `delta` seconds. Called from `update`.
+    pub(super) fn expire_blocklists(&mut self, delta: f32) {
+        for (list, ip) in self.blocklists.advance(delta as f64) {
+            self.events.push(WorldEvent::BlocklistChanged {
+                list,
+                ip,
+                change: BlocklistChange::Expired,
+            });
+        }
+    }
+
+    /// Add the sources detector `id` flagged since the last call to the blocklist it feeds,
+    /// if any.
+    pub(super) fn publish_detections(&mut self, id: BuildingId) {
+        let Some(detector) = self
+            .storage
+            .get_mut(id)
+            .and_then(|b| b.as_any_mut().downcast_mut::<ScanDetector>())
+        else {
+            return;
+        };
+        let sources = detector.take_reported();
+        let Some(feed) = detector.feed.clone() else {
+            return;
+        };
+        let list = feed.list;
+        let ttl_ms = (feed.ttl_ms > 0).then_some(feed.ttl_ms);
+        let ttl = ttl_ms.map(|ms| ms as f64 / 1000.0);
+        for source in sources {
+            // A source flagged again keeps the longer of its two entries.
+            if self.blocklists.extend(&list, &source, ttl) {
+                self.push_blocklist_added(&list, &source, ttl_ms, Some(id));
+            }
+        }
+    }
+
+    fn push_blocklist_added(
+        &mut self,
+        list: &str,
+        ip: &str,
+        ttl_ms: Option<u32>,
+        by: Option<BuildingId>,
+    ) {
+        self.events.push(WorldEvent::BlocklistChanged {
+            list: list.to_string(),
+            ip: ip.to_string(),
+            change: BlocklistChange::Added { by, ttl_ms },
+        });
+    }
+}
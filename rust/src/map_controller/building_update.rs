@@ -0,0 +1,262 @@
+use super::World;
+use crate::core::building::{Building, BuildingState, BuildingType};
+use crate::core::building_config::BuildingConfig;
+use crate::core::building_registry::{BuildingDef, registry};
+use crate::core::buildings::analyzer::Analyzer;
+use crate::core::buildings::conveyor::Conveyor;
+use crate::core::buildings::datacenter::Datacenter;
+use crate::core::buildings::filters::content_filter::ContentFilter;
+use crate::core::buildings::filters::ip_filter::IpFilter;
+use crate::core::buildings::filters::length_filter::LengthFilter;
+use crate::core::buildings::filters::port_filter::PortFilter;
+use crate::core::buildings::filters::protocol_filter::ProtocolFilter;
+use crate::core::buildings::filters::rate_limiter::RateLimiter;
+use crate::core::buildings::filters::scan_detector::ScanDetector;
+use crate::core::buildings::filters::stateful_firewall::StatefulFirewall;
+use crate::core::buildings::honeypot::Honeypot;
+use crate::core::buildings::internet::Internet;
+use crate::core::buildings::junction::Junction;
+use crate::core::buildings::merger::Merger;
+use crate::core::buildings::recycle_bin::RecycleBin;
+use crate::core::buildings::splitter::Splitter;
+use crate::core::buildings::tap::Tap;
+use crate::core::buildings::tunnel::{TunnelEntrance, TunnelExit};
+use crate::core::dto::{BuildingId, DropReason, Vec2i, WorldEvent};
+use crate::core::packet::Packet;
+
+impl World {
+    /// Turn building `id` in place. Its id, buffered packets and round-robin state are kept;
+    /// only the edges at its old and new ports are re-evaluated. A footprint that is not
+    /// square turns with it, and the rotation is refused when that would overlap another
+    /// building.
+    pub fn rotate_building(&mut self, id: BuildingId, rotation: i32) -> bool {
+        let Some(building) = self.storage.get(id) else {
+            return false;
+        };
+        if building.rotation() == rotation {
+            return false;
+        }
+        let pos = building.position();
+        let old_tiles = footprint(building);
+        let new_tiles = tiles(pos, building.layout().size(rotation));
+        if new_tiles
+            .iter()
+            .any(|tile| self.map.get(tile).is_some_and(|owner| owner != id))
+        {
+            return false;
+        }
+        // Sources at the old input tiles (and entrances behind a tunnel exit) lose their edges.
+        let removed = self.graph.remove_node(building, &self.map, &self.storage);
+
+        if let Some(building) = self.storage.get_mut(id) {
+            building.set_rotation(rotation);
+        }
+        for tile in &old_tiles {
+            self.map.remove(tile);
+        }
+        for tile in new_tiles {
+            self.map.insert(tile, id);
+        }
+        self.graph
+            .recompute_outputs(&removed, &self.map, &self.storage);
+        self.prune_route_counters(&removed);
+        self.update_connections_at(pos);
+        self.push_building_updated(id);
+        true
+    }
+
+    /// Swap building `id` for block `block_id` of the building registry under the same id,
+    /// position and rotation. Filter rules only fit their own filter type, so the new
+    /// building gets `config` or no rule; buffered packets move over when both buildings are
+    /// filters or both are conveyors. Packets that do not fit the slots of a smaller conveyor
+    /// tier are dropped as `DropReason::Overloaded`, back first; the packets of any other
+    /// building, delivered ones included, are dropped as `DropReason::Replaced`.
+    ///
+    /// Returns `false` when the block is unknown or unchanged, `config` does not fit it, or
+    /// the new footprint would overlap another building.
+    pub fn replace_building(
+        &mut self,
+        id: BuildingId,
+        block_id: i32,
+        config: Option<BuildingConfig>,
+    ) -> bool {
+        let Some(def) = registry().by_id(block_id) else {
+            return false;
+        };
+        let building_type = def.behaviour;
+        if config
+            .as_ref()
+            .is_some_and(|config| config.building_type() != building_type)
+        {
+            return false;
+        }
+        let Some(old) = self.storage.get(id) else {
+            return false;
+        };
+        let old_type = old.building_type();
+        if old.def_id() == block_id {
+            return false;
+        }
+
+        let pos = old.position();
+        let rotation = old.rotation();
+        let mut building = new_building(id, pos, def, rotation);
+        let old_tiles = footprint(old);
+        let new_tiles = footprint(building.as_ref());
+        if new_tiles
+            .iter()
+            .any(|tile| self.map.get(tile).is_some_and(|owner| owner != id))
+        {
+            return false;
+        }
+        let mut overflow = Vec::new();
+        let mut discarded = Vec::new();
+        if carries_state(old_type, building_type) {
+            let state = match old.save_state() {
+                BuildingState::Conveyor(mut buffer) => {
+                    let slots = building
+                        .as_any()
+                        .downcast_ref::<Conveyor>()
+                        .map_or(buffer.len(), Conveyor::capacity);
+                    overflow = buffer.split_off(slots.min(buffer.len()));
+                    BuildingState::Conveyor(buffer)
+                }
+                // Only the packet moves; pass times, sessions and flags belong to the old building.
+                BuildingState::RateLimiter(state) => {
+                    BuildingState::Packets(state.packet.into_iter().collect())
+                }
+                BuildingState::StatefulFirewall(state) => {
+                    BuildingState::Packets(state.packet.into_iter().collect())
+                }
+                BuildingState::ScanDetector(state) => {
+                    BuildingState::Packets(state.packet.into_iter().collect())
+                }
+                state => state,
+            };
+            building.restore_state(state);
+        } else {
+            discarded = held_packets(old);
+        }
+
+        // Same order as a removal followed by a placement, so only the ports of this
+        // building need re-evaluating.
+        let removed = self.graph.remove_node(old, &self.map, &self.storage);
+        for tile in &old_tiles {
+            self.map.remove(tile);
+        }
+        self.storage.remove(id);
+        self.storage.add_building(building);
+        for tile in new_tiles {
+            self.map.insert(tile, id);
+        }
+        self.graph
+            .recompute_outputs(&removed, &self.map, &self.storage);
+        match config {
+            Some(config) => {
+                self.set_filter_config(id, config);
+            }
+            None => self.clear_filter_config(id),
+        }
+        for (packet, _) in overflow {
+            self.record_drop(packet, id, building_type, DropReason::Overloaded);
+        }
+        for packet in discarded {
+            self.record_drop(packet, id, old_type, DropReason::Replaced);
+        }
+
+        self.prune_route_counters(&removed);
+        self.update_connections_at(pos);
+        self.push_building_updated(id);
+        true
+    }
+
+    fn push_building_updated(&mut self, id: BuildingId) {
+        let Some(building) = self.storage.get(id) else {
+            return;
+        };
+        self.events.push(WorldEvent::BuildingUpdated {
+            id,
+            pos: building.position(),
+            building_type: building.building_type(),
+            rotation: building.rotation(),
+        });
+    }
+}
+
+/// A new building of block `def`.
+pub(super) fn new_building(
+    id: BuildingId,
+    pos: Vec2i,
+    def: &BuildingDef,
+    rotation: i32,
+) -> Box<dyn Building> {
+    match def.behaviour {
+        BuildingType::Conveyor => Box::new(Conveyor::from_def(id, pos, rotation, def)),
+        BuildingType::Internet => Box::new(Internet::new(id, pos, rotation)),
+        BuildingType::Datacenter => Box::new(Datacenter::new(id, pos, rotation)),
+        BuildingType::RecycleBin => Box::new(RecycleBin::new(id, pos, rotation)),
+        BuildingType::IpFilter => Box::new(IpFilter::new(id, pos, rotation)),
+        BuildingType::PortFilter => Box::new(PortFilter::new(id, pos, rotation)),
+        BuildingType::LengthFilter => Box::new(LengthFilter::new(id, pos, rotation)),
+        BuildingType::ProtocolFilter => Box::new(ProtocolFilter::new(id, pos, rotation)),
+        BuildingType::ContentFilter => Box::new(ContentFilter::new(id, pos, rotation)),
+        BuildingType::Junction => Box::new(Junction::new(id, pos, rotation)),
+        BuildingType::TunnelEntrance => Box::new(TunnelEntrance::new(id, pos, rotation)),
+        BuildingType::TunnelExit => Box::new(TunnelExit::new(id, pos, rotation)),
+        BuildingType::Splitter => Box::new(Splitter::new(id, pos, rotation)),
+        BuildingType::Merger => Box::new(Merger::new(id, pos, rotation)),
+        BuildingType::Tap => Box::new(Tap::new(id, pos, rotation)),
+        BuildingType::Analyzer => Box::new(Analyzer::new(id, pos, rotation)),
+        BuildingType::RateLimiter => Box::new(RateLimiter::new(id, pos, rotation)),
+        BuildingType::StatefulFirewall => Box::new(StatefulFirewall::new(id, pos, rotation)),
+        BuildingType::ScanDetector => Box::new(ScanDetector::new(id, pos, rotation)),
+        BuildingType::Honeypot => Box::new(Honeypot::new(id, pos, rotation)),
+    }
+}
+
+fn footprint(building: &dyn Building) -> Vec<Vec2i> {
+    tiles(building.position(), building.get_size())
+}
+
+/// Tiles covered by a footprint of `size` whose top-left tile is `pos`.
+fn tiles(pos: Vec2i, size: Vec2i) -> Vec<Vec2i> {
+    let mut tiles = Vec::new();
+    for y in 0..size.y {
+        for x in 0..size.x {
+            tiles.push(Vec2i {
+                x: pos.x + x,
+                y: pos.y + y,
+            });
+        }
+    }
+    tiles
+}
+
+/// Every packet `building` holds, including those a datacenter has queued.
+fn held_packets(building: &dyn Building) -> Vec<Packet> {
+    let mut packets = building.get_packets();
+    if let Some(datacenter) = building.as_any().downcast_ref::<Datacenter>() {
+        packets.extend(datacenter.queued_packets().cloned());
+    }
+    packets
+}
+
+/// Whether the packets held by an `old` building make sense in a `new` one.
+fn carries_state(old: BuildingType, new: BuildingType) -> bool {
+    (is_filter(old) && is_filter(new))
+        || (old == BuildingType::Conveyor && new == BuildingType::Conveyor)
+}
+
+fn is_filter(building_type: BuildingType) -> bool {
+    matches!(
+        building_type,
+        BuildingType::IpFilter
+            | BuildingType::PortFilter
+            | BuildingType::LengthFilter
+            | BuildingType::ProtocolFilter
+            | BuildingType::ContentFilter
+            | BuildingType::RateLimiter
+            | BuildingType::StatefulFirewall
+            | BuildingType::ScanDetector
+    )
+}
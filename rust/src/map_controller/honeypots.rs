@@ -0,0 +1,47 @@
+use std::collections::BTreeMap;
+use std::collections::btree_map::Entry;
+
+use super::World;
+use crate::core::buildings::honeypot::{AttackerProfile, Honeypot};
+use crate::core::packet::{Packet, PacketLabel};
+
+impl World {
+    /// Score added to `World::score` for every attack packet delivered to a honeypot.
+    pub fn set_honeypot_reward(&mut self, reward: u32) {
+        self.honeypot_reward = reward;
+    }
+
+    /// Senders seen by all honeypots together, most attack packets first.
+    pub fn attacker_profiles(&self) -> Vec<AttackerProfile> {
+        let mut by_source: BTreeMap<&str, AttackerProfile> = BTreeMap::new();
+        for honeypot in self
+            .storage
+            .iter()
+            .filter_map(|b| b.as_any().downcast_ref::<Honeypot>())
+        {
+            for profile in honeypot.profiles().values() {
+                match by_source.entry(profile.source_ip.as_str()) {
+                    Entry::Occupied(mut known) => known.get_mut().merge(profile),
+                    Entry::Vacant(slot) => {
+                        slot.insert(profile.clone());
+                    }
+                }
+            }
+        }
+        let mut merged: Vec<AttackerProfile> = by_source.into_values().collect();
+        merged.sort_by(|a, b| {
+            b.attack_packets
+                .cmp(&a.attack_packets)
+                .then(b.packets.cmp(&a.packets))
+                .then(a.source_ip.cmp(&b.source_ip))
+        });
+        merged
+    }
+
+    /// Reward `packet` having been delivered to a honeypot, if it is attack traffic.
+    pub(super) fn reward_honeypot_catch(&mut self, packet: &Packet) {
+        if packet.label == PacketLabel::Incorrect && !packet.is_mirror_copy() {
+            self.score = self.score.saturating_add(self.honeypot_reward);
+        }
+    }
+}
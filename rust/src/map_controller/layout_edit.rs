@@ -0,0 +1,311 @@
+use serde::{Deserialize, Serialize};
+
+use super::World;
+use super::building_update::new_building;
+use crate::core::building_config::BuildingConfig;
+use crate::core::building_registry::registry;
+use crate::core::dto::{BuildingId, Vec2i};
+use crate::logic::blocklist::BlocklistEntry;
+
+/// One player edit of the layout. Every layout change made through `MapController` goes
+/// through [`World::apply_edit`], so it can be recorded and replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LayoutEdit {
+    /// Place block `block_id` of the building registry.
+    Place {
+        pos: Vec2i,
+        block_id: i32,
+        rotation: i32,
+    },
+    PlaceFilter {
+        pos: Vec2i,
+        rotation: i32,
+        config: BuildingConfig,
+    },
+    Remove {
+        pos: Vec2i,
+    },
+    SetFilterConfig {
+        building_id: BuildingId,
+        config: BuildingConfig,
+    },
+    /// Put back a removed building under its old id (undo of `Remove`, redo of `Place`).
+    Restore {
+        id: BuildingId,
+        pos: Vec2i,
+        block_id: i32,
+        rotation: i32,
+        config: Option<BuildingConfig>,
+    },
+    ClearFilterConfig {
+        building_id: BuildingId,
+    },
+    Rotate {
+        building_id: BuildingId,
+        rotation: i32,
+    },
+    /// Swap the building for another block in place (see [`World::replace_building`]).
+    ReplaceType {
+        building_id: BuildingId,
+        block_id: i32,
+        config: Option<BuildingConfig>,
+    },
+    /// Put an IP on a named blocklist, for `ttl_ms` or for good (see
+    /// [`World::set_blocklist_entry`]).
+    SetBlocklistEntry {
+        list: String,
+        ip: String,
+        ttl_ms: Option<u32>,
+    },
+    RemoveBlocklistEntry {
+        list: String,
+        ip: String,
+    },
+    /// Put back a blocklist entry as it was, expiry included (undo of the two edits above).
+    RestoreBlocklistEntry {
+        list: String,
+        ip: String,
+        entry: BlocklistEntry,
+    },
+}
+
+/// An edit that changed the world, with the edits that repeat and revert it.
+#[derive(Debug, Clone)]
+pub struct AppliedEdit {
+    pub redo: LayoutEdit,
+    pub undo: LayoutEdit,
+}
+
+impl World {
+    /// Apply `edit`. Returns `None` when it changed nothing, e.g. placing onto an occupied
+    /// tile or removing from an empty one.
+    pub fn apply_edit(&mut self, edit: &LayoutEdit) -> Option<AppliedEdit> {
+        let applied = self.make_edit(edit)?;
+        self.log_edit(applied.clone());
+        Some(applied)
+    }
+
+    /// `apply_edit` without keeping the edit for `rewind_to`.
+    pub(super) fn make_edit(&mut self, edit: &LayoutEdit) -> Option<AppliedEdit> {
+        match edit {
+            LayoutEdit::Place {
+                pos,
+                block_id,
+                rotation,
+            } => {
+                let id = self.next_id;
+                self.place_block(*pos, *block_id, *rotation);
+                self.placed_edit(id, *pos)
+            }
+            LayoutEdit::PlaceFilter {
+                pos,
+                rotation,
+                config,
+            } => {
+                let id = self.next_id;
+                self.place_filter(*pos, *rotation, config.clone());
+                self.placed_edit(id, *pos)
+            }
+            LayoutEdit::Remove { pos } => {
+                let id = self.get_building_id_at(pos)?;
+                let restore = self.restore_edit(id)?;
+                self.remove_building(pos);
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: restore,
+                })
+            }
+            LayoutEdit::SetFilterConfig {
+                building_id,
+                config,
+            } => {
+                let previous = self.filter_config(*building_id);
+                if !self.set_filter_config(*building_id, config.clone()) {
+                    return None;
+                }
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: config_edit(*building_id, previous),
+                })
+            }
+            LayoutEdit::Restore {
+                id,
+                pos,
+                block_id,
+                rotation,
+                config,
+            } => {
+                if self.storage.get(*id).is_some() {
+                    return None;
+                }
+                // Reuse the normal placement path with `next_id` pointed at the old id, so
+                // later edits that refer to the id keep working.
+                let next_id = self.next_id;
+                self.next_id = *id;
+                self.place_block(*pos, *block_id, *rotation);
+                if let Some(config) = config {
+                    self.set_filter_config(*id, config.clone());
+                }
+                self.next_id = next_id;
+                self.storage.get(*id)?;
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: LayoutEdit::Remove { pos: *pos },
+                })
+            }
+            LayoutEdit::ClearFilterConfig { building_id } => {
+                let previous = self.filter_config(*building_id)?;
+                self.clear_filter_config(*building_id);
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: config_edit(*building_id, Some(previous)),
+                })
+            }
+            LayoutEdit::Rotate {
+                building_id,
+                rotation,
+            } => {
+                let previous = self.storage.get(*building_id)?.rotation();
+                if !self.rotate_building(*building_id, *rotation) {
+                    return None;
+                }
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: LayoutEdit::Rotate {
+                        building_id: *building_id,
+                        rotation: previous,
+                    },
+                })
+            }
+            LayoutEdit::ReplaceType {
+                building_id,
+                block_id,
+                config,
+            } => {
+                let previous = self.storage.get(*building_id)?.def_id();
+                let previous_config = self.filter_config(*building_id);
+                if !self.replace_building(*building_id, *block_id, config.clone()) {
+                    return None;
+                }
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: LayoutEdit::ReplaceType {
+                        building_id: *building_id,
+                        block_id: previous,
+                        config: previous_config,
+                    },
+                })
+            }
+            LayoutEdit::SetBlocklistEntry { list, ip, ttl_ms } => {
+                let previous = self.blocklists.entry(list, ip);
+                if !self.set_blocklist_entry(list, ip, *ttl_ms) {
+                    return None;
+                }
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: blocklist_edit(list, ip, previous),
+                })
+            }
+            LayoutEdit::RemoveBlocklistEntry { list, ip } => {
+                let previous = self.blocklists.entry(list, ip);
+                if !self.remove_from_blocklist(list, ip) {
+                    return None;
+                }
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: blocklist_edit(list, ip, previous),
+                })
+            }
+            LayoutEdit::RestoreBlocklistEntry { list, ip, entry } => {
+                let previous = self.blocklists.entry(list, ip);
+                if !self.restore_blocklist_entry(list, ip, *entry) {
+                    return None;
+                }
+                Some(AppliedEdit {
+                    redo: edit.clone(),
+                    undo: blocklist_edit(list, ip, previous),
+                })
+            }
+        }
+    }
+
+    fn place_filter(&mut self, pos: Vec2i, rotation: i32, config: BuildingConfig) {
+        let def = registry().by_type(config.building_type());
+        let mut building = new_building(self.next_id, pos, def, rotation);
+        building.set_filter_config(config);
+        self.add_placed_building(building);
+    }
+
+    /// Undo/redo pair for a placement that was assigned `id`, if it succeeded.
+    fn placed_edit(&self, id: BuildingId, pos: Vec2i) -> Option<AppliedEdit> {
+        let restore = self.restore_edit(id)?;
+        Some(AppliedEdit {
+            redo: restore,
+            undo: LayoutEdit::Remove { pos },
+        })
+    }
+
+    /// Edit that re-creates building `id` as it is now, rule included.
+    fn restore_edit(&self, id: BuildingId) -> Option<LayoutEdit> {
+        let building = self.storage.get(id)?;
+        Some(LayoutEdit::Restore {
+            id,
+            pos: building.position(),
+            block_id: building.def_id(),
+            rotation: building.rotation(),
+            config: self.filter_config(id),
+        })
+    }
+
+    /// Replace the rule of filter `id`. Returns `false` when `id` is not a filter of the
+    /// config's type.
+    pub fn set_filter_config(&mut self, id: BuildingId, config: BuildingConfig) -> bool {
+        let changed = self
+            .storage
+            .get_mut(id)
+            .is_some_and(|building| building.set_filter_config(config));
+        // A datacenter whose queue shrank loses the packets that no longer fit.
+        self.drop_rejected_packets(id);
+        changed
+    }
+
+    /// Remove the rule of filter `id`; a filter without a rule passes nothing, and a
+    /// splitter or merger without one alternates evenly.
+    pub fn clear_filter_config(&mut self, id: BuildingId) {
+        if let Some(building) = self.storage.get_mut(id) {
+            building.clear_filter_config();
+        }
+    }
+
+    /// Current rule of filter `id`, if it is a filter with a rule.
+    pub fn filter_config(&self, id: BuildingId) -> Option<BuildingConfig> {
+        self.storage.get(id)?.filter_config()
+    }
+}
+
+/// Edit that puts blocklist entry `list`/`ip` back to how it was: absent when `previous`
+/// is `None`, otherwise present with the expiry it had, so undoing later does not extend it.
+fn blocklist_edit(list: &str, ip: &str, previous: Option<BlocklistEntry>) -> LayoutEdit {
+    match previous {
+        Some(entry) => LayoutEdit::RestoreBlocklistEntry {
+            list: list.to_string(),
+            ip: ip.to_string(),
+            entry,
+        },
+        None => LayoutEdit::RemoveBlocklistEntry {
+            list: list.to_string(),
+            ip: ip.to_string(),
+        },
+    }
+}
+
+fn config_edit(building_id: BuildingId, config: Option<BuildingConfig>) -> LayoutEdit {
+    match config {
+        Some(config) => LayoutEdit::SetFilterConfig {
+            building_id,
+            config,
+        },
+        None => LayoutEdit::ClearFilterConfig { building_id },
+    }
+}
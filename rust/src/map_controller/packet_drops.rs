@@ -0,0 +1,152 @@
+use godot::prelude::*;
+use serde::Serialize;
+
+use super::World;
+use crate::core::building::BuildingType;
+use crate::core::buildings::analyzer::Analyzer;
+use crate::core::buildings::datacenter::Datacenter;
+use crate::core::dto::{BuildingId, DropReason, WorldEvent};
+use crate::core::packet::{Packet as CorePacket, PacketLabel};
+
+/// A packet discarded during routing. Dropped packets count as resolved for completion.
+#[derive(Debug, Clone)]
+pub struct DroppedPacket {
+    pub packet: CorePacket,
+    pub building_id: BuildingId,
+    pub building_type: BuildingType,
+    pub reason: DropReason,
+    pub tick: u64,
+}
+
+/// Drop counters for the evaluation screen.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct DropStats {
+    pub total: usize,
+    pub no_outputs: usize,
+    pub routing_failed: usize,
+    pub closed_service: usize,
+    pub overloaded: usize,
+    pub replaced: usize,
+    pub misrouted: usize,
+    /// Legitimate (`PacketLabel::Correct`) packets lost to datacenter overload.
+    pub denial_of_service: usize,
+    pub correct: usize,
+    pub incorrect: usize,
+    pub unknown: usize,
+    /// Score subtracted for drops so far.
+    pub penalty: u64,
+}
+
+impl World {
+    /// Score subtracted from `World::score` for every dropped packet.
+    pub fn set_drop_penalty(&mut self, penalty: u32) {
+        self.drop_penalty = penalty;
+    }
+
+    pub fn dropped_packets(&self) -> &[DroppedPacket] {
+        &self.dropped
+    }
+
+    pub fn drop_stats(&self) -> DropStats {
+        let mut stats = DropStats {
+            total: self.dropped.len(),
+            penalty: self.drop_penalty_total,
+            ..DropStats::default()
+        };
+        for dropped in &self.dropped {
+            match dropped.reason {
+                DropReason::NoOutputs => stats.no_outputs += 1,
+                DropReason::RoutingFailed => stats.routing_failed += 1,
+                DropReason::ClosedService => stats.closed_service += 1,
+                DropReason::Overloaded => {
+                    stats.overloaded += 1;
+                    if dropped.building_type == BuildingType::Datacenter
+                        && dropped.packet.label == PacketLabel::Correct
+                    {
+                        stats.denial_of_service += 1;
+                    }
+                }
+                DropReason::Replaced => stats.replaced += 1,
+                DropReason::Misrouted => stats.misrouted += 1,
+            }
+            match dropped.packet.label {
+                PacketLabel::Correct => stats.correct += 1,
+                PacketLabel::Incorrect => stats.incorrect += 1,
+                PacketLabel::Unknown => stats.unknown += 1,
+            }
+        }
+        stats
+    }
+
+    /// Record the packets datacenter `id` refused or lost to overload, or analyzer `id` took
+    /// in place of a copy, as drops.
+    pub(super) fn drop_rejected_packets(&mut self, id: BuildingId) {
+        let Some(building) = self.storage.get_mut(id) else {
+            return;
+        };
+        let building_type = building.building_type();
+        let rejected = if let Some(datacenter) = building.as_any_mut().downcast_mut::<Datacenter>()
+        {
+            datacenter.take_rejected()
+        } else if let Some(analyzer) = building.as_any_mut().downcast_mut::<Analyzer>() {
+            analyzer.take_rejected()
+        } else {
+            return;
+        };
+        for (packet, reason) in rejected {
+            self.record_drop(packet, id, building_type, reason);
+        }
+    }
+
+    pub(super) fn record_drop(
+        &mut self,
+        packet: CorePacket,
+        building_id: BuildingId,
+        building_type: BuildingType,
+        reason: DropReason,
+    ) {
+        // コピーは採点にも完了判定にも数えない
+        if packet.is_mirror_copy() {
+            self.events.push(WorldEvent::PacketDropped {
+                packet,
+                building_id,
+                reason,
+            });
+            return;
+        }
+        self.score = self.score.saturating_sub(self.drop_penalty);
+        self.drop_penalty_total += self.drop_penalty as u64;
+        self.events.push(WorldEvent::PacketDropped {
+            packet: packet.clone(),
+            building_id,
+            reason,
+        });
+        self.dropped.push(DroppedPacket {
+            packet,
+            building_id,
+            building_type,
+            reason,
+            tick: self.tick,
+        });
+    }
+}
+
+pub(super) fn drop_stats_to_dictionary(stats: &DropStats) -> Dictionary {
+    let mut dict = Dictionary::new();
+    dict.set("total", (stats.total as i64).to_variant());
+    dict.set("no_outputs", (stats.no_outputs as i64).to_variant());
+    dict.set("routing_failed", (stats.routing_failed as i64).to_variant());
+    dict.set("closed_service", (stats.closed_service as i64).to_variant());
+    dict.set("overloaded", (stats.overloaded as i64).to_variant());
+    dict.set("replaced", (stats.replaced as i64).to_variant());
+    dict.set("misrouted", (stats.misrouted as i64).to_variant());
+    dict.set(
+        "denial_of_service",
+        (stats.denial_of_service as i64).to_variant(),
+    );
+    dict.set("correct", (stats.correct as i64).to_variant());
+    dict.set("incorrect", (stats.incorrect as i64).to_variant());
+    dict.set("unknown", (stats.unknown as i64).to_variant());
+    dict.set("penalty", (stats.penalty as i64).to_variant());
+    dict
+}
@@ -1,13 +1,16 @@
 use godot::prelude::*;
+use std::path::Path;
 
 use super::World;
 use crate::core::building::BuildingType;
 use crate::core::dto::BuildingId;
-use crate::core::packet::{Packet as CorePacket, PacketLabel, Protocol};
+use crate::core::packet::{Packet as CorePacket, PacketId, PacketLabel, Protocol};
+use crate::packet::pcap_writer;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PacketView {
     pub building_id: BuildingId,
+    pub id: Option<PacketId>,
     pub source_ip: String,
     pub dest_ip: String,
     pub source_port: u16,
@@ -23,6 +26,7 @@ impl PacketView {
     pub fn from_packet(packet: &CorePacket, building_id: BuildingId) -> Self {
         Self {
             building_id,
+            id: packet.id,
             source_ip: packet.source_ip.clone(),
             dest_ip: packet.dest_ip.clone(),
             source_port: packet.source_port,
@@ -47,6 +51,7 @@ impl PacketView {
         dict.set("label", self.label.to_raw().to_variant());
         dict.set("progress", self.progress.to_variant());
         dict.set("payload", self.payload.to_variant());
+        super::set_packet_id(&mut dict, self.id);
         dict
     }
 
@@ -87,6 +92,57 @@ pub(super) fn recyclebin_packets(world: &World) -> VariantArray {
         .collect::<VariantArray>()
 }
 
+/// Which sink buildings contribute packets to a pcap export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PcapExportScope {
+    Datacenter,
+    RecycleBin,
+    Honeypot,
+    AllSinks,
+}
+
+impl PcapExportScope {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "datacenter" => Some(Self::Datacenter),
+            "recyclebin" => Some(Self::RecycleBin),
+            "honeypot" => Some(Self::Honeypot),
+            "all" => Some(Self::AllSinks),
+            _ => None,
+        }
+    }
+
+    fn includes(self, building_type: BuildingType) -> bool {
+        match self {
+            Self::Datacenter => building_type == BuildingType::Datacenter,
+            Self::RecycleBin => building_type == BuildingType::RecycleBin,
+            Self::Honeypot => building_type == BuildingType::Honeypot,
+            Self::AllSinks => matches!(
+                building_type,
+                BuildingType::Datacenter | BuildingType::RecycleBin | BuildingType::Honeypot
+            ),
+        }
+    }
+}
+
+impl World {
+    /// Packets currently held by the sinks selected by `scope`.
+    pub fn sink_packets(&self, scope: PcapExportScope) -> Vec<CorePacket> {
+        self.storage
+            .iter()
+            .filter(|building| scope.includes(building.building_type()))
+            .flat_map(|building| building.get_packets())
+            .collect()
+    }
+
+    /// Write the packets held by the selected sinks to a pcap file at `path`.
+    ///
+    /// Returns the number of frames written.
+    pub fn export_pcap(&self, scope: PcapExportScope, path: &Path) -> Result<usize, String> {
+        pcap_writer::write_pcap_file(path, &self.sink_packets(scope))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
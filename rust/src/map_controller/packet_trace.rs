@@ -0,0 +1,150 @@
+use godot::prelude::*;
+use std::collections::HashMap;
+
+use super::World;
+use crate::core::building::BuildingType;
+use crate::core::dto::BuildingId;
+use crate::core::packet::PacketId;
+
+/// One building a packet passed through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PacketHop {
+    /// `World::tick` at which the packet entered the building; for the `Internet` a packet
+    /// came from, the tick it was spawned.
+    pub tick: u64,
+    pub building_id: BuildingId,
+    pub building_type: BuildingType,
+    /// Result of the filter when the packet left a filter building; `None` for other
+    /// buildings and for packets that have not left yet.
+    pub verdict: Option<bool>,
+}
+
+/// Hop traces of every packet that carries a `PacketId`, kept for the whole stage.
+#[derive(Debug, Default)]
+pub(crate) struct PacketTraces {
+    traces: HashMap<PacketId, Vec<PacketHop>>,
+}
+
+impl PacketTraces {
+    /// Record that `id` was spawned in `origin` at `tick`, unless it is already traced.
+    pub(crate) fn record_spawn(
+        &mut self,
+        id: PacketId,
+        tick: u64,
+        origin: (BuildingId, BuildingType),
+    ) {
+        self.traces.entry(id).or_insert_with(|| {
+            vec![PacketHop {
+                tick,
+                building_id: origin.0,
+                building_type: origin.1,
+                verdict: None,
+            }]
+        });
+    }
+
+    /// Record a transfer from `from` to `to`. A packet that was not seen spawning starts its
+    /// trace at `from`.
+    pub(crate) fn record_transfer(
+        &mut self,
+        id: PacketId,
+        tick: u64,
+        from: (BuildingId, BuildingType),
+        verdict: Option<bool>,
+        to: (BuildingId, BuildingType),
+    ) {
+        let hops = self.traces.entry(id).or_default();
+        if hops.is_empty() {
+            hops.push(PacketHop {
+                tick,
+                building_id: from.0,
+                building_type: from.1,
+                verdict: None,
+            });
+        }
+        if let Some(last) = hops.last_mut()
+            && last.building_id == from.0
+        {
+            last.verdict = verdict;
+        }
+        hops.push(PacketHop {
+            tick,
+            building_id: to.0,
+            building_type: to.1,
+            verdict: None,
+        });
+    }
+
+    /// Forget hops recorded after `tick`, for rewinding.
+    pub(crate) fn truncate_after(&mut self, tick: u64) {
+        self.traces.retain(|_, hops| {
+            hops.retain(|hop| hop.tick <= tick);
+            !hops.is_empty()
+        });
+    }
+
+    pub(crate) fn get(&self, id: PacketId) -> Option<&[PacketHop]> {
+        self.traces.get(&id).map(Vec::as_slice)
+    }
+}
+
+impl World {
+    /// Buildings `id` has passed through so far, in order.
+    pub fn packet_trace(&self, id: PacketId) -> Option<&[PacketHop]> {
+        self.traces.get(id)
+    }
+}
+
+pub(super) fn trace_to_variant(hops: &[PacketHop]) -> VariantArray {
+    hops.iter()
+        .map(|hop| {
+            let mut dict = Dictionary::new();
+            dict.set("tick", (hop.tick as i64).to_variant());
+            dict.set("building_id", hop.building_id.to_variant());
+            dict.set("building_type", (hop.building_type as i32).to_variant());
+            dict.set(
+                "verdict",
+                hop.verdict.map_or(Variant::nil(), |v| v.to_variant()),
+            );
+            dict.to_variant()
+        })
+        .collect()
+}
+
+/// Add `origin_id` and `packet_id` keys for packets spawned by an `Internet`.
+pub(crate) fn set_packet_id(dict: &mut Dictionary, id: Option<PacketId>) {
+    if let Some(id) = id {
+        dict.set("origin_id", id.origin.to_variant());
+        dict.set("packet_id", (id.index as i64).to_variant());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn traces_start_at_the_spawn_tick_and_are_all_kept() {
+        let mut traces = PacketTraces::default();
+        let internet = (0, BuildingType::Internet);
+        let conveyor = (1, BuildingType::Conveyor);
+        let id = |index| PacketId { origin: 0, index };
+        for index in 0..10_000 {
+            traces.record_spawn(id(index), index, internet);
+            traces.record_transfer(id(index), index + 3, internet, None, conveyor);
+        }
+
+        assert_eq!(
+            traces
+                .get(id(0))
+                .map(|hops| hops.iter().map(|hop| hop.tick).collect::<Vec<_>>()),
+            Some(vec![0, 3])
+        );
+        assert!(traces.get(id(9_999)).is_some());
+
+        traces.truncate_after(5);
+        assert_eq!(traces.get(id(2)).map(<[PacketHop]>::len), Some(2));
+        assert_eq!(traces.get(id(3)).map(<[PacketHop]>::len), Some(1));
+        assert!(traces.get(id(6)).is_none());
+    }
+}
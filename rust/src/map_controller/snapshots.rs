@@ -0,0 +1,156 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use super::{AppliedEdit, World};
+use crate::core::building::BuildingState;
+use crate::core::buildings::internet::Internet;
+use crate::core::dto::BuildingId;
+use crate::logic::blocklist::Blocklists;
+use crate::logic::connection_graph::OutputRole;
+use crate::logic::fixed_step::TICK_SECONDS;
+use crate::logic::packet_completion::TrafficPlan;
+
+/// Ticks between two snapshots (half a second of simulated time).
+pub const SNAPSHOT_INTERVAL: u64 = 30;
+/// Snapshots kept; older ones are discarded (one minute of simulated time).
+pub const MAX_SNAPSHOTS: usize = 120;
+
+/// Simulation state of the world at the start of a tick. Internets' states include how many
+/// packets they have emitted, which numbers the packets they emit next.
+#[derive(Debug, Clone)]
+struct WorldSnapshot {
+    tick: u64,
+    score: u32,
+    buildings: BTreeMap<BuildingId, BuildingState>,
+    route_counters: HashMap<(BuildingId, OutputRole), usize>,
+    /// `World::dropped` only grows, so its length is enough to restore it.
+    dropped_len: usize,
+    drop_penalty_total: u64,
+    blocklists: Blocklists,
+    plan: TrafficPlan,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Snapshots {
+    list: VecDeque<WorldSnapshot>,
+    /// Edits made after the oldest snapshot, with the tick they were made at, oldest first.
+    edits: Vec<(u64, AppliedEdit)>,
+}
+
+impl World {
+    /// Number of snapshots available for `rewind_to`.
+    pub fn snapshot_count(&self) -> usize {
+        self.snapshots.list.len()
+    }
+
+    /// Earliest tick `rewind_to` can reach.
+    pub fn oldest_snapshot_tick(&self) -> Option<u64> {
+        self.snapshots.list.front().map(|snapshot| snapshot.tick)
+    }
+
+    /// Keep `edit`, made at the current tick, for `rewind_to`.
+    pub(super) fn log_edit(&mut self, edit: AppliedEdit) {
+        if !self.snapshots.list.is_empty() {
+            self.snapshots.edits.push((self.tick, edit));
+        }
+    }
+
+    /// Take a snapshot when one is due. Called at the start of `update`.
+    pub(super) fn take_snapshot_if_due(&mut self) {
+        if !self.tick.is_multiple_of(SNAPSHOT_INTERVAL) {
+            return;
+        }
+        if self.snapshots.list.len() == MAX_SNAPSHOTS {
+            self.snapshots.list.pop_front();
+        }
+        self.snapshots.list.push_back(WorldSnapshot {
+            tick: self.tick,
+            score: self.score,
+            buildings: self
+                .storage
+                .iter()
+                .map(|building| (building.id(), building.snapshot_state()))
+                .collect(),
+            route_counters: self.route_counters.clone(),
+            dropped_len: self.dropped.len(),
+            drop_penalty_total: self.drop_penalty_total,
+            blocklists: self.blocklists.clone(),
+            plan: self.plan.clone(),
+        });
+        let oldest = self.snapshots.list.front().map_or(self.tick, |s| s.tick);
+        self.snapshots.edits.retain(|(tick, _)| *tick > oldest);
+    }
+
+    /// Go back to `tick`: restore the closest earlier snapshot and simulate forward from
+    /// there. The edits made after the snapshot are taken back, the snapshot is restored, and
+    /// the edits are made again at its tick, the way a replay whose edits were moved there
+    /// makes them. The layout ends up as it was, and the ticks after the snapshot are
+    /// re-simulated with it; buildings placed after the snapshot start out empty, and what
+    /// an edit drops or expires is counted from the snapshot's tick.
+    ///
+    /// Returns the tick of the restored snapshot, from which the current layout applies.
+    pub fn rewind_to(&mut self, tick: u64) -> Result<u64, String> {
+        if tick > self.tick {
+            return Err(format!(
+                "現在のティック {} より先には巻き戻せません: {}",
+                self.tick, tick
+            ));
+        }
+        let Some(index) = self
+            .snapshots
+            .list
+            .iter()
+            .rposition(|snapshot| snapshot.tick <= tick)
+        else {
+            return Err(format!(
+                "ティック {} 以前のスナップショットがありません",
+                tick
+            ));
+        };
+        let rewindable = self.storage.iter().all(|building| {
+            building
+                .as_any()
+                .downcast_ref::<Internet>()
+                .is_none_or(|internet| internet.can_rewind())
+        });
+        if !rewindable {
+            return Err("ストリーミング中のトラフィックは巻き戻せません".to_string());
+        }
+
+        // Later snapshots are retaken while simulating forward.
+        self.snapshots.list.truncate(index + 1);
+        let snapshot = self.snapshots.list.pop_back().expect("index is in range");
+        let since = self
+            .snapshots
+            .edits
+            .partition_point(|(tick, _)| *tick <= snapshot.tick);
+        let edits = self.snapshots.edits.split_off(since);
+        for (_, edit) in edits.iter().rev() {
+            self.make_edit(&edit.undo);
+        }
+
+        let ids: Vec<BuildingId> = self.storage.iter().map(|b| b.id()).collect();
+        let mut states = snapshot.buildings;
+        for id in ids {
+            let state = states.remove(&id).unwrap_or_else(BuildingState::empty);
+            if let Some(building) = self.storage.get_mut(id) {
+                building.restore_state(state);
+            }
+        }
+        self.tick = snapshot.tick;
+        self.score = snapshot.score;
+        self.route_counters = snapshot.route_counters;
+        self.dropped.truncate(snapshot.dropped_len);
+        self.drop_penalty_total = snapshot.drop_penalty_total;
+        self.blocklists = snapshot.blocklists;
+        self.plan = snapshot.plan;
+        self.traces.truncate_after(snapshot.tick);
+        for (_, edit) in edits {
+            self.apply_edit(&edit.redo);
+        }
+
+        while self.tick < tick {
+            self.update(TICK_SECONDS);
+        }
+        Ok(snapshot.tick)
+    }
+}
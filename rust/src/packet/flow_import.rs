@@ -0,0 +1,758 @@
+//! Godot-independent import of flow records (CSV tables and Zeek `conn.log`) as traffic.
+//!
+//! Public security datasets such as CIC-IDS or UNSW-NB15 are distributed as labeled flow
+//! tables rather than packet captures. [`parse_flow_csv`] and [`parse_zeek_conn`] map the
+//! columns named in [`FlowColumns`] onto [`TrafficEntry`] values, which are then turned into a
+//! `Traffic` resource exactly like JSON traffic.
+//!
+//! - Column names are matched case-insensitively after trimming, because several datasets pad
+//!   their headers with spaces.
+//! - `protocol` may be an IP protocol number or a name (`tcp`, `udp`, `icmp`).
+//! - `label` values listed in [`FlowImportOptions::benign_labels`] become `Correct`, any other
+//!   non-empty value becomes `Incorrect` and missing values stay `Unknown`.
+//! - Without a timestamp column, rows are spaced [`FlowImportOptions::row_interval_us`] apart
+//!   in file order.
+//! - With [`FlowImportOptions::expand`] set, each flow is split into several packets spread
+//!   over the flow's duration.
+use std::fmt;
+
+use serde_json::{Map, Value};
+
+use super::traffic_schema::TrafficEntry;
+use crate::core::packet::PacketLabel;
+
+/// Values Zeek writes for unset and empty fields.
+const ZEEK_UNSET: &str = "-";
+const ZEEK_EMPTY: &str = "(empty)";
+
+/// Names of the columns to read. Optional columns are ignored when absent from the file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowColumns {
+    pub src_ip: String,
+    pub dst_ip: String,
+    pub src_port: String,
+    pub dst_port: String,
+    pub protocol: String,
+    pub bytes: String,
+    pub timestamp: Option<String>,
+    pub label: Option<String>,
+    /// Packet count of the flow, used when expanding flows into packets.
+    pub packets: Option<String>,
+    /// Flow duration, used to spread expanded packets over time.
+    pub duration: Option<String>,
+}
+
+impl Default for FlowColumns {
+    fn default() -> Self {
+        Self {
+            src_ip: "src_ip".to_string(),
+            dst_ip: "dst_ip".to_string(),
+            src_port: "src_port".to_string(),
+            dst_port: "dst_port".to_string(),
+            protocol: "protocol".to_string(),
+            bytes: "bytes".to_string(),
+            timestamp: Some("timestamp".to_string()),
+            label: Some("label".to_string()),
+            packets: Some("packets".to_string()),
+            duration: Some("duration".to_string()),
+        }
+    }
+}
+
+impl FlowColumns {
+    /// Zeek `conn.log`. Labeled variants (e.g. IoT-23) add a `label` field.
+    pub fn zeek() -> Self {
+        Self {
+            src_ip: "id.orig_h".to_string(),
+            dst_ip: "id.resp_h".to_string(),
+            src_port: "id.orig_p".to_string(),
+            dst_port: "id.resp_p".to_string(),
+            protocol: "proto".to_string(),
+            bytes: "orig_bytes".to_string(),
+            timestamp: Some("ts".to_string()),
+            label: Some("label".to_string()),
+            packets: Some("orig_pkts".to_string()),
+            duration: Some("duration".to_string()),
+        }
+    }
+
+    /// CICFlowMeter CSV as shipped with CIC-IDS2017. Its timestamps are local date strings,
+    /// so rows are placed in file order instead.
+    pub fn cic_ids() -> Self {
+        Self {
+            src_ip: "Source IP".to_string(),
+            dst_ip: "Destination IP".to_string(),
+            src_port: "Source Port".to_string(),
+            dst_port: "Destination Port".to_string(),
+            protocol: "Protocol".to_string(),
+            bytes: "Total Length of Fwd Packets".to_string(),
+            timestamp: None,
+            label: Some("Label".to_string()),
+            packets: Some("Total Fwd Packets".to_string()),
+            duration: Some("Flow Duration".to_string()),
+        }
+    }
+
+    /// UNSW-NB15 CSV with the header row from its feature description.
+    pub fn unsw_nb15() -> Self {
+        Self {
+            src_ip: "srcip".to_string(),
+            dst_ip: "dstip".to_string(),
+            src_port: "sport".to_string(),
+            dst_port: "dsport".to_string(),
+            protocol: "proto".to_string(),
+            bytes: "sbytes".to_string(),
+            timestamp: Some("stime".to_string()),
+            label: Some("label".to_string()),
+            packets: Some("spkts".to_string()),
+            duration: Some("dur".to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimeUnit {
+    #[default]
+    Seconds,
+    Milliseconds,
+    Microseconds,
+}
+
+impl TimeUnit {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "s" | "seconds" => Some(Self::Seconds),
+            "ms" | "milliseconds" => Some(Self::Milliseconds),
+            "us" | "microseconds" => Some(Self::Microseconds),
+            _ => None,
+        }
+    }
+
+    fn to_micros(self, value: f64) -> i64 {
+        let factor = match self {
+            Self::Seconds => 1_000_000.0,
+            Self::Milliseconds => 1_000.0,
+            Self::Microseconds => 1.0,
+        };
+        (value * factor).round() as i64
+    }
+}
+
+/// Split every flow into up to `max_packets` packets.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlowExpansion {
+    pub max_packets: u32,
+    /// Packet size used to derive the packet count when the file has no packet column.
+    pub mtu: u32,
+}
+
+impl Default for FlowExpansion {
+    fn default() -> Self {
+        Self {
+            max_packets: 16,
+            mtu: 1500,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FlowImportOptions {
+    pub columns: FlowColumns,
+    /// Field separator for CSV input. Zeek logs declare their own separator.
+    pub delimiter: char,
+    pub timestamp_unit: TimeUnit,
+    pub duration_unit: TimeUnit,
+    /// Label values (case-insensitive) that mark benign traffic.
+    pub benign_labels: Vec<String>,
+    pub row_interval_us: i64,
+    pub expand: Option<FlowExpansion>,
+}
+
+impl Default for FlowImportOptions {
+    fn default() -> Self {
+        Self {
+            columns: FlowColumns::default(),
+            delimiter: ',',
+            timestamp_unit: TimeUnit::Seconds,
+            duration_unit: TimeUnit::Seconds,
+            benign_labels: ["benign", "normal", "0", "false"]
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            row_interval_us: 1_000,
+            expand: None,
+        }
+    }
+}
+
+impl FlowImportOptions {
+    pub fn zeek() -> Self {
+        Self {
+            columns: FlowColumns::zeek(),
+            delimiter: '\t',
+            ..Self::default()
+        }
+    }
+
+    pub fn cic_ids() -> Self {
+        Self {
+            columns: FlowColumns::cic_ids(),
+            duration_unit: TimeUnit::Microseconds,
+            ..Self::default()
+        }
+    }
+
+    pub fn unsw_nb15() -> Self {
+        Self {
+            columns: FlowColumns::unsw_nb15(),
+            ..Self::default()
+        }
+    }
+
+    /// Options for a named dataset layout: `generic`, `zeek`, `cic_ids` or `unsw_nb15`.
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "generic" => Some(Self::default()),
+            "zeek" => Some(Self::zeek()),
+            "cic_ids" => Some(Self::cic_ids()),
+            "unsw_nb15" => Some(Self::unsw_nb15()),
+            _ => None,
+        }
+    }
+
+    /// Options from the object documented for `FlowLoader` (`preset`, `columns`, ...), as found
+    /// in a stage's `flowOptions`. Missing keys keep the values of `default_preset`.
+    pub fn from_json(value: Option<&Value>, default_preset: &str) -> Result<Self, String> {
+        let empty = Map::new();
+        let dict = value.and_then(Value::as_object).unwrap_or(&empty);
+        let string = |key: &str| dict.get(key).and_then(Value::as_str);
+        // Godot's JSON writes every number as a float.
+        let int = |key: &str| {
+            dict.get(key)
+                .and_then(|v| v.as_i64().or_else(|| v.as_f64().map(|f| f as i64)))
+        };
+        let unit = |key: &str| -> Result<Option<TimeUnit>, String> {
+            string(key)
+                .map(|name| {
+                    TimeUnit::from_name(name).ok_or_else(|| format!("不明な時間単位: '{}'", name))
+                })
+                .transpose()
+        };
+
+        let preset = string("preset").unwrap_or(default_preset);
+        let mut options =
+            Self::preset(preset).ok_or_else(|| format!("不明なプリセット: '{}'", preset))?;
+
+        if let Some(columns) = dict.get("columns").and_then(Value::as_object) {
+            let name = |key: &str| columns.get(key).and_then(Value::as_str).map(String::from);
+            let c = &mut options.columns;
+            for (key, column) in [
+                ("src_ip", &mut c.src_ip),
+                ("dst_ip", &mut c.dst_ip),
+                ("src_port", &mut c.src_port),
+                ("dst_port", &mut c.dst_port),
+                ("protocol", &mut c.protocol),
+                ("bytes", &mut c.bytes),
+            ] {
+                if let Some(value) = name(key) {
+                    *column = value;
+                }
+            }
+            for (key, column) in [
+                ("timestamp", &mut c.timestamp),
+                ("label", &mut c.label),
+                ("packets", &mut c.packets),
+                ("duration", &mut c.duration),
+            ] {
+                if let Some(value) = name(key) {
+                    *column = (!value.is_empty()).then_some(value);
+                }
+            }
+        }
+        if let Some(delimiter) = string("delimiter") {
+            options.delimiter = delimiter
+                .chars()
+                .next()
+                .ok_or_else(|| "区切り文字が空です".to_string())?;
+        }
+        if let Some(unit) = unit("timestamp_unit")? {
+            options.timestamp_unit = unit;
+        }
+        if let Some(unit) = unit("duration_unit")? {
+            options.duration_unit = unit;
+        }
+        if let Some(labels) = dict.get("benign_labels").and_then(Value::as_array) {
+            options.benign_labels = labels
+                .iter()
+                .filter_map(Value::as_str)
+                .map(String::from)
+                .collect();
+        }
+        if let Some(interval) = int("row_interval_us") {
+            options.row_interval_us = interval.max(0);
+        }
+        if dict.get("expand").and_then(Value::as_bool).unwrap_or(false) {
+            let defaults = FlowExpansion::default();
+            let count = |key: &str| int(key).map(|n| n.clamp(1, u32::MAX as i64) as u32);
+            options.expand = Some(FlowExpansion {
+                max_packets: count("max_packets").unwrap_or(defaults.max_packets),
+                mtu: count("mtu").unwrap_or(defaults.mtu),
+            });
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowImportError {
+    pub message: String,
+    /// 1-based line of the offending row, `0` for errors about the file as a whole.
+    pub line: usize,
+}
+
+impl fmt::Display for FlowImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}行: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for FlowImportError {}
+
+fn error(line: usize, message: impl Into<String>) -> FlowImportError {
+    FlowImportError {
+        message: message.into(),
+        line,
+    }
+}
+
+/// Parse a CSV flow table whose first non-empty line is the header.
+pub fn parse_flow_csv(
+    text: &str,
+    options: &FlowImportOptions,
+) -> Result<Vec<TrafficEntry>, FlowImportError> {
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+    let mut rows = text
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty());
+
+    let Some((_, header)) = rows.next() else {
+        return Err(error(0, "ヘッダー行がありません"));
+    };
+    let layout = ColumnLayout::resolve(&split_csv(header, options.delimiter), &options.columns)?;
+
+    let mut importer = Importer::new(options);
+    for (index, line) in rows {
+        let fields = split_csv(line, options.delimiter);
+        importer.push_row(index + 1, &layout, &fields, None)?;
+    }
+    Ok(importer.entries)
+}
+
+/// Parse a Zeek `conn.log` in its default tab-separated format.
+pub fn parse_zeek_conn(
+    text: &str,
+    options: &FlowImportOptions,
+) -> Result<Vec<TrafficEntry>, FlowImportError> {
+    let mut separator = '\t';
+    let mut layout = None;
+    let mut importer = Importer::new(options);
+
+    for (index, line) in text.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(directive) = line.strip_prefix('#') {
+            if let Some(value) = directive.strip_prefix("separator ") {
+                separator = parse_zeek_separator(value)
+                    .ok_or_else(|| error(index + 1, format!("不正な区切り文字: {}", value)))?;
+            } else if let Some(fields) = directive.strip_prefix("fields") {
+                let names: Vec<String> = fields
+                    .split(separator)
+                    .filter(|name| !name.is_empty())
+                    .map(String::from)
+                    .collect();
+                layout = Some(ColumnLayout::resolve(&names, &options.columns)?);
+            }
+            continue;
+        }
+
+        let Some(layout) = &layout else {
+            return Err(error(index + 1, "#fields 行より前にデータがあります"));
+        };
+        let fields: Vec<String> = line.split(separator).map(String::from).collect();
+        importer.push_row(index + 1, layout, &fields, Some(ZEEK_UNSET))?;
+    }
+
+    if layout.is_none() {
+        return Err(error(0, "#fields 行がありません"));
+    }
+    Ok(importer.entries)
+}
+
+/// Zeek writes the separator as an escape such as `\x09`.
+fn parse_zeek_separator(value: &str) -> Option<char> {
+    match value.strip_prefix("\\x") {
+        Some(hex) => u8::from_str_radix(hex, 16).ok().map(char::from),
+        None => value.chars().next(),
+    }
+}
+
+/// Split one CSV line, honouring double-quoted fields with `""` escapes.
+fn split_csv(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            c if c == delimiter && !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields
+}
+
+/// Field indices of the configured columns in one file.
+struct ColumnLayout {
+    src_ip: usize,
+    dst_ip: usize,
+    src_port: usize,
+    dst_port: usize,
+    protocol: usize,
+    bytes: usize,
+    timestamp: Option<usize>,
+    label: Option<usize>,
+    packets: Option<usize>,
+    duration: Option<usize>,
+}
+
+impl ColumnLayout {
+    fn resolve(header: &[String], columns: &FlowColumns) -> Result<Self, FlowImportError> {
+        let find = |name: &str| {
+            header
+                .iter()
+                .position(|column| column.trim().eq_ignore_ascii_case(name.trim()))
+        };
+        let required = |name: &str| {
+            find(name).ok_or_else(|| error(0, format!("列 '{}' が見つかりません", name)))
+        };
+        let optional = |name: &Option<String>| name.as_deref().and_then(find);
+
+        Ok(Self {
+            src_ip: required(&columns.src_ip)?,
+            dst_ip: required(&columns.dst_ip)?,
+            src_port: required(&columns.src_port)?,
+            dst_port: required(&columns.dst_port)?,
+            protocol: required(&columns.protocol)?,
+            bytes: required(&columns.bytes)?,
+            timestamp: optional(&columns.timestamp),
+            label: optional(&columns.label),
+            packets: optional(&columns.packets),
+            duration: optional(&columns.duration),
+        })
+    }
+}
+
+/// A flow record after column mapping, before optional expansion.
+struct FlowRecord {
+    src_ip: String,
+    dst_ip: String,
+    src_port: u16,
+    dst_port: u16,
+    protocol: u8,
+    bytes: u64,
+    timestamp: i64,
+    label: PacketLabel,
+    packets: Option<u64>,
+    duration_us: i64,
+}
+
+struct Importer<'a> {
+    options: &'a FlowImportOptions,
+    row: i64,
+    entries: Vec<TrafficEntry>,
+}
+
+impl<'a> Importer<'a> {
+    fn new(options: &'a FlowImportOptions) -> Self {
+        Self {
+            options,
+            row: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    fn push_row(
+        &mut self,
+        line: usize,
+        layout: &ColumnLayout,
+        fields: &[String],
+        unset: Option<&str>,
+    ) -> Result<(), FlowImportError> {
+        let field = |index: usize| -> Option<&str> {
+            let value = fields.get(index)?.trim();
+            let is_unset = value.is_empty() || Some(value) == unset || value == ZEEK_EMPTY;
+            (!is_unset).then_some(value)
+        };
+        let required = |index: usize| {
+            field(index).ok_or_else(|| error(line, format!("{}列目の値がありません", index + 1)))
+        };
+        let number = |index: usize| -> Result<f64, FlowImportError> {
+            let value = required(index)?;
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|number| number.is_finite() && *number >= 0.0)
+                .ok_or_else(|| error(line, format!("数値ではありません: '{}'", value)))
+        };
+        let port = |index: usize| -> Result<u16, FlowImportError> {
+            // Zeek logs ICMP type/code in the port fields; missing ports become 0.
+            if field(index).is_none() {
+                return Ok(0);
+            }
+            let value = number(index)?;
+            if value.fract() != 0.0 || value > u16::MAX as f64 {
+                return Err(error(line, format!("不正なポート番号: {}", value)));
+            }
+            Ok(value as u16)
+        };
+
+        let protocol_value = required(layout.protocol)?;
+        let protocol = parse_protocol(protocol_value)
+            .ok_or_else(|| error(line, format!("不明なプロトコル: '{}'", protocol_value)))?;
+
+        let timestamp = match layout.timestamp {
+            Some(index) => self.options.timestamp_unit.to_micros(number(index)?),
+            None => self.row * self.options.row_interval_us,
+        };
+        let optional_number = |index: Option<usize>| -> Result<Option<f64>, FlowImportError> {
+            match index {
+                Some(index) if field(index).is_some() => number(index).map(Some),
+                _ => Ok(None),
+            }
+        };
+        let duration_us = optional_number(layout.duration)?
+            .map(|value| self.options.duration_unit.to_micros(value))
+            .unwrap_or(0);
+        let packets = optional_number(layout.packets)?.map(|value| value as u64);
+        let bytes = optional_number(Some(layout.bytes))?.unwrap_or(0.0) as u64;
+
+        let record = FlowRecord {
+            src_ip: required(layout.src_ip)?.to_string(),
+            dst_ip: required(layout.dst_ip)?.to_string(),
+            src_port: port(layout.src_port)?,
+            dst_port: port(layout.dst_port)?,
+            protocol,
+            bytes,
+            timestamp,
+            label: layout
+                .label
+                .and_then(field)
+                .map(|value| self.classify_label(value))
+                .unwrap_or_default(),
+            packets,
+            duration_us,
+        };
+        self.row += 1;
+        self.push_record(record);
+        Ok(())
+    }
+
+    fn classify_label(&self, value: &str) -> PacketLabel {
+        let benign = self
+            .options
+            .benign_labels
+            .iter()
+            .any(|label| label.eq_ignore_ascii_case(value));
+        if benign {
+            PacketLabel::Correct
+        } else {
+            PacketLabel::Incorrect
+        }
+    }
+
+    fn push_record(&mut self, record: FlowRecord) {
+        let count = match self.options.expand {
+            Some(expansion) => {
+                let derived = record.bytes.div_ceil(expansion.mtu.max(1) as u64);
+                record
+                    .packets
+                    .unwrap_or(derived)
+                    .clamp(1, expansion.max_packets.max(1) as u64)
+            }
+            None => 1,
+        };
+
+        let base_size = record.bytes / count;
+        let remainder = record.bytes % count;
+        let step = record.duration_us / count as i64;
+        for i in 0..count {
+            // The last packet carries whatever the even split left over.
+            let size = base_size + if i + 1 == count { remainder } else { 0 };
+            self.entries.push(TrafficEntry {
+                src_ip: record.src_ip.clone(),
+                dst_ip: record.dst_ip.clone(),
+                src_port: record.src_port,
+                dst_port: record.dst_port,
+                protocol: record.protocol,
+                size: size.min(u32::MAX as u64) as u32,
+                timestamp: Some(record.timestamp + step * i as i64),
+                label: record.label,
+                payload: Vec::new(),
+                tcp_flags: None,
+                tag: None,
+            });
+        }
+    }
+}
+
+fn parse_protocol(value: &str) -> Option<u8> {
+    if let Ok(number) = value.parse::<f64>() {
+        return (number.fract() == 0.0 && (0.0..=255.0).contains(&number)).then_some(number as u8);
+    }
+    match value.to_ascii_lowercase().as_str() {
+        "icmp" => Some(1),
+        "tcp" => Some(6),
+        "udp" => Some(17),
+        "ipv6-icmp" | "icmp6" | "icmpv6" => Some(58),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZEEK_LOG: &str = "#separator \\x09
+#set_separator\t,
+#fields\tts\tuid\tid.orig_h\tid.orig_p\tid.resp_h\tid.resp_p\tproto\tservice\tduration\torig_bytes\torig_pkts\tlabel
+#types\ttime\tstring\taddr\tport\taddr\tport\tenum\tstring\tinterval\tcount\tcount\tstring
+1545403816.962094\tC1\t192.168.1.5\t58687\t10.0.0.1\t80\ttcp\thttp\t0.5\t3000\t3\tMalicious
+1545403817.962094\tC2\t192.168.1.6\t53\t10.0.0.2\t53\tudp\t-\t-\t-\t-\t-
+#close\t2018-12-21-15-50-25
+";
+
+    #[test]
+    fn parses_zeek_conn_log() {
+        let entries = parse_zeek_conn(ZEEK_LOG, &FlowImportOptions::zeek()).unwrap();
+        assert_eq!(entries.len(), 2);
+
+        let first = &entries[0];
+        assert_eq!(first.src_ip, "192.168.1.5");
+        assert_eq!(first.dst_port, 80);
+        assert_eq!(first.protocol, 6);
+        assert_eq!(first.size, 3000);
+        assert_eq!(first.label, PacketLabel::Incorrect);
+        assert_eq!(first.timestamp, Some(1_545_403_816_962_094));
+
+        let second = &entries[1];
+        assert_eq!(second.protocol, 17);
+        assert_eq!(second.size, 0);
+        assert_eq!(second.label, PacketLabel::Unknown);
+    }
+
+    #[test]
+    fn zeek_requires_fields_header() {
+        let err = parse_zeek_conn("1\t2\t3\n", &FlowImportOptions::zeek()).unwrap_err();
+        assert_eq!(err.line, 1);
+    }
+
+    #[test]
+    fn parses_csv_with_padded_headers_and_quotes() {
+        let csv = "\u{feff} Source IP, Source Port, Destination IP, Destination Port, Protocol, Total Length of Fwd Packets, Label\n\
+                   10.0.0.1,40000,10.0.0.2,80,6,120,BENIGN\n\
+                   \"10.0.0.3\",40001,10.0.0.2,80,6,\"90\",\"DoS \"\"Hulk\"\"\"\n";
+        let entries = parse_flow_csv(csv, &FlowImportOptions::cic_ids()).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].label, PacketLabel::Correct);
+        assert_eq!(entries[1].src_ip, "10.0.0.3");
+        assert_eq!(entries[1].size, 90);
+        assert_eq!(entries[1].label, PacketLabel::Incorrect);
+        // No timestamp column: rows are spaced in file order.
+        assert_eq!(entries[0].timestamp, Some(0));
+        assert_eq!(entries[1].timestamp, Some(1_000));
+    }
+
+    #[test]
+    fn csv_reports_missing_columns_and_bad_values() {
+        let options = FlowImportOptions::default();
+        let err = parse_flow_csv("src_ip,dst_ip\n", &options).unwrap_err();
+        assert!(err.message.contains("src_port"));
+
+        let csv = "src_ip,dst_ip,src_port,dst_port,protocol,bytes\n\
+                   a,b,1,70000,tcp,10\n";
+        let err = parse_flow_csv(csv, &options).unwrap_err();
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn expands_flows_into_packets_over_duration() {
+        let csv = "src_ip,dst_ip,src_port,dst_port,protocol,bytes,timestamp,duration,packets,label\n\
+                   a,b,1,2,udp,1000,10,0.004,4,0\n\
+                   c,d,1,2,tcp,3001,20,0,,1\n";
+        let options = FlowImportOptions {
+            expand: Some(FlowExpansion {
+                max_packets: 3,
+                mtu: 1500,
+            }),
+            ..FlowImportOptions::default()
+        };
+        let entries = parse_flow_csv(csv, &options).unwrap();
+
+        // First flow: 4 packets clamped to 3, spread over 4ms.
+        let first: Vec<_> = entries.iter().filter(|e| e.src_ip == "a").collect();
+        assert_eq!(first.len(), 3);
+        assert_eq!(
+            first.iter().map(|e| e.size).collect::<Vec<_>>(),
+            vec![333, 333, 334]
+        );
+        assert_eq!(
+            first
+                .iter()
+                .map(|e| e.timestamp.unwrap())
+                .collect::<Vec<_>>(),
+            vec![10_000_000, 10_001_333, 10_002_666]
+        );
+        assert!(first.iter().all(|e| e.label == PacketLabel::Correct));
+
+        // Second flow has no packet count: derived from bytes / mtu.
+        let second: Vec<_> = entries.iter().filter(|e| e.src_ip == "c").collect();
+        assert_eq!(second.len(), 3);
+        assert_eq!(second.iter().map(|e| e.size as u64).sum::<u64>(), 3001);
+        assert_eq!(second[0].label, PacketLabel::Incorrect);
+    }
+
+    #[test]
+    fn options_from_json_override_the_preset() {
+        let value = serde_json::json!({
+            "preset": "unsw_nb15",
+            "columns": {"label": "", "src_ip": "source"},
+            "timestamp_unit": "ms",
+            "expand": true,
+            "max_packets": 8.0,
+        });
+        let options = FlowImportOptions::from_json(Some(&value), "generic").unwrap();
+        assert_eq!(options.columns.src_ip, "source");
+        assert_eq!(options.columns.label, None);
+        assert_eq!(options.timestamp_unit, TimeUnit::Milliseconds);
+        assert_eq!(options.expand.unwrap().max_packets, 8);
+
+        let defaults = FlowImportOptions::from_json(None, "zeek").unwrap();
+        assert_eq!(defaults, FlowImportOptions::zeek());
+        let unknown = serde_json::json!({"preset": "netflow"});
+        assert!(FlowImportOptions::from_json(Some(&unknown), "generic").is_err());
+    }
+}
@@ -1,13 +1,21 @@
+pub mod flow_import;
+pub mod flow_loader;
 mod helpers;
 pub mod json_loader;
 pub mod model;
 pub mod pcap_capture;
 pub mod pcap_frame;
 pub mod pcap_loader;
+pub mod pcap_writer;
+pub mod reassembly;
 pub mod traffic;
+pub mod traffic_schema;
+pub mod traffic_selector;
+pub mod traffic_source;
 
 pub(crate) use helpers::normalize_timestamp;
 
+pub use flow_loader::FlowLoader;
 pub use json_loader::JsonLoader;
 pub use model::Packet;
 pub use pcap_capture::PcapCapture;
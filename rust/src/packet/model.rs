@@ -1,6 +1,9 @@
 use godot::prelude::*;
+use std::sync::Arc;
 
-use crate::core::packet::encode_payload_bytes;
+use crate::core::packet::{
+    Packet as CorePacket, PacketLabel, Protocol, TcpFlags, encode_payload_bytes,
+};
 
 #[derive(GodotClass)]
 #[class(base = Resource)]
@@ -24,7 +27,12 @@ pub struct Packet {
     timestamp: i64,
     #[var]
     label: i64,
+    /// Tag from the traffic file; empty when it has none.
+    #[var]
+    tag: GString,
     payload: Vec<u8>,
+    stream: Option<Arc<[u8]>>,
+    tcp_flags: Option<TcpFlags>,
 }
 
 #[godot_api]
@@ -40,7 +48,10 @@ impl IResource for Packet {
             packet_size: 0,
             timestamp: 0,
             label: 0,
+            tag: GString::default(),
             payload: Vec::new(),
+            stream: None,
+            tcp_flags: None,
         }
     }
 }
@@ -76,6 +87,33 @@ impl Packet {
         self.payload = payload;
     }
 
+    /// Convert this resource into the simulation's packet representation.
+    pub(crate) fn to_core(&self) -> CorePacket {
+        let mut packet = CorePacket::new(
+            self.src_ip.to_string(),
+            self.dst_ip.to_string(),
+            self.src_port as u16,
+            self.dst_port as u16,
+            Protocol::from_ip_number(self.protocol),
+            self.packet_size as u32,
+            self.payload.clone(),
+        );
+        packet.label = PacketLabel::from_raw(self.label);
+        packet.timestamp = self.timestamp;
+        packet.stream = self.stream.clone();
+        packet.tcp_flags = self.tcp_flags;
+        packet.tag = (!self.tag.is_empty()).then(|| self.tag.to_string());
+        packet
+    }
+
+    pub(crate) fn set_tcp_flags(&mut self, flags: Option<TcpFlags>) {
+        self.tcp_flags = flags;
+    }
+
+    pub(crate) fn set_stream(&mut self, stream: Arc<[u8]>) {
+        self.stream = Some(stream);
+    }
+
     pub(crate) fn payload_to_string(&self) -> String {
         encode_payload_bytes(&self.payload)
     }
@@ -87,4 +125,20 @@ impl Packet {
     pub fn get_payload_string(&self) -> GString {
         self.payload_to_string().into()
     }
+
+    /// Whether a reassembled TCP stream is attached to this packet.
+    #[func]
+    pub fn has_stream(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    /// The reassembled TCP stream of this packet's flow, escaped like the payload string.
+    #[func]
+    pub fn get_stream_string(&self) -> GString {
+        self.stream
+            .as_deref()
+            .map(encode_payload_bytes)
+            .unwrap_or_default()
+            .into()
+    }
 }
@@ -1,7 +1,9 @@
 use godot::prelude::*;
 
+use crate::core::packet::Protocol;
 use crate::packet::PcapFrame;
 use crate::packet::normalize_timestamp;
+use crate::packet::reassembly::{FlowKey, TcpReassembler};
 use crate::packet::{Packet, Traffic};
 
 #[derive(GodotClass)]
@@ -88,6 +90,29 @@ impl PcapCapture {
         traffic
     }
 
+    /// Like `to_traffic`, but additionally reassembles every TCP flow and attaches the
+    /// resulting byte stream to each segment of that flow (see `Packet::has_stream`).
+    #[func]
+    pub fn to_traffic_reassembled(&self) -> Gd<Traffic> {
+        let mut reassembler = TcpReassembler::new();
+        for frame in self.frames.iter_shared() {
+            reassembler.push_frame(frame.bind().data().as_slice());
+        }
+        let streams = reassembler.finish();
+
+        let traffic = self.to_traffic();
+        for mut packet in traffic.bind().packets().iter_shared() {
+            let core = packet.bind().to_core();
+            if core.protocol != Protocol::Tcp {
+                continue;
+            }
+            if let Some(stream) = streams.get(&FlowKey::from_packet(&core)) {
+                packet.bind_mut().set_stream(stream.bytes.clone());
+            }
+        }
+        traffic
+    }
+
     #[func]
     pub fn push_frame(&mut self, frame: Gd<PcapFrame>) {
         self.frames.push(&frame);
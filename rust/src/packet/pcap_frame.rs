@@ -2,15 +2,24 @@ use godot::prelude::*;
 
 use etherparse::{NetSlice, SlicedPacket, TransportSlice};
 
+use crate::core::packet::TcpFlags;
 use crate::packet::Packet;
 
-struct ParsedPacket {
-    src_ip: String,
-    dst_ip: String,
-    src_port: u16,
-    dst_port: u16,
-    protocol: u8,
-    payload: Vec<u8>,
+pub(crate) struct ParsedPacket {
+    pub(crate) src_ip: String,
+    pub(crate) dst_ip: String,
+    pub(crate) src_port: u16,
+    pub(crate) dst_port: u16,
+    pub(crate) protocol: u8,
+    pub(crate) payload: Vec<u8>,
+    /// Sequence number and flags when the transport is TCP.
+    pub(crate) tcp: Option<TcpSegmentInfo>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct TcpSegmentInfo {
+    pub(crate) seq: u32,
+    pub(crate) flags: TcpFlags,
 }
 
 #[derive(GodotClass)]
@@ -89,6 +98,7 @@ impl PcapFrame {
         {
             let mut packet_mut = packet.bind_mut();
             packet_mut.set_payload_bytes(parsed.payload);
+            packet_mut.set_tcp_flags(parsed.tcp.map(|tcp| tcp.flags));
         }
 
         Some(packet)
@@ -123,7 +133,7 @@ impl IResource for PcapFrame {
     }
 }
 
-fn parse_packet_from_bytes(bytes: &[u8]) -> Option<ParsedPacket> {
+pub(crate) fn parse_packet_from_bytes(bytes: &[u8]) -> Option<ParsedPacket> {
     let parsed = SlicedPacket::from_ethernet(bytes).ok()?;
     let SlicedPacket {
         link: _,
@@ -152,6 +162,26 @@ fn parse_packet_from_bytes(bytes: &[u8]) -> Option<ParsedPacket> {
         _ => return None,
     };
 
+    let tcp = match &transport {
+        Some(TransportSlice::Tcp(tcp)) => Some(TcpSegmentInfo {
+            seq: tcp.sequence_number(),
+            flags: [
+                (tcp.fin(), TcpFlags::FIN),
+                (tcp.syn(), TcpFlags::SYN),
+                (tcp.rst(), TcpFlags::RST),
+                (tcp.psh(), TcpFlags::PSH),
+                (tcp.ack(), TcpFlags::ACK),
+                (tcp.urg(), TcpFlags::URG),
+            ]
+            .into_iter()
+            .filter(|(set, _)| *set)
+            .fold(TcpFlags::default(), |acc, (_, flag)| {
+                TcpFlags(acc.0 | flag.0)
+            }),
+        }),
+        _ => None,
+    };
+
     let (src_port, dst_port, transport_payload) = match transport {
         Some(TransportSlice::Tcp(tcp)) => (
             tcp.source_port(),
@@ -181,6 +211,7 @@ fn parse_packet_from_bytes(bytes: &[u8]) -> Option<ParsedPacket> {
         dst_port,
         protocol,
         payload,
+        tcp,
     })
 }
 
@@ -214,6 +245,13 @@ mod tests {
         assert_eq!(parsed.dst_port, 80);
         assert_eq!(parsed.protocol, 6); // TCP
         assert_eq!(parsed.payload, payload);
+        assert_eq!(
+            parsed.tcp,
+            Some(TcpSegmentInfo {
+                seq: 1,
+                flags: TcpFlags::default(),
+            })
+        );
     }
 
     #[test]
@@ -76,4 +76,12 @@ impl PcapLoader {
         };
         Some(traffic)
     }
+
+    /// Load a capture and reassemble its TCP flows so content filters can match whole streams.
+    #[func]
+    pub fn load_traffic_reassembled(&self, path: GString) -> Option<Gd<Traffic>> {
+        let capture = self.load_pcap(path)?;
+        let traffic = capture.bind().to_traffic_reassembled();
+        Some(traffic)
+    }
 }
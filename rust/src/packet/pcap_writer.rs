@@ -0,0 +1,216 @@
+//! Serialize simulated packets back into a pcap capture.
+//!
+//! The simulation only keeps the header fields that matter for filtering, so every frame is
+//! rebuilt from scratch: a placeholder Ethernet II header, an IPv4/IPv6 header derived from the
+//! packet's addresses and a TCP/UDP header carrying the original ports (and TCP flags, when
+//! known). The capture timestamp is the packet's `timestamp` (microseconds since the capture
+//! start) and `orig_len` is the larger of the recorded `length` and the rebuilt frame size.
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::net::IpAddr;
+use std::path::Path;
+use std::time::Duration;
+
+use etherparse::{IpNumber, PacketBuilder, PacketBuilderStep};
+use pcap_file::pcap::{PcapPacket, PcapWriter};
+
+use crate::core::packet::{Packet, Protocol, TcpFlags};
+
+const SOURCE_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+const DEST_MAC: [u8; 6] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x02];
+const DEFAULT_TTL: u8 = 64;
+const TCP_WINDOW: u16 = 65535;
+/// IANA "use for experimentation and testing" protocol number, used when the original
+/// transport protocol was not TCP or UDP.
+const UNKNOWN_IP_NUMBER: IpNumber = IpNumber(253);
+
+/// Rebuild an Ethernet frame for `packet`. Returns `None` if the addresses are not valid IPs
+/// or mix IPv4 and IPv6.
+pub fn build_frame(packet: &Packet) -> Option<Vec<u8>> {
+    let source: IpAddr = packet.source_ip.parse().ok()?;
+    let dest: IpAddr = packet.dest_ip.parse().ok()?;
+
+    let ethernet = PacketBuilder::ethernet2(SOURCE_MAC, DEST_MAC);
+    let ip = match (source, dest) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            ethernet.ipv4(src.octets(), dst.octets(), DEFAULT_TTL)
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            ethernet.ipv6(src.octets(), dst.octets(), DEFAULT_TTL)
+        }
+        _ => return None,
+    };
+
+    write_transport(ip, packet)
+}
+
+fn write_transport(
+    ip: PacketBuilderStep<etherparse::IpHeaders>,
+    packet: &Packet,
+) -> Option<Vec<u8>> {
+    let payload = packet.payload.as_slice();
+    let mut frame = Vec::new();
+    match packet.protocol {
+        Protocol::Tcp => {
+            let mut builder = ip.tcp(packet.source_port, packet.dest_port, 0, TCP_WINDOW);
+            let flags = packet.tcp_flags.unwrap_or_default();
+            if flags.contains(TcpFlags::FIN) {
+                builder = builder.fin();
+            }
+            if flags.contains(TcpFlags::SYN) {
+                builder = builder.syn();
+            }
+            if flags.contains(TcpFlags::RST) {
+                builder = builder.rst();
+            }
+            if flags.contains(TcpFlags::PSH) {
+                builder = builder.psh();
+            }
+            if flags.contains(TcpFlags::ACK) {
+                builder = builder.ack(0);
+            }
+            if flags.contains(TcpFlags::URG) {
+                builder = builder.urg(0);
+            }
+            frame.reserve(builder.size(payload.len()));
+            builder.write(&mut frame, payload).ok()?;
+        }
+        Protocol::Udp => {
+            let builder = ip.udp(packet.source_port, packet.dest_port);
+            frame.reserve(builder.size(payload.len()));
+            builder.write(&mut frame, payload).ok()?;
+        }
+        Protocol::Unknown => {
+            frame.reserve(ip.size(payload.len()));
+            ip.write(&mut frame, UNKNOWN_IP_NUMBER, payload).ok()?;
+        }
+    }
+    Some(frame)
+}
+
+/// Write `packets` as an Ethernet pcap stream, ordered by timestamp.
+///
+/// Packets whose addresses cannot be encoded are skipped. Returns the number of frames written.
+pub fn write_pcap<W: Write>(writer: W, packets: &[Packet]) -> Result<usize, String> {
+    let mut ordered: Vec<&Packet> = packets.iter().collect();
+    ordered.sort_by_key(|packet| packet.timestamp);
+
+    let mut pcap_writer =
+        PcapWriter::new(writer).map_err(|err| format!("pcapヘッダーの書き込みに失敗: {}", err))?;
+
+    let mut written = 0;
+    for packet in ordered {
+        let Some(frame) = build_frame(packet) else {
+            continue;
+        };
+
+        let micros = packet.timestamp.max(0) as u64;
+        let orig_len = packet.length.max(frame.len() as u32);
+        let pcap_packet = PcapPacket::new(Duration::from_micros(micros), orig_len, &frame);
+        pcap_writer
+            .write_packet(&pcap_packet)
+            .map_err(|err| format!("パケットの書き込みに失敗: {}", err))?;
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+/// Create (or truncate) the file at `path` and write `packets` into it with [`write_pcap`].
+pub fn write_pcap_file(path: &Path, packets: &[Packet]) -> Result<usize, String> {
+    let file = File::create(path)
+        .map_err(|err| format!("ファイル '{}' を作成できません: {}", path.display(), err))?;
+    let mut writer = BufWriter::new(file);
+    let written = write_pcap(&mut writer, packets)?;
+    writer
+        .flush()
+        .map_err(|err| format!("ファイル '{}' への書き込みに失敗: {}", path.display(), err))?;
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use etherparse::{NetSlice, SlicedPacket, TransportSlice};
+    use pcap_file::pcap::PcapReader;
+
+    fn packet(protocol: Protocol, timestamp: i64) -> Packet {
+        let mut packet = Packet::new(
+            "192.168.0.1".to_string(),
+            "10.0.0.5".to_string(),
+            40000,
+            80,
+            protocol,
+            1500,
+            b"GET / HTTP/1.1\r\n\r\n".to_vec(),
+        );
+        packet.timestamp = timestamp;
+        packet
+    }
+
+    #[test]
+    fn build_frame_round_trips_through_etherparse() {
+        let frame = build_frame(&packet(Protocol::Tcp, 0)).expect("frame");
+        let sliced = SlicedPacket::from_ethernet(&frame).expect("parse");
+
+        match sliced.net {
+            Some(NetSlice::Ipv4(ipv4)) => {
+                assert_eq!(ipv4.header().source_addr().to_string(), "192.168.0.1");
+                assert_eq!(ipv4.header().destination_addr().to_string(), "10.0.0.5");
+            }
+            _ => panic!("expected IPv4"),
+        }
+        match sliced.transport {
+            Some(TransportSlice::Tcp(tcp)) => {
+                assert_eq!(tcp.source_port(), 40000);
+                assert_eq!(tcp.destination_port(), 80);
+                assert_eq!(tcp.payload(), b"GET / HTTP/1.1\r\n\r\n");
+            }
+            _ => panic!("expected TCP"),
+        }
+    }
+
+    #[test]
+    fn build_frame_keeps_every_tcp_flag() {
+        use crate::packet::pcap_frame::parse_packet_from_bytes;
+
+        let mut flagged = packet(Protocol::Tcp, 0);
+        flagged.tcp_flags = TcpFlags::from_letters("FSRPAU");
+        let frame = build_frame(&flagged).expect("frame");
+        let parsed = parse_packet_from_bytes(&frame).expect("parse");
+        assert_eq!(parsed.tcp.map(|tcp| tcp.flags), flagged.tcp_flags);
+    }
+
+    #[test]
+    fn build_frame_rejects_mixed_address_families() {
+        let mut mixed = packet(Protocol::Udp, 0);
+        mixed.dest_ip = "::1".to_string();
+        assert!(build_frame(&mixed).is_none());
+
+        let mut invalid = packet(Protocol::Udp, 0);
+        invalid.source_ip = "not-an-ip".to_string();
+        assert!(build_frame(&invalid).is_none());
+    }
+
+    #[test]
+    fn write_pcap_orders_by_timestamp_and_keeps_lengths() {
+        let packets = vec![
+            packet(Protocol::Udp, 2_500_000),
+            packet(Protocol::Tcp, 1_000),
+            packet(Protocol::Unknown, 7),
+        ];
+
+        let mut buffer = Vec::new();
+        let written = write_pcap(&mut buffer, &packets).expect("write");
+        assert_eq!(written, 3);
+
+        let mut reader = PcapReader::new(buffer.as_slice()).expect("reader");
+        let mut timestamps = Vec::new();
+        while let Some(frame) = reader.next_packet() {
+            let frame = frame.expect("frame");
+            assert_eq!(frame.orig_len, 1500);
+            timestamps.push(frame.timestamp.as_micros());
+        }
+        assert_eq!(timestamps, vec![7, 1_000, 2_500_000]);
+    }
+}
@@ -0,0 +1,262 @@
+//! Optional TCP stream reassembly for captured traffic.
+//!
+//! Attacks such as SQL injection are often split over several TCP segments, so a content
+//! filter that only sees one segment can miss them. [`TcpReassembler`] groups TCP frames into
+//! directional flows, orders their segments by sequence number, drops retransmitted bytes and
+//! produces one contiguous byte stream per flow. The stream is then attached to every packet of
+//! the flow (`Packet::stream`) so filters can match against it.
+//!
+//! Overlapping segments keep the bytes that were seen first in sequence order. Missing ranges
+//! (segments that were never captured) are skipped and counted in [`ReassembledStream::gaps`].
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::pcap_frame::parse_packet_from_bytes;
+use crate::core::packet::{Packet, Protocol, TcpFlags};
+
+/// Directional TCP flow identifier.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct FlowKey {
+    pub src_ip: String,
+    pub dst_ip: String,
+    pub src_port: u16,
+    pub dst_port: u16,
+}
+
+impl FlowKey {
+    pub fn from_packet(packet: &Packet) -> Self {
+        Self {
+            src_ip: packet.source_ip.clone(),
+            dst_ip: packet.dest_ip.clone(),
+            src_port: packet.source_port,
+            dst_port: packet.dest_port,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReassembledStream {
+    pub bytes: Arc<[u8]>,
+    /// Number of segments (including retransmissions) that belonged to the flow.
+    pub segment_count: usize,
+    /// Payload bytes discarded because they had already been received.
+    pub retransmitted_bytes: usize,
+    /// Number of holes in the sequence space that could not be filled.
+    pub gaps: usize,
+}
+
+struct Segment {
+    seq: u32,
+    payload: Vec<u8>,
+}
+
+#[derive(Default)]
+struct FlowSegments {
+    /// Sequence number of the first payload byte, known once the SYN has been seen.
+    initial_seq: Option<u32>,
+    segments: Vec<Segment>,
+}
+
+#[derive(Default)]
+pub struct TcpReassembler {
+    flows: HashMap<FlowKey, FlowSegments>,
+}
+
+impl TcpReassembler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add one TCP segment to its flow. Segments may arrive in any order.
+    pub fn push_segment(&mut self, key: FlowKey, seq: u32, syn: bool, payload: &[u8]) {
+        let flow = self.flows.entry(key).or_default();
+        if syn {
+            // SYN consumes one sequence number; data starts right after it.
+            flow.initial_seq = Some(seq.wrapping_add(1));
+        }
+        flow.segments.push(Segment {
+            seq: if syn { seq.wrapping_add(1) } else { seq },
+            payload: payload.to_vec(),
+        });
+    }
+
+    /// Parse an Ethernet frame and add it if it carries TCP. Returns `false` for other frames.
+    pub fn push_frame(&mut self, bytes: &[u8]) -> bool {
+        let Some(parsed) = parse_packet_from_bytes(bytes) else {
+            return false;
+        };
+        let Some(tcp) = parsed.tcp else {
+            return false;
+        };
+        let key = FlowKey {
+            src_ip: parsed.src_ip,
+            dst_ip: parsed.dst_ip,
+            src_port: parsed.src_port,
+            dst_port: parsed.dst_port,
+        };
+        let syn = tcp.flags.contains(TcpFlags::SYN);
+        self.push_segment(key, tcp.seq, syn, &parsed.payload);
+        true
+    }
+
+    /// Reassemble every flow that has been fed so far.
+    pub fn finish(self) -> HashMap<FlowKey, ReassembledStream> {
+        self.flows
+            .into_iter()
+            .map(|(key, flow)| (key, reassemble_flow(flow)))
+            .collect()
+    }
+}
+
+fn reassemble_flow(flow: FlowSegments) -> ReassembledStream {
+    let segment_count = flow.segments.len();
+    let Some(first) = flow.segments.first() else {
+        return ReassembledStream {
+            bytes: Arc::from(Vec::new()),
+            segment_count,
+            retransmitted_bytes: 0,
+            gaps: 0,
+        };
+    };
+
+    // Without a SYN, the lowest sequence number (modulo wrap-around) starts the stream.
+    let base = flow.initial_seq.unwrap_or_else(|| {
+        let reference = first.seq;
+        let earliest = flow
+            .segments
+            .iter()
+            .map(|segment| segment.seq.wrapping_sub(reference) as i32)
+            .min()
+            .unwrap_or(0);
+        reference.wrapping_add(earliest as u32)
+    });
+
+    let mut ordered: Vec<(u32, &[u8])> = flow
+        .segments
+        .iter()
+        .filter(|segment| !segment.payload.is_empty())
+        .map(|segment| (segment.seq.wrapping_sub(base), segment.payload.as_slice()))
+        .collect();
+    ordered.sort_by_key(|(offset, _)| *offset);
+
+    let mut bytes = Vec::new();
+    let mut cursor: u64 = 0;
+    let mut retransmitted_bytes = 0;
+    let mut gaps = 0;
+
+    for (offset, payload) in ordered {
+        let start = offset as u64;
+        let end = start + payload.len() as u64;
+        if end <= cursor {
+            retransmitted_bytes += payload.len();
+            continue;
+        }
+        if start > cursor {
+            gaps += 1;
+            cursor = start;
+        }
+        let skip = (cursor - start) as usize;
+        retransmitted_bytes += skip;
+        bytes.extend_from_slice(&payload[skip..]);
+        cursor = end;
+    }
+
+    ReassembledStream {
+        bytes: Arc::from(bytes),
+        segment_count,
+        retransmitted_bytes,
+        gaps,
+    }
+}
+
+/// Attach the reassembled stream of each packet's flow to `Packet::stream`.
+pub fn attach_streams(packets: &mut [Packet], streams: &HashMap<FlowKey, ReassembledStream>) {
+    for packet in packets.iter_mut() {
+        if packet.protocol != Protocol::Tcp {
+            continue;
+        }
+        if let Some(stream) = streams.get(&FlowKey::from_packet(packet)) {
+            packet.stream = Some(stream.bytes.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> FlowKey {
+        FlowKey {
+            src_ip: "10.0.0.1".to_string(),
+            dst_ip: "10.0.0.2".to_string(),
+            src_port: 40000,
+            dst_port: 80,
+        }
+    }
+
+    #[test]
+    fn reorders_out_of_order_segments() {
+        let mut reassembler = TcpReassembler::new();
+        reassembler.push_segment(key(), 1000, true, b"");
+        reassembler.push_segment(key(), 1007, false, b"' OR 1=1");
+        reassembler.push_segment(key(), 1001, false, b"id=1 ");
+        reassembler.push_segment(key(), 1006, false, b"x");
+
+        let streams = reassembler.finish();
+        let stream = &streams[&key()];
+        assert_eq!(&*stream.bytes, b"id=1 x' OR 1=1");
+        assert_eq!(stream.segment_count, 4);
+        assert_eq!(stream.gaps, 0);
+    }
+
+    #[test]
+    fn drops_retransmitted_and_overlapping_bytes() {
+        let mut reassembler = TcpReassembler::new();
+        reassembler.push_segment(key(), 10, false, b"SELECT ");
+        reassembler.push_segment(key(), 10, false, b"SELECT ");
+        reassembler.push_segment(key(), 13, false, b"ECT * FROM");
+
+        let stream = &reassembler.finish()[&key()];
+        assert_eq!(&*stream.bytes, b"SELECT * FROM");
+        assert_eq!(stream.retransmitted_bytes, 7 + 4);
+    }
+
+    #[test]
+    fn handles_sequence_wrap_and_gaps() {
+        let mut reassembler = TcpReassembler::new();
+        reassembler.push_segment(key(), 0, false, b"cd");
+        reassembler.push_segment(key(), u32::MAX - 1, false, b"ab");
+        reassembler.push_segment(key(), 10, false, b"zz");
+
+        let stream = &reassembler.finish()[&key()];
+        assert_eq!(&*stream.bytes, b"abcdzz");
+        assert_eq!(stream.gaps, 1);
+    }
+
+    #[test]
+    fn attach_streams_sets_stream_on_matching_tcp_packets() {
+        let mut reassembler = TcpReassembler::new();
+        reassembler.push_segment(key(), 0, false, b"abc");
+        let streams = reassembler.finish();
+
+        let tcp = Packet::new(
+            "10.0.0.1".to_string(),
+            "10.0.0.2".to_string(),
+            40000,
+            80,
+            Protocol::Tcp,
+            3,
+            b"abc".to_vec(),
+        );
+        let mut udp = tcp.clone();
+        udp.protocol = Protocol::Udp;
+        let mut reply = tcp.clone();
+        std::mem::swap(&mut reply.source_ip, &mut reply.dest_ip);
+
+        let mut packets = [tcp, udp, reply];
+        attach_streams(&mut packets, &streams);
+        assert_eq!(packets[0].stream.as_deref(), Some(&b"abc"[..]));
+        assert!(packets[1].stream.is_none());
+        assert!(packets[2].stream.is_none());
+    }
+}
@@ -0,0 +1,574 @@
+//! Typed, Godot-independent schema for JSON traffic files.
+//!
+//! This is the parser behind [`JsonLoader`](super::JsonLoader). It reads the same format
+//! documented in [`json_loader`](super::json_loader) but validates it strictly through serde:
+//!
+//! - Numeric fields must be integers. Integral floats such as `80.0` are accepted because
+//!   existing stage files were exported that way, but `80.5` or `-1` are rejected.
+//! - `payload` (or its alias `content`) is either an escaped string (`"GET /\\r\\n"`), or an
+//!   object `{"hex": "474554"}` / `{"base64": "R0VU"}`.
+//! - `tcp_flags` is either tcpdump-style letters (`"SA"`) or the numeric flag byte.
+//! - `tag` is an optional string that stages use to split the traffic between sources.
+//! - In [`ParseOptions::strict`] mode unknown keys in packet entries are rejected.
+//!
+//! Every error carries the line and column reported by `serde_json`, so stage files can be
+//! validated in plain `cargo test` without a running engine.
+use std::fmt;
+
+use serde::Deserialize;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+
+use crate::core::packet::{PacketLabel, TcpFlags};
+
+/// One packet entry of a traffic file, after validation.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrafficEntry {
+    pub src_ip: String,
+    pub dst_ip: String,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: u8,
+    pub size: u32,
+    pub timestamp: Option<i64>,
+    pub label: PacketLabel,
+    pub payload: Vec<u8>,
+    pub tcp_flags: Option<TcpFlags>,
+    pub tag: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseOptions {
+    /// Reject keys that are not part of the packet schema.
+    pub strict: bool,
+}
+
+/// Parse failure with the position reported by `serde_json` (1-based, `0` when unknown).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TrafficJsonError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for TrafficJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}行{}列: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for TrafficJsonError {}
+
+impl From<serde_json::Error> for TrafficJsonError {
+    fn from(err: serde_json::Error) -> Self {
+        let line = err.line();
+        let column = err.column();
+        // serde_json appends " at line X column Y" to the message; keep only the description.
+        let message = err.to_string();
+        let message = match message.rfind(" at line ") {
+            Some(idx) if line > 0 => message[..idx].to_string(),
+            _ => message,
+        };
+        Self {
+            message,
+            line,
+            column,
+        }
+    }
+}
+
+/// Parse a traffic file. The root may be an array of packets or an object with a `packets`
+/// array; other keys of a root object are ignored.
+pub fn parse_traffic_json(
+    text: &str,
+    options: ParseOptions,
+) -> Result<Vec<TrafficEntry>, TrafficJsonError> {
+    let is_array = text.trim_start().starts_with('[');
+    let entries = match (is_array, options.strict) {
+        (true, false) => serde_json::from_str::<Vec<LenientEntry>>(text)?
+            .into_iter()
+            .map(TrafficEntry::from)
+            .collect(),
+        (true, true) => serde_json::from_str::<Vec<StrictEntry>>(text)?
+            .into_iter()
+            .map(TrafficEntry::from)
+            .collect(),
+        (false, false) => serde_json::from_str::<Root<LenientEntry>>(text)?
+            .packets
+            .into_iter()
+            .map(TrafficEntry::from)
+            .collect(),
+        (false, true) => serde_json::from_str::<Root<StrictEntry>>(text)?
+            .packets
+            .into_iter()
+            .map(TrafficEntry::from)
+            .collect(),
+    };
+    Ok(entries)
+}
+
+/// Sort entries by timestamp and rebase them so the earliest timestamp becomes `0`.
+///
+/// Entries without a timestamp are treated as `0`, matching the Godot loader.
+pub fn sort_and_normalize(entries: &mut [TrafficEntry]) {
+    entries.sort_by_key(|entry| entry.timestamp.unwrap_or(0));
+    let baseline = entries.iter().filter_map(|entry| entry.timestamp).min();
+    for entry in entries.iter_mut() {
+        let raw = entry.timestamp.unwrap_or(0);
+        entry.timestamp = Some(super::normalize_timestamp(raw, baseline));
+    }
+}
+
+#[derive(Deserialize)]
+struct Root<E> {
+    packets: Vec<E>,
+}
+
+// The strict and lenient entries only differ in `deny_unknown_fields`, which serde cannot
+// toggle at runtime.
+macro_rules! packet_entry {
+    ($name:ident $(, #[$attr:meta])*) => {
+        #[derive(Deserialize)]
+        $(#[$attr])*
+        struct $name {
+            src_ip: String,
+            dst_ip: String,
+            #[serde(deserialize_with = "integer")]
+            src_port: u16,
+            #[serde(deserialize_with = "integer")]
+            dst_port: u16,
+            #[serde(deserialize_with = "integer")]
+            protocol: u8,
+            #[serde(deserialize_with = "integer")]
+            size: u32,
+            #[serde(default, deserialize_with = "optional_integer")]
+            timestamp: Option<i64>,
+            #[serde(default)]
+            label: Option<LabelValue>,
+            #[serde(default, alias = "content")]
+            payload: Option<Payload>,
+            #[serde(default)]
+            tcp_flags: Option<TcpFlagsValue>,
+            #[serde(default)]
+            tag: Option<String>,
+        }
+
+        impl From<$name> for TrafficEntry {
+            fn from(entry: $name) -> Self {
+                Self {
+                    src_ip: entry.src_ip,
+                    dst_ip: entry.dst_ip,
+                    src_port: entry.src_port,
+                    dst_port: entry.dst_port,
+                    protocol: entry.protocol,
+                    size: entry.size,
+                    timestamp: entry.timestamp,
+                    label: entry.label.map(PacketLabel::from).unwrap_or_default(),
+                    payload: entry.payload.map(|payload| payload.0).unwrap_or_default(),
+                    tcp_flags: entry.tcp_flags.map(|flags| flags.0),
+                    tag: entry.tag.filter(|tag| !tag.is_empty()),
+                }
+            }
+        }
+    };
+}
+
+packet_entry!(LenientEntry);
+packet_entry!(StrictEntry, #[serde(deny_unknown_fields)]);
+
+#[derive(Copy, Clone)]
+enum LabelValue {
+    Correct,
+    Incorrect,
+    Unknown,
+}
+
+impl From<LabelValue> for PacketLabel {
+    fn from(value: LabelValue) -> Self {
+        match value {
+            LabelValue::Correct => PacketLabel::Correct,
+            LabelValue::Incorrect => PacketLabel::Incorrect,
+            LabelValue::Unknown => PacketLabel::Unknown,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for LabelValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        if text.eq_ignore_ascii_case("correct") {
+            Ok(LabelValue::Correct)
+        } else if text.eq_ignore_ascii_case("incorrect") {
+            Ok(LabelValue::Incorrect)
+        } else if text.eq_ignore_ascii_case("unknown") {
+            Ok(LabelValue::Unknown)
+        } else {
+            Err(de::Error::custom(format!("未知のラベル値 '{}'", text)))
+        }
+    }
+}
+
+/// TCP flags given as letters or as the flag byte.
+struct TcpFlagsValue(TcpFlags);
+
+impl<'de> Deserialize<'de> for TcpFlagsValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(TcpFlagsVisitor)
+    }
+}
+
+struct TcpFlagsVisitor;
+
+impl<'de> Visitor<'de> for TcpFlagsVisitor {
+    type Value = TcpFlagsValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("TCP flag letters such as \"SA\", or the flag byte")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<TcpFlagsValue, E> {
+        TcpFlags::from_letters(value)
+            .map(TcpFlagsValue)
+            .ok_or_else(|| E::custom(format!("未知のTCPフラグ '{}'", value)))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<TcpFlagsValue, E> {
+        u8::try_from(value)
+            .map(|byte| TcpFlagsValue(TcpFlags(byte)))
+            .map_err(|_| E::custom(format!("{} は範囲外です", value)))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<TcpFlagsValue, E> {
+        u8::try_from(value)
+            .map(|byte| TcpFlagsValue(TcpFlags(byte)))
+            .map_err(|_| E::custom(format!("{} は範囲外です", value)))
+    }
+}
+
+/// Decoded payload bytes.
+struct Payload(Vec<u8>);
+
+impl<'de> Deserialize<'de> for Payload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(PayloadVisitor)
+    }
+}
+
+struct PayloadVisitor;
+
+impl<'de> Visitor<'de> for PayloadVisitor {
+    type Value = Payload;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an escaped string, {\"hex\": ...} or {\"base64\": ...}")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Payload, E> {
+        decode_payload(value)
+            .map(Payload)
+            .ok_or_else(|| E::custom("'payload' のエスケープシーケンスが不正です"))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Payload, A::Error> {
+        let Some(encoding) = map.next_key::<String>()? else {
+            return Err(de::Error::custom("'payload' オブジェクトが空です"));
+        };
+        let value: String = map.next_value()?;
+        let bytes = match encoding.as_str() {
+            "hex" => decode_hex(&value)
+                .ok_or_else(|| de::Error::custom("'payload.hex' が16進文字列ではありません"))?,
+            "base64" => decode_base64(&value).ok_or_else(|| {
+                de::Error::custom("'payload.base64' がBase64文字列ではありません")
+            })?,
+            other => return Err(de::Error::unknown_field(other, &["hex", "base64"])),
+        };
+        if map.next_key::<de::IgnoredAny>()?.is_some() {
+            return Err(de::Error::custom(
+                "'payload' オブジェクトには 'hex' か 'base64' のどちらか一つだけを指定してください",
+            ));
+        }
+        Ok(Payload(bytes))
+    }
+}
+
+fn integer<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: TryFrom<i64>,
+{
+    let value = deserializer.deserialize_any(IntegerVisitor)?;
+    T::try_from(value).map_err(|_| de::Error::custom(format!("{} は範囲外です", value)))
+}
+
+fn optional_integer<'de, D>(deserializer: D) -> Result<Option<i64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    integer::<D, i64>(deserializer).map(Some)
+}
+
+/// Accepts JSON integers and floats with no fractional part.
+struct IntegerVisitor;
+
+impl<'de> Visitor<'de> for IntegerVisitor {
+    type Value = i64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<i64, E> {
+        Ok(value)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<i64, E> {
+        i64::try_from(value).map_err(|_| E::custom(format!("{} は範囲外です", value)))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<i64, E> {
+        if value.is_finite() && value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+            Ok(value as i64)
+        } else {
+            Err(E::custom(format!("{} は整数ではありません", value)))
+        }
+    }
+}
+
+/// Decode an ASCII string where non-printable bytes are written as `\xHH` or C-style escapes.
+pub(crate) fn decode_payload(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut iter = encoded.as_bytes().iter().copied();
+
+    while let Some(byte) = iter.next() {
+        if byte == b'\\' {
+            let next = iter.next()?;
+
+            if next == b'x' || next == b'X' {
+                let high = iter.next()?;
+                let low = iter.next()?;
+                let high_val = hex_value(high)?;
+                let low_val = hex_value(low)?;
+                bytes.push((high_val << 4) | low_val);
+            } else {
+                bytes.push(match next {
+                    b'\\' => b'\\',
+                    b'n' => b'\n',
+                    b'r' => b'\r',
+                    b't' => b'\t',
+                    b'0' => b'\0',
+                    other => other,
+                });
+            }
+        } else {
+            bytes.push(byte);
+        }
+    }
+
+    Some(bytes)
+}
+
+fn decode_hex(encoded: &str) -> Option<Vec<u8>> {
+    let digits: Vec<u8> = encoded
+        .bytes()
+        .filter(|b| !b.is_ascii_whitespace())
+        .collect();
+    if !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    digits
+        .chunks(2)
+        .map(|pair| Some((hex_value(pair[0])? << 4) | hex_value(pair[1])?))
+        .collect()
+}
+
+fn decode_base64(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(encoded.len() * 3 / 4);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    let mut padding = 0;
+
+    for byte in encoded.bytes().filter(|b| !b.is_ascii_whitespace()) {
+        if byte == b'=' {
+            padding += 1;
+            continue;
+        }
+        if padding > 0 {
+            return None; // data after padding
+        }
+        let value = match byte {
+            b'A'..=b'Z' => byte - b'A',
+            b'a'..=b'z' => byte - b'a' + 26,
+            b'0'..=b'9' => byte - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            _ => return None,
+        };
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    if padding > 2 || bits >= 6 {
+        return None;
+    }
+    Some(bytes)
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = r#"{"src_ip": "10.0.0.1", "dst_ip": "10.0.0.2", "src_port": 1024,
+        "dst_port": 80.0, "protocol": 6, "size": 64, "timestamp": 300, "label": "Correct",
+        "payload": "GET\\x20/"}"#;
+
+    fn strict() -> ParseOptions {
+        ParseOptions { strict: true }
+    }
+
+    #[test]
+    fn parses_array_and_packets_object_roots() {
+        let array = format!("[{}]", ENTRY);
+        let object = format!("{{\"meta\": 1, \"packets\": [{}]}}", ENTRY);
+
+        for text in [array, object] {
+            let entries = parse_traffic_json(&text, strict()).expect("valid traffic");
+            assert_eq!(entries.len(), 1);
+            let entry = &entries[0];
+            assert_eq!(entry.dst_port, 80);
+            assert_eq!(entry.label, PacketLabel::Correct);
+            assert_eq!(entry.payload, b"GET /");
+        }
+    }
+
+    #[test]
+    fn rejects_fractional_and_negative_ports_with_location() {
+        let text = "[\n  {\"src_ip\": \"a\", \"dst_ip\": \"b\", \"src_port\": 80.5,\n   \"dst_port\": 1, \"protocol\": 6, \"size\": 1}\n]";
+        let err = parse_traffic_json(text, ParseOptions::default()).unwrap_err();
+        assert_eq!(err.line, 2);
+        assert!(err.message.contains("80.5"), "{}", err.message);
+
+        let text = r#"[{"src_ip": "a", "dst_ip": "b", "src_port": -1, "dst_port": 1, "protocol": 6, "size": 1}]"#;
+        let err = parse_traffic_json(text, ParseOptions::default()).unwrap_err();
+        assert_eq!(err.line, 1);
+        assert!(err.column > 0);
+    }
+
+    #[test]
+    fn strict_mode_rejects_unknown_fields() {
+        let text = r#"[{"src_ip": "a", "dst_ip": "b", "src_port": 1, "dst_port": 1,
+            "protocol": 6, "size": 1, "comment": "x"}]"#;
+        assert!(parse_traffic_json(text, ParseOptions::default()).is_ok());
+
+        let err = parse_traffic_json(text, strict()).unwrap_err();
+        assert!(err.message.contains("comment"), "{}", err.message);
+        assert_eq!(err.line, 2);
+    }
+
+    #[test]
+    fn payload_accepts_hex_and_base64_objects() {
+        let base = r#"{"src_ip": "a", "dst_ip": "b", "src_port": 1, "dst_port": 1, "protocol": 6, "size": 1, "payload": "#;
+        let hex = format!("[{}{{\"hex\": \"48 69 21\"}}}}]", base);
+        let b64 = format!("[{}{{\"base64\": \"SGkh\"}}}}]", base);
+        let bad = format!("[{}{{\"hex\": \"4\"}}}}]", base);
+
+        assert_eq!(
+            parse_traffic_json(&hex, strict()).unwrap()[0].payload,
+            b"Hi!"
+        );
+        assert_eq!(
+            parse_traffic_json(&b64, strict()).unwrap()[0].payload,
+            b"Hi!"
+        );
+        assert!(parse_traffic_json(&bad, strict()).is_err());
+    }
+
+    #[test]
+    fn tcp_flags_accept_letters_and_numbers() {
+        let base = r#"{"src_ip": "a", "dst_ip": "b", "src_port": 1, "dst_port": 1, "protocol": 6, "size": 1, "tcp_flags": "#;
+        let flags = |value: &str| {
+            parse_traffic_json(&format!("[{}{}}}]", base, value), strict())
+                .map(|entries| entries[0].tcp_flags)
+        };
+
+        assert_eq!(
+            flags("\"SA\"").unwrap(),
+            Some(TcpFlags(TcpFlags::SYN.0 | TcpFlags::ACK.0))
+        );
+        assert_eq!(flags("18").unwrap(), Some(TcpFlags(18)));
+        assert_eq!(flags("\".\"").unwrap(), Some(TcpFlags::default()));
+        assert!(flags("\"SX\"").is_err());
+        assert!(flags("300").is_err());
+    }
+
+    #[test]
+    fn sort_and_normalize_rebases_timestamps() {
+        let text = r#"[
+            {"src_ip": "a", "dst_ip": "b", "src_port": 1, "dst_port": 1, "protocol": 6, "size": 1, "timestamp": 500},
+            {"src_ip": "c", "dst_ip": "d", "src_port": 1, "dst_port": 1, "protocol": 6, "size": 1, "timestamp": 200}
+        ]"#;
+        let mut entries = parse_traffic_json(text, strict()).unwrap();
+        sort_and_normalize(&mut entries);
+        assert_eq!(entries[0].src_ip, "c");
+        assert_eq!(entries[0].timestamp, Some(0));
+        assert_eq!(entries[1].timestamp, Some(300));
+    }
+
+    #[test]
+    fn decode_base64_handles_padding() {
+        assert_eq!(decode_base64("TQ=="), Some(b"M".to_vec()));
+        assert_eq!(decode_base64("TWE="), Some(b"Ma".to_vec()));
+        assert_eq!(decode_base64("TWFu"), Some(b"Man".to_vec()));
+        assert_eq!(decode_base64("T"), None);
+        assert_eq!(decode_base64("TQ==TQ"), None);
+    }
+
+    #[test]
+    fn decode_payload_supports_mixed_ascii_and_hex() {
+        let encoded = "Hello\\x20World\\x21";
+        let expected = b"Hello World!".to_vec();
+        assert_eq!(decode_payload(encoded), Some(expected));
+    }
+
+    #[test]
+    fn decode_payload_handles_escape_sequences() {
+        let encoded = "Line1\\nLine2";
+        assert_eq!(decode_payload(encoded), Some(b"Line1\nLine2".to_vec()));
+    }
+
+    #[test]
+    fn decode_payload_rejects_invalid_hex() {
+        assert_eq!(decode_payload("\\xZZ"), None);
+        assert_eq!(decode_payload("\\x1"), None);
+    }
+
+    #[test]
+    fn stage_packet_files_pass_strict_validation() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../godot/assets/packets");
+        let Ok(read_dir) = std::fs::read_dir(&dir) else {
+            return; // stage assets are not shipped with every checkout
+        };
+        for entry in read_dir {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+            let text = std::fs::read_to_string(&path).unwrap();
+            if let Err(err) = parse_traffic_json(&text, strict()) {
+                panic!("{}: {}", path.display(), err);
+            }
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Selection of the part of a traffic file one `Internet` emits.
+//!
+//! A stage can split a shared traffic file between several `Internet` buildings by source
+//! address (`cidr`) and/or by the `tag` of each packet. See `meta.sources` in
+//! [`stage`](crate::logic::stage).
+use std::net::IpAddr;
+
+use crate::core::packet::Packet;
+
+/// Address block such as `10.0.0.0/8` or `2001:db8::/32`. A bare address is a block of one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim();
+        let (address, prefix) = match text.split_once('/') {
+            Some((address, prefix)) => (address, Some(prefix)),
+            None => (text, None),
+        };
+        let network: IpAddr = address
+            .parse()
+            .map_err(|_| format!("CIDR '{}' のアドレスが不正です", text))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|&len| len <= max_len)
+                .ok_or_else(|| format!("CIDR '{}' のプレフィックス長が不正です", text))?,
+            None => max_len,
+        };
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+
+    /// Whether `ip` lies in the block. Addresses that do not parse, or are of the other IP
+    /// version, never do.
+    pub fn contains(&self, ip: &str) -> bool {
+        match (self.network, ip.parse::<IpAddr>()) {
+            (IpAddr::V4(network), Ok(IpAddr::V4(ip))) => Self::same_prefix(
+                network.to_bits().into(),
+                ip.to_bits().into(),
+                32,
+                self.prefix_len,
+            ),
+            (IpAddr::V6(network), Ok(IpAddr::V6(ip))) => {
+                Self::same_prefix(network.to_bits(), ip.to_bits(), 128, self.prefix_len)
+            }
+            _ => false,
+        }
+    }
+
+    fn same_prefix(a: u128, b: u128, bits: u32, prefix_len: u8) -> bool {
+        let host_bits = bits - prefix_len as u32;
+        host_bits >= bits || (a >> host_bits) == (b >> host_bits)
+    }
+}
+
+/// Which packets of a traffic an `Internet` emits. Both conditions must hold when both are
+/// set; a selector without any takes everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TrafficSelector {
+    /// Source addresses taken.
+    pub cidr: Option<Cidr>,
+    /// `Packet::tag` taken.
+    pub tag: Option<String>,
+}
+
+impl TrafficSelector {
+    pub fn matches(&self, packet: &Packet) -> bool {
+        self.cidr
+            .as_ref()
+            .is_none_or(|cidr| cidr.contains(&packet.source_ip))
+            && self
+                .tag
+                .as_ref()
+                .is_none_or(|tag| packet.tag.as_ref() == Some(tag))
+    }
+
+    pub fn takes_everything(&self) -> bool {
+        self.cidr.is_none() && self.tag.is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::packet::Protocol;
+
+    fn packet(source_ip: &str, tag: Option<&str>) -> Packet {
+        let mut packet = Packet::new(
+            source_ip.to_string(),
+            "10.0.0.2".to_string(),
+            40000,
+            80,
+            Protocol::Tcp,
+            64,
+            vec![],
+        );
+        packet.tag = tag.map(String::from);
+        packet
+    }
+
+    #[test]
+    fn cidr_blocks_match_their_addresses_only() {
+        let block = Cidr::parse("203.0.113.0/24").unwrap();
+        assert!(block.contains("203.0.113.200"));
+        assert!(!block.contains("203.0.114.1"));
+        assert!(!block.contains("2001:db8::1"));
+        assert!(Cidr::parse("0.0.0.0/0").unwrap().contains("8.8.8.8"));
+        assert!(
+            Cidr::parse("2001:db8::/32")
+                .unwrap()
+                .contains("2001:db8:ffff::1")
+        );
+        assert!(Cidr::parse("10.0.0.7").unwrap().contains("10.0.0.7"));
+        assert!(Cidr::parse("10.0.0.0/33").is_err());
+        assert!(Cidr::parse("example.com/8").is_err());
+    }
+
+    #[test]
+    fn selectors_need_every_condition() {
+        let selector = TrafficSelector {
+            cidr: Some(Cidr::parse("198.51.100.0/24").unwrap()),
+            tag: Some("botnet".to_string()),
+        };
+        assert!(selector.matches(&packet("198.51.100.9", Some("botnet"))));
+        assert!(!selector.matches(&packet("198.51.100.9", None)));
+        assert!(!selector.matches(&packet("192.0.2.1", Some("botnet"))));
+        assert!(TrafficSelector::default().matches(&packet("192.0.2.1", None)));
+    }
+}
@@ -0,0 +1,386 @@
+//! Pull-based packet sources for the `Internet` building.
+//!
+//! A [`TrafficSource`] hands out packets in timestamp order once the simulation clock has
+//! reached them. [`TrafficResourceSource`] wraps an already loaded `Traffic` resource, while
+//! [`PcapTrafficSource`] reads frames from a capture on demand and only keeps a bounded
+//! lookahead window in memory, so multi-hundred-MB captures never have to be materialized.
+//! [`SelectedTrafficSource`] passes on only part of another source.
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+use godot::prelude::*;
+use pcap_file::pcap::PcapReader;
+
+use super::Traffic;
+use super::normalize_timestamp;
+use super::pcap_frame::parse_packet_from_bytes;
+use super::traffic_selector::TrafficSelector;
+use crate::core::packet::{Packet as CorePacket, Protocol};
+
+/// Number of frames [`PcapTrafficSource`] buffers ahead of the simulation clock. Frames that
+/// arrive out of order within this window are still emitted in timestamp order.
+pub const DEFAULT_LOOKAHEAD: usize = 256;
+
+pub trait TrafficSource {
+    /// Remove and return the next packet whose timestamp (microseconds since the capture
+    /// start) is at or before `now_us`.
+    fn pop_due(&mut self, now_us: i64) -> Option<CorePacket>;
+
+    /// `true` once every packet has been handed out.
+    fn is_exhausted(&self) -> bool;
+
+    /// Whether [`rewind`](Self::rewind) is supported.
+    fn can_rewind(&self) -> bool {
+        false
+    }
+
+    /// Move back so that the next packet handed out is the one at `index` (the number of
+    /// packets popped so far when the position was recorded). Returns `false` when the
+    /// source cannot rewind.
+    fn rewind(&mut self, _index: u64) -> bool {
+        false
+    }
+}
+
+/// In-memory source over packets, handed out in timestamp order.
+#[derive(Default)]
+pub struct VecTrafficSource {
+    packets: Vec<CorePacket>,
+    next_index: usize,
+}
+
+impl VecTrafficSource {
+    pub fn new(mut packets: Vec<CorePacket>) -> Self {
+        packets.sort_by_key(|packet| packet.timestamp);
+        Self {
+            packets,
+            next_index: 0,
+        }
+    }
+}
+
+impl TrafficSource for VecTrafficSource {
+    fn pop_due(&mut self, now_us: i64) -> Option<CorePacket> {
+        let packet = self.packets.get(self.next_index)?;
+        if packet.timestamp > now_us {
+            return None;
+        }
+        self.next_index += 1;
+        Some(packet.clone())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.next_index >= self.packets.len()
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self, index: u64) -> bool {
+        self.next_index = (index as usize).min(self.packets.len());
+        true
+    }
+}
+
+/// Hands out only the packets of `inner` that `selector` takes; the rest are skipped as the
+/// clock passes them. Used to split a streamed capture between several `Internet` buildings.
+pub struct SelectedTrafficSource {
+    inner: Box<dyn TrafficSource>,
+    selector: TrafficSelector,
+}
+
+impl SelectedTrafficSource {
+    pub fn new(inner: Box<dyn TrafficSource>, selector: TrafficSelector) -> Self {
+        Self { inner, selector }
+    }
+}
+
+impl TrafficSource for SelectedTrafficSource {
+    fn pop_due(&mut self, now_us: i64) -> Option<CorePacket> {
+        while let Some(packet) = self.inner.pop_due(now_us) {
+            if self.selector.matches(&packet) {
+                return Some(packet);
+            }
+        }
+        None
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.inner.is_exhausted()
+    }
+}
+
+/// Source backed by a loaded `Traffic` resource. Packets are converted one at a time as
+/// they become due.
+pub struct TrafficResourceSource {
+    traffic: Gd<Traffic>,
+    next_index: usize,
+}
+
+impl TrafficResourceSource {
+    pub fn new(traffic: Gd<Traffic>) -> Self {
+        Self {
+            traffic,
+            next_index: 0,
+        }
+    }
+}
+
+impl TrafficSource for TrafficResourceSource {
+    fn pop_due(&mut self, now_us: i64) -> Option<CorePacket> {
+        let traffic = self.traffic.bind();
+        let resource = traffic.get_packet(self.next_index as i32)?;
+        let resource = resource.bind();
+        if resource.get_timestamp() > now_us {
+            return None;
+        }
+        self.next_index += 1;
+        Some(resource.to_core())
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.next_index as i64 >= self.traffic.bind().packet_count()
+    }
+
+    fn can_rewind(&self) -> bool {
+        true
+    }
+
+    fn rewind(&mut self, index: u64) -> bool {
+        self.next_index = index as usize;
+        true
+    }
+}
+
+struct PendingPacket {
+    timestamp: i64,
+    sequence: u64,
+    packet: CorePacket,
+}
+
+impl PartialEq for PendingPacket {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for PendingPacket {}
+
+impl PartialOrd for PendingPacket {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for PendingPacket {
+    // Reversed so that `BinaryHeap` pops the earliest packet first; ties keep file order.
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.timestamp, other.sequence).cmp(&(self.timestamp, self.sequence))
+    }
+}
+
+/// Streams packets from a pcap file with a bounded lookahead window.
+///
+/// Timestamps are rebased so that the earliest frame of the first window becomes `0`, which
+/// matches what `PcapCapture::to_traffic` does for whole captures.
+pub struct PcapTrafficSource<R: Read> {
+    reader: PcapReader<R>,
+    lookahead: BinaryHeap<PendingPacket>,
+    capacity: usize,
+    baseline: Option<i64>,
+    next_sequence: u64,
+    reader_done: bool,
+}
+
+impl PcapTrafficSource<BufReader<File>> {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|err| format!("ファイル '{}' を開けません: {}", path.display(), err))?;
+        Self::new(BufReader::new(file), DEFAULT_LOOKAHEAD)
+    }
+}
+
+impl<R: Read> PcapTrafficSource<R> {
+    pub fn new(reader: R, lookahead: usize) -> Result<Self, String> {
+        let reader =
+            PcapReader::new(reader).map_err(|err| format!("pcapヘッダーが不正です: {}", err))?;
+        let mut source = Self {
+            reader,
+            lookahead: BinaryHeap::with_capacity(lookahead.max(1)),
+            capacity: lookahead.max(1),
+            baseline: None,
+            next_sequence: 0,
+            reader_done: false,
+        };
+        source.fill();
+        source.baseline = source.lookahead.peek().map(|pending| pending.timestamp);
+        Ok(source)
+    }
+
+    fn fill(&mut self) {
+        while !self.reader_done && self.lookahead.len() < self.capacity {
+            let Some(frame) = self.reader.next_packet() else {
+                self.reader_done = true;
+                break;
+            };
+            // A read error leaves the reader where it was (e.g. a truncated last record), so
+            // nothing after it can be read.
+            let Ok(frame) = frame else {
+                self.reader_done = true;
+                break;
+            };
+            let Some(parsed) = parse_packet_from_bytes(&frame.data) else {
+                continue;
+            };
+
+            let timestamp = frame.timestamp.as_micros() as i64;
+            let mut packet = CorePacket::new(
+                parsed.src_ip,
+                parsed.dst_ip,
+                parsed.src_port,
+                parsed.dst_port,
+                Protocol::from_ip_number(parsed.protocol as i64),
+                frame.orig_len,
+                parsed.payload,
+            );
+            packet.timestamp = timestamp;
+
+            self.lookahead.push(PendingPacket {
+                timestamp,
+                sequence: self.next_sequence,
+                packet,
+            });
+            self.next_sequence += 1;
+        }
+    }
+
+    fn normalized(&self, raw: i64) -> i64 {
+        normalize_timestamp(raw, self.baseline).max(0)
+    }
+}
+
+impl<R: Read> TrafficSource for PcapTrafficSource<R> {
+    fn pop_due(&mut self, now_us: i64) -> Option<CorePacket> {
+        let next = self.lookahead.peek()?;
+        if self.normalized(next.timestamp) > now_us {
+            return None;
+        }
+        let pending = self.lookahead.pop()?;
+        self.fill();
+
+        let mut packet = pending.packet;
+        packet.timestamp = self.normalized(pending.timestamp);
+        Some(packet)
+    }
+
+    fn is_exhausted(&self) -> bool {
+        self.reader_done && self.lookahead.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::packet::Packet;
+    use crate::packet::pcap_writer::write_pcap;
+
+    fn packet_at(timestamp: i64, dest_port: u16) -> Packet {
+        let mut packet = Packet::new(
+            "10.0.0.1".to_string(),
+            "10.0.0.2".to_string(),
+            1234,
+            dest_port,
+            Protocol::Udp,
+            100,
+            b"data".to_vec(),
+        );
+        packet.timestamp = timestamp;
+        packet
+    }
+
+    fn drain(source: &mut dyn TrafficSource, now_us: i64) -> Vec<Packet> {
+        std::iter::from_fn(|| source.pop_due(now_us)).collect()
+    }
+
+    #[test]
+    fn vec_source_releases_packets_by_time() {
+        let mut source = VecTrafficSource::new(vec![packet_at(2_000, 2), packet_at(1_000, 1)]);
+
+        assert!(drain(&mut source, 999).is_empty());
+        let first = drain(&mut source, 1_500);
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].dest_port, 1);
+        assert!(!source.is_exhausted());
+
+        assert_eq!(drain(&mut source, 5_000).len(), 1);
+        assert!(source.is_exhausted());
+
+        assert!(source.rewind(1));
+        assert!(!source.is_exhausted());
+        assert_eq!(drain(&mut source, 5_000)[0].dest_port, 2);
+    }
+
+    #[test]
+    fn pcap_source_streams_and_rebases_timestamps() {
+        let packets: Vec<Packet> = (0..10)
+            .map(|i| packet_at(5_000_000 + i * 100, i as u16))
+            .collect();
+        let mut bytes = Vec::new();
+        write_pcap(&mut bytes, &packets).unwrap();
+
+        let mut source = PcapTrafficSource::new(bytes.as_slice(), 4).unwrap();
+        let early = drain(&mut source, 250);
+        assert_eq!(
+            early.iter().map(|p| p.timestamp).collect::<Vec<_>>(),
+            vec![0, 100, 200]
+        );
+        assert_eq!(early[0].protocol, Protocol::Udp);
+        assert_eq!(early[0].payload, b"data");
+        assert_eq!(early[0].length, 100);
+
+        let rest = drain(&mut source, i64::MAX);
+        assert_eq!(rest.len(), 7);
+        assert!(source.is_exhausted());
+    }
+
+    #[test]
+    fn pcap_source_reorders_within_lookahead() {
+        let mut bytes = Vec::new();
+        {
+            // write_pcap sorts its input, so write frames out of order by hand.
+            let mut writer = pcap_file::pcap::PcapWriter::new(&mut bytes).unwrap();
+            for (ts, port) in [(0, 0u16), (300, 3), (100, 1), (200, 2)] {
+                let frame = crate::packet::pcap_writer::build_frame(&packet_at(ts, port)).unwrap();
+                let pcap_packet = pcap_file::pcap::PcapPacket::new(
+                    std::time::Duration::from_micros(ts as u64),
+                    frame.len() as u32,
+                    &frame,
+                );
+                writer.write_packet(&pcap_packet).unwrap();
+            }
+        }
+
+        let mut source = PcapTrafficSource::new(bytes.as_slice(), 4).unwrap();
+        let ports: Vec<u16> = drain(&mut source, i64::MAX)
+            .iter()
+            .map(|p| p.dest_port)
+            .collect();
+        assert_eq!(ports, vec![0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn pcap_source_stops_at_truncated_record() {
+        let packets: Vec<Packet> = (0..3).map(|i| packet_at(i * 100, i as u16)).collect();
+        let mut bytes = Vec::new();
+        write_pcap(&mut bytes, &packets).unwrap();
+        bytes.truncate(bytes.len() - 10);
+
+        let mut source = PcapTrafficSource::new(bytes.as_slice(), 4).unwrap();
+        assert_eq!(drain(&mut source, i64::MAX).len(), 2);
+        assert!(source.is_exhausted());
+    }
+}
@@ -1,3 +1,6 @@
+pub mod test_connection_graph;
+pub mod test_edit_history;
 pub mod test_filters;
 pub mod test_map_controller;
 pub mod test_packet_completion;
+pub mod test_replay;
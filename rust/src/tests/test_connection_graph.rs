@@ -0,0 +1,82 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::core::building_registry::registry;
+use crate::core::buildings::filters::port_filter::{PortFilterConfig, PortFilterDirection};
+use crate::core::dto::Vec2i;
+use crate::logic::edit_history::EditHistory;
+use crate::map_controller::{BuildingConfig, LayoutEdit, World};
+
+/// Any block of the building registry.
+fn random_block(rng: &mut StdRng) -> i32 {
+    let defs = registry().defs();
+    defs[rng.gen_range(0..defs.len())].id
+}
+
+fn random_edit(world: &World, rng: &mut StdRng) -> LayoutEdit {
+    // A small board keeps buildings packed, so most edits touch neighbours.
+    let pos = Vec2i {
+        x: rng.gen_range(0..8),
+        y: rng.gen_range(0..8),
+    };
+    let rotation = rng.gen_range(0..4);
+    let building_id = world.get_building_id_at(&pos).unwrap_or(0);
+    match rng.gen_range(0..12) {
+        0..=2 => LayoutEdit::Remove { pos },
+        10 => LayoutEdit::Rotate {
+            building_id,
+            rotation,
+        },
+        11 => LayoutEdit::ReplaceType {
+            building_id,
+            block_id: random_block(rng),
+            config: None,
+        },
+        3 => LayoutEdit::PlaceFilter {
+            pos,
+            rotation,
+            config: BuildingConfig::Port(PortFilterConfig {
+                target_port: 80,
+                direction: PortFilterDirection::Destination,
+            }),
+        },
+        _ => LayoutEdit::Place {
+            pos,
+            block_id: random_block(rng),
+            rotation,
+        },
+    }
+}
+
+#[test]
+fn incremental_connections_always_match_full_rebuild() {
+    for seed in 0..64 {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut world = World::new();
+        let mut history = EditHistory::default();
+
+        for step in 0..200 {
+            // Undo and redo go through `Restore`, the other placement path.
+            match rng.gen_range(0..10) {
+                0 => {
+                    history.undo(&mut world);
+                }
+                1 => {
+                    history.redo(&mut world);
+                }
+                _ => {
+                    if let Some(applied) = world.apply_edit(&random_edit(&world, &mut rng)) {
+                        history.record(applied);
+                    }
+                }
+            }
+            assert_eq!(
+                world.connection_graph(),
+                &world.rebuilt_connection_graph(),
+                "seed {} step {}",
+                seed,
+                step
+            );
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::core::building::BuildingType;
+use crate::core::building_registry::block_id;
+use crate::core::buildings::filters::port_filter::{PortFilterConfig, PortFilterDirection};
+use crate::core::dto::Vec2i;
+use crate::logic::edit_history::EditHistory;
+use crate::map_controller::{BuildingConfig, LayoutEdit, World};
+
+fn port_rule(target_port: u16) -> BuildingConfig {
+    BuildingConfig::Port(PortFilterConfig {
+        target_port,
+        direction: PortFilterDirection::Destination,
+    })
+}
+
+fn apply(world: &mut World, history: &mut EditHistory, edit: LayoutEdit) {
+    let applied = world.apply_edit(&edit).expect("edit changes the world");
+    history.record(applied);
+}
+
+#[test]
+fn undo_remove_restores_building_with_id_and_rule() {
+    let mut world = World::new();
+    let mut history = EditHistory::default();
+    let pos = Vec2i { x: 4, y: 2 };
+
+    apply(
+        &mut world,
+        &mut history,
+        LayoutEdit::PlaceFilter {
+            pos,
+            rotation: 3,
+            config: port_rule(22),
+        },
+    );
+    let id = world.get_building_id_at(&pos).unwrap();
+    apply(
+        &mut world,
+        &mut history,
+        LayoutEdit::SetFilterConfig {
+            building_id: id,
+            config: port_rule(443),
+        },
+    );
+    apply(&mut world, &mut history, LayoutEdit::Remove { pos });
+    assert!(world.get_building_id_at(&pos).is_none());
+
+    history.undo(&mut world).unwrap();
+    assert_eq!(world.get_building_id_at(&pos), Some(id));
+    assert_eq!(world.get_building(id).unwrap().rotation(), 3);
+    assert_eq!(world.filter_config(id), Some(port_rule(443)));
+
+    history.undo(&mut world).unwrap();
+    assert_eq!(world.filter_config(id), Some(port_rule(22)));
+
+    history.undo(&mut world).unwrap();
+    assert!(world.get_building(id).is_none());
+    assert!(!history.can_undo());
+
+    // Redo places the filter under the same id, so the rule change applies to it again.
+    history.redo(&mut world).unwrap();
+    history.redo(&mut world).unwrap();
+    assert_eq!(world.get_building_id_at(&pos), Some(id));
+    assert_eq!(world.filter_config(id), Some(port_rule(443)));
+    assert!(history.can_redo());
+
+    // A new edit discards the remaining redo step.
+    apply(
+        &mut world,
+        &mut history,
+        LayoutEdit::Place {
+            pos: Vec2i { x: 0, y: 0 },
+            block_id: block_id(BuildingType::Conveyor),
+            rotation: 0,
+        },
+    );
+    assert!(!history.can_redo());
+}
+
+#[test]
+fn undo_rule_on_unconfigured_filter_clears_it() {
+    let mut world = World::new();
+    let mut history = EditHistory::default();
+    let pos = Vec2i { x: 1, y: 1 };
+    world.place_building(pos, BuildingType::PortFilter, 0);
+    let id = world.get_building_id_at(&pos).unwrap();
+
+    apply(
+        &mut world,
+        &mut history,
+        LayoutEdit::SetFilterConfig {
+            building_id: id,
+            config: port_rule(80),
+        },
+    );
+    history.undo(&mut world).unwrap();
+    assert_eq!(world.filter_config(id), None);
+}
+
+#[test]
+fn grouped_conveyor_line_is_one_undo_step() {
+    let mut world = World::new();
+    let mut history = EditHistory::default();
+
+    history.begin_group();
+    for x in 0..5 {
+        apply(
+            &mut world,
+            &mut history,
+            LayoutEdit::Place {
+                pos: Vec2i { x, y: 0 },
+                block_id: block_id(BuildingType::Conveyor),
+                rotation: 0,
+            },
+        );
+    }
+    history.end_group();
+    assert_eq!(world.storage.iter().count(), 5);
+
+    let reverted = history.undo(&mut world).unwrap();
+    assert_eq!(reverted.len(), 5);
+    assert_eq!(world.storage.iter().count(), 0);
+    assert!(!history.can_undo());
+
+    history.redo(&mut world).unwrap();
+    let ids: Vec<u64> = world.storage.iter().map(|b| b.id()).collect();
+    assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+}
+
+#[test]
+fn history_is_bounded() {
+    let mut world = World::new();
+    let mut history = EditHistory::new(3);
+
+    for x in 0..5 {
+        apply(
+            &mut world,
+            &mut history,
+            LayoutEdit::Place {
+                pos: Vec2i { x, y: 0 },
+                block_id: block_id(BuildingType::Conveyor),
+                rotation: 0,
+            },
+        );
+    }
+
+    let mut undone = 0;
+    while history.undo(&mut world).is_some() {
+        undone += 1;
+    }
+    assert_eq!(undone, 3);
+    assert_eq!(world.storage.iter().count(), 2);
+}
+
+#[test]
+fn undo_blocklist_edits_restores_previous_entries() {
+    let mut world = World::new();
+    let mut history = EditHistory::default();
+    let set = |ttl_ms| LayoutEdit::SetBlocklistEntry {
+        list: "scanners".to_string(),
+        ip: "10.6.6.6".to_string(),
+        ttl_ms,
+    };
+
+    apply(&mut world, &mut history, set(Some(2000)));
+    apply(&mut world, &mut history, set(None));
+    apply(
+        &mut world,
+        &mut history,
+        LayoutEdit::RemoveBlocklistEntry {
+            list: "scanners".to_string(),
+            ip: "10.6.6.6".to_string(),
+        },
+    );
+    assert!(!world.blocklists().contains("scanners", "10.6.6.6"));
+
+    history.undo(&mut world).unwrap();
+    assert_eq!(
+        world.blocklists().expires_in("scanners", "10.6.6.6"),
+        Some(None)
+    );
+    history.undo(&mut world).unwrap();
+    assert_eq!(
+        world.blocklists().expires_in("scanners", "10.6.6.6"),
+        Some(Some(2.0))
+    );
+    history.undo(&mut world).unwrap();
+    assert_eq!(world.blocklists().names().count(), 0);
+}
+
+#[test]
+fn undo_blocklist_edit_keeps_the_time_already_spent() {
+    let mut world = World::new();
+    let mut history = EditHistory::default();
+    let set = |ttl_ms| LayoutEdit::SetBlocklistEntry {
+        list: "scanners".to_string(),
+        ip: "10.6.6.6".to_string(),
+        ttl_ms,
+    };
+
+    apply(&mut world, &mut history, set(Some(2000)));
+    world.update(1.0);
+    apply(&mut world, &mut history, set(None));
+    history.undo(&mut world).unwrap();
+    let left = world.blocklists().expires_in("scanners", "10.6.6.6");
+    assert!(left.flatten().is_some_and(|secs| (secs - 1.0).abs() < 1e-6));
+}
@@ -173,10 +173,11 @@ fn test_length_filter_set_config() {
 
 #[test]
 fn test_content_filter_valid_pattern() {
-    use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
 
     let config = ContentFilterConfig {
         pattern: "payload".to_string(),
+        scope: ContentMatchScope::Segment,
     };
     let filter = ContentFilter::new_with_config(0, Default::default(), 0, config);
 
@@ -230,10 +231,11 @@ fn test_length_filter_direction_greater_than() {
 
 #[test]
 fn test_content_filter_regex_pattern() {
-    use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
 
     let config = ContentFilterConfig {
         pattern: r"p.*load".to_string(), // regex pattern
+        scope: ContentMatchScope::Segment,
     };
     let filter = ContentFilter::new_with_config(0, Default::default(), 0, config);
 
@@ -256,7 +258,7 @@ fn test_content_filter_no_config() {
 
 #[test]
 fn test_content_filter_set_config() {
-    use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
 
     let mut filter = ContentFilter::new(0, Default::default(), 0);
     let packet = create_test_packet();
@@ -267,6 +269,7 @@ fn test_content_filter_set_config() {
     // 設定後はフィルタリングが有効になる
     let config = ContentFilterConfig {
         pattern: "payload".to_string(),
+        scope: ContentMatchScope::Segment,
     };
     filter.set_config(config);
     assert!(filter.filter(&packet));
@@ -274,10 +277,11 @@ fn test_content_filter_set_config() {
 
 #[test]
 fn test_content_filter_invalid_regex() {
-    use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
 
     let config = ContentFilterConfig {
         pattern: "[invalid regex".to_string(), // 無効な正規表現
+        scope: ContentMatchScope::Segment,
     };
     let filter = ContentFilter::new_with_config(0, Default::default(), 0, config);
     let packet = create_test_packet();
@@ -286,6 +290,36 @@ fn test_content_filter_invalid_regex() {
     assert!(!filter.filter(&packet));
 }
 
+#[test]
+fn test_content_filter_stream_scope_matches_reassembled_flow() {
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
+
+    let segment_config = ContentFilterConfig {
+        pattern: r"' OR 1=1".to_string(),
+        scope: ContentMatchScope::Segment,
+    };
+    let stream_config = ContentFilterConfig {
+        scope: ContentMatchScope::Stream,
+        ..segment_config.clone()
+    };
+    let segment_filter = ContentFilter::new_with_config(0, Default::default(), 0, segment_config);
+    let stream_filter = ContentFilter::new_with_config(0, Default::default(), 0, stream_config);
+
+    // 攻撃文字列が2つのセグメントに分割されている
+    let mut packet = create_test_packet();
+    packet.payload = b"id=1' OR".to_vec();
+    packet.stream = Some(std::sync::Arc::from(&b"id=1' OR 1=1--"[..]));
+
+    assert!(!segment_filter.filter(&packet));
+    assert!(stream_filter.filter(&packet));
+
+    // ストリームがない場合はペイロードで判定する
+    packet.stream = None;
+    assert!(!stream_filter.filter(&packet));
+    packet.payload = b"x' OR 1=1".to_vec();
+    assert!(stream_filter.filter(&packet));
+}
+
 #[test]
 fn test_protocol_filter_tcp() {
     use crate::core::buildings::filters::protocol_filter::{ProtocolFilter, ProtocolFilterConfig};
@@ -49,10 +49,9 @@ mod tests {
         assert_eq!(world.storage.iter().count(), 0);
     }
 
-    // Note: move_building and rotate_building are not directly implemented on World.
-    // These operations are handled by place_building and remove_building.
-    // A higher-level controller would implement move/rotate.
-    // We will test the underlying logic.
+    // Note: move_building is not directly implemented on World.
+    // A move is handled by remove_building and place_building.
+    // Rotation in place is World::rotate_building (tested below).
 
     #[test]
     fn test_building_placement_and_removal_for_move() {
@@ -103,6 +102,22 @@ fn get_building_id_by_pos(world: &World, pos: Vec2i) -> Option<u64> {
     world.get_building_id_at(&pos)
 }
 
+// Rule buildings in the routing tests sit at (2,2) facing east:
+// input south (2,3), match east (3,2), mismatch north (2,1).
+const RULE_INPUT: Vec2i = Vec2i { x: 2, y: 3 };
+
+// Number of packets held by the building at (x, y), e.g. a recycle bin
+fn packets_at(world: &World, x: i32, y: i32) -> usize {
+    let id = get_building_id_by_pos(world, Vec2i { x, y }).unwrap();
+    world.get_building(id).unwrap().get_packets().len()
+}
+
+// Hands a packet to a building as if it came from `from`, then steps the world
+fn feed(world: &mut World, id: BuildingId, packet: Packet, from: Vec2i) {
+    world.storage.get_mut(id).unwrap().accept(packet, from);
+    world.update(0.1);
+}
+
 #[test]
 fn test_update_packet_movement_and_events() {
     let mut world = World::new();
@@ -896,13 +911,14 @@ fn test_port_filter_set_config_via_building_storage() {
 
 #[test]
 fn test_place_content_filter_with_config() {
-    use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
 
     let mut world = World::new();
     let pos = Vec2i { x: 5, y: 5 };
 
     let config = ContentFilterConfig {
         pattern: "malicious".to_string(),
+        scope: ContentMatchScope::Segment,
     };
 
     world.place_content_filter_with_config(pos, 0, config);
@@ -930,7 +946,7 @@ fn test_place_content_filter_with_config() {
 
 #[test]
 fn test_content_filter_set_config_via_building_storage() {
-    use crate::core::buildings::filters::content_filter::ContentFilterConfig;
+    use crate::core::buildings::filters::content_filter::{ContentFilterConfig, ContentMatchScope};
 
     let mut world = World::new();
     let pos = Vec2i { x: 3, y: 3 };
@@ -961,6 +977,7 @@ fn test_content_filter_set_config_via_building_storage() {
 
         let config = ContentFilterConfig {
             pattern: r"p.*load".to_string(), // regex pattern
+            scope: ContentMatchScope::Segment,
         };
         filter.set_config(config);
     }
@@ -980,3 +997,1142 @@ fn test_content_filter_set_config_via_building_storage() {
         assert!(!filter.filter(&test_packet_no_match));
     }
 }
+
+#[test]
+fn test_export_pcap_from_sinks() {
+    use crate::map_controller::PcapExportScope;
+    use pcap_file::pcap::PcapReader;
+
+    let mut world = World::new();
+    let datacenter_pos = Vec2i { x: 0, y: 0 };
+    let recycle_pos = Vec2i { x: 4, y: 0 };
+    world.place_building(datacenter_pos, BuildingType::Datacenter, 0);
+    world.place_building(recycle_pos, BuildingType::RecycleBin, 0);
+
+    let datacenter_id = get_building_id_by_pos(&world, datacenter_pos).unwrap();
+    let recycle_id = get_building_id_by_pos(&world, recycle_pos).unwrap();
+
+    for (id, count) in [(datacenter_id, 2), (recycle_id, 1)] {
+        for i in 0..count {
+            let mut packet = create_test_packet();
+            packet.timestamp = i * 1_000;
+            world
+                .storage
+                .get_mut(id)
+                .unwrap()
+                .accept(packet, Vec2i { x: -1, y: 0 });
+        }
+    }
+
+    assert_eq!(world.sink_packets(PcapExportScope::Datacenter).len(), 2);
+    assert_eq!(world.sink_packets(PcapExportScope::RecycleBin).len(), 1);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("all.pcap");
+    let written = world
+        .export_pcap(PcapExportScope::AllSinks, &path)
+        .expect("export should succeed");
+    assert_eq!(written, 3);
+
+    let file = std::fs::File::open(&path).unwrap();
+    let mut reader = PcapReader::new(file).unwrap();
+    let mut frames = 0;
+    while let Some(frame) = reader.next_packet() {
+        frame.unwrap();
+        frames += 1;
+    }
+    assert_eq!(frames, 3);
+}
+
+#[test]
+fn test_internet_pulls_from_traffic_source_by_timestamp() {
+    use crate::core::buildings::internet::Internet;
+    use crate::packet::traffic_source::VecTrafficSource;
+
+    let mut first = create_test_packet();
+    first.timestamp = 500_000;
+    let mut second = create_test_packet();
+    second.timestamp = 1_500_000;
+
+    let mut internet = Internet::new(0, Vec2i { x: 0, y: 0 }, 0);
+    internet.set_source(Box::new(VecTrafficSource::new(vec![second, first])));
+
+    internet.update(0.25);
+    assert!(!internet.can_offload());
+
+    internet.update(0.5);
+    assert_eq!(internet.get_packets().len(), 1);
+    assert_eq!(internet.offload().timestamp, 500_000);
+    assert!(!internet.is_exhausted());
+
+    internet.update(1.0);
+    assert_eq!(internet.offload().timestamp, 1_500_000);
+    assert!(internet.is_exhausted());
+}
+
+#[test]
+fn test_packet_ids_and_hop_trace() {
+    use crate::core::buildings::filters::ip_filter::{IpFilter, IpFilterConfig, IpFilterDirection};
+    use crate::core::buildings::internet::Internet;
+    use crate::core::packet::PacketId;
+    use crate::packet::traffic_source::VecTrafficSource;
+
+    // Internet(0,0) 2x2 -> IpFilter(2,1) facing right; match goes up/down, mismatch goes right.
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Internet, 0);
+    world.place_building(Vec2i { x: 2, y: 1 }, BuildingType::IpFilter, 1);
+    world.place_building(Vec2i { x: 3, y: 1 }, BuildingType::RecycleBin, 0);
+    world.place_building(Vec2i { x: 2, y: 2 }, BuildingType::RecycleBin, 0);
+    world.rebuild_connections();
+
+    let internet_id = get_building_id_by_pos(&world, Vec2i { x: 0, y: 0 }).unwrap();
+    let filter_id = get_building_id_by_pos(&world, Vec2i { x: 2, y: 1 }).unwrap();
+    let front_bin_id = get_building_id_by_pos(&world, Vec2i { x: 3, y: 1 }).unwrap();
+    let side_bin_id = get_building_id_by_pos(&world, Vec2i { x: 2, y: 2 }).unwrap();
+
+    world
+        .storage
+        .get_mut(filter_id)
+        .unwrap()
+        .as_any_mut()
+        .downcast_mut::<IpFilter>()
+        .unwrap()
+        .set_config(IpFilterConfig {
+            target_ip: "10.0.0.1".to_string(),
+            direction: IpFilterDirection::Source,
+        });
+
+    // Two identical packets: only their ids tell them apart.
+    let mut packets = vec![create_test_packet(), create_test_packet()];
+    packets[1].timestamp = 1;
+    world
+        .storage
+        .get_mut(internet_id)
+        .unwrap()
+        .as_any_mut()
+        .downcast_mut::<Internet>()
+        .unwrap()
+        .set_source(Box::new(VecTrafficSource::new(packets)));
+
+    for _ in 0..4 {
+        world.update(0.5);
+    }
+
+    let delivered = world.storage.get(front_bin_id).unwrap().get_packets();
+    let mut indices: Vec<u64> = delivered.iter().map(|p| p.id.unwrap().index).collect();
+    indices.sort();
+    assert_eq!(indices, vec![0, 1]);
+    assert!(
+        world
+            .storage
+            .get(side_bin_id)
+            .unwrap()
+            .get_packets()
+            .is_empty()
+    );
+
+    let first = PacketId {
+        origin: internet_id,
+        index: 0,
+    };
+    let trace = world.packet_trace(first).expect("trace for first packet");
+    let path: Vec<_> = trace.iter().map(|hop| hop.building_id).collect();
+    assert_eq!(path, vec![internet_id, filter_id, front_bin_id]);
+    assert_eq!(trace[0].building_type, BuildingType::Internet);
+    assert_eq!(trace[1].verdict, Some(false));
+    assert_eq!(trace[2].verdict, None);
+    assert!(trace[0].tick <= trace[1].tick && trace[1].tick < trace[2].tick);
+    assert!(world.tick() >= trace[2].tick);
+
+    // Both packets were spawned on the first tick; the second waited in the Internet.
+    let second = world
+        .packet_trace(PacketId {
+            origin: internet_id,
+            index: 1,
+        })
+        .expect("trace for second packet");
+    assert_eq!((trace[0].tick, second[0].tick), (1, 1));
+    assert!(second[1].tick > 1);
+}
+
+#[test]
+fn test_rewind_restores_and_resimulates() {
+    use crate::core::buildings::internet::Internet;
+    use crate::core::packet::PacketId;
+    use crate::logic::fixed_step::TICK_SECONDS;
+    use crate::logic::replay::digest;
+    use crate::map_controller::SNAPSHOT_INTERVAL;
+    use crate::packet::traffic_source::VecTrafficSource;
+
+    // Internet(0,0) -> three conveyors -> RecycleBin, one packet every 0.4s.
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Internet, 0);
+    for x in 2..5 {
+        world.place_building(Vec2i { x, y: 0 }, BuildingType::Conveyor, 0);
+    }
+    world.place_building(Vec2i { x: 5, y: 0 }, BuildingType::RecycleBin, 0);
+    let internet_id = get_building_id_by_pos(&world, Vec2i { x: 0, y: 0 }).unwrap();
+    let packets = (0..10)
+        .map(|i| {
+            let mut packet = create_test_packet();
+            packet.timestamp = i * 400_000;
+            packet
+        })
+        .collect();
+    world
+        .storage
+        .get_mut(internet_id)
+        .unwrap()
+        .as_any_mut()
+        .downcast_mut::<Internet>()
+        .unwrap()
+        .set_source(Box::new(VecTrafficSource::new(packets)));
+
+    let mut digests = Vec::new();
+    for _ in 0..300 {
+        world.update(TICK_SECONDS);
+        digests.push(digest(&world));
+    }
+    let first = PacketId {
+        origin: internet_id,
+        index: 0,
+    };
+    let full_trace = world.packet_trace(first).unwrap().to_vec();
+    let delivered_ids = |world: &World| -> Vec<Option<PacketId>> {
+        world
+            .storage
+            .iter()
+            .flat_map(|b| b.get_packets())
+            .map(|p| p.id)
+            .collect()
+    };
+    let full_ids = delivered_ids(&world);
+    assert_eq!(world.snapshot_count(), (300 / SNAPSHOT_INTERVAL) as usize);
+
+    // Not on a snapshot boundary: restores tick 90 and simulates 7 ticks forward.
+    assert_eq!(world.rewind_to(97), Ok(90));
+    assert_eq!(world.tick(), 97);
+    assert_eq!(digest(&world), digests[96]);
+    assert!(world.snapshot_count() < 10);
+
+    while world.tick() < 300 {
+        world.update(TICK_SECONDS);
+    }
+    assert_eq!(digest(&world), digests[299]);
+    assert_eq!(world.packet_trace(first).unwrap(), full_trace.as_slice());
+    // Packets re-emitted after the rewind get the ids they had the first time.
+    assert_eq!(delivered_ids(&world), full_ids);
+
+    assert_eq!(world.rewind_to(0), Ok(0));
+    assert_eq!(world.tick(), 0);
+    assert!(world.storage.iter().all(|b| b.get_packets().is_empty()));
+    assert!(world.rewind_to(1).is_err());
+}
+
+#[test]
+fn test_rewind_makes_later_edits_at_the_snapshot_like_a_replay() {
+    use crate::core::building_registry::block_id;
+    use crate::core::buildings::internet::Internet;
+    use crate::logic::fixed_step::TICK_SECONDS;
+    use crate::logic::replay::digest;
+    use crate::map_controller::LayoutEdit;
+    use crate::packet::traffic_source::VecTrafficSource;
+
+    // Internet(0,0) -> three conveyors -> RecycleBin, one packet every 0.1s.
+    let new_world = || {
+        let mut world = World::new();
+        world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Internet, 0);
+        for x in 2..5 {
+            world.place_building(Vec2i { x, y: 0 }, BuildingType::Conveyor, 0);
+        }
+        world.place_building(Vec2i { x: 5, y: 0 }, BuildingType::RecycleBin, 0);
+        let internet_id = get_building_id_by_pos(&world, Vec2i { x: 0, y: 0 }).unwrap();
+        let packets = (0..20)
+            .map(|i| {
+                let mut packet = create_test_packet();
+                packet.timestamp = i * 100_000;
+                packet
+            })
+            .collect();
+        world
+            .storage
+            .get_mut(internet_id)
+            .unwrap()
+            .as_any_mut()
+            .downcast_mut::<Internet>()
+            .unwrap()
+            .set_source(Box::new(VecTrafficSource::new(packets)));
+        world
+    };
+    let run_to = |world: &mut World, tick: u64| {
+        while world.tick() < tick {
+            world.update(TICK_SECONDS);
+        }
+    };
+    let make_edits = |world: &mut World| {
+        let conveyor = get_building_id_by_pos(world, Vec2i { x: 2, y: 0 }).unwrap();
+        let edits = [
+            LayoutEdit::ReplaceType {
+                building_id: conveyor,
+                block_id: block_id(BuildingType::RecycleBin),
+                config: None,
+            },
+            LayoutEdit::SetBlocklistEntry {
+                list: "manual".to_string(),
+                ip: "10.0.0.3".to_string(),
+                ttl_ms: Some(1000),
+            },
+        ];
+        for edit in &edits {
+            assert!(world.apply_edit(edit).is_some());
+        }
+    };
+
+    let mut live = new_world();
+    run_to(&mut live, 40);
+    make_edits(&mut live);
+    run_to(&mut live, 60);
+    assert_eq!(live.rewind_to(45), Ok(30));
+
+    // What a replay does with the edits moved to the restored tick.
+    let mut replay = new_world();
+    run_to(&mut replay, 30);
+    make_edits(&mut replay);
+    run_to(&mut replay, 45);
+
+    assert_eq!(digest(&live), digest(&replay));
+    assert_eq!(live.drop_stats(), replay.drop_stats());
+    assert!(live.drop_stats().replaced > 0);
+    let drops = |world: &World| -> Vec<(u64, BuildingId)> {
+        world
+            .dropped_packets()
+            .iter()
+            .map(|dropped| (dropped.tick, dropped.building_id))
+            .collect()
+    };
+    assert_eq!(drops(&live), drops(&replay));
+    assert_eq!(
+        live.blocklists().expires_in("manual", "10.0.0.3"),
+        replay.blocklists().expires_in("manual", "10.0.0.3")
+    );
+}
+
+#[test]
+fn test_rotate_building_keeps_id_packet_and_reroutes() {
+    let mut world = World::new();
+    let conveyor_pos = Vec2i { x: 2, y: 2 };
+    world.place_building(conveyor_pos, BuildingType::Conveyor, 0); // Faces right
+    world.place_building(Vec2i { x: 3, y: 2 }, BuildingType::RecycleBin, 0);
+    world.place_building(Vec2i { x: 2, y: 3 }, BuildingType::RecycleBin, 0);
+    let conveyor_id = get_building_id_by_pos(&world, conveyor_pos).unwrap();
+    let south_bin = get_building_id_by_pos(&world, Vec2i { x: 2, y: 3 }).unwrap();
+    world
+        .storage
+        .get_mut(conveyor_id)
+        .unwrap()
+        .accept(create_test_packet(), Vec2i { x: 1, y: 2 });
+    world.drain_events();
+
+    assert!(world.rotate_building(conveyor_id, 1)); // Faces down
+    assert!(!world.rotate_building(conveyor_id, 1));
+
+    let conveyor = world.get_building(conveyor_id).unwrap();
+    assert_eq!(conveyor.rotation(), 1);
+    assert_eq!(conveyor.get_packets().len(), 1);
+    let targets: Vec<_> = world
+        .get_output_connections(conveyor_id)
+        .unwrap()
+        .iter()
+        .map(|edge| edge.to_id)
+        .collect();
+    assert_eq!(targets, vec![south_bin]);
+    assert_eq!(world.connection_graph(), &world.rebuilt_connection_graph());
+
+    let events = world.drain_events();
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        events[0],
+        WorldEvent::BuildingUpdated { id, rotation: 1, .. } if id == conveyor_id
+    ));
+}
+
+#[test]
+fn test_replace_building_type_in_place() {
+    use crate::core::building_registry::block_id;
+    use crate::core::buildings::filters::ip_filter::{IpFilterConfig, IpFilterDirection};
+    use crate::core::buildings::filters::port_filter::{PortFilterConfig, PortFilterDirection};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    let pos = Vec2i { x: 4, y: 4 };
+    let port_rule = PortFilterConfig {
+        target_port: 80,
+        direction: PortFilterDirection::Destination,
+    };
+    world.place_port_filter_with_config(pos, 1, port_rule.clone());
+    world.place_building(Vec2i { x: 5, y: 5 }, BuildingType::RecycleBin, 0);
+    let id = get_building_id_by_pos(&world, pos).unwrap();
+    world
+        .storage
+        .get_mut(id)
+        .unwrap()
+        .accept(create_test_packet(), Vec2i { x: 3, y: 4 });
+
+    let ip_rule = BuildingConfig::Ip(IpFilterConfig {
+        target_ip: "10.0.0.0/8".to_string(),
+        direction: IpFilterDirection::Source,
+    });
+    let applied = world
+        .apply_edit(&LayoutEdit::ReplaceType {
+            building_id: id,
+            block_id: block_id(BuildingType::IpFilter),
+            config: Some(ip_rule.clone()),
+        })
+        .expect("replacement applies");
+
+    let building = world.get_building(id).unwrap();
+    assert_eq!(building.building_type(), BuildingType::IpFilter);
+    assert_eq!(building.rotation(), 1);
+    assert_eq!(building.get_packets().len(), 1);
+    assert_eq!(world.filter_config(id), Some(ip_rule));
+    assert_eq!(world.connection_graph(), &world.rebuilt_connection_graph());
+
+    world.apply_edit(&applied.undo).expect("undo applies");
+    assert_eq!(
+        world.get_building(id).unwrap().building_type(),
+        BuildingType::PortFilter
+    );
+    assert_eq!(
+        world.filter_config(id),
+        Some(BuildingConfig::Port(port_rule))
+    );
+
+    // A 2x2 datacenter would cover the recycle bin.
+    assert!(!world.replace_building(id, block_id(BuildingType::Datacenter), None));
+    assert!(!world.replace_building(id, block_id(BuildingType::PortFilter), None));
+}
+
+#[test]
+fn test_replacing_conveyor_with_smaller_tier_drops_overflow() {
+    use crate::core::building::BuildingState;
+    use crate::core::building_registry::{block_id, registry};
+    use crate::core::buildings::conveyor::{Conveyor, EntrySide};
+    use crate::core::dto::DropReason;
+
+    let mut world = World::new();
+    let pos = Vec2i { x: 1, y: 1 };
+    let express = registry().by_key("express_conveyor").unwrap();
+    world.place_block(pos, express.id, 0);
+    let id = get_building_id_by_pos(&world, pos).unwrap();
+    let slots = |world: &World| {
+        world
+            .get_building(id)
+            .and_then(|b| b.as_any().downcast_ref::<Conveyor>())
+            .map(Conveyor::capacity)
+            .unwrap()
+    };
+    let express_slots = slots(&world);
+    let buffer = (0..express_slots)
+        .map(|i| {
+            let mut packet = create_test_packet();
+            packet.source_port = i as u16;
+            (packet, EntrySide::Back)
+        })
+        .collect();
+    world
+        .storage
+        .get_mut(id)
+        .unwrap()
+        .restore_state(BuildingState::Conveyor(buffer));
+
+    assert!(world.replace_building(id, block_id(BuildingType::Conveyor), None));
+    let kept = world.get_building(id).unwrap().get_packets();
+    assert_eq!(kept.len(), slots(&world));
+    // The front packets stay on the belt.
+    assert!(
+        kept.iter()
+            .enumerate()
+            .all(|(i, p)| p.source_port == i as u16)
+    );
+
+    let dropped = world.dropped_packets();
+    assert_eq!(dropped.len(), express_slots - slots(&world));
+    assert!(dropped.iter().all(|d| d.reason == DropReason::Overloaded));
+    assert_eq!(world.drop_stats().denial_of_service, 0);
+}
+
+#[test]
+fn test_replacing_with_an_unrelated_type_drops_the_held_packets() {
+    use crate::core::building_registry::block_id;
+    use crate::core::dto::DropReason;
+
+    let mut world = World::new();
+    let pos = Vec2i { x: 4, y: 4 };
+    world.place_building(pos, BuildingType::PortFilter, 0);
+    let id = get_building_id_by_pos(&world, pos).unwrap();
+    world
+        .storage
+        .get_mut(id)
+        .unwrap()
+        .accept(create_test_packet(), Vec2i { x: 3, y: 4 });
+
+    assert!(world.replace_building(id, block_id(BuildingType::Conveyor), None));
+    assert!(world.get_building(id).unwrap().get_packets().is_empty());
+    let dropped = world.dropped_packets();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(
+        (dropped[0].building_type, dropped[0].reason),
+        (BuildingType::PortFilter, DropReason::Replaced)
+    );
+    assert_eq!(world.drop_stats().replaced, 1);
+    assert!(world.drain_events().iter().any(|event| matches!(
+        event,
+        WorldEvent::PacketDropped {
+            reason: DropReason::Replaced,
+            ..
+        }
+    )));
+}
+
+#[test]
+fn test_replaced_building_keeps_the_rule_that_fits() {
+    use crate::core::buildings::datacenter::{DatacenterConfig, HostedService};
+    use crate::core::buildings::filters::stateful_firewall::{
+        FirewallService, StatefulFirewallConfig,
+    };
+    use crate::map_controller::BuildingConfig;
+
+    let service = |port| HostedService {
+        protocol: Protocol::Tcp,
+        port,
+        ip: Some("10.0.0.5".to_string()),
+    };
+    let datacenter = BuildingConfig::Datacenter(DatacenterConfig {
+        services: vec![service(443), service(443), service(22)],
+        capacity_pps: 50,
+        queue_size: 8,
+    });
+    let firewall = datacenter
+        .migrate_to(BuildingType::StatefulFirewall)
+        .expect("services carry over");
+    let open = |port| FirewallService {
+        protocol: Protocol::Tcp,
+        port,
+    };
+    assert_eq!(
+        firewall,
+        BuildingConfig::Firewall(StatefulFirewallConfig::new(vec![open(443), open(22)]))
+    );
+    assert!(matches!(
+        firewall.migrate_to(BuildingType::Datacenter),
+        Some(BuildingConfig::Datacenter(config)) if config.services.len() == 2
+    ));
+    assert_eq!(
+        datacenter.migrate_to(BuildingType::Datacenter),
+        Some(datacenter.clone())
+    );
+    assert_eq!(datacenter.migrate_to(BuildingType::PortFilter), None);
+}
+
+#[test]
+fn test_tunnel_pairs_with_nearest_matching_exit() {
+    let mut world = World::new();
+    let entrance_pos = Vec2i { x: 0, y: 0 };
+    world.place_building(entrance_pos, BuildingType::TunnelEntrance, 0); // Faces right
+    world.place_building(Vec2i { x: 1, y: 0 }, BuildingType::Conveyor, 1); // Crossing belt
+    world.place_building(Vec2i { x: 2, y: 0 }, BuildingType::TunnelExit, 1); // Wrong way
+    world.place_building(Vec2i { x: 4, y: 0 }, BuildingType::TunnelExit, 0);
+    let entrance_id = get_building_id_by_pos(&world, entrance_pos).unwrap();
+    let far_exit = get_building_id_by_pos(&world, Vec2i { x: 4, y: 0 }).unwrap();
+    let targets = |world: &World| -> Vec<BuildingId> {
+        world
+            .get_output_connections(entrance_id)
+            .map(|edges| edges.iter().map(|edge| edge.to_id).collect())
+            .unwrap_or_default()
+    };
+    assert_eq!(targets(&world), vec![far_exit]);
+
+    world.place_building(Vec2i { x: 3, y: 0 }, BuildingType::TunnelExit, 0);
+    let near_exit = get_building_id_by_pos(&world, Vec2i { x: 3, y: 0 }).unwrap();
+    assert_eq!(targets(&world), vec![near_exit]);
+    assert_eq!(world.connection_graph(), &world.rebuilt_connection_graph());
+
+    world.remove_building(&Vec2i { x: 3, y: 0 });
+    assert_eq!(targets(&world), vec![far_exit]);
+    assert_eq!(world.connection_graph(), &world.rebuilt_connection_graph());
+
+    // Out of range once the only exit sits past the tunnel's reach.
+    world.remove_building(&Vec2i { x: 4, y: 0 });
+    let beyond = crate::core::buildings::tunnel::tunnel_range() + 1;
+    world.place_building(Vec2i { x: beyond, y: 0 }, BuildingType::TunnelExit, 0);
+    assert!(targets(&world).is_empty());
+}
+
+#[test]
+fn test_tunnel_carries_packets_under_other_buildings() {
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::TunnelEntrance, 0);
+    world.place_building(Vec2i { x: 1, y: 0 }, BuildingType::Conveyor, 1);
+    world.place_building(Vec2i { x: 3, y: 0 }, BuildingType::TunnelExit, 0);
+    world.place_building(Vec2i { x: 4, y: 0 }, BuildingType::RecycleBin, 0);
+    let entrance_id = get_building_id_by_pos(&world, Vec2i { x: 0, y: 0 }).unwrap();
+    let exit_id = get_building_id_by_pos(&world, Vec2i { x: 3, y: 0 }).unwrap();
+    let bin_id = get_building_id_by_pos(&world, Vec2i { x: 4, y: 0 }).unwrap();
+    world
+        .storage
+        .get_mut(entrance_id)
+        .unwrap()
+        .accept(create_test_packet(), Vec2i { x: -1, y: 0 });
+
+    world.update(0.1);
+    assert_eq!(world.get_building(exit_id).unwrap().get_packets().len(), 1);
+
+    // Three tiles underground take longer than one tick.
+    world.update(0.1);
+    assert!(world.get_building(bin_id).unwrap().get_packets().is_empty());
+    for _ in 0..30 {
+        world.update(0.1);
+    }
+    assert_eq!(world.get_building(bin_id).unwrap().get_packets().len(), 1);
+    assert!(
+        world
+            .get_building(exit_id)
+            .unwrap()
+            .get_packets()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_tunnel_packet_position_runs_between_front_edges() {
+    let tile_size = 16.0;
+    let entrance = Vec2i { x: 0, y: 0 };
+    let exit = Vec2i { x: 3, y: 0 };
+    let start = crate::map_controller::tunnel_packet_position(entrance, exit, 0, 0.0, tile_size);
+    let end = crate::map_controller::tunnel_packet_position(entrance, exit, 0, 1.0, tile_size);
+    assert_eq!(start, Vector2::new(16.0, 8.0));
+    assert_eq!(end, Vector2::new(64.0, 8.0));
+}
+
+#[test]
+fn test_splitter_follows_weights_and_overflow() {
+    use crate::core::buildings::splitter::{SplitterConfig, SplitterMode};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    // Splitter facing east: front (3,2), left (2,3), right (2,1).
+    let mut world = World::new();
+    let splitter_pos = Vec2i { x: 2, y: 2 };
+    let config = SplitterConfig {
+        weights: [2, 1, 0],
+        mode: SplitterMode::Weighted,
+    };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: splitter_pos,
+        rotation: 0,
+        config: BuildingConfig::Splitter(config),
+    });
+    for pos in [(3, 2), (2, 3), (2, 1)] {
+        world.place_building(Vec2i { x: pos.0, y: pos.1 }, BuildingType::RecycleBin, 0);
+    }
+    let splitter_id = get_building_id_by_pos(&world, splitter_pos).unwrap();
+    let input = Vec2i { x: 1, y: 2 };
+
+    for _ in 0..6 {
+        feed(&mut world, splitter_id, create_test_packet(), input);
+    }
+    assert_eq!(
+        (
+            packets_at(&world, 3, 2),
+            packets_at(&world, 2, 3),
+            packets_at(&world, 2, 1)
+        ),
+        (4, 2, 0)
+    );
+
+    // Without the front bin, the right output only takes what the left cannot.
+    world.remove_building(&Vec2i { x: 3, y: 2 });
+    let overflow = SplitterConfig {
+        weights: [1, 1, 0],
+        mode: SplitterMode::Overflow { output: 2 },
+    };
+    assert!(world.set_filter_config(splitter_id, BuildingConfig::Splitter(overflow.clone())));
+    assert_eq!(
+        world.filter_config(splitter_id),
+        Some(BuildingConfig::Splitter(overflow))
+    );
+    feed(&mut world, splitter_id, create_test_packet(), input);
+    assert_eq!((packets_at(&world, 2, 3), packets_at(&world, 2, 1)), (3, 0));
+
+    // Once the left output is gone as well, packets go to the overflow output.
+    world.remove_building(&Vec2i { x: 2, y: 3 });
+    feed(&mut world, splitter_id, create_test_packet(), input);
+    assert_eq!(packets_at(&world, 2, 1), 1);
+}
+
+#[test]
+fn test_original_routed_into_analyzer_is_dropped() {
+    use crate::core::dto::DropReason;
+
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Conveyor, 0);
+    world.place_building(Vec2i { x: 1, y: 0 }, BuildingType::Analyzer, 0);
+    let conveyor_id = get_building_id_by_pos(&world, Vec2i { x: 0, y: 0 }).unwrap();
+    world
+        .storage
+        .get_mut(conveyor_id)
+        .unwrap()
+        .accept(create_test_packet(), Vec2i { x: -1, y: 0 });
+    for _ in 0..20 {
+        world.update(0.1);
+    }
+
+    assert_eq!(packets_at(&world, 0, 0), 0);
+    let dropped = world.dropped_packets();
+    assert_eq!(dropped.len(), 1);
+    assert_eq!(
+        (dropped[0].building_type, dropped[0].reason),
+        (BuildingType::Analyzer, DropReason::Misrouted)
+    );
+}
+
+#[test]
+fn test_tap_mirrors_copies_to_analyzer() {
+    use crate::core::buildings::analyzer::Analyzer;
+
+    // Tap facing east: original goes to the bin at (2,0), copies to the analyzer at (1,1).
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 1, y: 0 }, BuildingType::Tap, 0);
+    world.place_building(Vec2i { x: 2, y: 0 }, BuildingType::RecycleBin, 0);
+    world.place_building(Vec2i { x: 1, y: 1 }, BuildingType::Analyzer, 0);
+    let tap_id = get_building_id_by_pos(&world, Vec2i { x: 1, y: 0 }).unwrap();
+    let bin_id = get_building_id_by_pos(&world, Vec2i { x: 2, y: 0 }).unwrap();
+    let analyzer_id = get_building_id_by_pos(&world, Vec2i { x: 1, y: 1 }).unwrap();
+    let analyzed = |world: &World| {
+        world
+            .get_building(analyzer_id)
+            .and_then(|b| b.as_any().downcast_ref::<Analyzer>())
+            .unwrap()
+            .stats()
+            .packets
+    };
+
+    for _ in 0..3 {
+        world
+            .storage
+            .get_mut(tap_id)
+            .unwrap()
+            .accept(create_test_packet(), Vec2i { x: 0, y: 0 });
+        world.update(0.1);
+    }
+    let delivered = world.get_building(bin_id).unwrap().get_packets();
+    assert_eq!(delivered.len(), 3);
+    assert!(delivered.iter().all(|p| !p.is_mirror_copy()));
+    assert_eq!(analyzed(&world), 3);
+
+    // Without an analyzer the copy is skipped; the original still goes through, unpenalized.
+    world.remove_building(&Vec2i { x: 1, y: 1 });
+    world
+        .storage
+        .get_mut(tap_id)
+        .unwrap()
+        .accept(create_test_packet(), Vec2i { x: 0, y: 0 });
+    world.update(0.1);
+    assert_eq!(world.get_building(bin_id).unwrap().get_packets().len(), 4);
+    assert_eq!(world.drop_stats().total, 0);
+
+    // Copies mirrored into a sink other than an analyzer are discarded there.
+    world.place_building(Vec2i { x: 1, y: 1 }, BuildingType::RecycleBin, 0);
+    feed(
+        &mut world,
+        tap_id,
+        create_test_packet(),
+        Vec2i { x: 0, y: 0 },
+    );
+    assert_eq!(world.get_building(bin_id).unwrap().get_packets().len(), 5);
+    assert_eq!(packets_at(&world, 1, 1), 0);
+}
+
+#[test]
+fn test_rate_limiter_sends_excess_to_mismatch() {
+    use crate::core::buildings::filters::rate_limiter::{RateLimitKey, RateLimiterConfig};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    let limiter_pos = Vec2i { x: 2, y: 2 };
+    let config = RateLimiterConfig {
+        limit: 2,
+        window_ms: 1000,
+        key: RateLimitKey::SourceIp,
+    };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: limiter_pos,
+        rotation: 0,
+        config: BuildingConfig::RateLimit(config.clone()),
+    });
+    world.place_building(Vec2i { x: 3, y: 2 }, BuildingType::RecycleBin, 0);
+    world.place_building(Vec2i { x: 2, y: 1 }, BuildingType::RecycleBin, 0);
+    let limiter_id = get_building_id_by_pos(&world, limiter_pos).unwrap();
+    assert_eq!(
+        world.filter_config(limiter_id),
+        Some(BuildingConfig::RateLimit(config))
+    );
+    let send = |world: &mut World| {
+        feed(world, limiter_id, create_test_packet(), RULE_INPUT);
+    };
+
+    for _ in 0..3 {
+        send(&mut world);
+    }
+    assert_eq!((packets_at(&world, 3, 2), packets_at(&world, 2, 1)), (2, 1));
+
+    // The first passes expire with simulated time.
+    for _ in 0..10 {
+        world.update(0.1);
+    }
+    send(&mut world);
+    assert_eq!((packets_at(&world, 3, 2), packets_at(&world, 2, 1)), (3, 1));
+}
+
+#[test]
+fn test_stateful_firewall_allows_replies_of_established_sessions() {
+    use crate::core::buildings::filters::stateful_firewall::{
+        FirewallService, StatefulFirewall, StatefulFirewallConfig,
+    };
+    use crate::core::packet::{Protocol, TcpFlags};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    let firewall_pos = Vec2i { x: 2, y: 2 };
+    let config = StatefulFirewallConfig::new(vec![FirewallService {
+        protocol: Protocol::Tcp,
+        port: 80,
+    }]);
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: firewall_pos,
+        rotation: 0,
+        config: BuildingConfig::Firewall(config.clone()),
+    });
+    world.place_building(Vec2i { x: 3, y: 2 }, BuildingType::RecycleBin, 0);
+    world.place_building(Vec2i { x: 2, y: 1 }, BuildingType::RecycleBin, 0);
+    let firewall_id = get_building_id_by_pos(&world, firewall_pos).unwrap();
+    assert_eq!(
+        world.filter_config(firewall_id),
+        Some(BuildingConfig::Firewall(config))
+    );
+    let send = |world: &mut World, reply: bool, flags: &str| {
+        let mut packet = create_test_packet();
+        if reply {
+            std::mem::swap(&mut packet.source_ip, &mut packet.dest_ip);
+            std::mem::swap(&mut packet.source_port, &mut packet.dest_port);
+        }
+        packet.tcp_flags = TcpFlags::from_letters(flags);
+        feed(world, firewall_id, packet, RULE_INPUT);
+    };
+
+    // An unsolicited reply is blocked; after the client's SYN the handshake goes through.
+    send(&mut world, true, "SA");
+    for (reply, flags) in [(false, "S"), (true, "SA"), (false, "A"), (true, "PA")] {
+        send(&mut world, reply, flags);
+    }
+    assert_eq!((packets_at(&world, 3, 2), packets_at(&world, 2, 1)), (4, 1));
+
+    let firewall = world
+        .get_building(firewall_id)
+        .and_then(|b| b.as_any().downcast_ref::<StatefulFirewall>())
+        .unwrap();
+    assert_eq!(firewall.sessions().len(), 1);
+}
+
+#[test]
+fn test_scan_detector_diverts_a_scanning_source() {
+    use crate::core::buildings::filters::scan_detector::{ScanDetector, ScanDetectorConfig};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    let detector_pos = Vec2i { x: 2, y: 2 };
+    let config = ScanDetectorConfig {
+        window_ms: 5000,
+        port_threshold: 2,
+        host_threshold: 0,
+        grace_ms: 0,
+    };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: detector_pos,
+        rotation: 0,
+        config: BuildingConfig::ScanDetector(config.clone()),
+    });
+    world.place_building(Vec2i { x: 3, y: 2 }, BuildingType::RecycleBin, 0);
+    world.place_building(Vec2i { x: 2, y: 1 }, BuildingType::RecycleBin, 0);
+    let detector_id = get_building_id_by_pos(&world, detector_pos).unwrap();
+    assert_eq!(
+        world.filter_config(detector_id),
+        Some(BuildingConfig::ScanDetector(config))
+    );
+    let send = |world: &mut World, source_ip: &str, dest_port: u16| {
+        let mut packet = create_test_packet();
+        packet.source_ip = source_ip.to_string();
+        packet.dest_port = dest_port;
+        feed(world, detector_id, packet, RULE_INPUT);
+    };
+
+    // The third distinct port flags the scanner; its later traffic stays diverted.
+    for port in [22, 23, 25, 80] {
+        send(&mut world, "10.6.6.6", port);
+    }
+    send(&mut world, "192.168.1.1", 80);
+    assert_eq!((packets_at(&world, 3, 2), packets_at(&world, 2, 1)), (3, 2));
+
+    let detector = world
+        .get_building(detector_id)
+        .and_then(|b| b.as_any().downcast_ref::<ScanDetector>())
+        .unwrap();
+    let flagged: Vec<&str> = detector
+        .flagged_sources()
+        .into_iter()
+        .map(|(source, _)| source)
+        .collect();
+    assert_eq!(flagged, ["10.6.6.6"]);
+}
+
+#[test]
+fn test_scan_detector_feeds_blocklist_used_by_ip_filter() {
+    use crate::core::buildings::filters::ip_filter::{IpBlocklistConfig, IpFilterDirection};
+    use crate::core::buildings::filters::scan_detector::{BlocklistFeed, ScanDetectorConfig};
+    use crate::core::dto::BlocklistChange;
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    // Both face east: input south, match east, mismatch north.
+    let mut world = World::new();
+    let detector_pos = Vec2i { x: 2, y: 2 };
+    let filter_pos = Vec2i { x: 6, y: 2 };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: detector_pos,
+        rotation: 0,
+        config: BuildingConfig::ScanDetectorFeed {
+            detector: ScanDetectorConfig {
+                window_ms: 5000,
+                port_threshold: 1,
+                host_threshold: 0,
+                grace_ms: 0,
+            },
+            feed: BlocklistFeed {
+                list: "scanners".to_string(),
+                ttl_ms: 1000,
+            },
+        },
+    });
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: filter_pos,
+        rotation: 0,
+        config: BuildingConfig::IpBlocklist(IpBlocklistConfig {
+            list: "scanners".to_string(),
+            direction: IpFilterDirection::Source,
+        }),
+    });
+    for (x, y) in [(3, 2), (2, 1), (7, 2), (6, 1)] {
+        world.place_building(Vec2i { x, y }, BuildingType::RecycleBin, 0);
+    }
+    let detector_id = get_building_id_by_pos(&world, detector_pos).unwrap();
+    let filter_id = get_building_id_by_pos(&world, filter_pos).unwrap();
+    let send = |world: &mut World, to: BuildingId, source_ip: &str, dest_port: u16| {
+        let mut packet = create_test_packet();
+        packet.source_ip = source_ip.to_string();
+        packet.dest_port = dest_port;
+        let pos = world.get_building(to).unwrap().position();
+        feed(
+            world,
+            to,
+            packet,
+            Vec2i {
+                x: pos.x,
+                y: pos.y + 1,
+            },
+        );
+    };
+
+    // Before detection the filter lets the scanner through to its mismatch side.
+    send(&mut world, filter_id, "10.6.6.6", 80);
+    world.drain_events();
+    send(&mut world, detector_id, "10.6.6.6", 22);
+    send(&mut world, detector_id, "10.6.6.6", 23);
+    assert!(world.blocklists().contains("scanners", "10.6.6.6"));
+    let changes: Vec<BlocklistChange> = world
+        .drain_events()
+        .into_iter()
+        .filter_map(|event| match event {
+            WorldEvent::BlocklistChanged { change, .. } => Some(change),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        changes,
+        [BlocklistChange::Added {
+            by: Some(detector_id),
+            ttl_ms: Some(1000),
+        }]
+    );
+
+    send(&mut world, filter_id, "10.6.6.6", 80);
+    send(&mut world, filter_id, "192.168.1.1", 80);
+    assert_eq!((packets_at(&world, 7, 2), packets_at(&world, 6, 1)), (1, 2));
+
+    for _ in 0..10 {
+        world.update(0.1);
+    }
+    assert!(!world.blocklists().contains("scanners", "10.6.6.6"));
+    assert!(world.drain_events().iter().any(|event| matches!(
+        event,
+        WorldEvent::BlocklistChanged {
+            change: BlocklistChange::Expired,
+            ..
+        }
+    )));
+}
+
+#[test]
+fn test_honeypot_rewards_diverted_attacks_and_profiles_senders() {
+    use crate::core::buildings::filters::ip_filter::{IpFilterConfig, IpFilterDirection};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    world.set_honeypot_reward(5);
+    let filter_pos = Vec2i { x: 2, y: 2 };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: filter_pos,
+        rotation: 0,
+        config: BuildingConfig::Ip(IpFilterConfig {
+            target_ip: "203.0.113.9".to_string(),
+            direction: IpFilterDirection::Source,
+        }),
+    });
+    world.place_building(Vec2i { x: 3, y: 2 }, BuildingType::Honeypot, 0);
+    world.place_building(Vec2i { x: 2, y: 1 }, BuildingType::RecycleBin, 0);
+    let filter_id = get_building_id_by_pos(&world, filter_pos).unwrap();
+    let send = |world: &mut World, source_ip: &str, dest_port: u16, label| {
+        let mut packet = create_test_packet();
+        packet.source_ip = source_ip.to_string();
+        packet.dest_port = dest_port;
+        packet.label = label;
+        feed(world, filter_id, packet, RULE_INPUT);
+    };
+
+    send(&mut world, "203.0.113.9", 22, PacketLabel::Incorrect);
+    send(&mut world, "203.0.113.9", 3389, PacketLabel::Incorrect);
+    send(&mut world, "192.168.1.1", 80, PacketLabel::Correct);
+    assert_eq!(world.score, 10);
+
+    let profiles = world.attacker_profiles();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].source_ip, "203.0.113.9");
+    assert_eq!(profiles[0].attack_packets, 2);
+    assert_eq!(
+        profiles[0].dest_ports.keys().copied().collect::<Vec<_>>(),
+        [22, 3389]
+    );
+}
+
+#[test]
+fn test_datacenter_drops_closed_services_and_counts_denial_of_service() {
+    use crate::core::buildings::datacenter::{Datacenter, DatacenterConfig, HostedService};
+    use crate::core::buildings::filters::ip_filter::{IpFilterConfig, IpFilterDirection};
+    use crate::core::dto::DropReason;
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    let filter_pos = Vec2i { x: 2, y: 2 };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: filter_pos,
+        rotation: 0,
+        config: BuildingConfig::Ip(IpFilterConfig {
+            target_ip: "192.168.1.1".to_string(),
+            direction: IpFilterDirection::Source,
+        }),
+    });
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: Vec2i { x: 3, y: 2 },
+        rotation: 0,
+        config: BuildingConfig::Datacenter(DatacenterConfig {
+            services: vec![HostedService {
+                protocol: Protocol::Tcp,
+                port: 80,
+                ip: None,
+            }],
+            capacity_pps: 1,
+            queue_size: 1,
+        }),
+    });
+    world.place_building(Vec2i { x: 2, y: 1 }, BuildingType::RecycleBin, 0);
+    let filter_id = get_building_id_by_pos(&world, filter_pos).unwrap();
+    let datacenter_id = get_building_id_by_pos(&world, Vec2i { x: 3, y: 2 }).unwrap();
+    let send = |world: &mut World, dest_port: u16, label| {
+        let mut packet = create_test_packet();
+        packet.dest_port = dest_port;
+        packet.label = label;
+        feed(world, filter_id, packet, RULE_INPUT);
+    };
+
+    send(&mut world, 22, PacketLabel::Incorrect);
+    send(&mut world, 80, PacketLabel::Correct);
+    // The first request is still waiting, so the second one is lost.
+    send(&mut world, 80, PacketLabel::Correct);
+
+    let stats = world.drop_stats();
+    assert_eq!(stats.total, 2);
+    assert_eq!(stats.closed_service, 1);
+    assert_eq!(stats.overloaded, 1);
+    assert_eq!(stats.denial_of_service, 1);
+    assert!(world.drain_events().iter().any(|event| matches!(
+        event,
+        WorldEvent::PacketDropped {
+            building_id,
+            reason: DropReason::ClosedService,
+            ..
+        } if *building_id == datacenter_id
+    )));
+
+    world.update(1.0);
+    let datacenter = world
+        .get_building(datacenter_id)
+        .and_then(|b| b.as_any().downcast_ref::<Datacenter>())
+        .unwrap();
+    assert_eq!(
+        (datacenter.get_packets().len(), datacenter.queued()),
+        (1, 0)
+    );
+}
+
+#[test]
+fn test_shrinking_a_datacenter_queue_counts_overload_drops() {
+    use crate::core::buildings::datacenter::{Datacenter, DatacenterConfig};
+    use crate::map_controller::{BuildingConfig, LayoutEdit};
+
+    let mut world = World::new();
+    let config = DatacenterConfig {
+        services: vec![],
+        capacity_pps: 1,
+        queue_size: 3,
+    };
+    world.apply_edit(&LayoutEdit::PlaceFilter {
+        pos: Vec2i { x: 2, y: 2 },
+        rotation: 0,
+        config: BuildingConfig::Datacenter(config.clone()),
+    });
+    let datacenter_id = get_building_id_by_pos(&world, Vec2i { x: 2, y: 2 }).unwrap();
+    for _ in 0..3 {
+        feed(&mut world, datacenter_id, create_test_packet(), RULE_INPUT);
+    }
+
+    assert!(world.set_filter_config(
+        datacenter_id,
+        BuildingConfig::Datacenter(DatacenterConfig {
+            queue_size: 1,
+            ..config
+        })
+    ));
+    assert_eq!(world.drop_stats().overloaded, 2);
+    let datacenter = world
+        .get_building(datacenter_id)
+        .and_then(|b| b.as_any().downcast_ref::<Datacenter>())
+        .unwrap();
+    assert_eq!(datacenter.queued(), 1);
+}
@@ -1,9 +1,12 @@
 use crate::core::building::BuildingType;
 use crate::core::dto::Vec2i;
-use crate::core::packet::{Packet, PacketLabel, Protocol};
+use crate::core::packet::{Packet, PacketId, PacketLabel, Protocol};
 use crate::logic::packet_completion;
 use crate::map_controller::World;
 
+/// Origin given to the packets of the tests that plan their traffic directly.
+const SOURCE: u64 = 99;
+
 fn make_packet(
     source_ip: &str,
     dest_ip: &str,
@@ -26,6 +29,17 @@ fn make_packet(
     packet
 }
 
+/// Plan `packets` for `SOURCE`, numbering them as its `Internet` would.
+fn plan(world: &mut World, packets: &mut [Packet]) {
+    for (index, packet) in packets.iter_mut().enumerate() {
+        packet.id = Some(PacketId {
+            origin: SOURCE,
+            index: index as u64,
+        });
+    }
+    packet_completion::register_source_core_packets(world, SOURCE, packets.iter());
+}
+
 fn ids_for(world: &World) -> (u64, u64) {
     let mut datacenter_id = None;
     let mut recycle_id = None;
@@ -44,9 +58,7 @@ fn ids_for(world: &World) -> (u64, u64) {
 
 #[test]
 fn completed_packets_reports_once_all_delivered() {
-    packet_completion::clear_planned_packets();
-
-    let planned_packets = vec![
+    let mut planned_packets = vec![
         make_packet(
             "10.0.0.1",
             "10.0.0.100",
@@ -66,9 +78,8 @@ fn completed_packets_reports_once_all_delivered() {
             PacketLabel::Incorrect,
         ),
     ];
-    packet_completion::register_planned_core_packets(planned_packets.iter());
-
     let mut world = World::new();
+    plan(&mut world, &mut planned_packets);
     world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Datacenter, 0);
     world.place_building(Vec2i { x: 3, y: 0 }, BuildingType::RecycleBin, 0);
 
@@ -95,9 +106,14 @@ fn completed_packets_reports_once_all_delivered() {
 }
 
 #[test]
-fn clear_planned_packets_resets_state() {
-    packet_completion::register_planned_core_packets(
-        [make_packet(
+fn world_without_planned_traffic_never_completes() {
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Datacenter, 0);
+    world.place_building(Vec2i { x: 3, y: 0 }, BuildingType::RecycleBin, 0);
+    let (datacenter_id, _) = ids_for(&world);
+    // Everything that arrives is delivered, but there is nothing to wait for.
+    world.storage.get_mut(datacenter_id).unwrap().accept(
+        make_packet(
             "10.0.0.1",
             "10.0.0.100",
             1234,
@@ -105,11 +121,304 @@ fn clear_planned_packets_resets_state() {
             Protocol::Tcp,
             128,
             PacketLabel::Correct,
-        )]
-        .iter(),
+        ),
+        Vec2i { x: 0, y: 0 },
+    );
+    assert!(packet_completion::completed_packet_count_for_test(&world).is_none());
+}
+
+#[test]
+fn dropped_packets_count_as_resolved_with_penalty() {
+    use crate::core::dto::{DropReason, WorldEvent};
+
+    let delivered = make_packet(
+        "10.0.0.1",
+        "10.0.0.100",
+        1234,
+        80,
+        Protocol::Tcp,
+        128,
+        PacketLabel::Correct,
+    );
+    let lost = make_packet(
+        "10.0.0.2",
+        "10.0.0.200",
+        4321,
+        53,
+        Protocol::Udp,
+        256,
+        PacketLabel::Incorrect,
+    );
+    let mut world = World::new();
+    let mut packets = [delivered, lost];
+    plan(&mut world, &mut packets);
+    let [delivered, lost] = packets;
+
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Datacenter, 0);
+    world.place_building(Vec2i { x: 3, y: 0 }, BuildingType::RecycleBin, 0);
+    // A conveyor with nothing in front of it: whatever it carries gets dropped.
+    world.place_building(Vec2i { x: 10, y: 10 }, BuildingType::Conveyor, 0);
+    world.rebuild_connections();
+    world.score = 10;
+    world.set_drop_penalty(3);
+
+    let (datacenter_id, _) = ids_for(&world);
+    let conveyor_id = world.get_building_id_at(&Vec2i { x: 10, y: 10 }).unwrap();
+    world
+        .storage
+        .get_mut(datacenter_id)
+        .unwrap()
+        .accept(delivered, Vec2i { x: 0, y: 0 });
+    world
+        .storage
+        .get_mut(conveyor_id)
+        .unwrap()
+        .accept(lost, Vec2i { x: 9, y: 10 });
+    assert!(packet_completion::completed_packet_count_for_test(&world).is_none());
+
+    world.drain_events();
+    world.update(2.0);
+
+    let events = world.drain_events();
+    assert!(events.iter().any(|event| matches!(
+        event,
+        WorldEvent::PacketDropped { building_id, reason: DropReason::NoOutputs, .. }
+            if *building_id == conveyor_id
+    )));
+
+    let stats = world.drop_stats();
+    assert_eq!(stats.total, 1);
+    assert_eq!(stats.no_outputs, 1);
+    assert_eq!(stats.incorrect, 1);
+    assert_eq!(stats.penalty, 3);
+    assert_eq!(world.score, 7);
+
+    let completed = packet_completion::completed_packet_count_for_test(&world)
+        .expect("dropped packets should count as resolved");
+    assert_eq!(completed, 2);
+}
+
+#[test]
+fn stage_sources_split_traffic_and_complete_per_source() {
+    use crate::logic::stage;
+
+    const STAGE: &str = r#"{
+      "meta": {
+        "packetsType": "json",
+        "packetsPath": "res://traffic.json",
+        "sources": [
+          {"x": 0, "y": 0, "cidr": "10.0.0.1/32"},
+          {"x": 0, "y": 5, "tag": "office"}
+        ]
+      },
+      "buildings": [
+        {"x": 0, "y": 0, "blockId": 0, "rotation": 0},
+        {"x": 0, "y": 5, "blockId": 0, "rotation": 0},
+        {"x": 0, "y": 9, "blockId": 0, "rotation": 0}
+      ]
+    }"#;
+    const TRAFFIC: &str = r#"[
+      {"src_ip": "10.0.0.1", "dst_ip": "10.0.0.9", "src_port": 1000, "dst_port": 80,
+       "protocol": 6, "size": 64, "timestamp": 0, "label": "incorrect"},
+      {"src_ip": "10.0.0.2", "dst_ip": "10.0.0.9", "src_port": 1001, "dst_port": 80,
+       "protocol": 6, "size": 64, "timestamp": 200000, "label": "correct", "tag": "office"},
+      {"src_ip": "10.0.0.1", "dst_ip": "10.0.0.9", "src_port": 1002, "dst_port": 80,
+       "protocol": 6, "size": 64, "timestamp": 900000, "label": "incorrect"},
+      {"src_ip": "10.0.0.3", "dst_ip": "10.0.0.9", "src_port": 1003, "dst_port": 53,
+       "protocol": 17, "size": 64, "timestamp": 1500000, "label": "correct", "tag": "office"}
+    ]"#;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("stage.json"), STAGE).unwrap();
+    std::fs::write(dir.path().join("traffic.json"), TRAFFIC).unwrap();
+    let mut world = stage::load_stage("res://stage.json", dir.path()).expect("stage loads");
+    let first = world.get_building_id_at(&Vec2i { x: 0, y: 0 }).unwrap();
+    let second = world.get_building_id_at(&Vec2i { x: 0, y: 5 }).unwrap();
+
+    let progress = packet_completion::source_progress(&world);
+    assert_eq!(
+        progress
+            .iter()
+            .map(|p| (p.source, p.planned, p.finished))
+            .collect::<Vec<_>>(),
+        [(first, Some(2), false), (second, Some(2), false)]
+    );
+
+    // Nothing is connected, so every packet is dropped at its Internet as it appears; the
+    // unlisted third Internet emits nothing.
+    for _ in 0..20 {
+        world.update(0.1);
+    }
+    assert_eq!(
+        packet_completion::completed_packet_count_for_test(&world),
+        Some(4)
+    );
+    assert!(
+        packet_completion::source_progress(&world)
+            .iter()
+            .all(|p| p.resolved == 2 && p.finished)
     );
-    packet_completion::clear_planned_packets();
+    for dropped in world.dropped_packets() {
+        let expected = if dropped.packet.source_ip == "10.0.0.1" {
+            first
+        } else {
+            second
+        };
+        assert_eq!(dropped.building_id, expected);
+    }
+}
+
+#[test]
+fn stage_without_sources_gives_the_traffic_to_the_first_internet() {
+    use crate::logic::stage;
+
+    const STAGE: &str = r#"{
+      "meta": {"packetsType": "json", "packetsPath": "res://traffic.json"},
+      "buildings": [
+        {"x": 0, "y": 0, "blockId": 0, "rotation": 0},
+        {"x": 0, "y": 5, "blockId": 0, "rotation": 0}
+      ]
+    }"#;
+    const TRAFFIC: &str = r#"[
+      {"src_ip": "10.0.0.1", "dst_ip": "10.0.0.9", "src_port": 1000, "dst_port": 80,
+       "protocol": 6, "size": 64, "timestamp": 0, "label": "correct"},
+      {"src_ip": "10.0.0.2", "dst_ip": "10.0.0.9", "src_port": 1001, "dst_port": 80,
+       "protocol": 6, "size": 64, "timestamp": 200000, "label": "correct"}
+    ]"#;
+
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::write(dir.path().join("stage.json"), STAGE).unwrap();
+    std::fs::write(dir.path().join("traffic.json"), TRAFFIC).unwrap();
+    let mut world = stage::load_stage("res://stage.json", dir.path()).expect("stage loads");
+    let first = world.get_building_id_at(&Vec2i { x: 0, y: 0 }).unwrap();
 
-    let world = World::new();
+    for _ in 0..10 {
+        world.update(0.1);
+    }
+    // Each packet is emitted once, not once per Internet.
+    assert_eq!(
+        packet_completion::completed_packet_count_for_test(&world),
+        Some(2)
+    );
+    assert!(
+        world
+            .dropped_packets()
+            .iter()
+            .all(|dropped| dropped.building_id == first)
+    );
+    assert_eq!(
+        packet_completion::source_progress(&world)
+            .iter()
+            .map(|p| (p.source, p.planned))
+            .collect::<Vec<_>>(),
+        [(first, Some(2))]
+    );
+}
+
+#[test]
+fn streamed_source_finishes_on_its_own_packets() {
+    use crate::core::buildings::internet::Internet;
+    use crate::packet::traffic_source::VecTrafficSource;
+
+    let mut world = World::new();
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Internet, 0);
+    world.place_building(Vec2i { x: 0, y: 5 }, BuildingType::Internet, 0);
+    world.place_building(Vec2i { x: 10, y: 10 }, BuildingType::Conveyor, 0);
+    let done = world.get_building_id_at(&Vec2i { x: 0, y: 0 }).unwrap();
+    let busy = world.get_building_id_at(&Vec2i { x: 0, y: 5 }).unwrap();
+    let conveyor_id = world.get_building_id_at(&Vec2i { x: 10, y: 10 }).unwrap();
+
+    let mut later = make_packet(
+        "10.0.0.5",
+        "10.0.0.9",
+        2000,
+        80,
+        Protocol::Tcp,
+        64,
+        PacketLabel::Correct,
+    );
+    later.timestamp = 60_000_000;
+    for (id, packets) in [(done, vec![]), (busy, vec![later])] {
+        world
+            .storage
+            .get_mut(id)
+            .and_then(|b| b.as_any_mut().downcast_mut::<Internet>())
+            .unwrap()
+            .set_source(Box::new(VecTrafficSource::new(packets)));
+        packet_completion::register_streaming_source(&mut world, id);
+    }
+
+    let mut in_transit = make_packet(
+        "10.0.0.6",
+        "10.0.0.9",
+        2001,
+        80,
+        Protocol::Tcp,
+        64,
+        PacketLabel::Correct,
+    );
+    in_transit.id = Some(PacketId {
+        origin: busy,
+        index: 0,
+    });
+    world
+        .storage
+        .get_mut(conveyor_id)
+        .unwrap()
+        .accept(in_transit.clone(), Vec2i { x: 9, y: 10 });
+
+    // The other source's backlog and its packet on the conveyor do not hold this one up.
+    let finished = |world: &World| {
+        packet_completion::source_progress(world)
+            .iter()
+            .map(|p| (p.source, p.finished))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(finished(&world), [(done, true), (busy, false)]);
+
+    in_transit.id = Some(PacketId {
+        origin: done,
+        index: 0,
+    });
+    world
+        .storage
+        .get_mut(conveyor_id)
+        .unwrap()
+        .accept(in_transit, Vec2i { x: 9, y: 10 });
+    assert_eq!(finished(&world), [(done, false), (busy, false)]);
+    assert!(packet_completion::completed_packet_count_for_test(&world).is_none());
+}
+
+#[test]
+fn identical_headers_do_not_stand_in_for_each_other() {
+    let packet = make_packet(
+        "10.0.0.1",
+        "10.0.0.100",
+        1234,
+        80,
+        Protocol::Tcp,
+        128,
+        PacketLabel::Correct,
+    );
+    let mut planned = [packet.clone(), packet];
+    let mut world = World::new();
+    plan(&mut world, &mut planned);
+    world.place_building(Vec2i { x: 0, y: 0 }, BuildingType::Datacenter, 0);
+    world.place_building(Vec2i { x: 3, y: 0 }, BuildingType::RecycleBin, 0);
+    let (datacenter_id, _) = ids_for(&world);
+    // The first packet twice over still leaves the second one unresolved.
+    let datacenter = world.storage.get_mut(datacenter_id).unwrap();
+    datacenter.accept(planned[0].clone(), Vec2i { x: 0, y: 0 });
+    datacenter.accept(planned[0].clone(), Vec2i { x: 0, y: 0 });
     assert!(packet_completion::completed_packet_count_for_test(&world).is_none());
+
+    world
+        .storage
+        .get_mut(datacenter_id)
+        .unwrap()
+        .accept(planned[1].clone(), Vec2i { x: 0, y: 0 });
+    assert_eq!(
+        packet_completion::completed_packet_count_for_test(&world),
+        Some(3)
+    );
 }
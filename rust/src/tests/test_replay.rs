@@ -0,0 +1,154 @@
+use std::fs;
+use std::path::Path;
+
+use crate::core::building::BuildingType;
+use crate::core::building_registry::block_id;
+use crate::core::buildings::filters::ip_filter::{IpFilterConfig, IpFilterDirection};
+use crate::core::dto::Vec2i;
+use crate::logic::replay::{self, REPLAY_VERSION, Replay, ReplayEdit, ReplayRecorder};
+use crate::map_controller::{BuildingConfig, LayoutEdit, World};
+
+const STAGE: &str = r#"{
+  "meta": {"packetsType": "json", "packetsPath": "res://traffic.json", "dropPenalty": 5},
+  "buildings": [
+    {"x": 0, "y": 0, "blockId": 0, "rotation": 0},
+    {"x": 3, "y": 1, "blockId": 16, "rotation": 0},
+    {"x": 2, "y": 2, "blockId": 16, "rotation": 0}
+  ]
+}"#;
+
+const TRAFFIC: &str = r#"[
+  {"src_ip": "10.0.0.1", "dst_ip": "10.0.0.9", "src_port": 1000, "dst_port": 80,
+   "protocol": 6, "size": 64, "timestamp": 0, "label": "incorrect"},
+  {"src_ip": "10.0.0.2", "dst_ip": "10.0.0.9", "src_port": 1001, "dst_port": 80,
+   "protocol": 6, "size": 64, "timestamp": 200000, "label": "correct"},
+  {"src_ip": "10.0.0.1", "dst_ip": "10.0.0.9", "src_port": 1002, "dst_port": 80,
+   "protocol": 6, "size": 64, "timestamp": 900000, "label": "incorrect"},
+  {"src_ip": "10.0.0.2", "dst_ip": "10.0.0.9", "src_port": 1003, "dst_port": 80,
+   "protocol": 17, "size": 64, "timestamp": 1500000, "label": "correct"}
+]"#;
+
+fn ip_rule(target_ip: &str) -> BuildingConfig {
+    BuildingConfig::Ip(IpFilterConfig {
+        target_ip: target_ip.to_string(),
+        direction: IpFilterDirection::Source,
+    })
+}
+
+/// Filter placed before the start, then retargeted while packets are in flight.
+fn recorded_replay() -> Replay {
+    let mut recorder = ReplayRecorder::default();
+    recorder.start("res://stage.json".to_string());
+    recorder.record(
+        0,
+        LayoutEdit::PlaceFilter {
+            pos: Vec2i { x: 2, y: 1 },
+            rotation: 1,
+            config: ip_rule("10.0.0.1"),
+        },
+    );
+    recorder.record(
+        70,
+        LayoutEdit::SetFilterConfig {
+            building_id: 3,
+            config: ip_rule("10.0.0.2"),
+        },
+    );
+    recorder.to_replay(400).expect("stage was started")
+}
+
+fn write_project(root: &Path) {
+    fs::write(root.join("stage.json"), STAGE).unwrap();
+    fs::write(root.join("traffic.json"), TRAFFIC).unwrap();
+}
+
+#[test]
+fn replay_round_trips_through_json() {
+    let replay = recorded_replay();
+    let parsed = Replay::from_json(&replay.to_json()).expect("valid replay");
+
+    assert_eq!(parsed.version, REPLAY_VERSION);
+    assert_eq!(parsed.stage, "res://stage.json");
+    assert_eq!(parsed.end_tick, 400);
+    assert_eq!(parsed.edits.len(), 2);
+    assert!(matches!(
+        &parsed.edits[1].edit,
+        LayoutEdit::SetFilterConfig { building_id: 3, config: BuildingConfig::Ip(rule) }
+            if rule.target_ip == "10.0.0.2"
+    ));
+
+    let mut other_version: serde_json::Value = serde_json::from_str(&replay.to_json()).unwrap();
+    other_version["version"] = serde_json::json!(REPLAY_VERSION + 1);
+    assert!(Replay::from_json(&other_version.to_string()).is_err());
+}
+
+#[test]
+fn replaying_twice_gives_identical_outcomes() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+    let replay = recorded_replay();
+
+    let first = replay::run_replay(&replay, dir.path()).expect("replay runs");
+    let second = replay::run_replay(&replay, dir.path()).expect("replay runs");
+
+    assert_eq!(first, second);
+    assert_eq!(first.ticks, 400);
+    assert!(first.completed);
+    let delivered: usize = first
+        .sinks
+        .iter()
+        .map(|sink| sink.correct + sink.incorrect + sink.unknown)
+        .sum();
+    assert_eq!(delivered + first.drops.total, 4);
+}
+
+#[test]
+fn edits_take_effect_at_their_tick() {
+    let dir = tempfile::tempdir().unwrap();
+    write_project(dir.path());
+    let mut replay = recorded_replay();
+    let baseline = replay::run_replay(&replay, dir.path()).unwrap();
+
+    // Retargeted after the last packet left the filter, so every packet keeps the first rule.
+    replay.edits[1].tick = 390;
+    let late = replay::run_replay(&replay, dir.path()).unwrap();
+    assert_ne!(baseline.sinks, late.sinks);
+}
+
+#[test]
+fn play_applies_edits_before_the_update_of_their_tick() {
+    let mut world = World::new();
+    let edits = vec![
+        ReplayEdit {
+            tick: 0,
+            edit: LayoutEdit::Place {
+                pos: Vec2i { x: 0, y: 0 },
+                block_id: block_id(BuildingType::Internet),
+                rotation: 0,
+            },
+        },
+        ReplayEdit {
+            tick: 5,
+            edit: LayoutEdit::Remove {
+                pos: Vec2i { x: 0, y: 0 },
+            },
+        },
+    ];
+
+    replay::play(&mut world, &edits, 5);
+    assert_eq!(world.tick(), 5);
+    assert_eq!(world.storage.iter().count(), 1);
+
+    replay::play(&mut world, &edits[1..], 6);
+    assert_eq!(world.storage.iter().count(), 0);
+}
+
+#[test]
+fn storage_iterates_in_placement_order() {
+    let mut world = World::new();
+    for x in (0..8).rev() {
+        world.place_building(Vec2i { x: x * 2, y: 0 }, BuildingType::Conveyor, 0);
+    }
+    let ids: Vec<u64> = world.storage.iter().map(|b| b.id()).collect();
+    assert_eq!(ids, (0..8).collect::<Vec<_>>());
+}